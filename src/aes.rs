@@ -0,0 +1,194 @@
+//! Finding AES keys in leaked memory by their key schedules, like
+//! aeskeyfind does in memory images.
+//!
+//! Code that encrypts with AES keeps the expanded key schedule around, the
+//! key followed by the round keys derived from it. A key can look like any
+//! other bytes, but the bytes after it only look like its schedule if that is
+//! what they are. So every place in a range is taken as the start of a key,
+//! of 16 bytes for AES-128 and 32 for AES-256, the schedule is expanded from
+//! it, and the place is reported if the bytes after it match the schedule to
+//! within a few misread bits. Only encryption schedules are found, not the
+//! inverted ones some implementations keep for decryption.
+
+use std::cmp::max;
+
+/// How many bits of a schedule can be misread, or not leak, and the key
+/// still be reported, unless told otherwise. A byte that didn't leak counts
+/// as 8.
+pub const DEFAULT_MAX_ERRORS: u32 = 10;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// The key lengths looked for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeySize {
+    Aes128,
+    Aes256,
+}
+
+impl KeySize {
+    /// The key's length in bytes.
+    pub fn key_len(self) -> usize {
+        match self {
+            KeySize::Aes128 => 16,
+            KeySize::Aes256 => 32,
+        }
+    }
+
+    /// The expanded schedule's length in bytes, the key included.
+    pub fn schedule_len(self) -> usize {
+        match self {
+            KeySize::Aes128 => 176,
+            KeySize::Aes256 => 240,
+        }
+    }
+}
+
+/// Expands `key`, of 16 or 32 bytes, into its encryption key schedule as
+/// FIPS-197 specifies, the key itself first.
+pub fn expand_key(key: &[u8]) -> Vec<u8> {
+    let nk = key.len() / 4;
+    assert!(key.len() == 16 || key.len() == 32);
+    let words = 4 * (nk + 7);
+    let mut schedule = key.to_vec();
+    for i in nk..words {
+        let mut word = [0; 4];
+        word.copy_from_slice(&schedule[(i - 1) * 4..i * 4]);
+        if i % nk == 0 {
+            word = [
+                SBOX[word[1] as usize] ^ RCON[i / nk - 1],
+                SBOX[word[2] as usize],
+                SBOX[word[3] as usize],
+                SBOX[word[0] as usize],
+            ];
+        } else if nk > 6 && i % nk == 4 {
+            for byte in &mut word {
+                *byte = SBOX[*byte as usize]
+            }
+        }
+        for j in 0..4 {
+            let byte = schedule[(i - nk) * 4 + j] ^ word[j];
+            schedule.push(byte)
+        }
+    }
+    schedule
+}
+
+/// A key whose schedule was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Key {
+    /// Where the schedule, and so the key, starts.
+    pub addr: usize,
+    pub size: KeySize,
+    pub key: Vec<u8>,
+    /// How many bits of the round keys didn't match the key's schedule.
+    pub errors: u32,
+}
+
+// the key schedule of size starting at bytes[0], if bytes holds one to
+// within max_errors bits
+fn check(bytes: &[Option<u8>], addr: usize, size: KeySize, max_errors: u32) -> Option<Key> {
+    let leaked = &bytes[..size.schedule_len()];
+    let key: Vec<u8> = leaked[..size.key_len()].iter().cloned().collect::<Option<_>>()?;
+    // zeroed memory, the most common thing there is, is never a key
+    if key.iter().all(|&byte| byte == 0) {
+        return None;
+    }
+    let mut errors = 0;
+    let expected = expand_key(&key);
+    for (leaked, expected) in leaked.iter().zip(&expected).skip(size.key_len()) {
+        errors += match *leaked {
+            Some(byte) => (byte ^ expected).count_ones(),
+            None => 8,
+        };
+        if errors > max_errors {
+            return None;
+        }
+    }
+    Some(Key {
+        addr,
+        size,
+        key,
+        errors,
+    })
+}
+
+/// Looks for key schedules in memory that is leaked and fed to it a piece at
+/// a time, so schedules that cross from one piece into the next are found.
+pub struct Scanner {
+    max_errors: u32,
+    // the address of bytes[0]
+    base: usize,
+    // bytes schedules starting in them could still run on past
+    bytes: Vec<Option<u8>>,
+}
+
+impl Scanner {
+    /// Reports schedules with at most `max_errors` bits wrong.
+    pub fn new(max_errors: u32) -> Scanner {
+        Scanner {
+            max_errors,
+            base: 0,
+            bytes: Vec::new(),
+        }
+    }
+
+    // the keys whose schedules start at bytes[start], of the sizes that fit
+    fn check_at(&self, start: usize) -> Vec<Key> {
+        let bytes = &self.bytes[start..];
+        let addr = self.base.wrapping_add(start);
+        [KeySize::Aes128, KeySize::Aes256]
+            .iter()
+            .filter(|size| size.schedule_len() <= bytes.len())
+            .filter_map(|&size| check(bytes, addr, size, self.max_errors))
+            .collect()
+    }
+
+    /// Feeds the bytes leaked from `addr` on, which has to be where the last
+    /// piece ended, and returns the keys found so far.
+    pub fn feed(&mut self, addr: usize, bytes: &[Option<u8>]) -> Vec<Key> {
+        if self.bytes.is_empty() {
+            self.base = addr;
+        }
+        self.bytes.extend_from_slice(bytes);
+        // schedules starting this early are whole, whatever their size
+        let longest = max(KeySize::Aes128.schedule_len(), KeySize::Aes256.schedule_len());
+        let done = self.bytes.len().saturating_sub(longest - 1);
+        let mut result = Vec::new();
+        for start in 0..done {
+            result.extend(self.check_at(start))
+        }
+        self.bytes.drain(..done);
+        self.base = self.base.wrapping_add(done);
+        result
+    }
+
+    /// The keys found in what was left over, which only fit the shorter
+    /// schedules.
+    pub fn finish(self) -> Vec<Key> {
+        let mut result = Vec::new();
+        for start in 0..self.bytes.len() {
+            result.extend(self.check_at(start))
+        }
+        result
+    }
+}
@@ -0,0 +1,67 @@
+use cli::parse_number;
+use hexdump::confidence_digit;
+use meltdown_rust::ByteGuess;
+use repl::{parse_addr, Addr};
+
+use std::fmt::Write;
+
+/// A read asked for by a line of a batch.
+pub struct Target {
+    pub addr: Addr,
+    pub len: usize,
+    pub label: String,
+}
+
+// the first word of s, and the rest of it, trimmed
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_left();
+    match s.find(char::is_whitespace) {
+        Some(end) => (&s[..end], s[end..].trim()),
+        None => (s, ""),
+    }
+}
+
+/// Parses lines of `address length [label]`, where the address can be a
+/// kernel symbol and the label runs to the end of the line. A line without
+/// a label is labelled with its address as written. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn parse(text: &str) -> Result<Vec<Target>, String> {
+    let mut targets = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (addr, rest) = split_word(line);
+        let (len, label) = split_word(rest);
+        if len.is_empty() {
+            return Err(format!("line {}: expected `address length [label]`", i + 1));
+        }
+        let len = parse_number(len).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if len == 0 {
+            return Err(format!("line {}: the length is 0", i + 1));
+        }
+        targets.push(Target {
+            addr: parse_addr(addr).map_err(|e| format!("line {}: {}", i + 1, e))?,
+            len,
+            label: if label.is_empty() { addr } else { label }.to_owned(),
+        });
+    }
+    Ok(targets)
+}
+
+/// The result of a read as a line of tab separated fields: the label, the
+/// address read from, the bytes in hex with `??` for those that didn't leak,
+/// and a confidence digit per byte, as in the last column of a hexdump.
+pub fn format_result(label: &str, addr: usize, guesses: &[ByteGuess]) -> String {
+    let mut line = format!("{}\t0x{:016x}\t", label, addr);
+    for guess in guesses {
+        match guess.value {
+            Some(byte) => write!(line, "{:02x}", byte).unwrap(),
+            None => line.push_str("??"),
+        }
+    }
+    line.push('\t');
+    line.extend(guesses.iter().map(confidence_digit));
+    line
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::*;
+
+use cpu::CpuFeatures;
+use timer::{self, Timer};
+
+pub mod slices;
+
+// 0 until detected, then 1 without clflushopt and 2 with it
+static CLFLUSHOPT: AtomicUsize = AtomicUsize::new(0);
+
+#[inline(always)]
+fn has_clflushopt() -> bool {
+    match CLFLUSHOPT.load(Relaxed) {
+        0 => {
+            let clflushopt = CpuFeatures::detect().clflushopt;
+            CLFLUSHOPT.store(1 + clflushopt as usize, Relaxed);
+            clflushopt
+        }
+        state => state == 2,
+    }
+}
+
+// flushes the cache line pointed to by adrs
+#[inline(always)]
+pub unsafe fn flush(adrs: *const u8) {
+    asm!(
+            "mfence; \
+             clflush 0($0)"
+            :: "r" (adrs)
+            :: "volatile"
+        );
+}
+
+// ensure the lines of the buffer we probe are completely out of cache.
+// clflushopts of different lines can overlap, so with it only the last one
+// has to be waited for
+#[inline(always)]
+pub unsafe fn flush_probe_buf(buf: *const u8, stride: usize, slots: usize) {
+    if has_clflushopt() {
+        for i in 0..slots {
+            asm!("clflushopt 0($0)" :: "r" (buf.add(i * stride)) :: "volatile");
+        }
+        asm!("mfence"::::"volatile");
+    } else {
+        for i in 0..slots {
+            flush(buf.add(i * stride))
+        }
+    }
+}
+
+#[inline(always)]
+pub fn time<T: Timer, F: FnOnce()>(timer: &T, f: F) -> u64 {
+    let start_reading = timer.start();
+    f();
+    timer.stop(start_reading)
+}
+
+// returns an elapsed time for accessing a memory location
+#[inline(always)]
+pub unsafe fn probe_with<T: Timer>(timer: &T, adrs: *const u8) -> u64 {
+    time(timer, #[inline(always)]
+    || {
+        adrs.read_volatile();
+    })
+}
+
+// probe_with the timer selected for the process
+#[inline(always)]
+pub unsafe fn probe(adrs: *const u8) -> u64 {
+    probe_with(&timer::selected(), adrs)
+}
+
+// returns an elapsed time for flushing a memory location, which is longer
+// when the line was cached. leaves the line flushed
+#[inline(always)]
+pub unsafe fn probe_flush_with<T: Timer>(timer: &T, adrs: *const u8) -> u64 {
+    time(timer, #[inline(always)]
+    || {
+        asm!(
+            "clflush 0($0); \
+             mfence"
+            :: "r" (adrs)
+            :: "volatile"
+        );
+    })
+}
+
+// probe_flush_with the timer selected for the process
+#[inline(always)]
+pub unsafe fn probe_flush(adrs: *const u8) -> u64 {
+    probe_flush_with(&timer::selected(), adrs)
+}
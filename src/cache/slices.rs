@@ -0,0 +1,166 @@
+//! Where a physical address lands in the last level cache of Intel parts
+//! with complex addressing.
+//!
+//! The LLC is split into one slice per core. The slice a line goes to is
+//! picked by XORing together a fixed selection of physical address bits for
+//! each bit of the slice number (Maurice et al., "Reverse Engineering Intel
+//! Last-Level Cache Complex Addressing Using Performance Counters"), and the
+//! set within a slice comes from the bits right above the line offset. Two
+//! lines can only evict each other if both match.
+//!
+//! Parts with 2^n slices use the first n functions, so hashing with all of
+//! them and with fewer sets than the cache really has splits lines up more
+//! finely than the hardware does, but never separates lines the hardware
+//! considers congruent.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use topology::Topology;
+use PAGE_SIZE;
+
+// the physical address bits XORed into each bit of the slice number
+const SLICE_FUNCTIONS: [u64; 3] = [
+    1 << 6 | 1 << 10 | 1 << 12 | 1 << 14 | 1 << 16 | 1 << 17 | 1 << 18 | 1 << 20 | 1 << 22
+        | 1 << 24 | 1 << 25 | 1 << 26 | 1 << 27 | 1 << 28 | 1 << 30 | 1 << 32 | 1 << 33
+        | 1 << 35 | 1 << 36,
+    1 << 7 | 1 << 11 | 1 << 13 | 1 << 15 | 1 << 17 | 1 << 19 | 1 << 20 | 1 << 21 | 1 << 22
+        | 1 << 23 | 1 << 24 | 1 << 26 | 1 << 28 | 1 << 29 | 1 << 31 | 1 << 33 | 1 << 34
+        | 1 << 35 | 1 << 37,
+    1 << 8 | 1 << 12 | 1 << 13 | 1 << 16 | 1 << 19 | 1 << 22 | 1 << 23 | 1 << 26 | 1 << 27
+        | 1 << 30 | 1 << 31 | 1 << 34 | 1 << 35 | 1 << 36 | 1 << 37,
+];
+
+/// The most slices the known hash functions can tell apart.
+pub const MAX_SLICES: usize = 1 << SLICE_FUNCTIONS.len();
+
+/// Every Intel part with complex addressing has at least this many sets per
+/// slice, 1 MiB or more of 16 ways.
+pub const MIN_SETS_PER_SLICE: usize = 1024;
+
+/// The LLC slice and set of a line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub slice: usize,
+    pub set: usize,
+}
+
+/// Maps physical addresses to LLC slices and sets for a cache of `slices`
+/// slices of `sets` sets each, both powers of two, with the line size
+/// `Topology` reports.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SliceMap {
+    slices: usize,
+    sets: usize,
+    line_bits: u32,
+}
+
+fn line_bits() -> u32 {
+    Topology::current().line_size().trailing_zeros()
+}
+
+impl SliceMap {
+    /// None unless `slices` is a power of two no larger than `MAX_SLICES`,
+    /// and `sets` a power of two.
+    pub fn new(slices: usize, sets: usize) -> Option<SliceMap> {
+        if slices.is_power_of_two() && slices <= MAX_SLICES && sets.is_power_of_two() {
+            Some(SliceMap {
+                slices,
+                sets,
+                line_bits: line_bits(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The finest split of lines that is still correct for every part: the
+    /// most slices and the fewest sets.
+    pub fn finest() -> SliceMap {
+        SliceMap {
+            slices: MAX_SLICES,
+            sets: MIN_SETS_PER_SLICE,
+            line_bits: line_bits(),
+        }
+    }
+
+    pub fn slice(&self, paddr: u64) -> usize {
+        let mut slice = 0;
+        for (bit, &function) in SLICE_FUNCTIONS.iter().enumerate() {
+            if 1 << bit >= self.slices {
+                break;
+            }
+            slice |= ((paddr & function).count_ones() as usize & 1) << bit;
+        }
+        slice
+    }
+
+    pub fn set(&self, paddr: u64) -> usize {
+        (paddr >> self.line_bits) as usize & (self.sets - 1)
+    }
+
+    pub fn locate(&self, paddr: u64) -> Location {
+        Location {
+            slice: self.slice(paddr),
+            set: self.set(paddr),
+        }
+    }
+}
+
+// bits of a /proc/self/pagemap entry
+const PAGE_PRESENT: u64 = 1 << 63;
+const PFN_MASK: u64 = (1 << 55) - 1;
+
+/// Translates virtual addresses of the calling process, or of another one,
+/// to physical ones through its `pagemap`. The kernel only reveals frame
+/// numbers to `CAP_SYS_ADMIN`, everyone else reads them as 0.
+pub struct Pagemap {
+    file: File,
+}
+
+impl Pagemap {
+    pub fn open() -> io::Result<Pagemap> {
+        Ok(Pagemap {
+            file: File::open("/proc/self/pagemap")?,
+        })
+    }
+
+    /// The pagemap of process `pid`, which takes being allowed to ptrace it.
+    pub fn of_process(pid: u32) -> io::Result<Pagemap> {
+        Ok(Pagemap {
+            file: File::open(format!("/proc/{}/pagemap", pid))?,
+        })
+    }
+
+    /// Fails if the page isn't mapped in yet, or frame numbers are hidden.
+    pub fn physical_address(&self, addr: *const u8) -> io::Result<u64> {
+        let addr = addr as usize;
+        let mut bytes = [0u8; 8];
+        let offset = (addr / PAGE_SIZE * bytes.len()) as u64;
+        if self.file.read_at(&mut bytes, offset)? != bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "short read from pagemap",
+            ));
+        }
+        let entry = bytes
+            .iter()
+            .rev()
+            .fold(0u64, |entry, &byte| entry << 8 | byte as u64);
+        if entry & PAGE_PRESENT == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "page is not present",
+            ));
+        }
+        let frame = entry & PFN_MASK;
+        if frame == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "physical addresses are hidden without CAP_SYS_ADMIN",
+            ));
+        }
+        Ok(frame * PAGE_SIZE as u64 + (addr % PAGE_SIZE) as u64)
+    }
+}
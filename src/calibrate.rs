@@ -0,0 +1,271 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use cache::{flush, probe_flush_with, probe_with};
+use channel::Channel;
+use cpu;
+use evict::EvictionSets;
+use timer::{self, Timer};
+
+/// Summary of a set of probe latencies, in the units of the timer that
+/// measured them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub min: u64,
+    pub p10: u64,
+    pub median: u64,
+    pub p90: u64,
+    pub max: u64,
+    pub mean: u64,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &mut [u64]) -> LatencyStats {
+        if samples.is_empty() {
+            return LatencyStats::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        LatencyStats {
+            min: samples[0],
+            p10: percentile(10),
+            median: percentile(50),
+            p90: percentile(90),
+            max: samples[samples.len() - 1],
+            mean: samples.iter().sum::<u64>() / samples.len() as u64,
+        }
+    }
+}
+
+/// Cache hit and miss latencies measured on this machine, and the cutoff
+/// used to tell them apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    pub hit: LatencyStats,
+    pub miss: LatencyStats,
+    /// Probes faster than this many cycles are treated as cache hits, or
+    /// slower ones when hits are the slower kind, as with Flush+Flush.
+    pub threshold: u64,
+}
+
+// closer to the hit latency than the miss latency, misses are spread much
+// wider than hits. where hits are the slower kind, flushing a cached line or
+// reloading a primed set that lost a line, the two are only a few cycles
+// apart, so those are split down the middle
+fn derive_threshold(hit: &LatencyStats, miss: &LatencyStats) -> u64 {
+    if hit.median <= miss.median {
+        (miss.median + hit.median * 2) / 3
+    } else {
+        (miss.median + hit.median + 1) / 2
+    }
+}
+
+impl Calibration {
+    // times `samples` probes of the line at line while cached and `samples`
+    // while flushed, probing the way channel does
+    pub(crate) unsafe fn measure(line: *const u8, samples: usize, channel: Channel) -> Calibration {
+        Calibration::measure_with(&timer::selected(), line, samples, channel)
+    }
+
+    // measure, timing the probes with timer
+    pub(crate) unsafe fn measure_with<T: Timer>(
+        timer: &T,
+        line: *const u8,
+        samples: usize,
+        channel: Channel,
+    ) -> Calibration {
+        let probe = |line| {
+            if channel.times_flush() {
+                probe_flush_with(timer, line)
+            } else {
+                probe_with(timer, line)
+            }
+        };
+        let mut hits = Vec::with_capacity(samples);
+        let mut misses = Vec::with_capacity(samples);
+        for _ in 0..samples {
+            line.read_volatile();
+            hits.push(probe(line));
+
+            flush(line);
+            misses.push(probe(line));
+        }
+        let hit = LatencyStats::from_samples(&mut hits);
+        let miss = LatencyStats::from_samples(&mut misses);
+        Calibration {
+            hit,
+            miss,
+            threshold: derive_threshold(&hit, &miss),
+        }
+    }
+
+    // measure, except that the hits are of the line loaded by a thread pinned
+    // to peer. on another physical core, those are served from the last
+    // level cache rather than L1 and take longer
+    pub(crate) unsafe fn measure_remote(
+        line: *const u8,
+        samples: usize,
+        peer: usize,
+    ) -> io::Result<Calibration> {
+        let timer = timer::selected();
+        // odd while the peer has a load to do, even once it has done it
+        let turn = Arc::new(AtomicUsize::new(0));
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let loader = {
+            let turn = turn.clone();
+            let line = line as usize;
+            thread::spawn(move || {
+                let pinned = cpu::pin_to_core(peer);
+                let ok = pinned.is_ok();
+                let _ = pinned_tx.send(pinned);
+                if !ok {
+                    return;
+                }
+                for round in 0..samples {
+                    while turn.load(Ordering::Acquire) != 2 * round + 1 {}
+                    (line as *const u8).read_volatile();
+                    turn.store(2 * round + 2, Ordering::Release);
+                }
+            })
+        };
+        let pinned = pinned_rx.recv().unwrap_or_else(|_| {
+            Err(io::Error::new(io::ErrorKind::Other, "calibration loader exited"))
+        });
+        if let Err(e) = pinned {
+            let _ = loader.join();
+            return Err(io::Error::new(
+                e.kind(),
+                format!("could not pin to cpu {}: {}", peer, e),
+            ));
+        }
+
+        let mut hits = Vec::with_capacity(samples);
+        let mut misses = Vec::with_capacity(samples);
+        for round in 0..samples {
+            flush(line);
+            turn.store(2 * round + 1, Ordering::Release);
+            while turn.load(Ordering::Acquire) != 2 * round + 2 {}
+            hits.push(probe_with(&timer, line));
+
+            flush(line);
+            misses.push(probe_with(&timer, line));
+        }
+        let _ = loader.join();
+
+        let hit = LatencyStats::from_samples(&mut hits);
+        let miss = LatencyStats::from_samples(&mut misses);
+        Ok(Calibration {
+            hit,
+            miss,
+            threshold: derive_threshold(&hit, &miss),
+        })
+    }
+
+    // times `samples` probes of the prime set of the line at line, the first
+    // of sets, after the line was loaded into it and `samples` after it wasn't
+    pub(crate) unsafe fn measure_primed(
+        sets: &EvictionSets,
+        line: *const u8,
+        samples: usize,
+    ) -> Calibration {
+        Calibration::measure_primed_with(&timer::selected(), sets, line, samples)
+    }
+
+    // measure_primed, timing the probes with timer
+    pub(crate) unsafe fn measure_primed_with<T: Timer>(
+        timer: &T,
+        sets: &EvictionSets,
+        line: *const u8,
+        samples: usize,
+    ) -> Calibration {
+        let mut hits = Vec::with_capacity(samples);
+        let mut misses = Vec::with_capacity(samples);
+        for _ in 0..samples {
+            sets.prime(0, 0);
+            line.read_volatile();
+            hits.push(sets.probe_with(timer, 0, 0));
+
+            sets.prime(0, 0);
+            misses.push(sets.probe_with(timer, 0, 0));
+        }
+        let hit = LatencyStats::from_samples(&mut hits);
+        let miss = LatencyStats::from_samples(&mut misses);
+        Calibration {
+            hit,
+            miss,
+            threshold: derive_threshold(&hit, &miss),
+        }
+    }
+
+    /// Whether a probe that took `time` was a cache hit.
+    pub fn is_hit(&self, time: u64) -> bool {
+        if self.hit.median <= self.miss.median {
+            time < self.threshold
+        } else {
+            time >= self.threshold
+        }
+    }
+
+    /// Writes the calibration as `key value` lines, for later runs to `load`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = File::create(path)?;
+        for &(prefix, stats) in &[("hit", &self.hit), ("miss", &self.miss)] {
+            writeln!(out, "{}_min {}", prefix, stats.min)?;
+            writeln!(out, "{}_p10 {}", prefix, stats.p10)?;
+            writeln!(out, "{}_median {}", prefix, stats.median)?;
+            writeln!(out, "{}_p90 {}", prefix, stats.p90)?;
+            writeln!(out, "{}_max {}", prefix, stats.max)?;
+            writeln!(out, "{}_mean {}", prefix, stats.mean)?;
+        }
+        writeln!(out, "threshold {}", self.threshold)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Calibration> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut hit = LatencyStats::default();
+        let mut miss = LatencyStats::default();
+        let mut threshold = None;
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let (key, value) = match (words.next(), words.next()) {
+                (Some(key), Some(value)) => (key, value),
+                (None, _) => continue,
+                _ => return Err(invalid(format!("malformed calibration line `{}`", line))),
+            };
+            let value: u64 = value
+                .parse()
+                .map_err(|_| invalid(format!("invalid value in calibration line `{}`", line)))?;
+            let (stats, field) = if key.starts_with("hit_") {
+                (&mut hit, &key[4..])
+            } else if key.starts_with("miss_") {
+                (&mut miss, &key[5..])
+            } else if key == "threshold" {
+                threshold = Some(value);
+                continue;
+            } else {
+                continue;
+            };
+            match field {
+                "min" => stats.min = value,
+                "p10" => stats.p10 = value,
+                "median" => stats.median = value,
+                "p90" => stats.p90 = value,
+                "max" => stats.max = value,
+                "mean" => stats.mean = value,
+                _ => {}
+            }
+        }
+        Ok(Calibration {
+            hit,
+            miss,
+            threshold: threshold.ok_or_else(|| invalid("calibration has no threshold".to_owned()))?,
+        })
+    }
+}
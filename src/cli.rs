@@ -0,0 +1,2348 @@
+use meltdown_rust::aes;
+use meltdown_rust::chase::Chase;
+use meltdown_rust::cpu;
+use meltdown_rust::diff;
+use meltdown_rust::entropy;
+use meltdown_rust::kaslr::{self, Locator, Region};
+use meltdown_rust::strings;
+use meltdown_rust::survey;
+use meltdown_rust::symbols::SymbolTarget;
+use meltdown_rust::sysreg::SystemRegister;
+use meltdown_rust::timer::TimerSource;
+use meltdown_rust::{Channel, Suppression};
+
+use hexdump::{CHUNK_SIZE, LINE_LEN};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &'static str = "\
+usage: meltdown <command> [options]
+
+commands:
+    dump     leak a range of memory and print it as a hexdump. the last column
+             gives the confidence in each byte in tenths, 9 meaning at least
+             90% of probe rounds agreed
+    dump-ram leak physical memory through the direct map into a file, page by
+             page, skipping pages that don't leak at all as holes
+    dump-file <file>
+             leak a file out of the page cache, whoever may read it, by finding
+             its pages through its inode. the file is read first to cache it,
+             if that's allowed, and pages that aren't cached are shown as ??.
+             needs the kernel's structure offsets, as ps does
+    gdbserver
+             serve gdb's remote protocol, answering its memory reads with
+             leaked reads, so gdb, IDA or radare2 can browse memory as if it
+             were a process being debugged. `target remote :1234` in gdb
+    repl     read commands like `read init_task 64` or `set probes 9` from stdin,
+             all leaking through one reader, calibrated once. `help` lists them
+    batch    leak every `address length [label]` line of a file or stdin with one
+             reader, calibrated once, printing a tab separated line per read of
+             its label, address, bytes in hex and confidence digits
+    daemon   keep one calibrated reader warm and serve reads with it to other
+             local tools over a unix socket, with the length prefixed protocol
+             the README describes
+    http     serve `GET /read?addr=0x..&len=..` on localhost, answering with the
+             leaked bytes and their confidences as JSON, for notebooks and scripts
+    strings  leak a range of memory and print only its runs of printable text,
+             each with its address, like `strings` does for files
+    search   find every address in a range that a byte pattern is at, leaking
+             only every nth byte, for a pattern of n bytes, and then the places
+             those say the pattern could start
+    entropy  leak a range of memory and list the regions of it that look
+             random, likely keys, by their entropy over a sliding window, the
+             most random first
+    aeskeys  leak a range of memory and print the AES-128 and AES-256 keys whose
+             key schedules are in it, like aeskeyfind
+    privkeys leak a range of memory and print the RSA and EC private keys in it,
+             stored as DER or PEM, as PEM
+    survey   map a range too big to dump by leaking a byte of it every stride,
+             listing where it is zero, holds data, or doesn't leak
+    watch    leak a small range of memory over and over, printing the lines of
+             it that change, with the time, until interrupted
+    diff <a> <b>
+             compare two dumps written with `dump --format json` byte by byte,
+             by address, telling the bytes that changed from those misread
+             by how confident their reads were. with `--passes` rather than
+             files, leak a range that many times and compare the passes
+    demo     leak a string from this process's own memory
+    calibrate
+             measure cache hit and miss latency and derive the probe threshold
+    check    test whether this machine appears exploitable. exits with 0 if it
+             does, 1 if it does not, 2 on a usage error and 3 if the check could
+             not be run
+    ps       list every process by walking the kernel's task list from
+             init_task with leaked reads, for the kernels whose structure
+             offsets are known
+    creds    demo: find the task of a process on the task list, as ps does, and
+             leak its credentials, its ids and capabilities, next to what
+             /proc/<pid>/status says. nothing is written
+    banner   leak the kernel's linux_proc_banner, fill it in the way the kernel
+             does for /proc/version, and compare the two. exits like check
+    covert <send|recv|bench>
+             pass data between two processes through the cache, by reloading
+             lines of a file both of them map. run `recv` first, then `send`
+             with the same options. `bench` sends a test pattern between two
+             cores of this process at a range of slot lengths, and reports the
+             bandwidth, error rate and capacity of each
+    spectre-v1
+             leak memory of this process through a mistrained bounds check
+             (Spectre variant 1) and print it as a hexdump, by default a
+             secret the victim's array never reaches
+    spectre-v2
+             leak memory of this process by mistraining an indirect call to
+             run a disclosure gadget (Spectre variant 2), with the same output
+             and options as spectre-v1
+    retbleed leak memory of this process through a return mispredicted into a
+             disclosure gadget, with the same output and options as
+             spectre-v1, and report the kernel's retbleed status
+    spectre-v4
+             leak a secret that a victim overwrites and loads back, through the
+             load running ahead of the store (Spectre variant 4), and report
+             whether speculative store bypass disable blocks it
+    l1tf     put a secret in a page that isn't present and try to read it back
+             from L1 (Foreshadow), and report whether the kernel's mitigation
+             stops it
+    lazy-fp  read a secret a thread on the same core leaves in its SSE registers,
+             right after a context switch (lazy FP state restore), and report
+             whether the kernel can switch FPU state lazily at all
+    mds      sample the line fill buffers with faulting loads (ZombieLoad, RIDL)
+             to leak a secret a thread on the sibling hyperthread keeps
+             loading, and print it as a hexdump
+    fallout  plant a pattern with stores and sample it back from the store
+             buffer with faulting loads to another page (Fallout), and report
+             what fraction of the samples match it
+    gds      sample a secret back from vector registers with faulting AVX2
+             gathers (Downfall), on the Intel models gather data sampling
+             affects, and report whether the microcode mitigation stops it
+    zenbleed leak upper halves of vector registers through a mispredicted
+             `vzeroupper` (Zenbleed), on AMD Zen 2, from a victim on the
+             sibling hyperthread, and report whether the chicken bit is set
+    sysreg   try to leak system registers through faulting `rdmsr` and control
+             register reads (Meltdown variant 3a), and report which of them leak
+    kaslr    find where the kernel image and the direct map of physical memory
+             are, by timing how soon TSX transactions abort on loads from each
+             place they could be (DrK), or how long prefetching them takes, and
+             print them as dump targets
+    physmap  find where the direct map of physical memory starts, by timing, and
+             confirm it by leaking a pattern planted in a page whose physical
+             address `/proc/self/pagemap` gives, which needs root
+    report   run a quick test of every attack above and print which of them
+             leaked, which were blocked and by what, as a table or JSON
+    help     print this message
+
+dump options:
+    --addr <address>    address to start reading from (hex with 0x prefix, or decimal)
+    --phys <address>    physical address to start reading from, through the direct map,
+                        instead of `--addr`
+    --symbol <name[+offset]>
+                        kernel symbol to start reading from, looked up in /proc/kallsyms,
+                        instead of `--addr`, e.g. linux_proc_banner or init_task+0x5f0
+    --chase <chase>     follow leaked pointers from an address or symbol, and read from
+                        where they lead, instead of `--addr`. `base -> +0x28 -> -0x10 : 64`
+                        leaks the pointer at base, adds 0x28, leaks the pointer there,
+                        subtracts 0x10, and reads 64 bytes from the result
+    --pid <pid>         read from the memory of process <pid> rather than this one, with
+                        `--addr`, `--region` or `--binary`. its pages are found through its
+                        pagemap and read through the direct map, which needs root
+    --region <name>     with `--pid`, read all of each of its mappings that `name` picks:
+                        heap, stack, anon for anonymous memory, or part of a file's path
+    --binary <file>     with `--pid`, look `--symbol` up in <file>, an executable or library
+                        the process has mapped, and read from where it is loaded there
+    --typed             with `--binary`, read the whole variable and print it as its type
+                        from the binary's DWARF debug info, rather than as a hexdump
+    --layout <file>     print what is read as the record <file> lays out, rather than as a
+                        hexdump, reading as much as it takes. see the README for the format
+    --system-map <file>
+                        look `--symbol` up in a System.map, relocated to where the kernel
+                        image is found, for when /proc/kallsyms hides addresses
+    --page-offset <address>
+                        where the direct map starts, for `--phys` and `--pid` [default:
+                        found as by `physmap`]
+    --allow-mmio        let `--phys` read outside the System RAM ranges in /proc/iomem,
+                        where devices are mapped and reads can hang the machine
+    --len <bytes>       number of bytes to read [default: 256]
+    --out <file>        write the dump to <file> instead of stdout
+    --format <hex|raw|json|xxd|base64|carray|rust|string>
+                        hex prints a hexdump. raw writes the leaked bytes as they are, for
+                        `strings`, binwalk or a disassembler, with 0 for bytes that didn't
+                        leak. json writes a line per byte with its address, value,
+                        confidence, hit and round counts, and probe time statistics. xxd
+                        prints what `xxd` prints for the raw dump, with offsets from the
+                        start of the dump, so `xxd -r` turns it back into one. base64
+                        prints the raw dump in base64, carray as a C `uint8_t data[]` and
+                        rust as a Rust `const DATA: &[u8]`. string decodes the dump as
+                        text, replacing invalid sequences and bytes that didn't leak with
+                        U+FFFD [default: hex]
+    --encoding <utf-8|utf-16le>
+                        what `--format string` decodes [default: utf-8]
+    --width <bytes>     bytes per hexdump line [default: 32]
+    --group <bytes>     bytes per group of hex digits [default: 8]
+    --relative-offsets  label hexdump lines with their offset from the start of the dump, or
+                        of each mapping with `--region`, rather than their address
+    --color <auto|always|never>
+                        color hexdumps: low confidence bytes red, printable ones green,
+                        zeros dim, and qwords that look like pointers underlined. auto
+                        colors them when writing to a terminal [default: auto]
+    --jobs <count>      split the range across <count> threads, each pinned to its own
+                        core with its own probe buffer. at most the number of cores the
+                        process can run on [default: 1]
+
+dump-ram options:
+    --out <file>        write the leaked memory to <file>, required
+    --start <address>   physical address to start reading from [default: 0]
+    --end <address>     physical address to stop reading at [default: the end of the last
+                        System RAM range in /proc/iomem]
+    --page-offset <address>
+                        where the direct map starts [default: found as by `physmap`]
+    --allow-mmio        read everything from `--start` to `--end` rather than only the
+                        System RAM ranges, with `--end` defaulting to the size of RAM
+    --format <raw|lime> raw writes physical address `--start` plus i to byte i of the
+                        file. lime writes each range read after a header giving its
+                        bounds, for Volatility [default: raw]
+    --resume <file>     save how far the dump got to <file> after every MiB, and if
+                        <file> exists, carry on from where the dump that saved it
+                        stopped, with its calibration. the other options have to be
+                        the same as that dump's. <file> is removed once the dump is done
+
+dump-file options:
+    --system-map <file> find super_blocks and vmemmap_base in a System.map, as for dump,
+                        rather than /proc/kallsyms
+    --page-offset <address>
+                        where the direct map starts [default: found as by `physmap`]
+    --out <file>        write the dump to <file> instead of stdout
+
+gdbserver options:
+    --port <port>       port to listen on [default: 1234]
+    --listen <address>  address to listen on. anyone who can connect can read kernel
+                        memory [default: 127.0.0.1]
+
+repl options:
+    --system-map <file> look symbols up in a System.map, as for dump, rather than
+                        /proc/kallsyms
+
+daemon options:
+    --socket <path>     where to create the socket, which only this user can connect to.
+                        a stale one left by a daemon that died is replaced [default:
+                        /tmp/meltdown.sock]
+
+http options:
+    --port <port>       port to listen on [default: 8080]
+    --listen <address>  address to listen on. anyone who can connect can read kernel
+                        memory [default: 127.0.0.1]
+
+batch options:
+    --input <file>      read the lines from <file> instead of stdin. addresses can be
+                        kernel symbols, and lines starting with # are skipped
+    --system-map <file> as for repl
+    --out <file>        write the results to <file> instead of stdout
+
+strings options:
+    --addr <address>    address to start reading from, required
+    --len <bytes>       number of bytes to read, required
+    --min <bytes>       the shortest run printed [default: 4]
+
+search options:
+    --addr <address>    address to start searching from, required
+    --len <bytes>       number of bytes to search, required
+    --pattern <text>    search for the bytes of <text>
+    --hex <bytes>       search for bytes given in hex, like DEADBEEF, instead
+
+entropy options:
+    --addr <address>    address to start scanning from, required
+    --len <bytes>       number of bytes to scan, required
+    --window <bytes>    bytes entropy is measured over [default: 64]
+    --step <bytes>      how far the window moves at a time, at most `--window`
+                        [default: 16]
+    --threshold <bits>  bits of entropy per byte a window needs to be flagged, out of
+                        at most 8, and at most log2 of `--window` [default: 5.0]
+    --top <count>       list only the <count> most random regions [default: 20]
+
+aeskeys options:
+    --addr <address>    address to start scanning from, required
+    --len <bytes>       number of bytes to scan, required
+    --max-errors <bits> bits of a key schedule that can be misread and the key still be
+                        printed. a byte that didn't leak counts as 8 [default: 10]
+
+privkeys options:
+    --addr <address>    address to start scanning from, required
+    --len <bytes>       number of bytes to scan, required
+
+survey options:
+    --addr <address>    address to start surveying from, required
+    --len <bytes>       number of bytes to survey, required
+    --sample-stride <bytes>
+                        distance between samples [default: 4096]
+    --word              sample 8 bytes at a time rather than 1, which tells data
+                        from zeros more surely at 8 times the leaks
+
+watch options:
+    --addr <address>    address to start watching from, required
+    --len <bytes>       number of bytes to watch [default: 16]
+    --interval <time>   how long to wait between reads, as e.g. 100ms, 2s or 1m.
+                        a bare number is milliseconds [default: 100ms]
+
+diff options:
+    --addr <address>    address to start reading from, required with `--passes`
+    --len <bytes>       number of bytes to read, required with `--passes`
+    --passes <count>    leak the range <count> times and compare the passes, rather
+                        than comparing dumps [default: 2]
+    --threshold <fraction>
+                        reads less confident than this are put down to noise when they
+                        differ [default: 0.5]
+
+banner options:
+    --system-map <file> find linux_proc_banner in a System.map, as for dump, rather than
+                        /proc/kallsyms
+
+ps options:
+    --system-map <file> find init_task in a System.map, as for dump, rather than
+                        /proc/kallsyms
+    --max <count>       stop after <count> tasks, in case a misread pointer loops
+                        [default: 4096]
+
+creds options:
+    --pid <pid>         the process whose credentials are leaked, required
+    --system-map <file> as for ps
+    --max <count>       as for ps
+
+check options:
+    --phys              also leak a pattern from a page of this process through the direct
+                        map, at the physical address /proc/self/pagemap gives. needs root
+    --page-offset <address>
+                        where the direct map starts, for `--phys` [default: found as by
+                        `physmap`]
+
+calibrate options:
+    --samples <count>   number of hits and misses to time [default: 100000]
+    --save <file>       save the calibration to <file> for later runs
+
+covert options:
+    --file <path>       the file whose lines carry the data, readable by both ends and
+                        at least 64 KiB long [default: this executable]
+    --slot <cycles>     timestamp counter cycles spent on each byte. `bench` starts from
+                        this and halves it four times [default: 200000]
+    --message <text>    the text to send [default: read from stdin]
+    --test <bytes>      send, or expect, <bytes> of a fixed pattern and report the error
+                        rate [default for bench: 1024]
+    --timeout <secs>    how long `recv` waits for a transmission to start [default: 30]
+    --cpu <core>        pin to <core> before calibrating. `bench` receives on <core>
+                        [default for bench: 0]
+    --peer <core>       the core the other end is pinned to. `recv` calibrates for hits
+                        on lines loaded there, which come from the shared last level
+                        cache when it is another physical core. `bench` sends from it
+                        [default for bench: the first core not sharing <cpu>'s L1]
+
+spectre-v1, spectre-v2 and retbleed options:
+    --addr <address>    address to start reading from, which has to be mapped in this
+                        process [default: the built in secret]
+    --len <bytes>       number of bytes to read [default: the secret's length]
+    --cpu <core>        pin to <core> before calibrating
+
+spectre-v4 options:
+    --message <text>    the secret the victim overwrites [default: a built in secret]
+    --ssbd              turn on speculative store bypass disable for this process first
+    --cpu <core>        pin to <core> before calibrating
+
+l1tf options:
+    --message <text>    the secret, at most a page of it [default: a built in secret]
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin to <core> before calibrating
+
+lazy-fp options:
+    --message <text>    the victim's secret, at most 16 bytes [default: a built in secret]
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin both threads to <core> [default: the current core]
+
+mds options:
+    --message <text>    the victim's secret, at most 63 bytes [default: a built in secret]
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin the sampling thread to <core> [default: the current core]
+    --victim <core>     pin the victim to <core>, which has to share a physical core with
+                        the sampling thread [default: a hyperthread sibling of <cpu>]
+
+fallout options:
+    --message <text>    the pattern to plant, at most a page of it [default: a built in
+                        secret]
+    --kernel            have the kernel make the stores, by reading the pattern from a
+                        pipe, instead of storing it directly
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin to <core> before calibrating
+
+gds options:
+    --message <text>    the secret, at most 32 bytes [default: a built in secret]
+    --force             run even if this CPU model isn't known to be affected
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin to <core> before calibrating
+
+zenbleed options:
+    --attempts <count>  number of attempts at a leak [default: 100000]
+    --force             run even if this CPU isn't Zen 2
+    --cpu <core>        pin the sampling thread to <core> [default: the current core]
+    --victim <core>     pin the victim to <core> [default: a hyperthread sibling of <cpu>,
+                        if there is one]
+
+sysreg options:
+    --register <name>   only try <name>, e.g. IA32_LSTAR or CR3 [default: every register]
+    --suppress <auto|tsx|signal|fork>
+                        how the fault is suppressed, as for the reader [default: auto]
+    --cpu <core>        pin to <core>, whose registers are read [default: the current core]
+
+kaslr options:
+    --region <text|direct-map>
+                        only scan for the kernel image or the direct map [default: both]
+    --tries <count>     loads timed per slot, keeping the fastest [default: 8]
+    --locator <tsx|prefetch|both>
+                        time TSX aborts, prefetches, or both and check that they agree
+                        [default: both if the CPU has TSX, prefetch otherwise]
+    --cpu <core>        pin to <core> before timing
+
+physmap options:
+    --locator <tsx|prefetch>
+                        how candidates are found, as for kaslr [default: tsx if the CPU
+                        has it, prefetch otherwise]
+    --tries <count>     loads timed per slot, keeping the fastest [default: 8]
+
+report options:
+    --json              print the report as a JSON document instead of a table
+    --out <file>        write the report to <file> instead of stdout
+    --cpu <core>        pin to <core>, whose hyperthread sibling runs the victims that
+                        need one [default: the current core]
+
+reader options (dump, dump-ram, dump-file, gdbserver, repl, batch, daemon, http, strings,
+                search, entropy, aeskeys, privkeys, survey, watch, diff, demo, check,
+                banner, ps, creds, calibrate, physmap):
+    --suppress <auto|tsx|signal|fork>
+                        how the fault from the transient read is suppressed. auto uses
+                        tsx if the CPU supports RTM, and signal otherwise [default: auto]
+    --channel <page|line|nibble|flush-flush|prime-probe|prime-probe-llc|evict-reload>
+                        how leaked values are encoded in the probe buffer. page uses a
+                        page per value, line a cache line per value, which needs a much
+                        smaller buffer, and nibble leaks each half of a byte separately
+                        through 16 pages. flush-flush is laid out like page, but times
+                        flushing each line instead of reloading it, which causes no
+                        cache misses. prime-probe leaks nibbles through L1 sets and
+                        prime-probe-llc bytes through LLC sets, without clflush or
+                        probing the buffer itself. evict-reload is page cleared with
+                        eviction sets, like `--evict` [default: page]
+    --dual-probe        leak two bytes per transaction into two probe buffers
+    --hugepages         back the probe buffer with 2 MiB pages
+    --cpu <core>        pin the leaking thread to <core> before calibrating, so the
+                        threshold stays valid for the whole run. with `--jobs`, workers
+                        are pinned to consecutive cores starting at <core>
+    --pipeline          flush and probe on helper threads while the main thread leaks
+    --evict             evict the probe buffer with eviction sets instead of clflush.
+                        finding the sets takes a while at startup
+    --timer <tsc|thread|pmc-llc|pmc-l1>
+                        how probe latency is measured. thread counts on a spinning helper
+                        thread, for VMs where rdtsc traps or is fuzzed. pmc-llc and pmc-l1
+                        count cache misses with perf_event hardware counters instead of
+                        timing [default: tsc]
+    --warmup <ms>       spin for <ms> milliseconds before calibrating, to bring the core
+                        up to full frequency [default: 100]
+    --calibration <file>
+                        use a threshold saved by `calibrate --save` instead of measuring
+                        one at startup
+    --min-confidence <fraction>
+                        probe bytes again while fewer than this fraction of probe rounds
+                        agree on their value [default: 0]
+    --retries <count>   how many times a byte is probed again to reach
+                        `--min-confidence` [default: 8]
+    --force             leak even on CPUs that aren't Intel, which don't forward data
+                        from faulting loads, so the dump is noise";
+
+#[derive(Clone)]
+pub struct ReaderOptions {
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    // None keeps the reader's default channel
+    pub channel: Option<Channel>,
+    pub dual_probe: bool,
+    pub hugepages: bool,
+    // None leaves the thread free to migrate between cores
+    pub cpu: Option<usize>,
+    pub timer: TimerSource,
+    pub warmup: Duration,
+    pub pipeline: bool,
+    pub evict: bool,
+    // None measures a fresh calibration when the reader is created
+    pub calibration: Option<PathBuf>,
+    pub min_confidence: f64,
+    // None keeps the reader's default retry budget
+    pub retries: Option<usize>,
+    // leak on vendors whose CPUs aren't affected
+    pub force: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            suppression: None,
+            channel: None,
+            dual_probe: false,
+            hugepages: false,
+            cpu: None,
+            timer: TimerSource::Tsc,
+            warmup: Duration::from_millis(100),
+            pipeline: false,
+            evict: false,
+            calibration: None,
+            min_confidence: 0.0,
+            retries: None,
+            force: false,
+        }
+    }
+}
+
+pub struct DumpOptions {
+    pub addr: usize,
+    // looked up and used instead of addr when set
+    pub symbol: Option<SymbolTarget>,
+    // followed from its base, and used instead of addr when set
+    pub chase: Option<Chase>,
+    // addr, or region, is in this process's memory
+    pub pid: Option<u32>,
+    // the mappings of pid to read, instead of addr
+    pub region: Option<String>,
+    // symbol is looked up in this binary of pid, not in the kernel
+    pub binary: Option<PathBuf>,
+    // print symbol as its type in binary's debug info
+    pub typed: bool,
+    // print what is read as the record this lays out
+    pub layout: Option<PathBuf>,
+    // None looks symbols up in kallsyms
+    pub system_map: Option<PathBuf>,
+    // addr is a physical address, read through the direct map
+    pub phys: bool,
+    // None finds the direct map when addr is physical
+    pub page_offset: Option<usize>,
+    // read physical addresses outside System RAM
+    pub allow_mmio: bool,
+    pub len: usize,
+    pub out: Option<PathBuf>,
+    pub format: DumpFormat,
+    pub width: usize,
+    pub group: usize,
+    pub relative_offsets: bool,
+    // None colors hexdumps written to a terminal
+    pub color: Option<bool>,
+    pub jobs: usize,
+    pub reader: ReaderOptions,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    Hex,
+    Raw,
+    Json,
+    Xxd,
+    Base64,
+    CArray,
+    Rust,
+    String(TextEncoding),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RamFormat {
+    Raw,
+    Lime,
+}
+
+pub struct DumpRamOptions {
+    pub out: PathBuf,
+    pub format: RamFormat,
+    pub start: usize,
+    // None stops at the end of RAM
+    pub end: Option<usize>,
+    // None finds the direct map
+    pub page_offset: Option<usize>,
+    // read all of start to end, not only System RAM
+    pub allow_mmio: bool,
+    // None doesn't save the dump's progress
+    pub resume: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+pub struct DumpFileOptions {
+    pub path: PathBuf,
+    // None looks symbols up in kallsyms
+    pub system_map: Option<PathBuf>,
+    // None finds the direct map
+    pub page_offset: Option<usize>,
+    // None writes to stdout
+    pub out: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+pub struct GdbServerOptions {
+    pub listen: String,
+    pub port: u16,
+    pub reader: ReaderOptions,
+}
+
+pub struct ReplOptions {
+    // None looks symbols up in kallsyms
+    pub system_map: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+pub struct BatchOptions {
+    // None reads stdin
+    pub input: Option<PathBuf>,
+    // None looks symbols up in kallsyms
+    pub system_map: Option<PathBuf>,
+    // None writes to stdout
+    pub out: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+pub struct DaemonOptions {
+    pub socket: PathBuf,
+    pub reader: ReaderOptions,
+}
+
+pub struct HttpOptions {
+    pub listen: String,
+    pub port: u16,
+    pub reader: ReaderOptions,
+}
+
+pub struct StringsOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub min_len: usize,
+    pub reader: ReaderOptions,
+}
+
+pub struct SearchOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub pattern: Vec<u8>,
+    pub reader: ReaderOptions,
+}
+
+pub struct EntropyOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub window: usize,
+    pub step: usize,
+    pub threshold: f64,
+    pub top: usize,
+    pub reader: ReaderOptions,
+}
+
+pub struct AesKeysOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub max_errors: u32,
+    pub reader: ReaderOptions,
+}
+
+pub struct PrivKeysOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub reader: ReaderOptions,
+}
+
+pub struct SurveyOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub stride: usize,
+    // sample 8 bytes rather than 1
+    pub word: bool,
+    pub reader: ReaderOptions,
+}
+
+pub struct WatchOptions {
+    pub addr: usize,
+    pub len: usize,
+    pub interval: Duration,
+    pub reader: ReaderOptions,
+}
+
+pub enum DiffSource {
+    // dumps written with `dump --format json`
+    Dumps(PathBuf, PathBuf),
+    Passes {
+        addr: usize,
+        len: usize,
+        passes: usize,
+    },
+}
+
+pub struct DiffOptions {
+    pub source: DiffSource,
+    pub threshold: f64,
+    pub reader: ReaderOptions,
+}
+
+pub struct BannerOptions {
+    // None looks linux_proc_banner up in kallsyms
+    pub system_map: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+pub struct PsOptions {
+    // None looks init_task up in kallsyms
+    pub system_map: Option<PathBuf>,
+    pub max: usize,
+    pub reader: ReaderOptions,
+}
+
+pub struct CredsOptions {
+    pub pid: u32,
+    // the walk to find it
+    pub ps: PsOptions,
+}
+
+pub struct CheckOptions {
+    // test reads through the direct map too
+    pub phys: bool,
+    // None finds the direct map
+    pub page_offset: Option<usize>,
+    pub reader: ReaderOptions,
+}
+
+pub struct CalibrateOptions {
+    pub samples: usize,
+    pub save: Option<PathBuf>,
+    pub reader: ReaderOptions,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CovertMode {
+    Send,
+    Recv,
+    // both ends in one process, on two cores
+    Bench,
+}
+
+pub struct CovertOptions {
+    pub mode: CovertMode,
+    // None uses the running executable, which both ends can always map
+    pub file: Option<PathBuf>,
+    pub slot_cycles: u64,
+    // None sends stdin
+    pub message: Option<String>,
+    // the length of the test pattern, None to send or receive real data
+    pub test: Option<usize>,
+    pub timeout: Duration,
+    pub cpu: Option<usize>,
+    // the core the other end runs on, if known
+    pub peer: Option<usize>,
+}
+
+pub struct SpectreOptions {
+    // None leaks the built in secret
+    pub addr: Option<usize>,
+    pub len: Option<usize>,
+    pub cpu: Option<usize>,
+}
+
+pub struct StoreBypassOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    pub ssbd: bool,
+    pub cpu: Option<usize>,
+}
+
+pub struct L1tfOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+}
+
+pub struct LazyFpOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+}
+
+pub struct MdsOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+    // None picks a hyperthread sibling of cpu
+    pub victim: Option<usize>,
+}
+
+pub struct FalloutOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    // have the kernel make the stores instead of this process
+    pub kernel: bool,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+}
+
+pub struct GdsOptions {
+    // None uses the built in secret
+    pub message: Option<String>,
+    // run on models not known to be affected
+    pub force: bool,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+}
+
+pub struct ZenbleedOptions {
+    pub attempts: usize,
+    // run on CPUs that aren't Zen 2
+    pub force: bool,
+    pub cpu: Option<usize>,
+    // None picks a hyperthread sibling of cpu
+    pub victim: Option<usize>,
+}
+
+pub struct SysregOptions {
+    // None tries every known register
+    pub register: Option<(String, SystemRegister)>,
+    // None picks a strategy based on the detected CPU features
+    pub suppression: Option<Suppression>,
+    pub cpu: Option<usize>,
+}
+
+pub struct KaslrOptions {
+    pub regions: Vec<Region>,
+    pub locators: Vec<Locator>,
+    pub tries: usize,
+    pub cpu: Option<usize>,
+}
+
+pub struct PhysmapOptions {
+    pub locator: Locator,
+    pub tries: usize,
+    pub reader: ReaderOptions,
+}
+
+pub struct ReportOptions {
+    pub json: bool,
+    // None writes to stdout
+    pub out: Option<PathBuf>,
+    pub cpu: Option<usize>,
+}
+
+pub enum Command {
+    Dump(DumpOptions),
+    DumpRam(DumpRamOptions),
+    DumpFile(DumpFileOptions),
+    GdbServer(GdbServerOptions),
+    Repl(ReplOptions),
+    Batch(BatchOptions),
+    Daemon(DaemonOptions),
+    Http(HttpOptions),
+    Strings(StringsOptions),
+    Search(SearchOptions),
+    Entropy(EntropyOptions),
+    AesKeys(AesKeysOptions),
+    PrivKeys(PrivKeysOptions),
+    Survey(SurveyOptions),
+    Watch(WatchOptions),
+    Diff(DiffOptions),
+    Demo(ReaderOptions),
+    Check(CheckOptions),
+    Banner(BannerOptions),
+    Ps(PsOptions),
+    Creds(CredsOptions),
+    Calibrate(CalibrateOptions),
+    Covert(CovertOptions),
+    SpectreV1(SpectreOptions),
+    SpectreV2(SpectreOptions),
+    SpectreV4(StoreBypassOptions),
+    Retbleed(SpectreOptions),
+    L1tf(L1tfOptions),
+    LazyFp(LazyFpOptions),
+    Mds(MdsOptions),
+    Fallout(FalloutOptions),
+    Gds(GdsOptions),
+    Zenbleed(ZenbleedOptions),
+    Sysreg(SysregOptions),
+    Kaslr(KaslrOptions),
+    Physmap(PhysmapOptions),
+    Report(ReportOptions),
+    Help,
+}
+
+// parses a number as hex if it has a 0x prefix, otherwise as decimal
+pub fn parse_number(s: &str) -> Result<usize, String> {
+    let digits = s.replace('_', "");
+    let result = if digits.starts_with("0x") || digits.starts_with("0X") {
+        usize::from_str_radix(&digits[2..], 16)
+    } else {
+        digits.parse()
+    };
+    result.map_err(|_| format!("invalid number `{}`", s))
+}
+
+// parses a core number, which has to fit in a CPU set
+fn parse_core(s: &str) -> Result<usize, String> {
+    match parse_number(s)? {
+        core if core < cpu::core_limit() => Ok(core),
+        _ => Err(format!("invalid core `{}`, cores only go up to {}", s, cpu::core_limit() - 1)),
+    }
+}
+
+// parses a process id, rejecting rather than truncating one too big for it
+fn parse_pid(s: &str) -> Result<u32, String> {
+    match parse_number(s)? {
+        pid if pid <= u32::max_value() as usize => Ok(pid as u32),
+        _ => Err(format!("invalid pid `{}`", s)),
+    }
+}
+
+// parses a number between 0 and 1 inclusive
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value <= 1.0 => Ok(value),
+        _ => Err(format!("invalid fraction `{}`, expected a number between 0 and 1", s)),
+    }
+}
+
+// a duration with a unit, ms, s or m, or a bare number of milliseconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let digits = s.find(|c: char| !c.is_digit(10)).unwrap_or_else(|| s.len());
+    let value = s[..digits].parse::<u64>();
+    let ms = match (value, &s[digits..]) {
+        (Ok(value), "") | (Ok(value), "ms") => Some(value),
+        (Ok(value), "s") => value.checked_mul(1000),
+        (Ok(value), "m") => value.checked_mul(60 * 1000),
+        _ => None,
+    };
+    ms.map(Duration::from_millis)
+        .ok_or_else(|| format!("invalid duration `{}`, expected e.g. 100ms, 2s or 1m", s))
+}
+
+// None for auto, which picks a strategy based on the detected CPU features
+fn parse_suppression(name: &str) -> Result<Option<Suppression>, String> {
+    if name == "auto" {
+        Ok(None)
+    } else {
+        Suppression::from_name(name)
+            .map(Some)
+            .ok_or_else(|| format!("unknown suppression strategy `{}`", name))
+    }
+}
+
+// iterates over `--flag value` and `--flag=value` style arguments
+struct Flags<I> {
+    args: I,
+}
+
+impl<I: Iterator<Item = String>> Flags<I> {
+    fn next_flag(&mut self) -> Result<Option<(String, Option<String>)>, String> {
+        let arg = match self.args.next() {
+            Some(arg) => arg,
+            None => return Ok(None),
+        };
+        if !arg.starts_with("--") {
+            return Err(format!("unexpected argument `{}`", arg));
+        }
+        let result = match arg.find('=') {
+            Some(idx) => (arg[..idx].to_owned(), Some(arg[idx + 1..].to_owned())),
+            None => (arg, None),
+        };
+        Ok(Some(result))
+    }
+
+    fn value(&mut self, flag: &str, inline: Option<String>) -> Result<String, String> {
+        match inline {
+            Some(value) => Ok(value),
+            None => self.args
+                .next()
+                .ok_or_else(|| format!("`{}` requires a value", flag)),
+        }
+    }
+}
+
+// handles the options shared by every command that leaks memory.
+// returns false if flag is not a reader option
+fn parse_reader_flag<I: Iterator<Item = String>>(
+    opts: &mut ReaderOptions,
+    flags: &mut Flags<I>,
+    flag: &str,
+    inline: Option<String>,
+) -> Result<bool, String> {
+    match flag {
+        "--suppress" => opts.suppression = parse_suppression(&flags.value(flag, inline)?)?,
+        "--channel" => {
+            let name = flags.value(flag, inline)?;
+            opts.channel = Some(Channel::from_name(&name)
+                .ok_or_else(|| format!("unknown channel `{}`", name))?)
+        }
+        "--dual-probe" => {
+            if inline.is_some() {
+                return Err(format!("`{}` does not take a value", flag));
+            }
+            opts.dual_probe = true
+        }
+        "--hugepages" => {
+            if inline.is_some() {
+                return Err(format!("`{}` does not take a value", flag));
+            }
+            opts.hugepages = true
+        }
+        "--pipeline" => {
+            if inline.is_some() {
+                return Err(format!("`{}` does not take a value", flag));
+            }
+            opts.pipeline = true
+        }
+        "--evict" => {
+            if inline.is_some() {
+                return Err(format!("`{}` does not take a value", flag));
+            }
+            opts.evict = true
+        }
+        "--timer" => {
+            let name = flags.value(flag, inline)?;
+            opts.timer = TimerSource::from_name(&name)
+                .ok_or_else(|| format!("unknown timer `{}`", name))?
+        }
+        "--warmup" => {
+            let ms = parse_number(&flags.value(flag, inline)?)?;
+            opts.warmup = Duration::from_millis(ms as u64)
+        }
+        "--cpu" => opts.cpu = Some(parse_core(&flags.value(flag, inline)?)?),
+        "--calibration" => opts.calibration = Some(PathBuf::from(flags.value(flag, inline)?)),
+        "--min-confidence" => opts.min_confidence = parse_fraction(&flags.value(flag, inline)?)?,
+        "--retries" => opts.retries = Some(parse_number(&flags.value(flag, inline)?)?),
+        "--force" => {
+            if inline.is_some() {
+                return Err(format!("`{}` does not take a value", flag));
+            }
+            opts.force = true
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// for commands that only take reader options
+fn parse_reader_options<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<ReaderOptions, String> {
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+            return Err(format!("unknown option `{}`", flag));
+        }
+    }
+    Ok(reader)
+}
+
+fn parse_dump<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<DumpOptions, String> {
+    let mut addr = None;
+    let mut phys = None;
+    let mut symbol = None;
+    let mut chase = None;
+    let mut pid = None;
+    let mut region = None;
+    let mut binary = None;
+    let mut typed = false;
+    let mut layout = None;
+    let mut system_map = None;
+    let mut page_offset = None;
+    let mut allow_mmio = false;
+    let mut len = 256;
+    let mut out = None;
+    let mut format = DumpFormat::Hex;
+    let mut encoding = None;
+    let mut width = None;
+    let mut group = None;
+    let mut relative_offsets = false;
+    let mut color = None;
+    let mut jobs = 1;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--phys" => phys = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--symbol" => {
+                let target = flags.value(&flag, inline)?;
+                symbol = Some(SymbolTarget::parse(&target)
+                    .ok_or_else(|| format!("invalid symbol `{}`", target))?)
+            }
+            "--chase" => chase = Some(Chase::parse(&flags.value(&flag, inline)?)?),
+            "--pid" => pid = Some(parse_pid(&flags.value(&flag, inline)?)?),
+            "--region" => region = Some(flags.value(&flag, inline)?),
+            "--binary" => binary = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--typed" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                typed = true
+            }
+            "--layout" => layout = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--system-map" => system_map = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--page-offset" => page_offset = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--allow-mmio" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                allow_mmio = true
+            }
+            "--len" => len = parse_number(&flags.value(&flag, inline)?)?,
+            "--out" => out = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--format" => {
+                let name = flags.value(&flag, inline)?;
+                format = match &name[..] {
+                    "hex" => DumpFormat::Hex,
+                    "raw" => DumpFormat::Raw,
+                    "json" => DumpFormat::Json,
+                    "xxd" => DumpFormat::Xxd,
+                    "base64" => DumpFormat::Base64,
+                    "carray" => DumpFormat::CArray,
+                    "rust" => DumpFormat::Rust,
+                    "string" => DumpFormat::String(TextEncoding::Utf8),
+                    _ => return Err(format!("unknown format `{}`", name)),
+                }
+            }
+            "--encoding" => {
+                let name = flags.value(&flag, inline)?;
+                encoding = match &name[..] {
+                    "utf-8" => Some(TextEncoding::Utf8),
+                    "utf-16le" => Some(TextEncoding::Utf16Le),
+                    _ => return Err(format!("unknown encoding `{}`", name)),
+                }
+            }
+            "--width" => width = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--group" => group = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--relative-offsets" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                relative_offsets = true
+            }
+            "--color" => {
+                let when = flags.value(&flag, inline)?;
+                color = match &when[..] {
+                    "auto" => None,
+                    "always" => Some(true),
+                    "never" => Some(false),
+                    _ => return Err(format!("unknown `--color` setting `{}`", when)),
+                }
+            }
+            "--jobs" => jobs = parse_number(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    if region.is_some() && pid.is_none() {
+        return Err("`--region` requires `--pid`".to_owned());
+    }
+    if binary.is_some() && (pid.is_none() || symbol.is_none()) {
+        return Err("`--binary` requires `--pid` and `--symbol`".to_owned());
+    }
+    let kernel_symbol = symbol.is_some() && binary.is_none();
+    if pid.is_some() && (phys.is_some() || chase.is_some() || kernel_symbol) {
+        return Err("`--pid` can only be used with `--addr`, `--region` or `--binary`".to_owned());
+    }
+    if typed && binary.is_none() {
+        return Err("`--typed` requires `--binary`".to_owned());
+    }
+    if layout.is_some() && (typed || region.is_some()) {
+        return Err("`--layout` can't be used with `--typed` or `--region`".to_owned());
+    }
+    if binary.is_some() && system_map.is_some() {
+        return Err("`--system-map` can't be used with `--binary`".to_owned());
+    }
+    if format != DumpFormat::Hex && (typed || layout.is_some()) {
+        return Err("`--format` can't be used with `--typed` or `--layout`".to_owned());
+    }
+    if let Some(encoding) = encoding {
+        match format {
+            DumpFormat::String(_) => format = DumpFormat::String(encoding),
+            _ => return Err("`--encoding` requires `--format string`".to_owned()),
+        }
+    }
+    if format != DumpFormat::Hex
+        && (color == Some(true) || width.is_some() || group.is_some() || relative_offsets)
+    {
+        return Err(
+            "`--color always`, `--width`, `--group` and `--relative-offsets` only apply to \
+             `--format hex`"
+                .to_owned(),
+        );
+    }
+    let width = width.unwrap_or(LINE_LEN);
+    let group = group.unwrap_or(CHUNK_SIZE);
+    if width == 0 || group == 0 {
+        return Err("`--width` and `--group` must be at least 1".to_owned());
+    }
+    if format == DumpFormat::Json && (pid.is_some() || jobs > 1) {
+        return Err("`--format json` can't be used with `--pid` or `--jobs`".to_owned());
+    }
+    let targets = [
+        addr.is_some(),
+        phys.is_some(),
+        symbol.is_some(),
+        chase.is_some(),
+        region.is_some(),
+    ];
+    match targets.iter().filter(|&&target| target).count() {
+        0 => return Err("`dump` requires `--addr`, `--phys`, `--symbol` or `--chase`".to_owned()),
+        1 => {}
+        _ => {
+            return Err(
+                "only one of `--addr`, `--phys`, `--symbol`, `--chase` and `--region` can be used"
+                    .to_owned(),
+            )
+        }
+    }
+    let (addr, phys) = match phys {
+        Some(phys) => (phys, true),
+        None => (addr.unwrap_or(0), false),
+    };
+    if system_map.is_some() && symbol.is_none() && chase.is_none() {
+        return Err("`--system-map` requires `--symbol` or `--chase`".to_owned());
+    }
+    if let Some(chase_len) = chase.as_ref().and_then(|chase| chase.len) {
+        len = chase_len
+    }
+    if page_offset.is_some() && !phys && pid.is_none() {
+        return Err("`--page-offset` requires `--phys` or `--pid`".to_owned());
+    }
+    if allow_mmio && !phys {
+        return Err("`--allow-mmio` requires `--phys`".to_owned());
+    }
+    if addr.checked_add(len).is_none() {
+        return Err("`--addr` + `--len` overflows the address space".to_owned());
+    }
+    if jobs == 0 {
+        return Err("`--jobs` must be at least 1".to_owned());
+    }
+    if jobs > 1 && (pid.is_some() || layout.is_some()) {
+        return Err("`--jobs` can't be used with `--pid` or `--layout`".to_owned());
+    }
+    if jobs > 1 {
+        let cores = cpu::allowed_cores()
+            .map_err(|e| format!("could not read the cores this process can run on: {}", e))?;
+        if jobs > cores.len() {
+            return Err(format!(
+                "`--jobs` can't be more than the {} cores this process can run on",
+                cores.len()
+            ));
+        }
+    }
+    Ok(DumpOptions {
+        addr,
+        symbol,
+        chase,
+        pid,
+        region,
+        binary,
+        typed,
+        layout,
+        system_map,
+        phys,
+        page_offset,
+        allow_mmio,
+        len,
+        out,
+        format,
+        width,
+        group,
+        relative_offsets,
+        color,
+        jobs,
+        reader,
+    })
+}
+
+fn parse_dump_ram<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<DumpRamOptions, String> {
+    let mut out = None;
+    let mut format = RamFormat::Raw;
+    let mut start = 0;
+    let mut end = None;
+    let mut page_offset = None;
+    let mut allow_mmio = false;
+    let mut resume = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--out" => out = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--format" => {
+                let name = flags.value(&flag, inline)?;
+                format = match &name[..] {
+                    "raw" => RamFormat::Raw,
+                    "lime" => RamFormat::Lime,
+                    _ => return Err(format!("unknown format `{}`", name)),
+                }
+            }
+            "--start" => start = parse_number(&flags.value(&flag, inline)?)?,
+            "--end" => end = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--page-offset" => page_offset = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--allow-mmio" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                allow_mmio = true
+            }
+            "--resume" => resume = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let out = out.ok_or("`dump-ram` requires `--out`")?;
+    if let Some(end) = end {
+        if end <= start {
+            return Err("`--end` must be past `--start`".to_owned());
+        }
+    }
+    Ok(DumpRamOptions {
+        out,
+        format,
+        start,
+        end,
+        page_offset,
+        allow_mmio,
+        resume,
+        reader,
+    })
+}
+
+fn parse_dump_file<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<DumpFileOptions, String> {
+    let path = match flags.args.next() {
+        Some(ref path) if path.starts_with("--") => {
+            return Err("`dump-file` requires a file before its options".to_owned())
+        }
+        Some(path) => PathBuf::from(path),
+        None => return Err("`dump-file` requires a file".to_owned()),
+    };
+    let mut opts = DumpFileOptions {
+        path,
+        system_map: None,
+        page_offset: None,
+        out: None,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--system-map" => opts.system_map = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--page-offset" => {
+                opts.page_offset = Some(parse_number(&flags.value(&flag, inline)?)?)
+            }
+            "--out" => opts.out = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_gdbserver<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<GdbServerOptions, String> {
+    let mut opts = GdbServerOptions {
+        listen: "127.0.0.1".to_owned(),
+        port: 1234,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--port" => {
+                let port = parse_number(&flags.value(&flag, inline)?)?;
+                if port > 0xffff {
+                    return Err(format!("invalid port `{}`", port));
+                }
+                opts.port = port as u16
+            }
+            "--listen" => opts.listen = flags.value(&flag, inline)?,
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_repl<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<ReplOptions, String> {
+    let mut system_map = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--system-map" => system_map = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(ReplOptions { system_map, reader })
+}
+
+fn parse_batch<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<BatchOptions, String> {
+    let mut opts = BatchOptions {
+        input: None,
+        system_map: None,
+        out: None,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--input" => opts.input = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--system-map" => opts.system_map = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--out" => opts.out = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_daemon<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<DaemonOptions, String> {
+    let mut socket = PathBuf::from("/tmp/meltdown.sock");
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--socket" => socket = PathBuf::from(flags.value(&flag, inline)?),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(DaemonOptions { socket, reader })
+}
+
+fn parse_http<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<HttpOptions, String> {
+    let mut opts = HttpOptions {
+        listen: "127.0.0.1".to_owned(),
+        port: 8080,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--port" => {
+                let port = parse_number(&flags.value(&flag, inline)?)?;
+                if port > 0xffff {
+                    return Err(format!("invalid port `{}`", port));
+                }
+                opts.port = port as u16
+            }
+            "--listen" => opts.listen = flags.value(&flag, inline)?,
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_strings<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<StringsOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut min_len = strings::DEFAULT_MIN_LEN;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--min" => min_len = parse_number(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`strings` requires `--addr`")?;
+    let len = len.ok_or("`strings` requires `--len`")?;
+    if min_len == 0 {
+        return Err("`--min` must be at least 1".to_owned());
+    }
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(StringsOptions {
+        addr,
+        len,
+        min_len,
+        reader,
+    })
+}
+
+// parses bytes given as pairs of hex digits
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>, String> {
+    let digits = if s.starts_with("0x") || s.starts_with("0X") {
+        &s[2..]
+    } else {
+        s
+    };
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_digit(16)) {
+        return Err(format!("`{}` isn't a whole number of bytes in hex", s));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn parse_search<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<SearchOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut pattern = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--pattern" | "--hex" if pattern.is_some() => {
+                return Err("only one of `--pattern` and `--hex` can be given".to_owned())
+            }
+            "--pattern" => pattern = Some(flags.value(&flag, inline)?.into_bytes()),
+            "--hex" => pattern = Some(parse_hex_bytes(&flags.value(&flag, inline)?)?),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`search` requires `--addr`")?;
+    let len = len.ok_or("`search` requires `--len`")?;
+    let pattern = pattern.ok_or("`search` requires `--pattern` or `--hex`")?;
+    if pattern.is_empty() {
+        return Err("the pattern can't be empty".to_owned());
+    }
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(SearchOptions {
+        addr,
+        len,
+        pattern,
+        reader,
+    })
+}
+
+fn parse_entropy<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<EntropyOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut window = entropy::DEFAULT_WINDOW;
+    let mut step = entropy::DEFAULT_STEP;
+    let mut threshold = entropy::DEFAULT_THRESHOLD;
+    let mut top = 20;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--window" => window = parse_number(&flags.value(&flag, inline)?)?,
+            "--step" => step = parse_number(&flags.value(&flag, inline)?)?,
+            "--threshold" => {
+                let value = flags.value(&flag, inline)?;
+                threshold = match value.parse::<f64>() {
+                    Ok(bits) if bits >= 0.0 && bits <= 8.0 => bits,
+                    _ => return Err(format!("invalid number of bits `{}`, expected 0 to 8", value)),
+                }
+            }
+            "--top" => top = parse_number(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`entropy` requires `--addr`")?;
+    let len = len.ok_or("`entropy` requires `--len`")?;
+    if step == 0 || step > window {
+        return Err("`--step` must be at least 1 and at most `--window`".to_owned());
+    }
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(EntropyOptions {
+        addr,
+        len,
+        window,
+        step,
+        threshold,
+        top,
+        reader,
+    })
+}
+
+fn parse_aes_keys<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<AesKeysOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut max_errors = aes::DEFAULT_MAX_ERRORS;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--max-errors" => {
+                let value = flags.value(&flag, inline)?;
+                let errors = parse_number(&value)?;
+                if errors > u32::max_value() as usize {
+                    return Err(format!("invalid number of errors `{}`", value));
+                }
+                max_errors = errors as u32
+            }
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`aeskeys` requires `--addr`")?;
+    let len = len.ok_or("`aeskeys` requires `--len`")?;
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(AesKeysOptions {
+        addr,
+        len,
+        max_errors,
+        reader,
+    })
+}
+
+fn parse_priv_keys<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<PrivKeysOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`privkeys` requires `--addr`")?;
+    let len = len.ok_or("`privkeys` requires `--len`")?;
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(PrivKeysOptions { addr, len, reader })
+}
+
+fn parse_survey<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<SurveyOptions, String> {
+    let mut addr = None;
+    let mut len = None;
+    let mut stride = survey::DEFAULT_STRIDE;
+    let mut word = false;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--sample-stride" => stride = parse_number(&flags.value(&flag, inline)?)?,
+            "--word" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                word = true
+            }
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`survey` requires `--addr`")?;
+    let len = len.ok_or("`survey` requires `--len`")?;
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    if stride < if word { 8 } else { 1 } {
+        return Err("`--sample-stride` has to be at least the size of a sample".to_owned());
+    }
+    Ok(SurveyOptions {
+        addr,
+        len,
+        stride,
+        word,
+        reader,
+    })
+}
+
+fn parse_watch<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<WatchOptions, String> {
+    let mut addr = None;
+    let mut len = 16;
+    let mut interval = Duration::from_millis(100);
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = parse_number(&flags.value(&flag, inline)?)?,
+            "--interval" => interval = parse_duration(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let addr = addr.ok_or("`watch` requires `--addr`")?;
+    if len == 0 {
+        return Err("`--len` has to be at least 1".to_owned());
+    }
+    if addr.checked_add(len).is_none() {
+        return Err("the range runs past the end of the address space".to_owned());
+    }
+    Ok(WatchOptions {
+        addr,
+        len,
+        interval,
+        reader,
+    })
+}
+
+fn parse_diff<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<DiffOptions, String> {
+    let mut files = Vec::new();
+    let mut first_flag = None;
+    while let Some(arg) = flags.args.next() {
+        if arg.starts_with("--") {
+            first_flag = Some(arg);
+            break;
+        }
+        files.push(PathBuf::from(arg))
+    }
+    let mut flags = Flags {
+        args: first_flag.into_iter().chain(&mut flags.args),
+    };
+    let mut addr = None;
+    let mut len = None;
+    let mut passes = None;
+    let mut threshold = diff::DEFAULT_THRESHOLD;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--passes" => passes = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--threshold" => threshold = parse_fraction(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut reader, &mut flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let source = if files.is_empty() {
+        let addr = addr.ok_or("`diff` requires two dumps, or `--addr`")?;
+        let len = len.ok_or("`diff` requires `--len` with `--addr`")?;
+        if addr.checked_add(len).is_none() {
+            return Err("the range runs past the end of the address space".to_owned());
+        }
+        let passes = passes.unwrap_or(2);
+        if passes < 2 {
+            return Err("`--passes` has to be at least 2".to_owned());
+        }
+        DiffSource::Passes { addr, len, passes }
+    } else {
+        if files.len() != 2 {
+            return Err("`diff` compares two dumps".to_owned());
+        }
+        if addr.is_some() || len.is_some() || passes.is_some() {
+            return Err("`--addr`, `--len` and `--passes` can't be used with dumps".to_owned());
+        }
+        let b = files.pop().unwrap();
+        let a = files.pop().unwrap();
+        DiffSource::Dumps(a, b)
+    };
+    Ok(DiffOptions {
+        source,
+        threshold,
+        reader,
+    })
+}
+
+fn parse_banner<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<BannerOptions, String> {
+    let mut system_map = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--system-map" => system_map = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    Ok(BannerOptions { system_map, reader })
+}
+
+// handles the options of ps, which creds shares. returns false if flag is
+// not one of them
+fn parse_ps_flag<I: Iterator<Item = String>>(
+    opts: &mut PsOptions,
+    flags: &mut Flags<I>,
+    flag: &str,
+    inline: Option<String>,
+) -> Result<bool, String> {
+    match flag {
+        "--system-map" => opts.system_map = Some(PathBuf::from(flags.value(flag, inline)?)),
+        "--max" => {
+            opts.max = parse_number(&flags.value(flag, inline)?)?;
+            if opts.max == 0 {
+                return Err("`--max` must be at least 1".to_owned());
+            }
+        }
+        _ => return parse_reader_flag(&mut opts.reader, flags, flag, inline),
+    }
+    Ok(true)
+}
+
+fn default_ps_options() -> PsOptions {
+    PsOptions {
+        system_map: None,
+        max: 4096,
+        reader: ReaderOptions::default(),
+    }
+}
+
+fn parse_ps<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<PsOptions, String> {
+    let mut opts = default_ps_options();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        if !parse_ps_flag(&mut opts, flags, &flag, inline)? {
+            return Err(format!("unknown option `{}`", flag));
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_creds<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<CredsOptions, String> {
+    let mut pid = None;
+    let mut ps = default_ps_options();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--pid" => pid = Some(parse_pid(&flags.value(&flag, inline)?)?),
+            _ => {
+                if !parse_ps_flag(&mut ps, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    let pid = pid.ok_or("`creds` requires `--pid`")?;
+    Ok(CredsOptions { pid, ps })
+}
+
+fn parse_check<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<CheckOptions, String> {
+    let mut opts = CheckOptions {
+        phys: false,
+        page_offset: None,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--phys" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.phys = true
+            }
+            "--page-offset" => {
+                opts.page_offset = Some(parse_number(&flags.value(&flag, inline)?)?)
+            }
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    if opts.page_offset.is_some() && !opts.phys {
+        return Err("`--page-offset` requires `--phys`".to_owned());
+    }
+    Ok(opts)
+}
+
+fn parse_calibrate<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<CalibrateOptions, String> {
+    let mut samples = 100_000;
+    let mut save = None;
+    let mut reader = ReaderOptions::default();
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--samples" => samples = parse_number(&flags.value(&flag, inline)?)?,
+            "--save" => save = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            _ => {
+                if !parse_reader_flag(&mut reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    if samples == 0 {
+        return Err("`--samples` must be at least 1".to_owned());
+    }
+    Ok(CalibrateOptions {
+        samples,
+        save,
+        reader,
+    })
+}
+
+// the mode comes first, `covert send` or `covert recv`, then the options
+fn parse_covert<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<CovertOptions, String> {
+    let mode = match flags.args.next() {
+        Some(ref mode) if mode == "send" => CovertMode::Send,
+        Some(ref mode) if mode == "recv" => CovertMode::Recv,
+        Some(ref mode) if mode == "bench" => CovertMode::Bench,
+        Some(mode) => return Err(format!("unknown covert mode `{}`", mode)),
+        None => return Err("`covert` requires `send`, `recv` or `bench`".to_owned()),
+    };
+    let mut opts = CovertOptions {
+        mode,
+        file: None,
+        slot_cycles: 200_000,
+        message: None,
+        test: None,
+        timeout: Duration::from_secs(30),
+        cpu: None,
+        peer: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--file" => opts.file = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--slot" => opts.slot_cycles = parse_number(&flags.value(&flag, inline)?)? as u64,
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--test" => opts.test = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--timeout" => {
+                let secs = parse_number(&flags.value(&flag, inline)?)?;
+                opts.timeout = Duration::from_secs(secs as u64)
+            }
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            "--peer" => opts.peer = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    // a slot has to fit a flush of every line and a probe of every line
+    if opts.slot_cycles < 10_000 {
+        return Err("`--slot` must be at least 10000 cycles".to_owned());
+    }
+    if opts.message.is_some() && opts.test.is_some() {
+        return Err("`--message` and `--test` can't be used together".to_owned());
+    }
+    if opts.message.is_some() && opts.mode != CovertMode::Send {
+        return Err("`--message` only applies to `covert send`".to_owned());
+    }
+    if opts.mode == CovertMode::Bench {
+        // halved four times, the last slot still has to fit
+        if opts.slot_cycles < 16 * 10_000 {
+            return Err("`covert bench` needs a `--slot` of at least 160000 cycles".to_owned());
+        }
+        if opts.test.is_none() {
+            opts.test = Some(1024)
+        }
+        if opts.cpu.is_some() && opts.cpu == opts.peer {
+            return Err("`--cpu` and `--peer` must be different cores".to_owned());
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_spectre<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<SpectreOptions, String> {
+    let mut opts = SpectreOptions {
+        addr: None,
+        len: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--addr" => opts.addr = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--len" => opts.len = Some(parse_number(&flags.value(&flag, inline)?)?),
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    if opts.len.is_some() && opts.addr.is_none() {
+        return Err("`--len` requires `--addr`".to_owned());
+    }
+    if let (Some(addr), Some(len)) = (opts.addr, opts.len) {
+        if addr.checked_add(len).is_none() {
+            return Err("`--addr` + `--len` overflows the address space".to_owned());
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_store_bypass<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<StoreBypassOptions, String> {
+    let mut opts = StoreBypassOptions {
+        message: None,
+        ssbd: false,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--ssbd" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.ssbd = true
+            }
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_l1tf<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<L1tfOptions, String> {
+    let mut opts = L1tfOptions {
+        message: None,
+        suppression: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_lazy_fp<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<LazyFpOptions, String> {
+    let mut opts = LazyFpOptions {
+        message: None,
+        suppression: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_mds<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<MdsOptions, String> {
+    let mut opts = MdsOptions {
+        message: None,
+        suppression: None,
+        cpu: None,
+        victim: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            "--victim" => opts.victim = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    if opts.cpu.is_some() && opts.cpu == opts.victim {
+        return Err("`--cpu` and `--victim` must be different cores".to_owned());
+    }
+    Ok(opts)
+}
+
+fn parse_fallout<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<FalloutOptions, String> {
+    let mut opts = FalloutOptions {
+        message: None,
+        kernel: false,
+        suppression: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--kernel" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.kernel = true
+            }
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_gds<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<GdsOptions, String> {
+    let mut opts = GdsOptions {
+        message: None,
+        force: false,
+        suppression: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--message" => opts.message = Some(flags.value(&flag, inline)?),
+            "--force" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.force = true
+            }
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_zenbleed<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<ZenbleedOptions, String> {
+    let mut opts = ZenbleedOptions {
+        attempts: 100_000,
+        force: false,
+        cpu: None,
+        victim: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--attempts" => opts.attempts = parse_number(&flags.value(&flag, inline)?)?,
+            "--force" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.force = true
+            }
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            "--victim" => opts.victim = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    if opts.cpu.is_some() && opts.cpu == opts.victim {
+        return Err("`--cpu` and `--victim` must be different cores".to_owned());
+    }
+    Ok(opts)
+}
+
+fn parse_sysreg<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<SysregOptions, String> {
+    let mut opts = SysregOptions {
+        register: None,
+        suppression: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--register" => {
+                let name = flags.value(&flag, inline)?;
+                let register = SystemRegister::from_name(&name)
+                    .ok_or_else(|| format!("unknown register `{}`", name))?;
+                opts.register = Some((name.to_uppercase(), register))
+            }
+            "--suppress" => opts.suppression = parse_suppression(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_kaslr<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<KaslrOptions, String> {
+    let mut opts = KaslrOptions {
+        regions: vec![kaslr::KERNEL_TEXT, kaslr::DIRECT_MAP],
+        locators: match Locator::detect() {
+            Locator::Tsx => vec![Locator::Tsx, Locator::Prefetch],
+            Locator::Prefetch => vec![Locator::Prefetch],
+        },
+        tries: 8,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--region" => {
+                let name = flags.value(&flag, inline)?;
+                opts.regions = match &name[..] {
+                    "text" => vec![kaslr::KERNEL_TEXT],
+                    "direct-map" => vec![kaslr::DIRECT_MAP],
+                    _ => return Err(format!("unknown region `{}`", name)),
+                }
+            }
+            "--locator" => {
+                let name = flags.value(&flag, inline)?;
+                opts.locators = match &name[..] {
+                    "tsx" => vec![Locator::Tsx],
+                    "prefetch" => vec![Locator::Prefetch],
+                    "both" => vec![Locator::Tsx, Locator::Prefetch],
+                    _ => return Err(format!("unknown locator `{}`", name)),
+                }
+            }
+            "--tries" => opts.tries = parse_number(&flags.value(&flag, inline)?)?,
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    if opts.tries == 0 {
+        return Err("`--tries` must be at least 1".to_owned());
+    }
+    Ok(opts)
+}
+
+fn parse_physmap<I: Iterator<Item = String>>(
+    flags: &mut Flags<I>,
+) -> Result<PhysmapOptions, String> {
+    let mut opts = PhysmapOptions {
+        locator: Locator::detect(),
+        tries: 8,
+        reader: ReaderOptions::default(),
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--locator" => {
+                let name = flags.value(&flag, inline)?;
+                opts.locator = match &name[..] {
+                    "tsx" => Locator::Tsx,
+                    "prefetch" => Locator::Prefetch,
+                    _ => return Err(format!("unknown locator `{}`", name)),
+                }
+            }
+            "--tries" => opts.tries = parse_number(&flags.value(&flag, inline)?)?,
+            _ => {
+                if !parse_reader_flag(&mut opts.reader, flags, &flag, inline)? {
+                    return Err(format!("unknown option `{}`", flag));
+                }
+            }
+        }
+    }
+    if opts.tries == 0 {
+        return Err("`--tries` must be at least 1".to_owned());
+    }
+    Ok(opts)
+}
+
+fn parse_report<I: Iterator<Item = String>>(flags: &mut Flags<I>) -> Result<ReportOptions, String> {
+    let mut opts = ReportOptions {
+        json: false,
+        out: None,
+        cpu: None,
+    };
+    while let Some((flag, inline)) = flags.next_flag()? {
+        match &flag[..] {
+            "--json" => {
+                if inline.is_some() {
+                    return Err(format!("`{}` does not take a value", flag));
+                }
+                opts.json = true
+            }
+            "--out" => opts.out = Some(PathBuf::from(flags.value(&flag, inline)?)),
+            "--cpu" => opts.cpu = Some(parse_core(&flags.value(&flag, inline)?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+    Ok(opts)
+}
+
+// parses the process arguments, not including the program name
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Ok(Command::Help),
+    };
+    let mut flags = Flags { args };
+    match &command[..] {
+        "dump" => parse_dump(&mut flags).map(Command::Dump),
+        "dump-ram" => parse_dump_ram(&mut flags).map(Command::DumpRam),
+        "dump-file" => parse_dump_file(&mut flags).map(Command::DumpFile),
+        "gdbserver" => parse_gdbserver(&mut flags).map(Command::GdbServer),
+        "repl" => parse_repl(&mut flags).map(Command::Repl),
+        "batch" => parse_batch(&mut flags).map(Command::Batch),
+        "daemon" => parse_daemon(&mut flags).map(Command::Daemon),
+        "http" => parse_http(&mut flags).map(Command::Http),
+        "strings" => parse_strings(&mut flags).map(Command::Strings),
+        "search" => parse_search(&mut flags).map(Command::Search),
+        "entropy" => parse_entropy(&mut flags).map(Command::Entropy),
+        "aeskeys" => parse_aes_keys(&mut flags).map(Command::AesKeys),
+        "privkeys" => parse_priv_keys(&mut flags).map(Command::PrivKeys),
+        "survey" => parse_survey(&mut flags).map(Command::Survey),
+        "watch" => parse_watch(&mut flags).map(Command::Watch),
+        "diff" => parse_diff(&mut flags).map(Command::Diff),
+        "demo" => parse_reader_options(&mut flags).map(Command::Demo),
+        "check" => parse_check(&mut flags).map(Command::Check),
+        "banner" => parse_banner(&mut flags).map(Command::Banner),
+        "ps" => parse_ps(&mut flags).map(Command::Ps),
+        "creds" => parse_creds(&mut flags).map(Command::Creds),
+        "calibrate" => parse_calibrate(&mut flags).map(Command::Calibrate),
+        "covert" => parse_covert(&mut flags).map(Command::Covert),
+        "spectre-v1" => parse_spectre(&mut flags).map(Command::SpectreV1),
+        "spectre-v2" => parse_spectre(&mut flags).map(Command::SpectreV2),
+        "spectre-v4" => parse_store_bypass(&mut flags).map(Command::SpectreV4),
+        "retbleed" => parse_spectre(&mut flags).map(Command::Retbleed),
+        "l1tf" => parse_l1tf(&mut flags).map(Command::L1tf),
+        "lazy-fp" => parse_lazy_fp(&mut flags).map(Command::LazyFp),
+        "mds" => parse_mds(&mut flags).map(Command::Mds),
+        "fallout" => parse_fallout(&mut flags).map(Command::Fallout),
+        "gds" => parse_gds(&mut flags).map(Command::Gds),
+        "zenbleed" => parse_zenbleed(&mut flags).map(Command::Zenbleed),
+        "sysreg" => parse_sysreg(&mut flags).map(Command::Sysreg),
+        "kaslr" => parse_kaslr(&mut flags).map(Command::Kaslr),
+        "physmap" => parse_physmap(&mut flags).map(Command::Physmap),
+        "report" => parse_report(&mut flags).map(Command::Report),
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        _ => Err(format!("unknown command `{}`", command)),
+    }
+}
@@ -0,0 +1,180 @@
+//! Every tunable of a reader in one place, set with chained calls and
+//! consumed by `MeltdownReader::with_config`:
+//!
+//! ```ignore
+//! let reader = MeltdownConfig::new()
+//!     .channel(Channel::Line)
+//!     .probe_count(32)
+//!     .min_confidence(0.5)
+//!     .build()?;
+//! ```
+//!
+//! Anything left unset keeps the default a reader from `MeltdownReader::new`
+//! has. The stride between probe lines isn't a setting of its own, it
+//! follows from the channel: a page per value for `Page`, a line per value
+//! for `Line`.
+
+use std::io;
+
+use calibrate::Calibration;
+use channel::Channel;
+use reader::{MeltdownReader, DEFAULT_CALIBRATION_SAMPLES, DEFAULT_MAX_RETRIES,
+             DEFAULT_PROBE_COUNT, DEFAULT_ZERO_RETRIES};
+use suppress::{ExceptionSuppressor, Suppression};
+use timer::TimerSource;
+
+/// How to set up a reader.
+#[derive(Clone, Debug)]
+pub struct MeltdownConfig<S: ExceptionSuppressor = Suppression> {
+    pub(crate) suppressor: S,
+    pub(crate) channel: Channel,
+    // None leaves the selected timer
+    pub(crate) timer: Option<TimerSource>,
+    pub(crate) hugepages: bool,
+    pub(crate) dual_probe: bool,
+    pub(crate) evict: bool,
+    pub(crate) pipeline: bool,
+    // None measures one when the reader is created
+    pub(crate) calibration: Option<Calibration>,
+    pub(crate) calibration_samples: usize,
+    // None keeps the calibration's
+    pub(crate) threshold: Option<u64>,
+    pub(crate) probe_count: usize,
+    pub(crate) zero_retries: usize,
+    pub(crate) min_confidence: f64,
+    pub(crate) max_retries: usize,
+}
+
+impl MeltdownConfig {
+    /// The defaults, with the best suppression strategy this CPU supports.
+    pub fn new() -> MeltdownConfig {
+        MeltdownConfig::with_suppressor(Suppression::detect())
+    }
+
+    /// Suppresses faults with one of the built in strategies.
+    pub fn suppression(self, suppression: Suppression) -> MeltdownConfig {
+        MeltdownConfig {
+            suppressor: suppression,
+            ..self
+        }
+    }
+}
+
+impl Default for MeltdownConfig {
+    fn default() -> MeltdownConfig {
+        MeltdownConfig::new()
+    }
+}
+
+impl<S: ExceptionSuppressor> MeltdownConfig<S> {
+    /// The defaults, suppressing faults with `suppressor`.
+    pub fn with_suppressor(suppressor: S) -> MeltdownConfig<S> {
+        MeltdownConfig {
+            suppressor,
+            channel: Channel::Page,
+            timer: None,
+            hugepages: false,
+            dual_probe: false,
+            evict: false,
+            pipeline: false,
+            calibration: None,
+            calibration_samples: DEFAULT_CALIBRATION_SAMPLES,
+            threshold: None,
+            probe_count: DEFAULT_PROBE_COUNT,
+            zero_retries: DEFAULT_ZERO_RETRIES,
+            min_confidence: 0.0,
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    /// How leaked values are encoded in the probe buffer, as for
+    /// `MeltdownReader::set_channel`.
+    pub fn channel(mut self, channel: Channel) -> MeltdownConfig<S> {
+        self.channel = channel;
+        self
+    }
+
+    /// Selects the timer probes are timed with. The selection is process
+    /// wide, so it applies to every reader.
+    pub fn timer(mut self, source: TimerSource) -> MeltdownConfig<S> {
+        self.timer = Some(source);
+        self
+    }
+
+    /// As `MeltdownReader::set_hugepages`.
+    pub fn hugepages(mut self, hugepages: bool) -> MeltdownConfig<S> {
+        self.hugepages = hugepages;
+        self
+    }
+
+    /// As `MeltdownReader::set_dual_probe`.
+    pub fn dual_probe(mut self, dual_probe: bool) -> MeltdownConfig<S> {
+        self.dual_probe = dual_probe;
+        self
+    }
+
+    /// As `MeltdownReader::set_evict`.
+    pub fn evict(mut self, evict: bool) -> MeltdownConfig<S> {
+        self.evict = evict;
+        self
+    }
+
+    /// As `MeltdownReader::set_pipelined`.
+    pub fn pipeline(mut self, pipeline: bool) -> MeltdownConfig<S> {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Uses `calibration`, e.g. one saved by an earlier run, rather than the
+    /// one measured when the reader is created.
+    pub fn calibration(mut self, calibration: Calibration) -> MeltdownConfig<S> {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// How many hits and misses calibrating times, both when the reader is
+    /// created and when a change of channel calibrates again.
+    pub fn calibration_samples(mut self, samples: usize) -> MeltdownConfig<S> {
+        self.calibration_samples = samples;
+        self
+    }
+
+    /// Counts probes faster than `threshold` as hits, whatever the
+    /// calibration says.
+    pub fn threshold(mut self, threshold: u64) -> MeltdownConfig<S> {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// As `MeltdownReader::set_probe_count`.
+    pub fn probe_count(mut self, probe_count: usize) -> MeltdownConfig<S> {
+        self.probe_count = probe_count;
+        self
+    }
+
+    /// How many transient reads in a row have to leak 0 before it is
+    /// believed, since a read that loses the race with the fault leaks 0.
+    pub fn zero_retries(mut self, zero_retries: usize) -> MeltdownConfig<S> {
+        self.zero_retries = zero_retries;
+        self
+    }
+
+    /// Probes bytes again while fewer than this fraction of rounds agree, as
+    /// `MeltdownReader::set_min_confidence`.
+    pub fn min_confidence(mut self, min_confidence: f64) -> MeltdownConfig<S> {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    /// How many times a byte is probed again to reach the minimum
+    /// confidence.
+    pub fn max_retries(mut self, max_retries: usize) -> MeltdownConfig<S> {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Creates the reader, as `MeltdownReader::with_config`.
+    pub fn build(self) -> io::Result<MeltdownReader<S>> {
+        MeltdownReader::with_config(self)
+    }
+}
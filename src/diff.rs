@@ -0,0 +1,86 @@
+//! Comparing reads of the same memory, from dumps or from passes over it,
+//! to tell the bytes that really changed from the ones that were misread.
+//!
+//! A byte whose reads differ counts as changed only if two of them that
+//! were confident enough disagree. If the reads only differ by ones that
+//! didn't leak or that few probe rounds agreed on, the difference is put
+//! down to noise.
+
+use std::io::{self, BufRead};
+
+use reader::ByteGuess;
+
+/// The fraction of probe rounds a read has to have agreed on to be trusted
+/// unless told otherwise.
+pub const DEFAULT_THRESHOLD: f64 = 0.5;
+
+/// How the reads of a byte differ.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// Confident reads disagree, so the memory changed between them.
+    Changed,
+    /// Only reads that didn't leak or weren't confident disagree.
+    Noise,
+}
+
+/// How `reads` of the same byte differ, or None if they all agree. Reads
+/// with a confidence below `threshold` aren't trusted.
+pub fn compare(reads: &[ByteGuess], threshold: f64) -> Option<Difference> {
+    let first = reads.first()?;
+    if reads.iter().all(|read| read.value == first.value) {
+        return None;
+    }
+    let mut confident = reads
+        .iter()
+        .filter(|read| read.value.is_some() && read.confidence() >= threshold);
+    let value = confident.next().map(|read| read.value);
+    if confident.any(|read| Some(read.value) != value) {
+        Some(Difference::Changed)
+    } else {
+        Some(Difference::Noise)
+    }
+}
+
+// the text of the value of key in a line of a JSON dump
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(&format!("\"{}\": ", key))? + key.len() + 4;
+    let len = line[start..].find(|c| c == ',' || c == '}')?;
+    Some(&line[start..start + len])
+}
+
+// the address and read of a line of a JSON dump
+fn parse_line(line: &str) -> Option<(usize, ByteGuess)> {
+    let addr = field(line, "addr")?.trim_matches('"');
+    if !addr.starts_with("0x") {
+        return None;
+    }
+    let addr = usize::from_str_radix(&addr[2..], 16).ok()?;
+    let value = match field(line, "value")? {
+        "null" => None,
+        value => Some(value.parse().ok()?),
+    };
+    let guess = ByteGuess {
+        value,
+        hits: field(line, "hits")?.parse().ok()?,
+        rounds: field(line, "rounds")?.parse().ok()?,
+    };
+    Some((addr, guess))
+}
+
+/// Reads a dump written by `dump --format json`, as the address and read of
+/// each of its bytes.
+pub fn read_json<R: BufRead>(input: R) -> io::Result<Vec<(usize, ByteGuess)>> {
+    let mut result = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let byte = parse_line(&line).ok_or_else(|| {
+            let message = format!("line {} isn't a byte of a JSON dump", i + 1);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        result.push(byte)
+    }
+    Ok(result)
+}
@@ -0,0 +1,569 @@
+//! The types of a binary's variables, from its DWARF debug info, so a
+//! leaked variable can be printed as the struct, array or string it is.
+//!
+//! Every entry of `.debug_info` is read up front, and types are built from
+//! them on demand. Only 32 bit DWARF 2 to 4 is understood, which is what
+//! compilers emitted before DWARF 5 became GCC's default in version 11.
+//! Newer binaries can be built with `-gdwarf-4`.
+
+use std::collections::HashMap;
+use std::io;
+
+use elf::Elf;
+use types::{Field, Type};
+
+const TAG_ARRAY_TYPE: u64 = 0x01;
+const TAG_CLASS_TYPE: u64 = 0x02;
+const TAG_ENUMERATION_TYPE: u64 = 0x04;
+const TAG_MEMBER: u64 = 0x0d;
+const TAG_POINTER_TYPE: u64 = 0x0f;
+const TAG_REFERENCE_TYPE: u64 = 0x10;
+const TAG_STRUCTURE_TYPE: u64 = 0x13;
+const TAG_TYPEDEF: u64 = 0x16;
+const TAG_UNION_TYPE: u64 = 0x17;
+const TAG_SUBRANGE_TYPE: u64 = 0x21;
+const TAG_BASE_TYPE: u64 = 0x24;
+const TAG_CONST_TYPE: u64 = 0x26;
+const TAG_ENUMERATOR: u64 = 0x28;
+const TAG_VARIABLE: u64 = 0x34;
+const TAG_VOLATILE_TYPE: u64 = 0x35;
+const TAG_RESTRICT_TYPE: u64 = 0x37;
+const TAG_ATOMIC_TYPE: u64 = 0x47;
+
+const AT_NAME: u64 = 0x03;
+const AT_BYTE_SIZE: u64 = 0x0b;
+const AT_CONST_VALUE: u64 = 0x1c;
+const AT_UPPER_BOUND: u64 = 0x2f;
+const AT_COUNT: u64 = 0x37;
+const AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+const AT_DECLARATION: u64 = 0x3c;
+const AT_ENCODING: u64 = 0x3e;
+const AT_SPECIFICATION: u64 = 0x47;
+const AT_TYPE: u64 = 0x49;
+
+const ATE_BOOLEAN: u64 = 0x02;
+const ATE_FLOAT: u64 = 0x04;
+const ATE_SIGNED: u64 = 0x05;
+const ATE_SIGNED_CHAR: u64 = 0x06;
+const ATE_UNSIGNED: u64 = 0x07;
+const ATE_UNSIGNED_CHAR: u64 = 0x08;
+
+const OP_PLUS_UCONST: u8 = 0x23;
+
+// how deep types are built before the rest is left opaque
+const MAX_DEPTH: usize = 16;
+
+// the value of an attribute
+#[derive(Clone, Debug)]
+enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    Str(String),
+    // the offset of another entry in .debug_info
+    Ref(usize),
+    Block(Vec<u8>),
+    Flag(bool),
+    // forms nothing here needs
+    Skipped,
+}
+
+// an entry of .debug_info
+struct Die {
+    tag: u64,
+    attrs: Vec<(u64, Value)>,
+    children: Vec<usize>,
+}
+
+impl Die {
+    fn attr(&self, at: u64) -> Option<&Value> {
+        self.attrs
+            .iter()
+            .find(|&&(attr, _)| attr == at)
+            .map(|&(_, ref value)| value)
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self.attr(AT_NAME) {
+            Some(&Value::Str(ref name)) => Some(&name[..]),
+            _ => None,
+        }
+    }
+
+    fn unsigned(&self, at: u64) -> Option<u64> {
+        match self.attr(at) {
+            Some(&Value::Unsigned(value)) => Some(value),
+            Some(&Value::Signed(value)) => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    fn reference(&self, at: u64) -> Option<usize> {
+        match self.attr(at) {
+            Some(&Value::Ref(offset)) => Some(offset),
+            _ => None,
+        }
+    }
+
+    fn flag(&self, at: u64) -> bool {
+        match self.attr(at) {
+            Some(&Value::Flag(flag)) => flag,
+            _ => false,
+        }
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    // a little endian integer of len bytes
+    fn fixed(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.bytes(len)?;
+        Some(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Option<&'a str> {
+        let len = self.data.get(self.pos..)?.iter().position(|&byte| byte == 0)?;
+        let s = ::std::str::from_utf8(self.bytes(len)?).ok()?;
+        self.pos += 1;
+        Some(s)
+    }
+}
+
+// an abbreviation: the tag of the entries using it, whether they have
+// children, and the attributes and forms they hold
+struct Abbrev {
+    tag: u64,
+    children: bool,
+    attrs: Vec<(u64, u64)>,
+}
+
+fn parse_abbrevs(data: &[u8], offset: usize) -> Option<HashMap<u64, Abbrev>> {
+    let mut cursor = Cursor { data, pos: offset };
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = cursor.uleb()?;
+        if code == 0 {
+            return Some(abbrevs);
+        }
+        let tag = cursor.uleb()?;
+        let children = cursor.u8()? != 0;
+        let mut attrs = Vec::new();
+        loop {
+            match (cursor.uleb()?, cursor.uleb()?) {
+                (0, 0) => break,
+                attr => attrs.push(attr),
+            }
+        }
+        abbrevs.insert(code, Abbrev { tag, children, attrs });
+    }
+}
+
+// what reading a value needs to know of its unit
+struct Unit<'a> {
+    offset: usize,
+    version: u16,
+    address_size: usize,
+    strings: &'a [u8],
+}
+
+fn block(cursor: &mut Cursor, len: u64) -> Option<Value> {
+    Some(Value::Block(cursor.bytes(len as usize)?.to_vec()))
+}
+
+fn read_value(cursor: &mut Cursor, form: u64, unit: &Unit) -> Option<Value> {
+    let unit_ref = |offset: u64| Some(Value::Ref(unit.offset + offset as usize));
+    match form {
+        // addr
+        0x01 => Some(Value::Unsigned(cursor.fixed(unit.address_size)?)),
+        // block2, block4, block, block1 and exprloc
+        0x03 => {
+            let len = cursor.fixed(2)?;
+            block(cursor, len)
+        }
+        0x04 => {
+            let len = cursor.fixed(4)?;
+            block(cursor, len)
+        }
+        0x09 | 0x18 => {
+            let len = cursor.uleb()?;
+            block(cursor, len)
+        }
+        0x0a => {
+            let len = cursor.u8()? as u64;
+            block(cursor, len)
+        }
+        // data2, data4, data8 and data1
+        0x05 => Some(Value::Unsigned(cursor.fixed(2)?)),
+        0x06 => Some(Value::Unsigned(cursor.fixed(4)?)),
+        0x07 => Some(Value::Unsigned(cursor.fixed(8)?)),
+        0x0b => Some(Value::Unsigned(cursor.fixed(1)?)),
+        // string and strp
+        0x08 => Some(Value::Str(cursor.cstr()?.to_owned())),
+        0x0e => {
+            let offset = cursor.fixed(4)? as usize;
+            let mut strings = Cursor {
+                data: unit.strings,
+                pos: offset,
+            };
+            Some(Value::Str(strings.cstr()?.to_owned()))
+        }
+        // flag and flag_present
+        0x0c => Some(Value::Flag(cursor.u8()? != 0)),
+        0x19 => Some(Value::Flag(true)),
+        // sdata and udata
+        0x0d => Some(Value::Signed(cursor.sleb()?)),
+        0x0f => Some(Value::Unsigned(cursor.uleb()?)),
+        // ref_addr, which DWARF 2 made address sized
+        0x10 => {
+            let len = if unit.version == 2 { unit.address_size } else { 4 };
+            Some(Value::Ref(cursor.fixed(len)? as usize))
+        }
+        // ref1, ref2, ref4, ref8 and ref_udata
+        0x11 => unit_ref(cursor.fixed(1)?),
+        0x12 => unit_ref(cursor.fixed(2)?),
+        0x13 => unit_ref(cursor.fixed(4)?),
+        0x14 => unit_ref(cursor.fixed(8)?),
+        0x15 => unit_ref(cursor.uleb()?),
+        // indirect
+        0x16 => {
+            let form = cursor.uleb()?;
+            read_value(cursor, form, unit)
+        }
+        // sec_offset
+        0x17 => cursor.fixed(4).map(|_| Value::Skipped),
+        // ref_sig8, into type units, which aren't read
+        0x20 => cursor.fixed(8).map(|_| Value::Skipped),
+        _ => None,
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed DWARF debug info")
+}
+
+/// The debug info of a binary.
+pub struct Dwarf {
+    dies: HashMap<usize, Die>,
+}
+
+impl Dwarf {
+    /// Reads the debug info of `elf`. Fails if it has none, or it is in a
+    /// format that isn't understood.
+    pub fn parse(elf: &Elf) -> io::Result<Dwarf> {
+        let (info, abbrev) = match (elf.section(".debug_info"), elf.section(".debug_abbrev")) {
+            (Some(info), Some(abbrev)) => (info, abbrev),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "the binary has no debug info",
+                ))
+            }
+        };
+        let strings = elf.section(".debug_str").unwrap_or(&[]);
+        let mut dies: HashMap<usize, Die> = HashMap::new();
+        let mut cursor = Cursor { data: info, pos: 0 };
+        while cursor.pos < info.len() {
+            let offset = cursor.pos;
+            let len = cursor.fixed(4).ok_or_else(malformed)? as usize;
+            if len >= 0xffff_fff0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "64 bit DWARF isn't supported",
+                ));
+            }
+            let end = cursor.pos + len;
+            let version = cursor.fixed(2).ok_or_else(malformed)? as u16;
+            if version < 2 || version > 4 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "DWARF {} isn't supported, rebuild the binary with -gdwarf-4",
+                        version
+                    ),
+                ));
+            }
+            let abbrev_offset = cursor.fixed(4).ok_or_else(malformed)? as usize;
+            let address_size = cursor.u8().ok_or_else(malformed)? as usize;
+            let abbrevs = parse_abbrevs(abbrev, abbrev_offset).ok_or_else(malformed)?;
+            let unit = Unit {
+                offset,
+                version,
+                address_size,
+                strings,
+            };
+            // the entries whose children are being read, innermost last
+            let mut parents: Vec<usize> = Vec::new();
+            while cursor.pos < end {
+                let die = cursor.pos;
+                let code = cursor.uleb().ok_or_else(malformed)?;
+                if code == 0 {
+                    parents.pop();
+                    continue;
+                }
+                let abbrev = abbrevs.get(&code).ok_or_else(malformed)?;
+                let mut attrs = Vec::with_capacity(abbrev.attrs.len());
+                for &(at, form) in &abbrev.attrs {
+                    let value = read_value(&mut cursor, form, &unit).ok_or_else(malformed)?;
+                    attrs.push((at, value));
+                }
+                if let Some(parent) = parents.last().and_then(|parent| dies.get_mut(parent)) {
+                    parent.children.push(die)
+                }
+                dies.insert(
+                    die,
+                    Die {
+                        tag: abbrev.tag,
+                        attrs,
+                        children: Vec::new(),
+                    },
+                );
+                if abbrev.children {
+                    parents.push(die)
+                }
+            }
+            cursor.pos = end;
+        }
+        Ok(Dwarf { dies })
+    }
+
+    /// The type of the variable called `name`.
+    pub fn variable_type(&self, name: &str) -> io::Result<Type> {
+        let mut variables: Vec<(&usize, &Die)> = self.dies
+            .iter()
+            .filter(|&(_, die)| die.tag == TAG_VARIABLE && die.name() == Some(name))
+            .collect();
+        // the definition rather than a declaration, and the first of those
+        variables.sort_by_key(|&(&offset, die)| (die.flag(AT_DECLARATION), offset));
+        let ty = variables
+            .iter()
+            .filter_map(|&(_, die)| {
+                die.reference(AT_TYPE).or_else(|| {
+                    let declaration = self.dies.get(&die.reference(AT_SPECIFICATION)?)?;
+                    declaration.reference(AT_TYPE)
+                })
+            })
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("the debug info has no variable called `{}`", name),
+                )
+            })?;
+        Ok(self.build(ty, 0))
+    }
+
+    fn byte_size(&self, die: &Die) -> usize {
+        die.unsigned(AT_BYTE_SIZE).unwrap_or(0) as usize
+    }
+
+    // the type the entry at offset describes
+    fn build(&self, offset: usize, depth: usize) -> Type {
+        let die = match self.dies.get(&offset) {
+            Some(die) if depth < MAX_DEPTH => die,
+            _ => {
+                return Type::Opaque {
+                    name: "?".to_owned(),
+                    size: 0,
+                }
+            }
+        };
+        let size = self.byte_size(die);
+        let name = die.name().unwrap_or("").to_owned();
+        match die.tag {
+            TAG_BASE_TYPE => match die.unsigned(AT_ENCODING).unwrap_or(0) {
+                ATE_BOOLEAN => Type::Bool { size },
+                ATE_FLOAT => Type::Float { name, size },
+                ATE_SIGNED_CHAR | ATE_UNSIGNED_CHAR if size == 1 => Type::Char {
+                    signed: die.unsigned(AT_ENCODING) == Some(ATE_SIGNED_CHAR),
+                },
+                ATE_SIGNED | ATE_SIGNED_CHAR => Type::Int {
+                    name,
+                    size,
+                    signed: true,
+                },
+                ATE_UNSIGNED | ATE_UNSIGNED_CHAR => Type::Int {
+                    name,
+                    size,
+                    signed: false,
+                },
+                _ => Type::Opaque { name, size },
+            },
+            TAG_POINTER_TYPE | TAG_REFERENCE_TYPE => Type::Pointer {
+                target: die.reference(AT_TYPE)
+                    .map_or_else(|| "void".to_owned(), |target| self.type_name(target, depth)),
+                size: if size == 0 { 8 } else { size },
+            },
+            TAG_STRUCTURE_TYPE | TAG_UNION_TYPE | TAG_CLASS_TYPE => {
+                let fields = die.children
+                    .iter()
+                    .filter_map(|child| self.dies.get(child))
+                    .filter(|member| member.tag == TAG_MEMBER)
+                    .map(|member| Field {
+                        name: member.name().unwrap_or("?").to_owned(),
+                        offset: member_offset(member),
+                        ty: member.reference(AT_TYPE).map_or_else(
+                            || Type::Opaque {
+                                name: "?".to_owned(),
+                                size: 0,
+                            },
+                            |ty| self.build(ty, depth + 1),
+                        ),
+                    })
+                    .collect();
+                Type::Struct {
+                    name: self.type_name(offset, depth),
+                    size,
+                    fields,
+                }
+            }
+            TAG_ARRAY_TYPE => {
+                let element = die.reference(AT_TYPE).map_or_else(
+                    || Type::Opaque {
+                        name: "?".to_owned(),
+                        size: 0,
+                    },
+                    |ty| self.build(ty, depth + 1),
+                );
+                // int a[2][3] has a subrange per dimension, outermost first
+                let lens: Vec<usize> = die.children
+                    .iter()
+                    .filter_map(|child| self.dies.get(child))
+                    .filter(|range| range.tag == TAG_SUBRANGE_TYPE)
+                    .map(|range| {
+                        match (range.unsigned(AT_COUNT), range.unsigned(AT_UPPER_BOUND)) {
+                            (Some(count), _) => count as usize,
+                            (None, Some(upper)) => upper as usize + 1,
+                            // a flexible array member
+                            (None, None) => 0,
+                        }
+                    })
+                    .collect();
+                lens.iter().rev().fold(element, |element, &len| Type::Array {
+                    element: Box::new(element),
+                    len,
+                })
+            }
+            TAG_ENUMERATION_TYPE => {
+                let values: Vec<(i64, String)> = die.children
+                    .iter()
+                    .filter_map(|child| self.dies.get(child))
+                    .filter(|value| value.tag == TAG_ENUMERATOR)
+                    .filter_map(|value| {
+                        let number = match value.attr(AT_CONST_VALUE)? {
+                            &Value::Unsigned(number) => number as i64,
+                            &Value::Signed(number) => number,
+                            _ => return None,
+                        };
+                        Some((number, value.name()?.to_owned()))
+                    })
+                    .collect();
+                Type::Enum {
+                    name: self.type_name(offset, depth),
+                    size,
+                    signed: values.iter().any(|&(number, _)| number < 0),
+                    values,
+                }
+            }
+            TAG_TYPEDEF | TAG_CONST_TYPE | TAG_VOLATILE_TYPE | TAG_RESTRICT_TYPE
+            | TAG_ATOMIC_TYPE => match die.reference(AT_TYPE) {
+                Some(ty) => self.build(ty, depth + 1),
+                None => Type::Opaque {
+                    name: "void".to_owned(),
+                    size: 0,
+                },
+            },
+            _ => Type::Opaque {
+                name: self.type_name(offset, depth),
+                size,
+            },
+        }
+    }
+
+    // the C name of the type the entry at offset describes
+    fn type_name(&self, offset: usize, depth: usize) -> String {
+        let die = match self.dies.get(&offset) {
+            Some(die) if depth < MAX_DEPTH => die,
+            _ => return "?".to_owned(),
+        };
+        let name = die.name().unwrap_or("<anonymous>");
+        let target = || {
+            die.reference(AT_TYPE)
+                .map_or_else(|| "void".to_owned(), |ty| self.type_name(ty, depth + 1))
+        };
+        match die.tag {
+            TAG_STRUCTURE_TYPE => format!("struct {}", name),
+            TAG_UNION_TYPE => format!("union {}", name),
+            TAG_CLASS_TYPE => format!("class {}", name),
+            TAG_ENUMERATION_TYPE => format!("enum {}", name),
+            TAG_POINTER_TYPE | TAG_REFERENCE_TYPE => format!("{} *", target()),
+            TAG_CONST_TYPE => format!("const {}", target()),
+            TAG_VOLATILE_TYPE => format!("volatile {}", target()),
+            TAG_ARRAY_TYPE => format!("{}[]", target()),
+            TAG_RESTRICT_TYPE | TAG_ATOMIC_TYPE => target(),
+            _ => name.to_owned(),
+        }
+    }
+}
+
+// where a member is in its struct. unions leave it out, and older compilers
+// give it as an expression adding it to the struct's address
+fn member_offset(member: &Die) -> usize {
+    match member.attr(AT_DATA_MEMBER_LOCATION) {
+        Some(&Value::Unsigned(offset)) => offset as usize,
+        Some(&Value::Signed(offset)) => offset as usize,
+        Some(&Value::Block(ref expr)) if expr.first() == Some(&OP_PLUS_UCONST) => {
+            let mut cursor = Cursor {
+                data: expr,
+                pos: 1,
+            };
+            cursor.uleb().unwrap_or(0) as usize
+        }
+        _ => 0,
+    }
+}
@@ -0,0 +1,185 @@
+//! Symbols of userspace binaries, so a variable of another process can be
+//! leaked by name rather than by an address looked up in a debugger.
+//!
+//! A symbol's value is where the binary was linked to put it. A position
+//! independent executable or library is loaded at a random base instead, and
+//! everything in it moves by the same bias. That bias is where the process's
+//! maps file shows the start of the binary, less where its first segment was
+//! linked to go.
+
+use std::cmp::min;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use maps::Mapping;
+use PAGE_SIZE;
+
+const ET_DYN: u16 = 3;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const SHN_UNDEF: u16 = 0;
+
+const PHDR_LEN: usize = 56;
+const SHDR_LEN: usize = 64;
+const SYM_LEN: usize = 24;
+
+/// A symbol defined in the binary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ElfSymbol {
+    /// Where it was linked to go.
+    pub value: usize,
+    pub size: usize,
+}
+
+/// What is needed of a 64 bit little endian ELF file to find its symbols in
+/// a running process.
+#[derive(Clone, Debug)]
+pub struct Elf {
+    /// Whether it is loaded at a random base.
+    pub position_independent: bool,
+    /// Where its lowest loadable segment was linked to go.
+    pub first_vaddr: usize,
+    symbols: HashMap<String, ElfSymbol>,
+    // where each named section is in data
+    sections: HashMap<String, Range<usize>>,
+    data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    let bytes = data.get(at..at.checked_add(2)?)?;
+    Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u16_at(data, at)? as u32 | (u16_at(data, at + 2)? as u32) << 16)
+}
+
+fn u64_at(data: &[u8], at: usize) -> Option<usize> {
+    Some(u32_at(data, at)? as usize | (u32_at(data, at + 4)? as usize) << 32)
+}
+
+// the NUL terminated string at offset `at` of the string table at `table`
+fn str_at(data: &[u8], table: usize, at: usize) -> Option<&str> {
+    let start = table.checked_add(at)?;
+    let bytes = data.get(start..)?;
+    let len = bytes.iter().position(|&byte| byte == 0)?;
+    ::std::str::from_utf8(&bytes[..len]).ok()
+}
+
+// adds the defined symbols of the symbol table described by the section
+// header at `shdr` to symbols, keeping names already there
+fn read_symbols(
+    data: &[u8],
+    shdr: usize,
+    symbols: &mut HashMap<String, ElfSymbol>,
+) -> Option<()> {
+    let offset = u64_at(data, shdr + 24)?;
+    let size = u64_at(data, shdr + 32)?;
+    let link = u32_at(data, shdr + 40)? as usize;
+    let shoff = u64_at(data, 40)?;
+    let strtab = u64_at(data, shoff.checked_add(link.checked_mul(SHDR_LEN)?)? + 24)?;
+    for sym in (offset..offset.checked_add(size)?).step_by(SYM_LEN) {
+        let value = u64_at(data, sym + 8)?;
+        if u16_at(data, sym + 6)? == SHN_UNDEF || value == 0 {
+            continue;
+        }
+        let name = str_at(data, strtab, u32_at(data, sym)? as usize)?;
+        if name.is_empty() {
+            continue;
+        }
+        symbols.entry(name.to_owned()).or_insert(ElfSymbol {
+            value,
+            size: u64_at(data, sym + 16)?,
+        });
+    }
+    Some(())
+}
+
+impl Elf {
+    /// Parses the contents of a binary. Both the full symbol table and the
+    /// dynamic one are read, so stripped binaries still have their exports.
+    pub fn parse(data: &[u8]) -> io::Result<Elf> {
+        if !data.starts_with(b"\x7fELF\x02\x01") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a 64 bit little endian ELF file",
+            ));
+        }
+        Elf::parse_headers(data).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "truncated or malformed ELF file")
+        })
+    }
+
+    fn parse_headers(data: &[u8]) -> Option<Elf> {
+        let phoff = u64_at(data, 32)?;
+        let shoff = u64_at(data, 40)?;
+        let phnum = u16_at(data, 56)? as usize;
+        let shnum = u16_at(data, 60)? as usize;
+        let shstrndx = u16_at(data, 62)? as usize;
+        let mut first_vaddr = None;
+        for i in 0..phnum {
+            let phdr = phoff.checked_add(i * PHDR_LEN)?;
+            if u32_at(data, phdr)? == PT_LOAD {
+                let vaddr = u64_at(data, phdr + 16)?;
+                first_vaddr = Some(first_vaddr.map_or(vaddr, |first| min(first, vaddr)));
+            }
+        }
+        let shstrtab = u64_at(data, shoff.checked_add(shstrndx * SHDR_LEN)? + 24)?;
+        let mut symbols = HashMap::new();
+        let mut sections = HashMap::new();
+        for i in 0..shnum {
+            let shdr = shoff.checked_add(i * SHDR_LEN)?;
+            let kind = u32_at(data, shdr + 4)?;
+            if kind == SHT_SYMTAB || kind == SHT_DYNSYM {
+                read_symbols(data, shdr, &mut symbols)?;
+            }
+            let name = str_at(data, shstrtab, u32_at(data, shdr)? as usize)?;
+            let offset = u64_at(data, shdr + 24)?;
+            let size = u64_at(data, shdr + 32)?;
+            sections.insert(name.to_owned(), offset..offset.checked_add(size)?);
+        }
+        Some(Elf {
+            position_independent: u16_at(data, 16)? == ET_DYN,
+            first_vaddr: first_vaddr? & !(PAGE_SIZE - 1),
+            symbols,
+            sections,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Reads and parses the binary at `path`.
+    pub fn load(path: &Path) -> io::Result<Elf> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        Elf::parse(&data)
+    }
+
+    /// The symbol called `name`, if the binary defines it.
+    pub fn symbol(&self, name: &str) -> Option<ElfSymbol> {
+        self.symbols.get(name).cloned()
+    }
+
+    /// The contents of the section called `name`, like `.debug_info`. None
+    /// if there is no such section, or it runs past the end of the file.
+    pub fn section(&self, name: &str) -> Option<&[u8]> {
+        self.data.get(self.sections.get(name)?.clone())
+    }
+
+    /// How far the binary was moved from where it was linked to go, in a
+    /// process with `mappings`, where `path` is how its maps file names the
+    /// binary. None if the binary isn't mapped there.
+    pub fn load_bias(&self, mappings: &[Mapping], path: &str) -> Option<usize> {
+        if !self.position_independent {
+            return Some(0);
+        }
+        mappings
+            .iter()
+            .filter(|mapping| mapping.offset == 0)
+            .find(|mapping| mapping.path.as_ref().map(|p| &p[..]) == Some(path))
+            .map(|mapping| mapping.start.wrapping_sub(self.first_vaddr))
+    }
+}
@@ -0,0 +1,132 @@
+//! Finding regions of leaked memory that look random, by their Shannon
+//! entropy over a sliding window. Keys and other cryptographic material are
+//! about as random as memory gets, while code, text, pointers and padding
+//! are far from it, so the most random regions are the first places to look
+//! for keys.
+//!
+//! Misread bytes look random too, so a window with a byte that didn't leak
+//! is never flagged. Reading with a minimum confidence keeps the rest of the
+//! noise out.
+
+use std::cmp::Ordering;
+use std::mem;
+
+/// The window, in bytes, entropy is measured over unless told otherwise.
+/// 64 bytes hold the largest AES key twice over, and can be at most 6 bits
+/// per byte random.
+pub const DEFAULT_WINDOW: usize = 64;
+
+/// How far the window moves at a time unless told otherwise.
+pub const DEFAULT_STEP: usize = 16;
+
+/// The entropy, in bits per byte, a window has to reach to be flagged
+/// unless told otherwise. Random 64 byte windows mostly land between 5.5
+/// and 5.8, and text and code well below 5.
+pub const DEFAULT_THRESHOLD: f64 = 5.0;
+
+/// The Shannon entropy of `bytes`, in bits per byte, between 0 for a single
+/// value repeated and 8. It can't be more than the log of the length.
+pub fn shannon(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1
+    }
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// A run of overlapping windows that were all random enough.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub addr: usize,
+    pub len: usize,
+    /// The entropy of the region's most random window.
+    pub score: f64,
+}
+
+/// Measures windows of memory that is leaked and fed to it a piece at a
+/// time, so windows that cross from one piece into the next are measured
+/// too.
+pub struct Scanner {
+    window: usize,
+    step: usize,
+    threshold: f64,
+    // the address of bytes[0], which is where the next window starts
+    base: usize,
+    bytes: Vec<Option<u8>>,
+    // the region the last window was part of, if it was flagged
+    current: Option<Region>,
+    regions: Vec<Region>,
+}
+
+impl Scanner {
+    /// Measures windows of `window` bytes, `step` bytes apart, flagging
+    /// those with at least `threshold` bits of entropy per byte. The step
+    /// can't be 0 or more than the window, so that every byte is measured.
+    pub fn new(window: usize, step: usize, threshold: f64) -> Scanner {
+        assert!(step > 0 && step <= window);
+        Scanner {
+            window,
+            step,
+            threshold,
+            base: 0,
+            bytes: Vec::new(),
+            current: None,
+            regions: Vec::new(),
+        }
+    }
+
+    // the entropy of the window at the start of bytes, or None if a byte of
+    // it didn't leak
+    fn measure(&self) -> Option<f64> {
+        let window = &self.bytes[..self.window];
+        if window.iter().any(|byte| byte.is_none()) {
+            return None;
+        }
+        let window: Vec<u8> = window.iter().map(|byte| byte.unwrap()).collect();
+        Some(shannon(&window))
+    }
+
+    /// Feeds the bytes leaked from `addr` on, which has to be where the last
+    /// piece ended.
+    pub fn feed(&mut self, addr: usize, bytes: &[Option<u8>]) {
+        if self.bytes.is_empty() {
+            self.base = addr;
+        }
+        self.bytes.extend_from_slice(bytes);
+        while self.bytes.len() >= self.window {
+            match self.measure() {
+                Some(entropy) if entropy >= self.threshold => {
+                    let end = self.base + self.window;
+                    let region = self.current.get_or_insert(Region {
+                        addr: self.base,
+                        len: 0,
+                        score: entropy,
+                    });
+                    region.len = end - region.addr;
+                    if entropy > region.score {
+                        region.score = entropy
+                    }
+                }
+                _ => self.regions.extend(self.current.take()),
+            }
+            self.bytes.drain(..self.step);
+            self.base += self.step;
+        }
+    }
+
+    /// Every region found, the most random first.
+    pub fn finish(mut self) -> Vec<Region> {
+        let mut regions = mem::replace(&mut self.regions, Vec::new());
+        regions.extend(self.current.take());
+        regions.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        regions
+    }
+}
@@ -0,0 +1,253 @@
+use std::io;
+
+use cache::{probe, time};
+use cache::slices::{Pagemap, SliceMap};
+use probe_buf::ProbeBuffer;
+use rng::Rng;
+use timer::{self, Timer};
+use topology::Topology;
+use PAGE_SIZE;
+
+// lines are loaded from a pool twice the size of the last level cache, so
+// that every cache set has plenty of candidates in it. this is used when
+// CPUID doesn't describe the cache
+const DEFAULT_POOL_LEN: usize = 32 << 20;
+
+// the associativity assumed when CPUID doesn't describe the cache
+const DEFAULT_WAYS: usize = 16;
+
+// the L1 data cache geometry assumed when CPUID doesn't describe it
+const DEFAULT_L1_SETS: usize = 64;
+const DEFAULT_L1_WAYS: usize = 8;
+
+// times each eviction test is repeated, the median of which decides it
+const TRIALS: usize = 5;
+
+// walks over an eviction set per eviction. a second walk evicts the lines
+// that adaptive replacement policies kept after the first
+const PASSES: usize = 2;
+
+// Evicting lines from the cache with ordinary loads instead of clflush, for
+// modelling attackers that can't use the instruction.
+//
+// Each target line gets an eviction set: lines of a separate pool that map
+// to the same cache set and slice, so that loading all of them pushes the
+// target out. Where physical addresses can be read, the candidates are the
+// pool lines the slice hash puts in the target's slice and set. Otherwise
+// every pool line sharing the target's page offset is a candidate. Either
+// way, groups of candidates are then dropped for as long as what's left
+// still evicts the target
+//
+// The same sets make up the receiver of Prime+Probe: loading a set primes
+// it, and timing another load of it afterwards tells whether the target's
+// line was brought in since, evicting one of the set's own
+pub struct EvictionSets {
+    pool: ProbeBuffer,
+    // for every target, the offsets into pool of its set
+    sets: Vec<Vec<usize>>,
+    lanes: usize,
+    lines_per_lane: usize,
+}
+
+// the pool is owned, and only ever read through the sets
+unsafe impl Send for EvictionSets {}
+
+// loads every line of set, PASSES times
+#[inline(always)]
+unsafe fn load(pool: *const u8, set: &[usize]) {
+    for _ in 0..PASSES {
+        for &offset in set {
+            pool.add(offset).read_volatile();
+        }
+    }
+}
+
+// whether loading set reliably makes target miss
+unsafe fn evicts(pool: *const u8, target: *const u8, set: &[usize], threshold: u64) -> bool {
+    let mut times = [0; TRIALS];
+    for time in times.iter_mut() {
+        target.read_volatile();
+        load(pool, set);
+        *time = probe(target);
+    }
+    times.sort_unstable();
+    times[TRIALS / 2] >= threshold
+}
+
+// splits set into ways + 1 groups. a set that evicts has at most ways lines
+// that matter, so at least one group is made up of lines that don't and can
+// be dropped. repeats until no group can be, or only ways lines are left
+unsafe fn reduce(
+    pool: *const u8,
+    target: *const u8,
+    mut set: Vec<usize>,
+    ways: usize,
+    threshold: u64,
+) -> Vec<usize> {
+    'shrink: while set.len() > ways {
+        let group_len = (set.len() + ways) / (ways + 1);
+        for group in 0..(set.len() + group_len - 1) / group_len {
+            let start = group * group_len;
+            let end = (start + group_len).min(set.len());
+            let rest: Vec<usize> = set[..start].iter().chain(&set[end..]).cloned().collect();
+            if evicts(pool, target, &rest, threshold) {
+                set = rest;
+                continue 'shrink;
+            }
+        }
+        break;
+    }
+    set
+}
+
+impl EvictionSets {
+    // finds a set for every slot of every lane of buf, laid out like
+    // ProbeOrder lays them out. threshold tells hits and misses apart
+    pub unsafe fn build(
+        buf: *const u8,
+        lanes: usize,
+        lane_len: usize,
+        stride: usize,
+        slots: usize,
+        threshold: u64,
+    ) -> io::Result<EvictionSets> {
+        let llc = Topology::current().llc;
+        let pool_len = llc.map_or(DEFAULT_POOL_LEN, |llc| {
+            (2 * llc.size() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+        });
+        let ways = llc.map_or(DEFAULT_WAYS, |llc| llc.ways);
+        let pool = ProbeBuffer::private(pool_len, false)?;
+        let base = pool.as_ptr();
+        let mut rng = Rng::from_tsc();
+        let map = SliceMap::finest();
+        // the physical address of every page of the pool, if they are known
+        let pagemap = Pagemap::open().ok();
+        let frames: Option<Vec<u64>> = pagemap.as_ref().and_then(|pagemap| {
+            (0..pool_len / PAGE_SIZE)
+                .map(|page| pagemap.physical_address(base.add(page * PAGE_SIZE)).ok())
+                .collect()
+        });
+        let mut sets: Vec<Vec<usize>> = Vec::with_capacity(lanes * slots);
+        for lane in 0..lanes {
+            for slot in 0..slots {
+                let target = buf.add(lane * lane_len + slot * stride);
+
+                // targets often share a cache set, and then a set that is
+                // already known does the job
+                let known = sets
+                    .iter()
+                    .find(|set| evicts(base, target, set, threshold))
+                    .cloned();
+                if let Some(set) = known {
+                    sets.push(set);
+                    continue;
+                }
+
+                let page_offset = target as usize % PAGE_SIZE;
+                let mut candidates: Vec<usize> = (0..pool_len / PAGE_SIZE)
+                    .map(|page| page * PAGE_SIZE + page_offset)
+                    .collect();
+                let physical = pagemap
+                    .as_ref()
+                    .and_then(|pagemap| pagemap.physical_address(target).ok());
+                if let (Some(frames), Some(physical)) = (frames.as_ref(), physical) {
+                    let location = map.locate(physical);
+                    let congruent: Vec<usize> = candidates
+                        .iter()
+                        .cloned()
+                        .filter(|&offset| {
+                            map.locate(frames[offset / PAGE_SIZE] + page_offset as u64)
+                                == location
+                        })
+                        .collect();
+                    // too few congruent lines means the hash doesn't match
+                    // this part, and the search below has to find them
+                    if congruent.len() >= ways && evicts(base, target, &congruent, threshold) {
+                        sets.push(reduce(base, target, congruent, ways, threshold));
+                        continue;
+                    }
+                }
+                rng.shuffle(&mut candidates);
+                if !evicts(base, target, &candidates, threshold) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "no eviction set found for a probe line",
+                    ));
+                }
+                sets.push(reduce(base, target, candidates, ways, threshold));
+            }
+        }
+        Ok(EvictionSets {
+            pool,
+            sets,
+            lanes,
+            lines_per_lane: slots,
+        })
+    }
+
+    // sets for the L1 data cache, which is indexed by address bits inside
+    // the page, so a set is just a line at the target's offset in each of
+    // `ways` consecutive set spans of the pool
+    pub unsafe fn l1(
+        buf: *const u8,
+        lanes: usize,
+        lane_len: usize,
+        stride: usize,
+        slots: usize,
+    ) -> io::Result<EvictionSets> {
+        let topology = Topology::current();
+        let l1d = topology.l1d;
+        let ways = l1d.map_or(DEFAULT_L1_WAYS, |l1d| l1d.ways);
+        let span = l1d.map_or(DEFAULT_L1_SETS, |l1d| l1d.sets) * topology.line_size();
+        let pool_len = (ways * span + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let pool = ProbeBuffer::private(pool_len, false)?;
+        let mut sets = Vec::with_capacity(lanes * slots);
+        for lane in 0..lanes {
+            for slot in 0..slots {
+                let target = buf.add(lane * lane_len + slot * stride);
+                let set_offset = target as usize % span;
+                sets.push((0..ways).map(|way| way * span + set_offset).collect());
+            }
+        }
+        Ok(EvictionSets {
+            pool,
+            sets,
+            lanes,
+            lines_per_lane: slots,
+        })
+    }
+
+    // evicts every line of the first `lanes` lanes
+    #[inline(always)]
+    pub unsafe fn evict(&self, lanes: usize) {
+        let pool = self.pool.as_ptr();
+        for set in &self.sets[..lanes.min(self.lanes) * self.lines_per_lane] {
+            load(pool, set);
+        }
+    }
+
+    // loads only the set of slot in lane
+    #[inline(always)]
+    pub unsafe fn prime(&self, lane: usize, slot: usize) {
+        load(self.pool.as_ptr(), &self.sets[lane * self.lines_per_lane + slot]);
+    }
+
+    // times a single walk over the set of slot in lane, which primes it again
+    #[inline(always)]
+    pub unsafe fn probe(&self, lane: usize, slot: usize) -> u64 {
+        self.probe_with(&timer::selected(), lane, slot)
+    }
+
+    // probe, timed with timer
+    #[inline(always)]
+    pub unsafe fn probe_with<T: Timer>(&self, timer: &T, lane: usize, slot: usize) -> u64 {
+        let pool = self.pool.as_ptr();
+        let set = &self.sets[lane * self.lines_per_lane + slot];
+        time(timer, #[inline(always)]
+        || {
+            for &offset in set {
+                pool.add(offset).read_volatile();
+            }
+        })
+    }
+}
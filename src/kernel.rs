@@ -0,0 +1,200 @@
+//! The running kernel's release, and where the fields of the structures the
+//! higher level commands walk sit in it, so they can make sense of leaked
+//! kernel memory.
+//!
+//! Structure layouts depend on the kernel's configuration as much as on its
+//! version, so offsets are kept per distribution build, keyed by release
+//! string. `struct cred` has barely changed since user namespaces came in,
+//! and all of them share its layout, as they do the start of `struct inode`.
+//! A kernel built with randomized structure layouts can't be described by
+//! any table.
+
+use libc;
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+
+/// What `uname` says about the running kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Uname {
+    /// `Linux`.
+    pub sysname: String,
+    /// Like `4.15.0-20-generic`.
+    pub release: String,
+    /// The build number and date, like `#21-Ubuntu SMP Tue Apr 24 06:16:15 UTC 2018`.
+    pub version: String,
+}
+
+/// Calls `uname`.
+pub fn uname() -> io::Result<Uname> {
+    let mut name: libc::utsname = unsafe { mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let field = |field: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(field.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(Uname {
+        sysname: field(&name.sysname),
+        release: field(&name.release),
+        version: field(&name.version),
+    })
+}
+
+/// The running kernel's release, like `4.15.0-20-generic`.
+pub fn release() -> io::Result<String> {
+    Ok(uname()?.release)
+}
+
+/// Offsets of fields of `struct task_struct`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaskOffsets {
+    /// The `list_head` linking every process, starting from `init_task`.
+    pub tasks: usize,
+    pub pid: usize,
+    pub tgid: usize,
+    /// The credentials others see the task by.
+    pub real_cred: usize,
+    /// The credentials the task acts with.
+    pub cred: usize,
+    /// The executable name, up to 16 bytes.
+    pub comm: usize,
+}
+
+/// Offsets of fields of `struct cred`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CredOffsets {
+    pub uid: usize,
+    pub gid: usize,
+    pub euid: usize,
+    pub egid: usize,
+    pub cap_inheritable: usize,
+    pub cap_permitted: usize,
+    pub cap_effective: usize,
+}
+
+/// Offsets of fields of `struct inode`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InodeOffsets {
+    /// The `address_space` holding its cached pages.
+    pub i_mapping: usize,
+    pub i_ino: usize,
+    /// The `list_head` linking every inode of its filesystem.
+    pub i_sb_list: usize,
+}
+
+/// How an `address_space` indexes its pages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PageTree {
+    /// A radix tree, up to 4.19.
+    RadixTree,
+    /// An XArray, from 4.20.
+    XArray,
+}
+
+/// What finding a file's pages in the page cache takes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PageCacheOffsets {
+    /// Where `super_block.s_inodes`, the head of its list of inodes, is.
+    pub s_inodes: usize,
+    pub inode: InodeOffsets,
+    pub tree: PageTree,
+}
+
+/// The layout of one distribution kernel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KernelOffsets {
+    /// The releases this applies to. A `*` stands for any run of characters,
+    /// usually the distribution's ABI number.
+    pub release: &'static str,
+    pub task: TaskOffsets,
+    pub cred: CredOffsets,
+    /// None where the page cache can't be walked.
+    pub page_cache: Option<PageCacheOffsets>,
+}
+
+// without CONFIG_DEBUG_CREDENTIALS, which no distribution turns on
+const CRED: CredOffsets = CredOffsets {
+    uid: 0x04,
+    gid: 0x08,
+    euid: 0x14,
+    egid: 0x18,
+    cap_inheritable: 0x28,
+    cap_permitted: 0x30,
+    cap_effective: 0x38,
+};
+
+// with CONFIG_FS_POSIX_ACL, CONFIG_SECURITY and CONFIG_CGROUP_WRITEBACK, as
+// every distribution kernel here has
+const INODE: InodeOffsets = InodeOffsets {
+    i_mapping: 0x30,
+    i_ino: 0x40,
+    i_sb_list: 0x118,
+};
+
+// the page cache part of a row of the table
+macro_rules! page_cache {
+    ($tree:ident, $s_inodes:expr) => {
+        Some(PageCacheOffsets {
+            s_inodes: $s_inodes,
+            inode: INODE,
+            tree: PageTree::$tree,
+        })
+    };
+}
+
+// a row of the table. real_cred, cred and comm come one after another in
+// every release here, except where cached_requested_key sits between the
+// last two
+macro_rules! kernel {
+    ($release:expr, $tasks:expr, $pid:expr, $real_cred:expr, $comm:expr, $page_cache:expr) => {
+        KernelOffsets {
+            release: $release,
+            task: TaskOffsets {
+                tasks: $tasks,
+                pid: $pid,
+                tgid: $pid + 4,
+                real_cred: $real_cred,
+                cred: $real_cred + 8,
+                comm: $comm,
+            },
+            cred: CRED,
+            page_cache: $page_cache,
+        }
+    };
+}
+
+/// The table. The radix tree nodes of 4.4 don't record their height, so its
+/// page cache isn't walked.
+pub static KERNELS: [KernelOffsets; 5] = [
+    kernel!("4.4.0-*-generic", 0x308, 0x3f8, 0x5e0, 0x5f0, None),
+    kernel!("4.9.0-*-amd64", 0x350, 0x440, 0x5f8, 0x608, page_cache!(RadixTree, 0x590)),
+    kernel!("4.15.0-*-generic", 0x398, 0x498, 0x638, 0x648, page_cache!(RadixTree, 0x5c0)),
+    kernel!("4.19.0-*-amd64", 0x3a0, 0x4a0, 0x648, 0x658, page_cache!(RadixTree, 0x5d0)),
+    kernel!("5.4.0-*-generic", 0x3f8, 0x4f8, 0x6c8, 0x6e0, page_cache!(XArray, 0x600)),
+];
+
+// whether release matches pattern, where a single `*` in pattern matches
+// anything
+fn matches(pattern: &str, release: &str) -> bool {
+    match pattern.find('*') {
+        None => pattern == release,
+        Some(star) => {
+            let (prefix, suffix) = (&pattern[..star], &pattern[star + 1..]);
+            release.len() >= prefix.len() + suffix.len() && release.starts_with(prefix)
+                && release.ends_with(suffix)
+        }
+    }
+}
+
+/// The offsets for `release`, if the table has them.
+pub fn lookup(release: &str) -> Option<&'static KernelOffsets> {
+    KERNELS.iter().find(|kernel| matches(kernel.release, release))
+}
+
+/// The offsets for the running kernel, if the table has them.
+pub fn current() -> io::Result<Option<&'static KernelOffsets>> {
+    Ok(lookup(&release()?))
+}
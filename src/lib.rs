@@ -0,0 +1,73 @@
+#![feature(asm)]
+#![feature(pointer_methods)]
+#![feature(thread_local)]
+
+extern crate libc;
+extern crate llvmint;
+extern crate x86;
+
+pub mod aes;
+pub mod chase;
+pub mod covert;
+pub mod cpu;
+pub mod daemon;
+pub mod diff;
+pub mod dwarf;
+pub mod elf;
+pub mod entropy;
+pub mod fallout;
+pub mod ffi;
+pub mod gds;
+pub mod http;
+pub mod kaslr;
+pub mod kernel;
+pub mod l1tf;
+pub mod layout;
+pub mod lazy_fp;
+pub mod lime;
+pub mod maps;
+pub mod mds;
+pub mod memory;
+pub mod mitigations;
+pub mod page_cache;
+pub mod physmap;
+pub mod privkey;
+pub mod retbleed;
+pub mod rsp;
+pub mod search;
+pub mod spectre_btb;
+pub mod spectre_pht;
+pub mod spectre_ssb;
+pub mod strings;
+pub mod survey;
+pub mod symbols;
+pub mod sysreg;
+pub mod tasks;
+pub mod timer;
+pub mod topology;
+pub mod types;
+pub mod uarch;
+pub mod zenbleed;
+
+mod cache;
+mod calibrate;
+mod channel;
+mod config;
+mod evict;
+mod pipeline;
+mod probe_buf;
+mod reader;
+mod receiver;
+mod rng;
+mod suppress;
+
+pub use cache::slices;
+pub use calibrate::{Calibration, LatencyStats};
+pub use channel::Channel;
+pub use config::MeltdownConfig;
+pub use reader::{ByteGuess, ByteProfile, MeltdownReader, RangeIter};
+pub use receiver::Receiver;
+pub use suppress::{ExceptionSuppressor, ForkSuppressor, SignalSuppressor, Suppression,
+                   TsxSuppressor};
+
+pub const PAGE_SIZE: usize = 4096;
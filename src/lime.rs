@@ -0,0 +1,31 @@
+//! The LiME memory image format, which Volatility and other forensic tools
+//! read. An image is a series of ranges of physical memory, each a header
+//! giving its bounds followed by its contents.
+
+use std::ops::Range;
+
+// "LiME", read as a little endian u32
+const MAGIC: u32 = 0x4c69_4d45;
+const VERSION: u32 = 1;
+
+/// The length of a range header.
+pub const HEADER_LEN: usize = 32;
+
+/// The header of `range`, which has to be non-empty. Headers give the last
+/// address of the range rather than the one past it.
+pub fn header(range: &Range<usize>) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    let fields = [
+        (0, MAGIC as u64, 4),
+        (4, VERSION as u64, 4),
+        (8, range.start as u64, 8),
+        (16, range.end as u64 - 1, 8),
+    ];
+    // the last 8 bytes are reserved, and left zero
+    for &(offset, value, len) in &fields {
+        for i in 0..len {
+            header[offset + i] = (value >> (8 * i)) as u8;
+        }
+    }
+    header
+}
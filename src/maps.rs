@@ -0,0 +1,64 @@
+//! The memory mappings of a process, from `/proc/<pid>/maps`, so its heap,
+//! stack or libraries can be found and leaked through the direct map.
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// One line of a maps file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// Like `r-xp`.
+    pub perms: String,
+    /// The offset into the mapped file.
+    pub offset: usize,
+    /// The mapped file, or a name like `[heap]` or `[stack]`. None for
+    /// anonymous memory.
+    pub path: Option<String>,
+}
+
+impl Mapping {
+    /// Parses a line like
+    /// `7f5c3a400000-7f5c3a5e7000 r-xp 00000000 08:01 1835 /lib/libc-2.27.so`.
+    pub fn parse(line: &str) -> Option<Mapping> {
+        let mut fields = line.split_whitespace();
+        let mut range = fields.next()?.splitn(2, '-');
+        let start = usize::from_str_radix(range.next()?, 16).ok()?;
+        let end = usize::from_str_radix(range.next()?, 16).ok()?;
+        let perms = fields.next()?.to_owned();
+        let offset = usize::from_str_radix(fields.next()?, 16).ok()?;
+        // the device and inode
+        fields.next()?;
+        fields.next()?;
+        // paths can have spaces in them
+        let path = fields.collect::<Vec<_>>().join(" ");
+        Some(Mapping {
+            start,
+            end,
+            perms,
+            offset,
+            path: if path.is_empty() { None } else { Some(path) },
+        })
+    }
+
+    /// Whether the mapping is the region `name` picks: `heap` and `stack`
+    /// pick those, `anon` picks anonymous memory, and anything else picks
+    /// files whose path contains it.
+    pub fn matches(&self, name: &str) -> bool {
+        match (name, self.path.as_ref()) {
+            ("heap", Some(path)) => path == "[heap]",
+            ("stack", Some(path)) => path == "[stack]",
+            ("anon", path) => path.is_none(),
+            (name, Some(path)) => !path.starts_with('[') && path.contains(name),
+            (_, None) => false,
+        }
+    }
+}
+
+/// The mappings of process `pid`, in address order.
+pub fn read(pid: u32) -> io::Result<Vec<Mapping>> {
+    let mut maps = String::new();
+    File::open(format!("/proc/{}/maps", pid))?.read_to_string(&mut maps)?;
+    Ok(maps.lines().filter_map(Mapping::parse).collect())
+}
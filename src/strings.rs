@@ -0,0 +1,78 @@
+//! Finding the runs of printable text in leaked memory, like `strings` does
+//! for files, which is what's worth looking at when surveying a big region.
+
+use std::mem;
+
+/// The shortest run reported unless told otherwise, as for `strings`.
+pub const DEFAULT_MIN_LEN: usize = 4;
+
+// printable ASCII and tab, which `strings` counts as text too
+fn printable(byte: u8) -> bool {
+    match byte {
+        b' '...b'~' | b'\t' => true,
+        _ => false,
+    }
+}
+
+/// A run of printable bytes, and where it starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Run {
+    pub addr: usize,
+    pub text: String,
+}
+
+/// Finds runs in memory that is leaked and fed to it a piece at a time, so
+/// runs that cross from one piece into the next are found whole.
+pub struct Scanner {
+    min_len: usize,
+    // the run the last piece ended in, and where it starts
+    start: usize,
+    run: Vec<u8>,
+}
+
+impl Scanner {
+    /// Reports runs of at least `min_len` bytes.
+    pub fn new(min_len: usize) -> Scanner {
+        Scanner {
+            min_len,
+            start: 0,
+            run: Vec::new(),
+        }
+    }
+
+    // the run so far if it is long enough, starting a new one either way
+    fn end_run(&mut self) -> Option<Run> {
+        let run = mem::replace(&mut self.run, Vec::new());
+        if run.len() < self.min_len {
+            return None;
+        }
+        Some(Run {
+            addr: self.start,
+            text: String::from_utf8(run).unwrap(),
+        })
+    }
+
+    /// Feeds the bytes leaked from `addr` on, which has to be where the last
+    /// piece ended, and returns the runs that ended in them. A byte that
+    /// didn't leak ends a run like an unprintable one does.
+    pub fn feed(&mut self, addr: usize, bytes: &[Option<u8>]) -> Vec<Run> {
+        let mut result = Vec::new();
+        for (i, &byte) in bytes.iter().enumerate() {
+            match byte {
+                Some(byte) if printable(byte) => {
+                    if self.run.is_empty() {
+                        self.start = addr.wrapping_add(i)
+                    }
+                    self.run.push(byte)
+                }
+                _ => result.extend(self.end_run()),
+            }
+        }
+        result
+    }
+
+    /// The run the last piece ended in, if it is long enough.
+    pub fn finish(mut self) -> Option<Run> {
+        self.end_run()
+    }
+}
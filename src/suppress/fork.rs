@@ -0,0 +1,36 @@
+use libc;
+
+use super::ExceptionSuppressor;
+
+/// Does the read in a forked child that is allowed to crash.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ForkSuppressor;
+
+impl ExceptionSuppressor for ForkSuppressor {
+    // the probe buffer is a MAP_SHARED mapping, so the lines the child brings
+    // into the cache are the same physical lines the parent probes afterwards
+    fn needs_shared_probe_buf(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    unsafe fn suppress<F: FnOnce()>(&self, f: F) {
+        match libc::fork() {
+            0 => {
+                // every probe crashes a child, don't leave a core dump behind for each one
+                libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+                // a handler inherited from the signal strategy would jump into the
+                // parent's stale stack
+                libc::signal(libc::SIGSEGV, libc::SIG_DFL);
+                libc::signal(libc::SIGBUS, libc::SIG_DFL);
+                f();
+                libc::_exit(0)
+            }
+            -1 => {}
+            child => {
+                let mut status = 0;
+                libc::waitpid(child, &mut status, 0);
+            }
+        }
+    }
+}
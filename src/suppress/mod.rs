@@ -0,0 +1,92 @@
+use std::io;
+
+use cpu::CpuFeatures;
+
+mod fork;
+mod signal;
+pub(crate) mod tsx;
+
+pub use self::fork::ForkSuppressor;
+pub use self::signal::SignalSuppressor;
+pub use self::tsx::TsxSuppressor;
+
+/// A way of keeping the fault raised by the transient read from killing the
+/// process.
+pub trait ExceptionSuppressor {
+    /// Performs any process wide setup the strategy needs. Called once when a
+    /// reader is created.
+    fn install(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether the probe buffer has to stay shared with other processes.
+    fn needs_shared_probe_buf(&self) -> bool {
+        false
+    }
+
+    /// Runs `f`, which is expected to fault, and returns normally whether or
+    /// not it did. Only the cache side effects of `f` are observable afterwards.
+    unsafe fn suppress<F: FnOnce()>(&self, f: F);
+}
+
+/// Runtime selection between the built in suppressors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Suppression {
+    /// Run the read inside a TSX (RTM) transaction. Requires Haswell or later.
+    Tsx,
+    /// Catch SIGSEGV and `siglongjmp` past the faulting read. Works on any CPU
+    /// but the signal round trip makes each probe slower.
+    Signal,
+    /// Do the read in a forked child that is allowed to crash, while the parent
+    /// probes a probe buffer shared with it. Much slower, but needs neither TSX
+    /// nor reliable signal recovery.
+    Fork,
+}
+
+impl Suppression {
+    /// The fastest strategy this CPU supports: TSX if RTM is available,
+    /// otherwise the signal handler.
+    pub fn detect() -> Suppression {
+        if CpuFeatures::detect().rtm {
+            Suppression::Tsx
+        } else {
+            Suppression::Signal
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Suppression> {
+        match name {
+            "tsx" => Some(Suppression::Tsx),
+            "signal" => Some(Suppression::Signal),
+            "fork" => Some(Suppression::Fork),
+            _ => None,
+        }
+    }
+}
+
+impl ExceptionSuppressor for Suppression {
+    fn install(&self) -> io::Result<()> {
+        match *self {
+            Suppression::Tsx => TsxSuppressor.install(),
+            Suppression::Signal => SignalSuppressor.install(),
+            Suppression::Fork => ForkSuppressor.install(),
+        }
+    }
+
+    fn needs_shared_probe_buf(&self) -> bool {
+        match *self {
+            Suppression::Tsx => TsxSuppressor.needs_shared_probe_buf(),
+            Suppression::Signal => SignalSuppressor.needs_shared_probe_buf(),
+            Suppression::Fork => ForkSuppressor.needs_shared_probe_buf(),
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn suppress<F: FnOnce()>(&self, f: F) {
+        match *self {
+            Suppression::Tsx => TsxSuppressor.suppress(f),
+            Suppression::Signal => SignalSuppressor.suppress(f),
+            Suppression::Fork => ForkSuppressor.suppress(f),
+        }
+    }
+}
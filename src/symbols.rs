@@ -0,0 +1,131 @@
+//! Kernel symbol addresses, so dumps can be aimed at `linux_proc_banner`
+//! rather than at a number. `/proc/kallsyms` lists every symbol with its
+//! address, but with `kptr_restrict` set, as most distributions do, only
+//! root sees the addresses and everyone else gets zeros.
+//!
+//! The System.map installed with the kernel has the same symbols, but at the
+//! addresses they were linked at. With KASLR the whole image is moved by one
+//! slide, so relocating the map to where `kaslr` finds the image gives the
+//! runtime addresses, without any help from the kernel.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A symbol and an offset into it, like `init_task+0x5f0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolTarget {
+    pub name: String,
+    pub offset: usize,
+}
+
+impl SymbolTarget {
+    /// Parses `name` or `name+offset`, with the offset in hex if it has a
+    /// `0x` prefix and in decimal otherwise.
+    pub fn parse(s: &str) -> Option<SymbolTarget> {
+        let mut parts = s.splitn(2, '+');
+        let name = parts.next()?.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let offset = match parts.next().map(str::trim) {
+            None => 0,
+            Some(offset) if offset.starts_with("0x") => {
+                usize::from_str_radix(&offset[2..], 16).ok()?
+            }
+            Some(offset) => offset.parse().ok()?,
+        };
+        Some(SymbolTarget {
+            name: name.to_owned(),
+            offset,
+        })
+    }
+}
+
+/// Symbol names and their addresses.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    addresses: HashMap<String, usize>,
+}
+
+impl Symbols {
+    /// Parses the `address type name [module]` lines of kallsyms or a
+    /// System.map. A name that appears more than once, as static functions
+    /// of different files can, keeps its first address.
+    pub fn parse(text: &str) -> Symbols {
+        let mut addresses = HashMap::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let (addr, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(addr), Some(_), Some(name)) => (addr, name),
+                _ => continue,
+            };
+            if let Ok(addr) = usize::from_str_radix(addr, 16) {
+                addresses.entry(name.to_owned()).or_insert(addr);
+            }
+        }
+        Symbols { addresses }
+    }
+
+    /// Reads `/proc/kallsyms`. Fails if it hides the addresses.
+    pub fn kallsyms() -> io::Result<Symbols> {
+        let mut text = String::new();
+        File::open("/proc/kallsyms")?.read_to_string(&mut text)?;
+        let symbols = Symbols::parse(&text);
+        if symbols.addresses.values().all(|&addr| addr == 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "/proc/kallsyms hides addresses, because of kptr_restrict or not running as root",
+            ));
+        }
+        Ok(symbols)
+    }
+
+    /// Reads a System.map, as installed at `/boot/System.map-<release>`.
+    pub fn load(path: &Path) -> io::Result<Symbols> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Ok(Symbols::parse(&text))
+    }
+
+    /// Moves every symbol by the slide that puts `_text`, the start of the
+    /// kernel image, at `text_base`. None if there is no `_text`.
+    pub fn relocated(&self, text_base: usize) -> Option<Symbols> {
+        let slide = text_base.wrapping_sub(self.address("_text")?);
+        let addresses = self.addresses
+            .iter()
+            .map(|(name, &addr)| (name.clone(), addr.wrapping_add(slide)))
+            .collect();
+        Some(Symbols { addresses })
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// The address of the symbol called `name`, if there is one.
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.addresses.get(name).cloned()
+    }
+
+    /// The address `target` points at.
+    pub fn resolve(&self, target: &SymbolTarget) -> io::Result<usize> {
+        let addr = self.address(&target.name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no symbol called `{}`", target.name),
+            )
+        })?;
+        addr.checked_add(target.offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("`{}+0x{:x}` overflows the address space", target.name, target.offset),
+            )
+        })
+    }
+}
